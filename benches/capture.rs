@@ -0,0 +1,85 @@
+//! Timings for screen_capture against a synthetic 4K screen, so changes
+//! that slow down the capture path show up. Run with `cargo bench`; each
+//! case prints its mean time per call.
+
+use image::{DynamicImage, ImageFormat, Rgba, RgbaImage};
+use serde_json::{json, Value};
+use std::io::Cursor;
+use std::time::Instant;
+
+use desktop_mcp::backend::{set_screen_backend, FakeScreen};
+use desktop_mcp::{handle_request_catching_panics, parse_request};
+
+const ITERATIONS: u32 = 20;
+
+/// A 3840x2160 screen of UI-like content: flat panels, rules and a band
+/// of fine detail standing in for text.
+fn synthetic_screen() -> Vec<u8> {
+    let image = RgbaImage::from_fn(3840, 2160, |x, y| {
+        if y % 240 < 4 || x % 480 < 4 {
+            Rgba([60, 60, 70, 255])
+        } else if (y % 240) < 40 && (x / 3 + y / 5) % 7 < 3 {
+            Rgba([20, 20, 20, 255])
+        } else {
+            Rgba([(x / 15) as u8, 230, (y / 9) as u8, 255])
+        }
+    });
+    let mut png = Vec::new();
+    DynamicImage::ImageRgba8(image)
+        .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+        .unwrap();
+    png
+}
+
+fn bench(name: &str, arguments: Value) {
+    let line = json!({
+        "jsonrpc": "2.0",
+        "id": 1,
+        "method": "tools/call",
+        "params": { "name": "screen_capture", "arguments": arguments }
+    })
+    .to_string();
+    let Ok(request) = parse_request(&line) else {
+        panic!("Bad request for {}", name);
+    };
+    let call = || {
+        let response = serde_json::to_value(handle_request_catching_panics(&request)).unwrap();
+        if response["result"]["isError"] != json!(false) && response["result"]["isError"] != Value::Null
+            || response.get("error").is_some()
+        {
+            panic!("{} failed: {}", name, response);
+        }
+    };
+
+    call();
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        call();
+    }
+    let mean = start.elapsed().as_secs_f64() * 1000.0 / ITERATIONS as f64;
+    println!("{:<32} {:>9.2} ms", name, mean);
+}
+
+fn main() {
+    set_screen_backend(FakeScreen::from_png(&synthetic_screen(), 2.0).unwrap());
+
+    bench("full screen, jpeg", json!({}));
+    bench("full screen, png", json!({ "format": "png" }));
+    bench("full screen, native jpeg", json!({ "max_width": 3840, "max_height": 2160 }));
+    bench(
+        "200x100 region, png",
+        json!({ "region": { "x": 100, "y": 100, "width": 200, "height": 100 }, "format": "png" }),
+    );
+    bench(
+        "three regions, png",
+        json!({
+            "regions": [
+                { "x": 0, "y": 0, "width": 200, "height": 50 },
+                { "x": 900, "y": 0, "width": 200, "height": 50 },
+                { "x": 0, "y": 800, "width": 1920, "height": 280 }
+            ],
+            "format": "png"
+        }),
+    );
+    bench("full screen, jpeg with cursor", json!({ "include_cursor": true }));
+}
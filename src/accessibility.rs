@@ -0,0 +1,648 @@
+//! Querying the macOS accessibility tree for UI elements.
+
+use serde_json::{json, Value};
+#[cfg(target_os = "macos")]
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use crate::cancel::sleep_cancellable;
+#[cfg(target_os = "macos")]
+use crate::tools::availability::{permission_granted, Permission};
+use crate::tools::output::ToolOutput;
+#[cfg(target_os = "macos")]
+use crate::window::list_windows;
+use crate::window::{WindowInfo, WindowState};
+#[cfg(target_os = "macos")]
+use crate::window::WINDOW_ANIMATION_MS;
+
+/// Elements deeper than this below a window aren't visited.
+#[cfg(target_os = "macos")]
+const ACCESSIBILITY_MAX_DEPTH: usize = 64;
+
+/// Cap on elements visited in one walk of the tree.
+#[cfg(target_os = "macos")]
+const ACCESSIBILITY_MAX_ELEMENTS: usize = 20_000;
+
+/// How long a single accessibility request to an app may take before it's
+/// abandoned, so a hung app can't stall a walk.
+#[cfg(target_os = "macos")]
+const ACCESSIBILITY_MESSAGING_TIMEOUT_SECS: f32 = 0.5;
+
+/// An element of the platform accessibility tree. Bounds are in global
+/// logical coordinates, like the mouse tools.
+#[derive(Debug, Clone, Default)]
+struct AxElement {
+    role: String,
+    subrole: Option<String>,
+    title: Option<String>,
+    description: Option<String>,
+    value: Option<String>,
+    identifier: Option<String>,
+    enabled: Option<bool>,
+    focused: Option<bool>,
+    /// (x, y, width, height)
+    bounds: Option<(f64, f64, f64, f64)>,
+    /// Title of the window containing the element.
+    window: String,
+    app_name: String,
+    pid: i64,
+}
+
+impl AxElement {
+    /// The text an element is best known by: its title, or failing that its
+    /// description or identifier.
+    fn label(&self) -> Option<&str> {
+        [&self.title, &self.description, &self.identifier]
+            .into_iter()
+            .flatten()
+            .map(String::as_str)
+            .find(|s| !s.is_empty())
+    }
+
+    fn to_json(&self) -> Value {
+        let mut element = json!({
+            "role": self.role,
+            "subrole": self.subrole,
+            "title": self.title,
+            "description": self.description,
+            "value": self.value,
+            "identifier": self.identifier,
+            "enabled": self.enabled,
+            "focused": self.focused,
+            "window": self.window,
+            "app_name": self.app_name,
+            "pid": self.pid,
+        });
+        if let Some((x, y, width, height)) = self.bounds {
+            element["bounds"] = json!({ "x": x, "y": y, "width": width, "height": height });
+            element["center"] = json!({ "x": x + width / 2.0, "y": y + height / 2.0 });
+        }
+        element
+    }
+}
+
+/// Criteria for finding an accessibility element. Roles match exactly,
+/// ignoring case and the "AX" prefix ("button" matches AXButton). Labels
+/// match as a case-insensitive substring of the title, description or
+/// identifier. The window, if given, matches a window title or app name
+/// the same way; otherwise the frontmost app is searched.
+#[derive(Debug, Clone)]
+pub(crate) struct ElementQuery {
+    role: Option<String>,
+    label: Option<String>,
+    window: Option<String>,
+}
+
+impl ElementQuery {
+    pub(crate) fn parse(args: &Value) -> Result<Self, String> {
+        let string = |name: &str| {
+            args.get(name)
+                .and_then(|v| v.as_str())
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .map(String::from)
+        };
+        let query = ElementQuery {
+            role: string("role"),
+            label: string("label"),
+            window: string("window"),
+        };
+        if query.role.is_none() && query.label.is_none() {
+            return Err("Provide role and/or label to match".to_string());
+        }
+        Ok(query)
+    }
+
+    fn role_matches(&self, element: &AxElement) -> bool {
+        let normalize = |role: &str| role.trim_start_matches("AX").to_lowercase();
+        self.role
+            .as_ref()
+            .is_none_or(|role| normalize(role) == normalize(&element.role))
+    }
+
+    fn label_matches(&self, element: &AxElement) -> bool {
+        let Some(label) = &self.label else {
+            return true;
+        };
+        let label = label.to_lowercase();
+        [&element.title, &element.description, &element.identifier]
+            .into_iter()
+            .flatten()
+            .any(|text| text.to_lowercase().contains(&label))
+    }
+
+    fn matches(&self, element: &AxElement) -> bool {
+        self.role_matches(element) && self.label_matches(element)
+    }
+}
+
+/// What a walk of the accessibility tree covered.
+#[derive(Debug, Clone, Copy, Default)]
+struct AxWalk {
+    /// Windows searched.
+    windows: usize,
+    /// Elements visited.
+    visited: usize,
+    /// False if the walk stopped at the time budget or element cap before
+    /// covering the whole tree.
+    complete: bool,
+}
+
+impl AxWalk {
+    fn to_json(self) -> Value {
+        json!({ "windows": self.windows, "visited": self.visited, "complete": self.complete })
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod ax_ffi {
+    use core_foundation::base::CFTypeRef;
+    use core_foundation::string::CFStringRef;
+    use std::ffi::c_void;
+
+    pub type AXUIElementRef = CFTypeRef;
+    pub const K_AX_VALUE_CG_POINT_TYPE: u32 = 1;
+    pub const K_AX_VALUE_CG_SIZE_TYPE: u32 = 2;
+
+    #[link(name = "ApplicationServices", kind = "framework")]
+    extern "C" {
+        pub fn AXUIElementCreateApplication(pid: i32) -> AXUIElementRef;
+        pub fn AXUIElementCopyAttributeValue(
+            element: AXUIElementRef,
+            attribute: CFStringRef,
+            value: *mut CFTypeRef,
+        ) -> i32;
+        pub fn AXUIElementSetAttributeValue(element: AXUIElementRef, attribute: CFStringRef, value: CFTypeRef) -> i32;
+        pub fn AXUIElementPerformAction(element: AXUIElementRef, action: CFStringRef) -> i32;
+        pub fn AXUIElementSetMessagingTimeout(element: AXUIElementRef, timeout: f32) -> i32;
+        pub fn AXValueGetValue(value: CFTypeRef, value_type: u32, out: *mut c_void) -> u8;
+        pub fn AXValueCreate(value_type: u32, value: *const c_void) -> CFTypeRef;
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn ax_attribute(element: &core_foundation::base::CFType, name: &str) -> Option<core_foundation::base::CFType> {
+    use core_foundation::base::{CFType, CFTypeRef, TCFType};
+    use core_foundation::string::CFString;
+
+    let attribute = CFString::new(name);
+    let mut value: CFTypeRef = std::ptr::null();
+    let error = unsafe {
+        ax_ffi::AXUIElementCopyAttributeValue(
+            element.as_CFTypeRef(),
+            attribute.as_concrete_TypeRef(),
+            &mut value,
+        )
+    };
+    (error == 0 && !value.is_null()).then(|| unsafe { CFType::wrap_under_create_rule(value) })
+}
+
+/// Read an attribute as text. Numbers and booleans are formatted.
+#[cfg(target_os = "macos")]
+fn ax_string(element: &core_foundation::base::CFType, name: &str) -> Option<String> {
+    use core_foundation::boolean::CFBoolean;
+    use core_foundation::number::CFNumber;
+    use core_foundation::string::CFString;
+
+    let value = ax_attribute(element, name)?;
+    if let Some(s) = value.downcast::<CFString>() {
+        return Some(s.to_string());
+    }
+    if let Some(n) = value.downcast::<CFNumber>() {
+        return n.to_f64().map(|n| n.to_string());
+    }
+    value.downcast::<CFBoolean>().map(|b| bool::from(b).to_string())
+}
+
+#[cfg(target_os = "macos")]
+fn ax_bool(element: &core_foundation::base::CFType, name: &str) -> Option<bool> {
+    use core_foundation::boolean::CFBoolean;
+
+    ax_attribute(element, name)?.downcast::<CFBoolean>().map(bool::from)
+}
+
+#[cfg(target_os = "macos")]
+fn ax_bounds(element: &core_foundation::base::CFType) -> Option<(f64, f64, f64, f64)> {
+    use core_foundation::base::TCFType;
+    use core_graphics::geometry::{CGPoint, CGSize};
+
+    let position = ax_attribute(element, "AXPosition")?;
+    let size = ax_attribute(element, "AXSize")?;
+    let mut point = CGPoint::new(0.0, 0.0);
+    let mut extent = CGSize::new(0.0, 0.0);
+    let ok = unsafe {
+        ax_ffi::AXValueGetValue(
+            position.as_CFTypeRef(),
+            ax_ffi::K_AX_VALUE_CG_POINT_TYPE,
+            &mut point as *mut CGPoint as *mut _,
+        ) != 0
+            && ax_ffi::AXValueGetValue(
+                size.as_CFTypeRef(),
+                ax_ffi::K_AX_VALUE_CG_SIZE_TYPE,
+                &mut extent as *mut CGSize as *mut _,
+            ) != 0
+    };
+    ok.then_some((point.x, point.y, extent.width, extent.height))
+}
+
+#[cfg(target_os = "macos")]
+fn ax_children(element: &core_foundation::base::CFType) -> Vec<core_foundation::base::CFType> {
+    ax_elements(element, "AXChildren")
+}
+
+#[cfg(target_os = "macos")]
+fn ax_elements(element: &core_foundation::base::CFType, name: &str) -> Vec<core_foundation::base::CFType> {
+    use core_foundation::array::CFArray;
+    use core_foundation::base::{CFType, TCFType};
+
+    let Some(array) = ax_attribute(element, name).and_then(|v| v.downcast::<CFArray>()) else {
+        return Vec::new();
+    };
+    array
+        .iter()
+        .filter(|item| !item.is_null())
+        .map(|item| unsafe { CFType::wrap_under_get_rule(*item) })
+        .collect()
+}
+
+/// Walk the accessibility trees of the windows matching `window` (or of the
+/// frontmost app) breadth-first, calling `visit` on each element until it
+/// returns true. Stops early once `budget` has elapsed.
+#[cfg(target_os = "macos")]
+fn walk_accessibility_tree(
+    window: Option<&str>,
+    budget: Duration,
+    visit: &mut dyn FnMut(&AxElement) -> bool,
+) -> Result<AxWalk, String> {
+    use core_foundation::base::{CFType, TCFType};
+
+    if !permission_granted(Permission::Accessibility) {
+        return Err("Accessibility queries need the Accessibility permission".to_string());
+    }
+
+    let deadline = Instant::now() + budget;
+    let filter = window.map(str::to_lowercase);
+    let matches = |text: &str| filter.as_ref().is_none_or(|f| text.to_lowercase().contains(f));
+
+    // Apps owning a matching window, front to back; the frontmost app only
+    // when no window is given
+    let mut apps: Vec<(i64, String)> = Vec::new();
+    for w in list_windows()? {
+        if (matches(&w.title) || matches(&w.app_name)) && !apps.iter().any(|(pid, _)| *pid == w.pid) {
+            apps.push((w.pid, w.app_name.clone()));
+        }
+        if filter.is_none() && !apps.is_empty() {
+            break;
+        }
+    }
+
+    let mut walk = AxWalk::default();
+    let mut queue = VecDeque::new();
+    for (pid, app_name) in apps {
+        let app = unsafe { CFType::wrap_under_create_rule(ax_ffi::AXUIElementCreateApplication(pid as i32)) };
+        unsafe {
+            ax_ffi::AXUIElementSetMessagingTimeout(app.as_CFTypeRef(), ACCESSIBILITY_MESSAGING_TIMEOUT_SECS);
+        }
+        let app_matches = matches(&app_name);
+        for window in ax_elements(&app, "AXWindows") {
+            let title = ax_string(&window, "AXTitle").unwrap_or_default();
+            if app_matches || matches(&title) {
+                walk.windows += 1;
+                queue.push_back((window, 0, title, app_name.clone(), pid));
+            }
+        }
+    }
+
+    while let Some((element, depth, window, app_name, pid)) = queue.pop_front() {
+        if Instant::now() >= deadline || walk.visited >= ACCESSIBILITY_MAX_ELEMENTS {
+            return Ok(walk);
+        }
+        walk.visited += 1;
+
+        let info = AxElement {
+            role: ax_string(&element, "AXRole").unwrap_or_default(),
+            subrole: ax_string(&element, "AXSubrole"),
+            title: ax_string(&element, "AXTitle"),
+            description: ax_string(&element, "AXDescription"),
+            value: ax_string(&element, "AXValue"),
+            identifier: ax_string(&element, "AXIdentifier"),
+            enabled: ax_bool(&element, "AXEnabled"),
+            focused: ax_bool(&element, "AXFocused"),
+            bounds: ax_bounds(&element),
+            window: window.clone(),
+            app_name: app_name.clone(),
+            pid,
+        };
+        if visit(&info) {
+            return Ok(walk);
+        }
+        if depth < ACCESSIBILITY_MAX_DEPTH {
+            for child in ax_children(&element) {
+                queue.push_back((child, depth + 1, window.clone(), app_name.clone(), pid));
+            }
+        }
+    }
+
+    walk.complete = true;
+    Ok(walk)
+}
+
+/// The application element owning `window` and the window's own element.
+/// The window is matched among the app's accessibility windows by its
+/// bounds, or failing that its title.
+#[cfg(target_os = "macos")]
+fn ax_window(
+    window: &WindowInfo,
+) -> Result<(core_foundation::base::CFType, core_foundation::base::CFType), String> {
+    use core_foundation::base::{CFType, TCFType};
+
+    if !permission_granted(Permission::Accessibility) {
+        return Err("Managing windows needs the Accessibility permission".to_string());
+    }
+
+    let app = unsafe { CFType::wrap_under_create_rule(ax_ffi::AXUIElementCreateApplication(window.pid as i32)) };
+    unsafe {
+        ax_ffi::AXUIElementSetMessagingTimeout(app.as_CFTypeRef(), ACCESSIBILITY_MESSAGING_TIMEOUT_SECS);
+    }
+    let windows = ax_elements(&app, "AXWindows");
+    let same_bounds = |element: &&CFType| {
+        ax_bounds(element).is_some_and(|(x, y, width, height)| {
+            (x - window.x).abs() < 1.0
+                && (y - window.y).abs() < 1.0
+                && (width - window.width).abs() < 1.0
+                && (height - window.height).abs() < 1.0
+        })
+    };
+    let target = windows.iter().find(same_bounds).or_else(|| {
+        windows
+            .iter()
+            .find(|element| ax_string(element, "AXTitle").as_deref() == Some(window.title.as_str()))
+    });
+    match target {
+        Some(target) => Ok((app.clone(), target.clone())),
+        None => Err(format!(
+            "{} has no accessible window matching \"{}\"",
+            window.app_name, window.title
+        )),
+    }
+}
+
+/// Set an attribute of an element, naming `what` was being done on failure.
+#[cfg(target_os = "macos")]
+fn ax_set(
+    element: &core_foundation::base::CFType,
+    name: &str,
+    value: &core_foundation::base::CFType,
+    what: &str,
+) -> Result<(), String> {
+    use core_foundation::base::TCFType;
+    use core_foundation::string::CFString;
+
+    let error = unsafe {
+        ax_ffi::AXUIElementSetAttributeValue(
+            element.as_CFTypeRef(),
+            CFString::new(name).as_concrete_TypeRef(),
+            value.as_CFTypeRef(),
+        )
+    };
+    if error != 0 {
+        return Err(format!("Failed to {} (AX error {})", what, error));
+    }
+    Ok(())
+}
+
+/// Perform an action on an element, naming `what` was being done on
+/// failure.
+#[cfg(target_os = "macos")]
+fn ax_perform(element: &core_foundation::base::CFType, action: &str, what: &str) -> Result<(), String> {
+    use core_foundation::base::TCFType;
+    use core_foundation::string::CFString;
+
+    let error =
+        unsafe { ax_ffi::AXUIElementPerformAction(element.as_CFTypeRef(), CFString::new(action).as_concrete_TypeRef()) };
+    if error != 0 {
+        return Err(format!("Failed to {} (AX error {})", what, error));
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "macos")]
+fn ax_bool_value(value: bool) -> core_foundation::base::CFType {
+    use core_foundation::base::TCFType;
+    use core_foundation::boolean::CFBoolean;
+
+    CFBoolean::from(value).as_CFType()
+}
+
+/// Bring `window` to the front: make its app frontmost, then raise the
+/// window above the app's others.
+#[cfg(target_os = "macos")]
+pub(crate) fn raise_window(window: &WindowInfo) -> Result<(), String> {
+    let (app, element) = ax_window(window)?;
+    ax_set(&app, "AXFrontmost", &ax_bool_value(true), &format!("activate {}", window.app_name))?;
+    ax_perform(&element, "AXRaise", &format!("raise \"{}\"", window.title))
+}
+
+#[cfg(not(target_os = "macos"))]
+pub(crate) fn raise_window(_window: &WindowInfo) -> Result<(), String> {
+    Err("Focusing windows is only supported on macOS".to_string())
+}
+
+/// Move `window` so its top-left corner is at `position`, and resize it to
+/// `size`, in global logical coordinates. The app may adjust either.
+#[cfg(target_os = "macos")]
+pub(crate) fn set_window_frame(window: &WindowInfo, position: (f64, f64), size: (f64, f64)) -> Result<(), String> {
+    use core_foundation::base::{CFType, TCFType};
+    use core_graphics::geometry::{CGPoint, CGSize};
+
+    let (_, element) = ax_window(window)?;
+    let point = CGPoint::new(position.0, position.1);
+    let extent = CGSize::new(size.0, size.1);
+    let (point, extent) = unsafe {
+        (
+            CFType::wrap_under_create_rule(ax_ffi::AXValueCreate(
+                ax_ffi::K_AX_VALUE_CG_POINT_TYPE,
+                &point as *const CGPoint as *const _,
+            )),
+            CFType::wrap_under_create_rule(ax_ffi::AXValueCreate(
+                ax_ffi::K_AX_VALUE_CG_SIZE_TYPE,
+                &extent as *const CGSize as *const _,
+            )),
+        )
+    };
+    // Size before and after moving: a window moved to a smaller screen may
+    // be shrunk to fit, and one resized near a screen edge may be pushed
+    // back on screen
+    ax_set(&element, "AXSize", &extent, "resize the window")?;
+    ax_set(&element, "AXPosition", &point, "move the window")?;
+    ax_set(&element, "AXSize", &extent, "resize the window")
+}
+
+#[cfg(not(target_os = "macos"))]
+pub(crate) fn set_window_frame(_window: &WindowInfo, _position: (f64, f64), _size: (f64, f64)) -> Result<(), String> {
+    Err("Managing windows is only supported on macOS".to_string())
+}
+
+/// Minimize, maximize (zoom), restore or make `window` full screen.
+#[cfg(target_os = "macos")]
+pub(crate) fn set_window_state(window: &WindowInfo, state: WindowState) -> Result<(), String> {
+    let (_, element) = ax_window(window)?;
+    match state {
+        WindowState::Minimized => ax_set(&element, "AXMinimized", &ax_bool_value(true), "minimize the window"),
+        WindowState::Fullscreen => ax_set(&element, "AXFullScreen", &ax_bool_value(true), "make the window full screen"),
+        WindowState::Restored => {
+            if ax_bool(&element, "AXFullScreen") == Some(true) {
+                ax_set(&element, "AXFullScreen", &ax_bool_value(false), "leave full screen")?;
+            }
+            ax_set(&element, "AXMinimized", &ax_bool_value(false), "unminimize the window")
+        }
+        WindowState::Maximized => {
+            if ax_bool(&element, "AXMinimized") == Some(true) {
+                ax_set(&element, "AXMinimized", &ax_bool_value(false), "unminimize the window")?;
+            }
+            let area = |bounds: Option<(f64, f64, f64, f64)>| bounds.map_or(0.0, |(_, _, w, h)| w * h);
+            let before = area(ax_bounds(&element));
+            let Some(zoom) = ax_attribute(&element, "AXZoomButton") else {
+                return Err(format!("\"{}\" can't be zoomed", window.title));
+            };
+            ax_perform(&zoom, "AXPress", "zoom the window")?;
+            // Zoom toggles; if the window shrank it was already zoomed
+            std::thread::sleep(Duration::from_millis(WINDOW_ANIMATION_MS));
+            if area(ax_bounds(&element)) < before {
+                ax_perform(&zoom, "AXPress", "zoom the window")?;
+            }
+            Ok(())
+        }
+    }
+}
+
+#[cfg(not(target_os = "macos"))]
+pub(crate) fn set_window_state(_window: &WindowInfo, _state: WindowState) -> Result<(), String> {
+    Err("Managing windows is only supported on macOS".to_string())
+}
+
+#[cfg(not(target_os = "macos"))]
+fn walk_accessibility_tree(
+    _window: Option<&str>,
+    _budget: Duration,
+    _visit: &mut dyn FnMut(&AxElement) -> bool,
+) -> Result<AxWalk, String> {
+    Err("Accessibility queries are only supported on macOS".to_string())
+}
+
+/// Levenshtein distance over characters.
+pub(crate) fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, &cb) in b.iter().enumerate() {
+            let next = (diagonal + (ca != cb) as usize).min(row[j] + 1).min(row[j + 1] + 1);
+            diagonal = row[j + 1];
+            row[j + 1] = next;
+        }
+    }
+    row[b.len()]
+}
+
+/// Number of near-matches reported when wait_for_element times out.
+const NEAR_MATCH_COUNT: usize = 5;
+
+/// The closest labels seen so far to a query's label, among elements of
+/// the right role, to help spot typos.
+#[derive(Default)]
+struct NearMatches {
+    best: Vec<(usize, AxElement)>,
+}
+
+impl NearMatches {
+    fn observe(&mut self, query: &ElementQuery, element: &AxElement) {
+        let (Some(wanted), Some(label)) = (&query.label, element.label()) else {
+            return;
+        };
+        if !query.role_matches(element) {
+            return;
+        }
+        let distance = edit_distance(&wanted.to_lowercase(), &label.to_lowercase());
+        if self.best.iter().any(|(_, e)| e.label() == Some(label)) {
+            return;
+        }
+        if self.best.len() == NEAR_MATCH_COUNT && self.best.last().is_some_and(|(d, _)| *d <= distance) {
+            return;
+        }
+        let pos = self.best.partition_point(|(d, _)| *d <= distance);
+        self.best.insert(pos, (distance, element.clone()));
+        self.best.truncate(NEAR_MATCH_COUNT);
+    }
+
+    fn to_json(&self) -> Value {
+        self.best
+            .iter()
+            .map(|(distance, element)| {
+                let mut json = element.to_json();
+                json["distance"] = json!(distance);
+                json
+            })
+            .collect()
+    }
+}
+
+/// Upper bound on how long wait_for_element may block.
+pub(crate) const MAX_WAIT_FOR_ELEMENT_TIMEOUT_MS: u64 = 120_000;
+
+/// Shortest poll interval wait_for_element accepts, which is also the
+/// smallest time budget a single walk of the tree gets.
+pub(crate) const MIN_ELEMENT_POLL_INTERVAL_MS: u64 = 100;
+
+/// Poll the accessibility tree until an element matches, returning it; or
+/// time out with the closest near-matches seen. Each poll's walk is cut off
+/// at the poll interval so a huge tree can't stretch it.
+pub(crate) fn wait_for_element(
+    query: &ElementQuery,
+    poll_interval: Duration,
+    timeout: Duration,
+) -> Result<ToolOutput, String> {
+    let start = Instant::now();
+    let deadline = start + timeout;
+    let mut near = NearMatches::default();
+    let mut polls = 0;
+
+    loop {
+        let poll_start = Instant::now();
+        let budget = poll_interval
+            .min(deadline.saturating_duration_since(poll_start))
+            .max(Duration::from_millis(MIN_ELEMENT_POLL_INTERVAL_MS));
+        let mut found = None;
+        let walk = walk_accessibility_tree(query.window.as_deref(), budget, &mut |element| {
+            if query.matches(element) {
+                found = Some(element.clone());
+                return true;
+            }
+            near.observe(query, element);
+            false
+        })?;
+        polls += 1;
+
+        if let Some(element) = found {
+            return Ok(ToolOutput::json(&json!({
+                "found": true,
+                "element": element.to_json(),
+                "waited_ms": start.elapsed().as_millis() as u64,
+                "polls": polls
+            })));
+        }
+        if Instant::now() >= deadline {
+            return Ok(ToolOutput::json(&json!({
+                "found": false,
+                "timed_out": true,
+                "waited_ms": start.elapsed().as_millis() as u64,
+                "polls": polls,
+                "last_poll": walk.to_json(),
+                "near_matches": near.to_json()
+            })));
+        }
+        let next = (poll_start + poll_interval).min(deadline);
+        sleep_cancellable(next.saturating_duration_since(Instant::now()))?;
+    }
+}
@@ -0,0 +1,275 @@
+//! Opt-in audit log of executed tool calls: one JSON line per call, written
+//! by a background thread so that calls don't wait on the disk. Each entry
+//! carries the SHA-256 of the line before it, so editing, removing or
+//! reordering lines breaks the chain. Images, whether passed in as
+//! templates or returned as screenshots, are logged as a hash and byte
+//! length rather than their data, and typed or pasted text, which may be a
+//! password, only by its length.
+
+use base64::Engine;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Sender};
+use std::sync::OnceLock;
+use std::thread;
+use std::time::Duration;
+
+use crate::clock::Timestamp;
+use crate::config::loaded_config;
+use crate::tools::output::{ContentItem, ToolOutput};
+
+/// Size past which the log is moved to `<path>.1`, replacing any earlier
+/// one, and a new file started.
+const DEFAULT_MAX_BYTES: u64 = 10 * 1024 * 1024;
+
+/// A finished call, turned into an entry on the writer thread.
+struct Call {
+    tool: String,
+    args: Value,
+    output: ToolOutput,
+    started: Timestamp,
+    duration: Duration,
+}
+
+enum Message {
+    Call(Box<Call>),
+    /// Reply once everything sent before has been written.
+    Sync(Sender<()>),
+}
+
+struct AuditLog {
+    path: PathBuf,
+    sender: Sender<Message>,
+}
+
+static AUDIT_LOG: OnceLock<AuditLog> = OnceLock::new();
+
+/// Start appending to the audit log at `path`, or at the config file's
+/// `[audit] path` if None. Does nothing if neither is given. Only the first
+/// call has any effect.
+pub fn open_audit_log(path: Option<PathBuf>) -> Result<(), String> {
+    let audit = &loaded_config().config.audit;
+    let Some(path) = path.or_else(|| audit.path.clone()) else {
+        return Ok(());
+    };
+    let mut writer = Writer::open(&path, audit.max_bytes.unwrap_or(DEFAULT_MAX_BYTES))?;
+    let (sender, receiver) = mpsc::channel();
+    thread::Builder::new()
+        .name("audit-log".to_string())
+        .spawn(move || {
+            for message in receiver {
+                match message {
+                    Message::Call(call) => writer.write(&call),
+                    Message::Sync(done) => {
+                        let _ = done.send(());
+                    }
+                }
+            }
+        })
+        .map_err(|e| format!("Failed to start the audit log writer: {}", e))?;
+    log::info!("Audit log: {}", path.display());
+    let _ = AUDIT_LOG.set(AuditLog { path, sender });
+    Ok(())
+}
+
+/// Queue an entry for a call that ran, if the audit log is on.
+pub(crate) fn record_call(tool: &str, args: &Value, output: &ToolOutput, started: Timestamp, duration: Duration) {
+    let Some(log) = AUDIT_LOG.get() else {
+        return;
+    };
+    let _ = log.sender.send(Message::Call(Box::new(Call {
+        tool: tool.to_string(),
+        args: args.clone(),
+        output: output.clone(),
+        started,
+        duration,
+    })));
+}
+
+/// The log's path and its last `count` entries, oldest first, reaching
+/// back into the rotated file if needed. Waits for queued entries to be
+/// written first.
+pub(crate) fn audit_tail(count: usize) -> Result<(PathBuf, Vec<Value>), String> {
+    let log = AUDIT_LOG
+        .get()
+        .ok_or("The audit log is off (start the server with --audit-log <path>)")?;
+    let (done, wait) = mpsc::channel();
+    if log.sender.send(Message::Sync(done)).is_ok() {
+        let _ = wait.recv_timeout(Duration::from_secs(1));
+    }
+
+    let mut lines = Vec::new();
+    for path in [rotated_path(&log.path), log.path.clone()] {
+        if let Ok(text) = fs::read_to_string(&path) {
+            lines.extend(text.lines().map(str::to_string));
+        }
+    }
+    let entries = lines[lines.len().saturating_sub(count)..]
+        .iter()
+        .map(|line| serde_json::from_str(line).unwrap_or_else(|_| json!({ "unparsed": line })))
+        .collect();
+    Ok((log.path.clone(), entries))
+}
+
+/// Where the log is moved when it reaches its size limit.
+fn rotated_path(path: &Path) -> PathBuf {
+    let mut rotated = OsString::from(path.as_os_str());
+    rotated.push(".1");
+    PathBuf::from(rotated)
+}
+
+fn sha256_hex(bytes: &[u8]) -> String {
+    Sha256::digest(bytes).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// The hash and decoded length of a base64 image (or data URL), or of the
+/// string itself if it doesn't decode.
+fn image_digest(data: &str) -> Value {
+    let encoded = data.split_once("base64,").map_or(data, |(_, d)| d).trim();
+    let bytes = base64::engine::general_purpose::STANDARD
+        .decode(encoded)
+        .unwrap_or_else(|_| data.as_bytes().to_vec());
+    json!({ "sha256": sha256_hex(&bytes), "bytes": bytes.len() })
+}
+
+/// `args` with every template image, at any depth (run_sequence steps,
+/// assert_screen conditions), replaced by its digest, and every `text`
+/// by its length. A hash of short text would be easy to reverse.
+fn redact(args: &Value) -> Value {
+    match args {
+        Value::Object(map) => map
+            .iter()
+            .map(|(key, value)| {
+                let value = match value.as_str() {
+                    Some(data) if key == "template" => image_digest(data),
+                    Some(text) if key == "text" => json!({ "redacted": true, "chars": text.chars().count() }),
+                    _ => redact(value),
+                };
+                (key.clone(), value)
+            })
+            .collect(),
+        Value::Array(items) => items.iter().map(redact).collect(),
+        other => other.clone(),
+    }
+}
+
+/// An entry without its seq and prev.
+fn entry(call: &Call) -> Value {
+    let mut entry = call.started.to_json();
+    entry["tool"] = json!(call.tool);
+    entry["arguments"] = redact(&call.args);
+    entry["duration_ms"] = json!(call.duration.as_millis() as u64);
+    entry["outcome"] = json!(if call.output.is_error { "error" } else { "ok" });
+    if call.output.retries > 0 {
+        entry["retries"] = json!(call.output.retries);
+    }
+    if call.output.is_error {
+        for item in &call.output.content {
+            match item {
+                ContentItem::Text(text) if entry.get("error").is_none() => {
+                    entry["error"] = json!(text.strip_prefix("Error: ").unwrap_or(text));
+                }
+                ContentItem::Json(details) if details.get("error_code").is_some() => {
+                    entry["error_code"] = details["error_code"].clone();
+                }
+                _ => {}
+            }
+        }
+    }
+    let images: Vec<Value> = call
+        .output
+        .content
+        .iter()
+        .filter_map(|item| match item {
+            ContentItem::Image { data, mime_type } => {
+                let mut image = image_digest(data);
+                image["mime_type"] = json!(mime_type);
+                Some(image)
+            }
+            _ => None,
+        })
+        .collect();
+    if !images.is_empty() {
+        entry["images"] = json!(images);
+    }
+    entry
+}
+
+/// The open log file, owned by the writer thread.
+struct Writer {
+    path: PathBuf,
+    file: File,
+    bytes: u64,
+    max_bytes: u64,
+    seq: u64,
+    prev: String,
+}
+
+impl Writer {
+    fn open(path: &Path, max_bytes: u64) -> Result<Self, String> {
+        // Carry on the chain from the last entry of an earlier run
+        let last = fs::read_to_string(path)
+            .ok()
+            .and_then(|text| text.lines().last().map(str::to_string));
+        let seq = last
+            .as_deref()
+            .and_then(|line| serde_json::from_str::<Value>(line).ok())
+            .and_then(|entry| entry["seq"].as_u64())
+            .unwrap_or(0);
+        let prev = last.map(|line| sha256_hex(line.as_bytes())).unwrap_or_default();
+
+        let file = Self::open_file(path)?;
+        let bytes = file.metadata().map(|m| m.len()).unwrap_or(0);
+        Ok(Writer {
+            path: path.to_path_buf(),
+            file,
+            bytes,
+            max_bytes,
+            seq,
+            prev,
+        })
+    }
+
+    fn open_file(path: &Path) -> Result<File, String> {
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| format!("Failed to open audit log {}: {}", path.display(), e))
+    }
+
+    /// Move the full log aside and start a new one. The chain carries on
+    /// across the two files.
+    fn rotate(&mut self) -> Result<(), String> {
+        fs::rename(&self.path, rotated_path(&self.path))
+            .map_err(|e| format!("Failed to rotate audit log {}: {}", self.path.display(), e))?;
+        self.file = Self::open_file(&self.path)?;
+        self.bytes = 0;
+        Ok(())
+    }
+
+    fn write(&mut self, call: &Call) {
+        let mut entry = entry(call);
+        entry["seq"] = json!(self.seq + 1);
+        entry["prev"] = json!(self.prev);
+        let line = serde_json::to_string(&entry).unwrap_or_default();
+
+        if self.bytes > 0 && self.bytes + line.len() as u64 + 1 > self.max_bytes {
+            if let Err(e) = self.rotate() {
+                log::warn!("{}", e);
+            }
+        }
+        // The whole line in one write, so it lands intact or not at all
+        if let Err(e) = self.file.write_all(format!("{}\n", line).as_bytes()) {
+            log::warn!("Failed to write audit log {}: {}", self.path.display(), e);
+            return;
+        }
+        self.bytes += line.len() as u64 + 1;
+        self.seq += 1;
+        self.prev = sha256_hex(line.as_bytes());
+    }
+}
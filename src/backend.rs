@@ -0,0 +1,462 @@
+//! The OS boundary: enumerating and capturing screens, and posting input
+//! events. Everything that needs a display or input permissions goes
+//! through a ScreenBackend or InputBackend, so that an in-memory fake can
+//! stand in for them when driving the server headless.
+
+use image::RgbaImage;
+use rdev::{Button, EventType, SimulateError};
+use screenshots::Screen;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::input::physical::{deliver_physical, start_system_listener};
+#[cfg(target_os = "macos")]
+use crate::input::{BACK_BUTTON, FORWARD_BUTTON};
+use crate::wayland::with_wayland_note;
+use crate::window::system_windows;
+pub use crate::window::WindowInfo;
+
+/// A region in logical pixels, relative to a screen and within its bounds.
+pub type ScreenRect = (u32, u32, u32, u32);
+
+/// A connected screen, in global logical coordinates.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Display {
+    pub id: u32,
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    /// Physical pixels per logical pixel.
+    pub scale_factor: f32,
+    pub is_primary: bool,
+}
+
+/// Enumerates and captures screens.
+pub trait ScreenBackend: Send + Sync {
+    /// The connected screens, in the order screen indexes refer to.
+    fn displays(&self) -> Result<Vec<Display>, String>;
+
+    /// Capture a whole screen at full resolution.
+    fn capture(&self, display: &Display) -> Result<RgbaImage, String>;
+
+    /// Capture a logical rectangle of a screen at full resolution.
+    fn capture_area(&self, display: &Display, rect: ScreenRect) -> Result<RgbaImage, String>;
+
+    /// The application windows on these screens, front to back, optionally
+    /// only the on-screen ones.
+    fn windows(&self, on_screen_only: bool) -> Result<Vec<WindowInfo>, String> {
+        system_windows(on_screen_only)
+    }
+}
+
+/// Posts simulated input events.
+pub trait InputBackend: Send + Sync {
+    fn simulate(&self, event: &EventType) -> Result<(), SimulateError>;
+
+    /// Move the cursor while `button` is held. Some platforms need a
+    /// distinct drag event for the target to see a drag rather than a
+    /// hover.
+    fn drag_move(&self, x: f64, y: f64, _button: Button) -> Result<(), SimulateError> {
+        self.simulate(&EventType::MouseMove { x, y })
+    }
+
+    /// Start passing events from the real mouse and keyboard to
+    /// deliver_physical, if that isn't already happening.
+    fn start_listener(&self) -> Result<(), String> {
+        start_system_listener()
+    }
+}
+
+/// The real screens, via the screenshots crate.
+pub struct SystemScreen;
+
+impl SystemScreen {
+    fn screen(display: &Display) -> Result<Screen, String> {
+        Screen::all()
+            .map_err(|e| format!("Failed to get screens: {:?}", e))?
+            .into_iter()
+            .find(|s| s.display_info.id == display.id)
+            .ok_or_else(|| format!("Screen {} is no longer connected", display.id))
+    }
+}
+
+impl ScreenBackend for SystemScreen {
+    /// Primary first, then by id, so that indexes don't depend on the
+    /// order the OS happens to list displays in.
+    fn displays(&self) -> Result<Vec<Display>, String> {
+        let screens = Screen::all().map_err(|e| format!("Failed to get screens: {:?}", e))?;
+        let mut displays: Vec<Display> = screens
+            .iter()
+            .map(|s| {
+                let info = &s.display_info;
+                Display {
+                    id: info.id,
+                    x: info.x,
+                    y: info.y,
+                    width: info.width,
+                    height: info.height,
+                    scale_factor: info.scale_factor,
+                    is_primary: info.is_primary,
+                }
+            })
+            .collect();
+        displays.sort_by_key(|d| (!d.is_primary, d.id));
+        Ok(displays)
+    }
+
+    fn capture(&self, display: &Display) -> Result<RgbaImage, String> {
+        let capture = Self::screen(display)?
+            .capture()
+            .map_err(|e| with_wayland_note(format!("Failed to capture: {:?}", e)))?;
+        RgbaImage::from_raw(capture.width(), capture.height(), capture.into_vec())
+            .ok_or_else(|| "Failed to create image from buffer".to_string())
+    }
+
+    fn capture_area(&self, display: &Display, rect: ScreenRect) -> Result<RgbaImage, String> {
+        let (x, y, w, h) = rect;
+        let capture = Self::screen(display)?
+            .capture_area(x as i32, y as i32, w, h)
+            .map_err(|e| with_wayland_note(format!("Failed to capture: {:?}", e)))?;
+        RgbaImage::from_raw(capture.width(), capture.height(), capture.into_vec())
+            .ok_or_else(|| "Failed to create image from buffer".to_string())
+    }
+}
+
+/// Real input, via rdev.
+pub struct SystemInput;
+
+impl InputBackend for SystemInput {
+    fn simulate(&self, event: &EventType) -> Result<(), SimulateError> {
+        // rdev only simulates the left and right buttons on macOS
+        #[cfg(target_os = "macos")]
+        if let EventType::ButtonPress(button) | EventType::ButtonRelease(button) = *event {
+            if let Some(number) = other_button_number(button) {
+                return post_other_button(number, matches!(event, EventType::ButtonPress(_)));
+            }
+        }
+        rdev::simulate(event)
+    }
+
+    /// macOS only delivers a drag to the target as a mouse-dragged event;
+    /// a plain move with the button down reads as a hover.
+    #[cfg(target_os = "macos")]
+    fn drag_move(&self, x: f64, y: f64, button: Button) -> Result<(), SimulateError> {
+        use core_graphics::event::{CGEvent, CGEventTapLocation, CGEventType, CGMouseButton, EventField};
+        use core_graphics::event_source::{CGEventSource, CGEventSourceStateID};
+        use core_graphics::geometry::CGPoint;
+
+        let (event_type, cg_button) = match button {
+            Button::Left => (CGEventType::LeftMouseDragged, CGMouseButton::Left),
+            Button::Right => (CGEventType::RightMouseDragged, CGMouseButton::Right),
+            _ => (CGEventType::OtherMouseDragged, CGMouseButton::Center),
+        };
+        let source = CGEventSource::new(CGEventSourceStateID::HIDSystemState).map_err(|_| SimulateError)?;
+        let event = CGEvent::new_mouse_event(source, event_type, CGPoint::new(x, y), cg_button)
+            .map_err(|_| SimulateError)?;
+        if let Some(number) = other_button_number(button) {
+            event.set_integer_value_field(EventField::MOUSE_EVENT_BUTTON_NUMBER, number);
+        }
+        event.post(CGEventTapLocation::HID);
+        Ok(())
+    }
+
+    /// Many Windows drop targets ignore the moves rdev sends during a drag,
+    /// so post an absolute move across the whole virtual desktop with
+    /// SendInput, as a real mouse does. Windows tracks the held button
+    /// itself.
+    #[cfg(target_os = "windows")]
+    fn drag_move(&self, x: f64, y: f64, _button: Button) -> Result<(), SimulateError> {
+        #[repr(C)]
+        struct MouseInput {
+            dx: i32,
+            dy: i32,
+            mouse_data: u32,
+            flags: u32,
+            time: u32,
+            extra_info: usize,
+        }
+
+        /// INPUT with its union narrowed to MOUSEINPUT, its largest member.
+        #[repr(C)]
+        struct Input {
+            kind: u32,
+            mouse: MouseInput,
+        }
+
+        #[link(name = "user32")]
+        extern "system" {
+            fn GetSystemMetrics(index: i32) -> i32;
+            fn SendInput(count: u32, inputs: *const Input, size: i32) -> u32;
+        }
+
+        const INPUT_MOUSE: u32 = 0;
+        const MOUSEEVENTF_MOVE: u32 = 0x0001;
+        const MOUSEEVENTF_ABSOLUTE: u32 = 0x8000;
+        const MOUSEEVENTF_VIRTUALDESK: u32 = 0x4000;
+        const SM_XVIRTUALSCREEN: i32 = 76;
+        const SM_YVIRTUALSCREEN: i32 = 77;
+        const SM_CXVIRTUALSCREEN: i32 = 78;
+        const SM_CYVIRTUALSCREEN: i32 = 79;
+
+        let (left, top, width, height) = unsafe {
+            (
+                GetSystemMetrics(SM_XVIRTUALSCREEN),
+                GetSystemMetrics(SM_YVIRTUALSCREEN),
+                GetSystemMetrics(SM_CXVIRTUALSCREEN),
+                GetSystemMetrics(SM_CYVIRTUALSCREEN),
+            )
+        };
+        if width <= 1 || height <= 1 {
+            return Err(SimulateError);
+        }
+        // Absolute coordinates run from 0 to 65535 across the virtual desktop
+        let normalize = |v: f64, origin: i32, size: i32| {
+            (((v - origin as f64) * 65535.0 / (size - 1) as f64).round() as i32).clamp(0, 65535)
+        };
+        let input = Input {
+            kind: INPUT_MOUSE,
+            mouse: MouseInput {
+                dx: normalize(x, left, width),
+                dy: normalize(y, top, height),
+                mouse_data: 0,
+                flags: MOUSEEVENTF_MOVE | MOUSEEVENTF_ABSOLUTE | MOUSEEVENTF_VIRTUALDESK,
+                time: 0,
+                extra_info: 0,
+            },
+        };
+        let sent = unsafe { SendInput(1, &input, std::mem::size_of::<Input>() as i32) };
+        if sent == 1 {
+            Ok(())
+        } else {
+            Err(SimulateError)
+        }
+    }
+}
+
+/// CoreGraphics' number for a button other than left and right: 2 for
+/// middle, 3 for back and 4 for forward.
+#[cfg(target_os = "macos")]
+fn other_button_number(button: Button) -> Option<i64> {
+    match button {
+        Button::Left | Button::Right => None,
+        BACK_BUTTON => Some(3),
+        FORWARD_BUTTON => Some(4),
+        _ => Some(2),
+    }
+}
+
+/// Press or release button `number` where the pointer is, as an
+/// other-mouse event.
+#[cfg(target_os = "macos")]
+fn post_other_button(number: i64, press: bool) -> Result<(), SimulateError> {
+    use core_graphics::event::{CGEvent, CGEventTapLocation, CGEventType, CGMouseButton, EventField};
+    use core_graphics::event_source::{CGEventSource, CGEventSourceStateID};
+
+    let source = || CGEventSource::new(CGEventSourceStateID::HIDSystemState).map_err(|_| SimulateError);
+    let position = CGEvent::new(source()?).map_err(|_| SimulateError)?.location();
+    let event_type = if press {
+        CGEventType::OtherMouseDown
+    } else {
+        CGEventType::OtherMouseUp
+    };
+    let event =
+        CGEvent::new_mouse_event(source()?, event_type, position, CGMouseButton::Center).map_err(|_| SimulateError)?;
+    event.set_integer_value_field(EventField::MOUSE_EVENT_BUTTON_NUMBER, number);
+    event.post(CGEventTapLocation::HID);
+    Ok(())
+}
+
+/// Logs simulated events instead of posting them, for a dry run.
+pub struct DryRunInput;
+
+impl InputBackend for DryRunInput {
+    fn simulate(&self, event: &EventType) -> Result<(), SimulateError> {
+        log::debug!("Dry run: {:?}", event);
+        Ok(())
+    }
+}
+
+/// A single screen that always shows the same image, with no windows
+/// unless given some.
+pub struct FakeScreen {
+    display: Display,
+    image: RgbaImage,
+    windows: Vec<WindowInfo>,
+}
+
+impl FakeScreen {
+    /// A screen at the origin showing `png`, sized to match it at the given
+    /// scale factor.
+    pub fn from_png(png: &[u8], scale_factor: f32) -> Result<Self, String> {
+        let image = image::load_from_memory(png)
+            .map_err(|e| format!("Failed to decode PNG: {}", e))?
+            .to_rgba8();
+        let display = Display {
+            id: 1,
+            x: 0,
+            y: 0,
+            width: (image.width() as f32 / scale_factor).round() as u32,
+            height: (image.height() as f32 / scale_factor).round() as u32,
+            scale_factor,
+            is_primary: true,
+        };
+        Ok(FakeScreen {
+            display,
+            image,
+            windows: Vec::new(),
+        })
+    }
+
+    /// The same screen with its top-left corner at (x, y) on the virtual
+    /// desktop, e.g. left of or above the primary screen.
+    pub fn with_origin(mut self, x: i32, y: i32) -> Self {
+        self.display.x = x;
+        self.display.y = y;
+        self
+    }
+
+    /// The same screen showing `windows`, frontmost first. All of them
+    /// count as on-screen.
+    pub fn with_windows(mut self, windows: Vec<WindowInfo>) -> Self {
+        self.windows = windows;
+        self
+    }
+}
+
+impl ScreenBackend for FakeScreen {
+    fn displays(&self) -> Result<Vec<Display>, String> {
+        Ok(vec![self.display])
+    }
+
+    fn capture(&self, _display: &Display) -> Result<RgbaImage, String> {
+        Ok(self.image.clone())
+    }
+
+    fn capture_area(&self, display: &Display, rect: ScreenRect) -> Result<RgbaImage, String> {
+        let (x, y, w, h) = rect;
+        let scale = display.scale_factor as f64;
+        let physical = |v: u32| (v as f64 * scale) as u32;
+        // Copy just the region, not the whole screen
+        Ok(image::imageops::crop_imm(&self.image, physical(x), physical(y), physical(w), physical(h)).to_image())
+    }
+
+    fn windows(&self, _on_screen_only: bool) -> Result<Vec<WindowInfo>, String> {
+        Ok(self.windows.clone())
+    }
+}
+
+/// Records simulated events instead of posting them. Clones share the same
+/// record.
+#[derive(Clone, Default)]
+pub struct FakeInput {
+    events: Arc<Mutex<Vec<EventType>>>,
+    failing: Arc<Mutex<Vec<EventType>>>,
+}
+
+impl FakeInput {
+    /// Every event simulated so far, oldest first.
+    pub fn events(&self) -> Vec<EventType> {
+        self.events.lock().unwrap().clone()
+    }
+
+    /// Fail every later attempt to simulate `event`, retries included, as
+    /// an OS that stopped accepting it would. Failed events aren't recorded.
+    pub fn fail_on(&self, event: EventType) {
+        self.failing.lock().unwrap().push(event);
+    }
+
+    /// Deliver `event` as if the user had pressed, released or moved
+    /// something on the real hardware. Returns whether anything was
+    /// listening for it.
+    pub fn physical(&self, event: EventType) -> bool {
+        deliver_physical(event)
+    }
+}
+
+impl InputBackend for FakeInput {
+    fn simulate(&self, event: &EventType) -> Result<(), SimulateError> {
+        if self.failing.lock().unwrap().contains(event) {
+            return Err(SimulateError);
+        }
+        self.events.lock().unwrap().push(*event);
+        Ok(())
+    }
+
+    /// Physical events come from FakeInput::physical instead.
+    fn start_listener(&self) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+static SCREEN_BACKEND: Mutex<Option<Arc<dyn ScreenBackend>>> = Mutex::new(None);
+static INPUT_BACKEND: Mutex<Option<Arc<dyn InputBackend>>> = Mutex::new(None);
+
+/// Capture from `backend` instead of the real screens.
+pub fn set_screen_backend(backend: impl ScreenBackend + 'static) {
+    *SCREEN_BACKEND.lock().unwrap() = Some(Arc::new(backend));
+    *DISPLAYS.lock().unwrap() = None;
+}
+
+/// Send input to `backend` instead of the OS.
+pub fn set_input_backend(backend: impl InputBackend + 'static) {
+    *INPUT_BACKEND.lock().unwrap() = Some(Arc::new(backend));
+}
+
+pub(crate) fn screen_backend() -> Arc<dyn ScreenBackend> {
+    SCREEN_BACKEND
+        .lock()
+        .unwrap()
+        .get_or_insert_with(|| Arc::new(SystemScreen))
+        .clone()
+}
+
+/// How long an enumeration of the screens is reused.
+const DISPLAYS_TTL: Duration = Duration::from_secs(2);
+
+/// The last enumeration of the screens and when it was taken.
+static DISPLAYS: Mutex<Option<(Instant, Vec<Display>)>> = Mutex::new(None);
+
+/// The connected screens, enumerated at most DISPLAYS_TTL ago. Enumerating
+/// is slow on some platforms (tens of ms) and can fail briefly while
+/// displays are reconfigured, so calls share a recent enumeration.
+pub(crate) fn displays() -> Result<Vec<Display>, String> {
+    if let Some((at, displays)) = &*DISPLAYS.lock().unwrap() {
+        if at.elapsed() < DISPLAYS_TTL {
+            return Ok(displays.clone());
+        }
+    }
+    refresh_displays()
+}
+
+/// Enumerate the screens afresh, replacing the cached list.
+pub(crate) fn refresh_displays() -> Result<Vec<Display>, String> {
+    let displays = screen_backend().displays()?;
+    *DISPLAYS.lock().unwrap() = Some((Instant::now(), displays.clone()));
+    Ok(displays)
+}
+
+/// Run `f`, and if it fails and a fresh enumeration shows the screens have
+/// changed since they were cached, run it once more against the fresh list.
+/// Anything that looks screens up through displays() can fail on a stale
+/// list: a screen that's gone, or one that's moved to another index.
+pub(crate) fn retry_with_fresh_displays<T, E>(f: impl Fn() -> Result<T, E>) -> Result<T, E> {
+    f().or_else(|e| {
+        let cached = DISPLAYS.lock().unwrap().as_ref().map(|(_, displays)| displays.clone());
+        match refresh_displays() {
+            Ok(fresh) if cached.as_ref() != Some(&fresh) => {
+                log::debug!("Screens changed since they were cached; retrying");
+                f()
+            }
+            _ => Err(e),
+        }
+    })
+}
+
+pub(crate) fn input_backend() -> Arc<dyn InputBackend> {
+    INPUT_BACKEND
+        .lock()
+        .unwrap()
+        .get_or_insert_with(|| Arc::new(SystemInput))
+        .clone()
+}
@@ -0,0 +1,134 @@
+//! Cancelling in-flight tool calls with `notifications/cancelled`.
+
+use serde_json::Value;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::session::current_session_id;
+use crate::tools::output::ToolError;
+
+/// How often long-running steps check whether their call was cancelled.
+pub(crate) const CANCEL_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Cancellation flags of the tool calls in flight, keyed by session and
+/// request id, since each client numbers its own requests.
+static IN_FLIGHT: Mutex<Option<HashMap<String, Arc<AtomicBool>>>> = Mutex::new(None);
+
+thread_local! {
+    /// The cancellation flag of the tool call running on this thread.
+    static CANCEL_FLAG: RefCell<Option<Arc<AtomicBool>>> = const { RefCell::new(None) };
+}
+
+/// A tool call that `notifications/cancelled` can reach, registered before
+/// its thread starts so that an early cancellation isn't missed. Dropping
+/// it unregisters the call.
+pub struct InFlight {
+    key: String,
+    flag: Arc<AtomicBool>,
+}
+
+impl InFlight {
+    pub fn register(id: &Value) -> InFlight {
+        let flag = Arc::new(AtomicBool::new(false));
+        let key = in_flight_key(id);
+        IN_FLIGHT
+            .lock()
+            .unwrap()
+            .get_or_insert_with(HashMap::new)
+            .insert(key.clone(), flag.clone());
+        InFlight { key, flag }
+    }
+
+    /// Make this the call running on the current thread.
+    pub fn enter(&self) {
+        CANCEL_FLAG.set(Some(self.flag.clone()));
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        if let Some(in_flight) = IN_FLIGHT.lock().unwrap().as_mut() {
+            in_flight.remove(&self.key);
+        }
+        CANCEL_FLAG.take();
+    }
+}
+
+/// Flag the call with this request id as cancelled. Returns false if no
+/// such call is running, e.g. because it has already been answered.
+pub(crate) fn cancel_request(id: &Value) -> bool {
+    let in_flight = IN_FLIGHT.lock().unwrap();
+    match in_flight.as_ref().and_then(|calls| calls.get(&in_flight_key(id))) {
+        Some(flag) => {
+            flag.store(true, Ordering::SeqCst);
+            true
+        }
+        None => false,
+    }
+}
+
+/// A request id in the current session.
+fn in_flight_key(id: &Value) -> String {
+    format!("{}:{}", current_session_id(), id)
+}
+
+/// Flag every call in flight from the session with this id as cancelled,
+/// e.g. once its connection has closed.
+pub(crate) fn cancel_session(session_id: u64) {
+    let prefix = format!("{}:", session_id);
+    if let Some(in_flight) = IN_FLIGHT.lock().unwrap().as_ref() {
+        for (_, flag) in in_flight.iter().filter(|(key, _)| key.starts_with(&prefix)) {
+            flag.store(true, Ordering::SeqCst);
+        }
+    }
+}
+
+/// Flag every call in flight as cancelled.
+pub(crate) fn cancel_all() {
+    if let Some(in_flight) = IN_FLIGHT.lock().unwrap().as_ref() {
+        for flag in in_flight.values() {
+            flag.store(true, Ordering::SeqCst);
+        }
+    }
+}
+
+/// The cancellation flag of the call running on this thread, for handing
+/// the call to another thread with enter_cancel_flag.
+pub(crate) fn cancel_flag() -> Option<Arc<AtomicBool>> {
+    CANCEL_FLAG.with_borrow(|flag| flag.clone())
+}
+
+pub(crate) fn enter_cancel_flag(flag: Option<Arc<AtomicBool>>) {
+    CANCEL_FLAG.set(flag);
+}
+
+/// Whether the call running on this thread has been cancelled.
+pub(crate) fn is_cancelled() -> bool {
+    CANCEL_FLAG.with_borrow(|flag| flag.as_ref().is_some_and(|f| f.load(Ordering::SeqCst)))
+}
+
+/// Fail if the call running on this thread has been cancelled. Long-running
+/// steps call this between steps.
+pub(crate) fn check_cancelled() -> Result<(), String> {
+    if is_cancelled() {
+        return Err(ToolError::Cancelled.message());
+    }
+    Ok(())
+}
+
+/// Sleep, failing as soon as the call is cancelled.
+pub(crate) fn sleep_cancellable(duration: Duration) -> Result<(), String> {
+    let deadline = Instant::now() + duration;
+    loop {
+        check_cancelled()?;
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Ok(());
+        }
+        thread::sleep(remaining.min(CANCEL_POLL_INTERVAL));
+    }
+}
@@ -0,0 +1,228 @@
+//! Checking that the screen is in an expected state.
+
+use image::imageops::FilterType;
+use image::DynamicImage;
+use serde_json::{json, Value};
+
+use crate::capture::ocr::recognize_frame;
+use crate::capture::{
+    capture_frame, capture_screenshot, CaptureArea, CaptureFormat, EncodeOptions,
+};
+use crate::tools::output::{ContentItem, ToolOutput};
+use crate::tools::screen::parse_region;
+use crate::window::frontmost_window;
+
+/// 64-bit DCT perceptual hash. Visually similar images have hashes with a
+/// small Hamming distance.
+fn phash(img: &DynamicImage) -> u64 {
+    use std::f64::consts::PI;
+
+    let small = img.resize_exact(32, 32, FilterType::Triangle).to_luma8();
+    let pixels: Vec<f64> = small.pixels().map(|p| p[0] as f64).collect();
+
+    // 2D DCT-II, keeping only the lowest 8x8 frequencies
+    let mut coeffs = [0.0f64; 64];
+    for v in 0..8 {
+        for u in 0..8 {
+            let mut sum = 0.0;
+            for y in 0..32 {
+                let cy = ((2 * y + 1) as f64 * v as f64 * PI / 64.0).cos();
+                for x in 0..32 {
+                    let cx = ((2 * x + 1) as f64 * u as f64 * PI / 64.0).cos();
+                    sum += pixels[y * 32 + x] * cx * cy;
+                }
+            }
+            coeffs[v * 8 + u] = sum;
+        }
+    }
+
+    // Threshold against the median, ignoring the DC term
+    let mut sorted = coeffs[1..].to_vec();
+    sorted.sort_by(f64::total_cmp);
+    let median = sorted[sorted.len() / 2];
+    coeffs
+        .iter()
+        .enumerate()
+        .filter(|(_, &c)| c > median)
+        .fold(0u64, |hash, (i, _)| hash | (1 << i))
+}
+
+/// Parse a "#rrggbb" color or an [r, g, b] array.
+pub(crate) fn parse_color(value: &Value) -> Result<[u8; 3], String> {
+    if let Some(hex) = value.as_str() {
+        let hex = hex.trim_start_matches('#');
+        if hex.len() == 6 {
+            if let Ok(rgb) = u32::from_str_radix(hex, 16) {
+                return Ok([(rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8]);
+            }
+        }
+    } else if let Some([r, g, b]) = value.as_array().map(Vec::as_slice) {
+        let channel = |v: &Value| v.as_u64().filter(|&c| c <= 255).map(|c| c as u8);
+        if let (Some(r), Some(g), Some(b)) = (channel(r), channel(g), channel(b)) {
+            return Ok([r, g, b]);
+        }
+    }
+    Err(format!("Invalid color {} (expected \"#rrggbb\" or [r, g, b])", value))
+}
+
+fn color_hex([r, g, b]: [u8; 3]) -> String {
+    format!("#{:02x}{:02x}{:02x}", r, g, b)
+}
+
+/// Evaluate one assert_screen condition. Returns the condition report and,
+/// for a failed capture-based condition, the area to attach a capture of.
+fn evaluate_condition(condition: &Value) -> (Value, Option<CaptureArea>) {
+    let kind = condition.get("type").and_then(|v| v.as_str()).unwrap_or("");
+    let screen_index = condition
+        .get("screen_index")
+        .and_then(|v| v.as_u64())
+        .map(|v| v as usize);
+
+    let outcome: Result<(bool, Value, Option<CaptureArea>), String> = (|| match kind {
+        "window_title" => {
+            let expected = condition
+                .get("contains")
+                .and_then(|v| v.as_str())
+                .ok_or("window_title condition needs contains")?;
+            let window = frontmost_window()?;
+            let title = window.as_ref().map(|w| w.title.clone()).unwrap_or_default();
+            let passed = title.to_lowercase().contains(&expected.to_lowercase());
+            Ok((
+                passed,
+                json!({ "title": title, "window": window.map(|w| w.to_json()) }),
+                None,
+            ))
+        }
+
+        "pixel" => {
+            let coord = |name: &str| {
+                condition
+                    .get(name)
+                    .and_then(|v| v.as_i64())
+                    .and_then(|v| i32::try_from(v).ok())
+                    .ok_or_else(|| format!("pixel condition needs integer {}", name))
+            };
+            let (x, y) = (coord("x")?, coord("y")?);
+            let expected = parse_color(condition.get("color").unwrap_or(&Value::Null))?;
+            let tolerance = condition
+                .get("tolerance")
+                .and_then(|v| v.as_u64())
+                .unwrap_or(0);
+
+            let frame = capture_frame(&CaptureArea::Screen {
+                index: screen_index,
+                region: Some((x, y, 1, 1)),
+                strict_bounds: true,
+            })?;
+            let pixel = frame.image.to_rgb8().get_pixel(0, 0).0;
+            let delta = (0..3)
+                .map(|i| (pixel[i] as i32 - expected[i] as i32).unsigned_abs() as u64)
+                .max()
+                .unwrap_or(0);
+
+            // On failure, show the neighborhood of the pixel
+            let area = CaptureArea::Screen {
+                index: screen_index,
+                region: Some(((x - 16).max(0), (y - 16).max(0), 32, 32)),
+                strict_bounds: false,
+            };
+            Ok((delta <= tolerance, json!({ "color": color_hex(pixel), "delta": delta }), Some(area)))
+        }
+
+        "phash" => {
+            let expected = condition
+                .get("hash")
+                .and_then(|v| v.as_str())
+                .ok_or("phash condition needs hash")?;
+            let expected = u64::from_str_radix(expected.trim_start_matches("0x"), 16)
+                .map_err(|_| format!("Invalid hash {} (expected 16 hex digits)", expected))?;
+            let max_distance = condition
+                .get("max_distance")
+                .and_then(|v| v.as_u64())
+                .unwrap_or(8) as u32;
+
+            let area = CaptureArea::Screen {
+                index: screen_index,
+                region: parse_region(condition)?,
+                strict_bounds: false,
+            };
+            let hash = phash(&capture_frame(&area)?.image);
+            let distance = (hash ^ expected).count_ones();
+            Ok((
+                distance <= max_distance,
+                json!({ "hash": format!("{:016x}", hash), "distance": distance }),
+                Some(area),
+            ))
+        }
+
+        "text" => {
+            let expected = condition
+                .get("contains")
+                .and_then(|v| v.as_str())
+                .ok_or("text condition needs contains")?;
+            let area = CaptureArea::Screen {
+                index: screen_index,
+                region: parse_region(condition)?,
+                strict_bounds: false,
+            };
+            let (text, _) = recognize_frame(&capture_frame(&area)?, None)?;
+            // OCR line breaks don't follow the layout exactly, so compare
+            // with whitespace collapsed
+            let normalize = |s: &str| s.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase();
+            let passed = normalize(&text).contains(&normalize(expected));
+            Ok((passed, json!({ "text": text }), Some(area)))
+        }
+
+        other => Err(format!(
+            "Unknown condition type: {} (expected window_title, pixel, phash or text)",
+            other
+        )),
+    })();
+
+    let mut report = condition.clone();
+    match outcome {
+        Ok((passed, measured, area)) => {
+            report["passed"] = json!(passed);
+            if !passed {
+                report["measured"] = measured;
+            }
+            (report, if passed { None } else { area })
+        }
+        Err(e) => {
+            report["passed"] = json!(false);
+            report["error"] = json!(e);
+            (report, None)
+        }
+    }
+}
+
+/// Evaluate every condition and report pass/fail for each. The result is an
+/// error result if any condition fails, so orchestrators can branch on it.
+pub(crate) fn assert_screen(conditions: &[Value], capture_failures: bool) -> Result<ToolOutput, String> {
+    let mut reports = Vec::new();
+    let mut captures = Vec::new();
+    for condition in conditions {
+        let (report, area) = evaluate_condition(condition);
+        if let (true, Some(area)) = (capture_failures, area) {
+            captures.push(area);
+        }
+        reports.push(report);
+    }
+
+    let failed = reports.iter().filter(|r| r["passed"] != json!(true)).count();
+    let mut output = ToolOutput::json(&json!({
+        "passed": failed == 0,
+        "failed": failed,
+        "conditions": reports
+    }));
+    for area in captures {
+        let screenshot = capture_screenshot(&area, &EncodeOptions::png(), None)?;
+        output = output.with(ContentItem::Image {
+            data: screenshot.data,
+            mime_type: CaptureFormat::Png.mime_type().to_string(),
+        });
+    }
+    output.is_error = failed > 0;
+
+    Ok(output)
+}
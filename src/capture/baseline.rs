@@ -0,0 +1,177 @@
+//! Named reference captures, kept on disk so they outlive the server, for
+//! baseline_save and baseline_compare. Each baseline is a downscaled PNG
+//! plus a JSON record of the screen and region it was taken of, so that a
+//! comparison captures the same area again. They live in the config file's
+//! `[baselines]` dir, or else `baselines` in the config directory.
+
+use image::imageops::FilterType;
+use image::DynamicImage;
+use serde_json::{json, Value};
+use std::fs;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use crate::capture::{capture_frame, encode_image, CaptureArea, CaptureFormat};
+use crate::clock::format_rfc3339;
+use crate::config::{config_dir, loaded_config};
+
+/// Longest baseline name.
+const MAX_NAME_LENGTH: usize = 64;
+
+/// The directory baselines are kept in.
+pub(crate) fn baselines_dir() -> Result<PathBuf, String> {
+    if let Some(dir) = &loaded_config().config.baselines.dir {
+        return Ok(dir.clone());
+    }
+    config_dir()
+        .map(|dir| dir.join("baselines"))
+        .ok_or_else(|| "There's no config directory to keep baselines in; set dir under [baselines] in the config file".to_string())
+}
+
+/// Names become file names, so they're limited to letters, digits, '-',
+/// '_' and '.', and can't start with '.'.
+pub(crate) fn check_name(name: &str) -> Result<(), String> {
+    if name.is_empty() || name.len() > MAX_NAME_LENGTH {
+        return Err(format!("Baseline names are 1 to {} characters long", MAX_NAME_LENGTH));
+    }
+    if name.starts_with('.') || !name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.')) {
+        return Err(format!(
+            "Invalid baseline name: {} (use letters, digits, '-', '_' and '.', not at the start)",
+            name
+        ));
+    }
+    Ok(())
+}
+
+/// The image and record files of a baseline.
+fn baseline_paths(name: &str) -> Result<(PathBuf, PathBuf), String> {
+    let dir = baselines_dir()?;
+    Ok((dir.join(format!("{}.png", name)), dir.join(format!("{}.json", name))))
+}
+
+pub(crate) fn baseline_exists(name: &str) -> Result<bool, String> {
+    Ok(baseline_paths(name)?.1.is_file())
+}
+
+/// Capture a screen or region, shrink it to fit max_width x max_height and
+/// save it as `name`, replacing any baseline of that name. Returns its
+/// record, with the image's path.
+pub(crate) fn save_baseline(
+    name: &str,
+    screen_index: Option<usize>,
+    region: Option<(i32, i32, u32, u32)>,
+    max_width: u32,
+    max_height: u32,
+) -> Result<Value, String> {
+    let (image_path, record_path) = baseline_paths(name)?;
+    let frame = capture_frame(&CaptureArea::Screen {
+        index: screen_index,
+        region,
+        strict_bounds: false,
+    })?;
+    let (original_width, original_height) = (frame.image.width(), frame.image.height());
+    let image = if original_width > max_width || original_height > max_height {
+        frame.image.resize(max_width, max_height, FilterType::Lanczos3)
+    } else {
+        frame.image
+    };
+    let bytes = encode_image(&image, CaptureFormat::Png, 0)?;
+
+    let mut record = json!({
+        "name": name,
+        "created": format_rfc3339(SystemTime::now()),
+        // The screen actually captured, so a later default screen change
+        // doesn't move the comparison
+        "screen_index": frame.screens.first(),
+        "region": region.map(|(x, y, width, height)| json!({ "x": x, "y": y, "width": width, "height": height })),
+        "width": image.width(),
+        "height": image.height(),
+        "original_width": original_width,
+        "original_height": original_height
+    });
+    if let Some(dir) = image_path.parent() {
+        fs::create_dir_all(dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    }
+    fs::write(&image_path, bytes).map_err(|e| format!("Failed to write {}: {}", image_path.display(), e))?;
+    let text = serde_json::to_string_pretty(&record).map_err(|e| e.to_string())?;
+    fs::write(&record_path, text).map_err(|e| format!("Failed to write {}: {}", record_path.display(), e))?;
+
+    record["path"] = json!(image_path.display().to_string());
+    Ok(record)
+}
+
+/// The area a baseline's record says it was taken of.
+fn recorded_area(record: &Value) -> Result<CaptureArea, String> {
+    let region = match &record["region"] {
+        Value::Null => None,
+        region => {
+            let field = |name: &str| region[name].as_i64().ok_or_else(|| format!("The record's region has no {}", name));
+            Some((
+                field("x")? as i32,
+                field("y")? as i32,
+                field("width")? as u32,
+                field("height")? as u32,
+            ))
+        }
+    };
+    Ok(CaptureArea::Screen {
+        index: record["screen_index"].as_u64().map(|i| i as usize),
+        region,
+        strict_bounds: false,
+    })
+}
+
+/// A saved baseline's image, the area to capture to compare with it, and
+/// its record. None if there's no baseline of that name.
+pub(crate) fn load_baseline(name: &str) -> Result<Option<(DynamicImage, CaptureArea, Value)>, String> {
+    let (image_path, record_path) = baseline_paths(name)?;
+    let text = match fs::read_to_string(&record_path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(format!("Failed to read {}: {}", record_path.display(), e)),
+    };
+    let record: Value =
+        serde_json::from_str(&text).map_err(|e| format!("Baseline {} has a broken record: {}", name, e))?;
+    let area = recorded_area(&record).map_err(|e| format!("Baseline {} has a broken record: {}", name, e))?;
+    let image = image::open(&image_path).map_err(|e| format!("Failed to read {}: {}", image_path.display(), e))?;
+    Ok(Some((image, area, record)))
+}
+
+/// Every saved baseline's record, sorted by name. Records that can't be
+/// read are skipped.
+pub(crate) fn list_baselines() -> Result<Vec<Value>, String> {
+    let dir = baselines_dir()?;
+    let entries = match fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(format!("Failed to read {}: {}", dir.display(), e)),
+    };
+    let mut records: Vec<Value> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|e| e == "json"))
+        .filter_map(|path| match fs::read_to_string(&path).map(|text| serde_json::from_str::<Value>(&text)) {
+            Ok(Ok(record)) if record["name"].is_string() => Some(record),
+            _ => {
+                log::debug!("Skipping unreadable baseline record {}", path.display());
+                None
+            }
+        })
+        .collect();
+    records.sort_by(|a, b| a["name"].as_str().cmp(&b["name"].as_str()));
+    Ok(records)
+}
+
+/// Delete a baseline. Returns whether there was one.
+pub(crate) fn delete_baseline(name: &str) -> Result<bool, String> {
+    let (image_path, record_path) = baseline_paths(name)?;
+    let mut found = false;
+    for path in [record_path, image_path] {
+        match fs::remove_file(&path) {
+            Ok(()) => found = true,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(format!("Failed to delete {}: {}", path.display(), e)),
+        }
+    }
+    Ok(found)
+}
@@ -0,0 +1,149 @@
+//! Detecting when part of the screen changes.
+
+use image::imageops::FilterType;
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::cancel::sleep_cancellable;
+use crate::capture::{
+    capture_frame, encode_frame, CaptureArea, CaptureFormat, EncodeOptions, Frame,
+};
+use crate::tools::output::{ContentItem, ToolOutput};
+
+/// Side of the grayscale thumbnails compared by `only_if_changed`.
+const CHANGE_THUMBNAIL_SIDE: u32 = 64;
+
+/// Per-pixel difference (0-255) below which a thumbnail pixel counts as
+/// unchanged, to absorb scaling noise.
+const CHANGE_PIXEL_TOLERANCE: u8 = 8;
+
+/// The last capture of an area that was reported as changed.
+struct ChangeBaseline {
+    width: u32,
+    height: u32,
+    thumbnail: image::GrayImage,
+}
+
+impl ChangeBaseline {
+    fn of(frame: &Frame) -> Self {
+        let side = CHANGE_THUMBNAIL_SIDE;
+        ChangeBaseline {
+            width: frame.image.width(),
+            height: frame.image.height(),
+            thumbnail: frame
+                .image
+                .resize_exact(side, side, FilterType::Triangle)
+                .to_luma8(),
+        }
+    }
+
+    /// The fraction of thumbnail pixels that differ, or None if the frames
+    /// aren't comparable because their sizes differ.
+    fn difference(&self, other: &ChangeBaseline) -> Option<f64> {
+        if (self.width, self.height) != (other.width, other.height) {
+            return None;
+        }
+        let changed = self
+            .thumbnail
+            .pixels()
+            .zip(other.thumbnail.pixels())
+            .filter(|(a, b)| a[0].abs_diff(b[0]) > CHANGE_PIXEL_TOLERANCE)
+            .count();
+        Some(changed as f64 / self.thumbnail.len() as f64)
+    }
+}
+
+/// Baselines for `only_if_changed`, keyed by change_key.
+static CHANGE_BASELINES: Mutex<Option<HashMap<String, ChangeBaseline>>> = Mutex::new(None);
+
+/// Baselines are per screen and region, so interleaved polls of different
+/// areas don't interfere.
+fn change_key(area: &CaptureArea) -> String {
+    match area {
+        CaptureArea::Screen { index, region, .. } => format!("screen {:?} {:?}", index, region),
+        CaptureArea::Global { region, .. } => format!("global {:?}", region),
+    }
+}
+
+/// Forget the baseline for an area, so the next comparison reports a change.
+pub(crate) fn reset_baseline(area: &CaptureArea) {
+    if let Some(baselines) = CHANGE_BASELINES.lock().unwrap().as_mut() {
+        baselines.remove(&change_key(area));
+    }
+}
+
+/// Compare a frame against the area's baseline. Returns the fraction of
+/// thumbnail pixels that differ, or None if there's no comparable baseline.
+/// The baseline is only replaced when the difference exceeds `threshold`
+/// (or there was none), so slow drift accumulates until it's reported.
+pub(crate) fn compare_to_baseline(area: &CaptureArea, frame: &Frame, threshold: f64) -> Option<f64> {
+    let current = ChangeBaseline::of(frame);
+    let mut baselines = CHANGE_BASELINES.lock().unwrap();
+    let baselines = baselines.get_or_insert_with(HashMap::new);
+    let key = change_key(area);
+    let difference = baselines.get(&key).and_then(|b| b.difference(&current));
+
+    if difference.is_none_or(|d| d > threshold) {
+        baselines.insert(key, current);
+    }
+    difference
+}
+
+/// Upper bound on how long wait_for_region_change may block.
+pub(crate) const MAX_WAIT_FOR_CHANGE_TIMEOUT_MS: u64 = 120_000;
+
+/// Lower bound on the wait_for_region_change poll interval.
+pub(crate) const MIN_CHANGE_POLL_INTERVAL_MS: u64 = 50;
+
+/// Capture a baseline of the area, then poll until more than `threshold` of
+/// it differs or the timeout passes. Returns the new capture on change.
+pub(crate) fn wait_for_region_change(
+    area: &CaptureArea,
+    poll_interval: Duration,
+    timeout: Duration,
+    threshold: f64,
+) -> Result<ToolOutput, String> {
+    let start = Instant::now();
+    let baseline = ChangeBaseline::of(&capture_frame(area)?);
+
+    loop {
+        let remaining = timeout.saturating_sub(start.elapsed());
+        if remaining.is_zero() {
+            return Ok(ToolOutput::json(&json!({
+                "changed": false,
+                "timed_out": true,
+                "waited_ms": start.elapsed().as_millis() as u64
+            })));
+        }
+        sleep_cancellable(poll_interval.min(remaining))?;
+
+        let frame = capture_frame(area)?;
+        // A different frame size (e.g. a resolution change) counts as changed
+        let difference = baseline.difference(&ChangeBaseline::of(&frame));
+        if difference.is_some_and(|d| d <= threshold) {
+            continue;
+        }
+
+        let options = EncodeOptions {
+            format: CaptureFormat::Jpeg,
+            ..EncodeOptions::png()
+        };
+        let screenshot = encode_frame(frame, &options, None, None)?;
+        return Ok(ToolOutput::json(&json!({
+            "changed": true,
+            "timed_out": false,
+            "difference": difference,
+            "waited_ms": start.elapsed().as_millis() as u64,
+            "output_width": screenshot.output_width,
+            "output_height": screenshot.output_height,
+            "origin": { "x": screenshot.origin_x, "y": screenshot.origin_y },
+            "logical_per_pixel": screenshot.logical_per_pixel()
+        }))
+        .with(ContentItem::Image {
+            data: screenshot.data,
+            mime_type: options.format.mime_type().to_string(),
+        }));
+    }
+}
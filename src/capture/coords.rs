@@ -0,0 +1,185 @@
+//! Mapping screen-relative, physical and screenshot coordinates to the
+//! global logical coordinates the input backend uses.
+
+use serde_json::{json, Value};
+
+use crate::backend::{displays, retry_with_fresh_displays, Display};
+use crate::tools::output::ToolError;
+
+/// How a mouse tool's coordinates map to the global logical coordinates
+/// the input backend expects: relative to one screen or global, and in
+/// logical points or physical pixels. Physical pixels are logical
+/// coordinates multiplied by the screen's scale factor, as get_screen_info
+/// reports them.
+pub(crate) struct CoordinateSpace {
+    screens: Vec<Display>,
+    /// Index of the screen coordinates are relative to, or None for global.
+    screen: Option<usize>,
+    physical: bool,
+}
+
+impl CoordinateSpace {
+    /// The space named by `screen_index` and `coordinate_space`, or None
+    /// when the coordinates are already global and logical.
+    pub(crate) fn from_args(args: &Value) -> Result<Option<Self>, String> {
+        let physical = match args.get("coordinate_space").and_then(|v| v.as_str()) {
+            None | Some("logical") => false,
+            Some("physical") => true,
+            Some(other) => {
+                return Err(format!(
+                    "Unknown coordinate_space \"{}\" (expected logical or physical)",
+                    other
+                ))
+            }
+        };
+        let screen = args
+            .get("screen_index")
+            .and_then(|v| v.as_u64())
+            .map(|i| i as usize);
+        if screen.is_none() && !physical {
+            return Ok(None);
+        }
+
+        let screens = retry_with_fresh_displays(|| {
+            let screens = displays()?;
+            if let Some(index) = screen {
+                if index >= screens.len() {
+                    return Err(format!(
+                        "Screen index {} not found. Available screens: 0-{}",
+                        index,
+                        screens.len().saturating_sub(1)
+                    ));
+                }
+            }
+            Ok(screens)
+        })?;
+
+        Ok(Some(CoordinateSpace {
+            screens,
+            screen,
+            physical,
+        }))
+    }
+
+    fn scale(&self, info: &Display) -> f64 {
+        if self.physical {
+            info.scale_factor as f64
+        } else {
+            1.0
+        }
+    }
+
+    /// Translate a point to global logical coordinates, rejecting points
+    /// that fall outside the named screen, or outside every screen for
+    /// global physical coordinates.
+    fn to_global(&self, (x, y): (f64, f64)) -> Result<(f64, f64), String> {
+        let Some(index) = self.screen else {
+            // Global physical: each screen covers its logical bounds scaled
+            // by its own scale factor
+            return self
+                .screens
+                .iter()
+                .find_map(|info| {
+                    let scale = self.scale(info);
+                    let (gx, gy) = (x / scale, y / scale);
+                    screen_contains(info, gx - info.x as f64, gy - info.y as f64).then_some((gx, gy))
+                })
+                .ok_or_else(|| format!("({}, {}) is not on any screen in physical coordinates", x, y));
+        };
+
+        let info = &self.screens[index];
+        let scale = self.scale(info);
+        let (local_x, local_y) = (x / scale, y / scale);
+        if !screen_contains(info, local_x, local_y) {
+            return Err(format!(
+                "({}, {}) is outside screen {}; valid range is x 0-{}, y 0-{}",
+                x,
+                y,
+                index,
+                info.width as f64 * scale - 1.0,
+                info.height as f64 * scale - 1.0
+            ));
+        }
+        Ok((info.x as f64 + local_x, info.y as f64 + local_y))
+    }
+}
+
+/// Whether a point relative to a screen's top-left corner, in logical
+/// coordinates, is on that screen.
+fn screen_contains(info: &Display, x: f64, y: f64) -> bool {
+    (0.0..info.width as f64).contains(&x) && (0.0..info.height as f64).contains(&y)
+}
+
+/// Translate a point to global logical coordinates if the call names a
+/// screen or coordinate space.
+pub(crate) fn to_global(space: &Option<CoordinateSpace>, point: (f64, f64)) -> Result<(f64, f64), ToolError> {
+    match space {
+        Some(space) => space.to_global(point).map_err(ToolError::OutOfBounds),
+        None => Ok(point),
+    }
+}
+
+/// Convert a point in an image returned by screen_capture (after any
+/// downscaling) to clickable global logical coordinates, using the
+/// capture's `origin` and `logical_per_pixel`, and report which screen it
+/// falls on.
+pub(crate) fn image_to_screen_coords(args: &Value) -> Result<Value, String> {
+    let coord = |value: Option<&Value>, name: &str| {
+        value
+            .and_then(|v| v.as_f64())
+            .ok_or_else(|| format!("Missing {}", name))
+    };
+    let (x, y) = (coord(args.get("x"), "x")?, coord(args.get("y"), "y")?);
+    let origin = args.get("origin").ok_or("Missing origin")?;
+    let (origin_x, origin_y) = (
+        coord(origin.get("x"), "origin.x")?,
+        coord(origin.get("y"), "origin.y")?,
+    );
+    let per_pixel = coord(args.get("logical_per_pixel"), "logical_per_pixel")?;
+    if per_pixel <= 0.0 {
+        return Err("logical_per_pixel must be positive".to_string());
+    }
+
+    let (gx, gy) = (origin_x + x * per_pixel, origin_y + y * per_pixel);
+    let screens = displays()?;
+    let screen = screens.iter().enumerate().find(|(_, info)| {
+        screen_contains(info, gx - info.x as f64, gy - info.y as f64)
+    });
+
+    let mut result = json!({
+        "x": gx.round(),
+        "y": gy.round(),
+        "screen_index": screen.map(|(i, _)| i)
+    });
+    if let Some((_, info)) = screen {
+        let (local_x, local_y) = (gx - info.x as f64, gy - info.y as f64);
+        let scale = info.scale_factor as f64;
+        result["screen_relative"] = json!({ "x": local_x.round(), "y": local_y.round() });
+        result["physical"] = json!({ "x": (gx * scale).round(), "y": (gy * scale).round() });
+    }
+    Ok(result)
+}
+
+#[cfg(target_os = "macos")]
+pub(crate) fn get_mouse_position() -> Result<Value, String> {
+    use core_graphics::event::CGEvent;
+    use core_graphics::event_source::{CGEventSource, CGEventSourceStateID};
+
+    let source = CGEventSource::new(CGEventSourceStateID::HIDSystemState)
+        .map_err(|_| "Failed to create event source")?;
+    
+    let event = CGEvent::new(source)
+        .map_err(|_| "Failed to create event")?;
+    
+    let point = event.location();
+    
+    Ok(json!({
+        "x": point.x,
+        "y": point.y
+    }))
+}
+
+#[cfg(not(target_os = "macos"))]
+pub(crate) fn get_mouse_position() -> Result<Value, String> {
+    Err("get_mouse_position is only supported on macOS".to_string())
+}
@@ -0,0 +1,297 @@
+//! Comparing a fresh capture against an earlier one, for compare_screens
+//! and baseline_compare.
+
+use base64::Engine;
+use image::imageops::FilterType;
+use image::{DynamicImage, Rgb, RgbImage};
+use serde_json::{json, Value};
+
+use crate::capture::{capture_frame, encode_image, CaptureArea, CaptureFormat};
+use crate::tools::output::{ContentItem, ToolError, ToolOutput};
+
+/// Changed pixels are grouped on a grid of cells this many pixels wide;
+/// changes in touching cells belong to the same region.
+const DIFF_CELL_SIDE: u32 = 8;
+
+/// How far apart the two images' aspect ratios may be for the capture to
+/// be scaled to the base image's size.
+const MAX_ASPECT_DIFFERENCE: f64 = 0.02;
+
+/// Longest side of the diff visualization.
+const DIFF_IMAGE_MAX_SIDE: u32 = 512;
+
+pub(crate) struct DiffOptions {
+    /// Largest per-channel difference (0-255) still counted as unchanged.
+    pub(crate) channel_tolerance: u8,
+    /// Regions with fewer changed pixels than this are dropped as noise.
+    pub(crate) min_region_pixels: u64,
+    /// Rectangles (x, y, width, height) in global logical coordinates left
+    /// out of the comparison.
+    pub(crate) ignore: Vec<(f64, f64, f64, f64)>,
+    /// Scale a capture of a different size to the base image's size
+    /// rather than failing.
+    pub(crate) scale_mismatch: bool,
+    /// Return a picture of the changes.
+    pub(crate) diff_image: bool,
+}
+
+/// The outcome of a comparison.
+pub(crate) struct Diff {
+    /// changed, changed_percent, the regions and so on.
+    pub(crate) result: Value,
+    /// The base64 PNG of the changes, if one was asked for.
+    pub(crate) image: Option<String>,
+}
+
+impl Diff {
+    pub(crate) fn into_output(self) -> ToolOutput {
+        let output = ToolOutput::json(&self.result);
+        match self.image {
+            Some(data) => output.with(ContentItem::Image {
+                data,
+                mime_type: CaptureFormat::Png.mime_type().to_string(),
+            }),
+            None => output,
+        }
+    }
+}
+
+/// The bounding box of some changed pixels, in compared-image pixels. The
+/// right and bottom edges are exclusive.
+#[derive(Debug, Clone, Copy)]
+struct ChangedRegion {
+    left: u32,
+    top: u32,
+    right: u32,
+    bottom: u32,
+    pixels: u64,
+}
+
+impl ChangedRegion {
+    fn pixel(x: u32, y: u32) -> Self {
+        ChangedRegion {
+            left: x,
+            top: y,
+            right: x + 1,
+            bottom: y + 1,
+            pixels: 1,
+        }
+    }
+
+    fn merge(&mut self, other: &ChangedRegion) {
+        self.left = self.left.min(other.left);
+        self.top = self.top.min(other.top);
+        self.right = self.right.max(other.right);
+        self.bottom = self.bottom.max(other.bottom);
+        self.pixels += other.pixels;
+    }
+}
+
+/// Group changed pixels into regions: each cell of the grid gets the
+/// bounding box of its changed pixels, and cells touching (diagonally too)
+/// are merged. Largest first.
+fn changed_regions(changed: &[bool], width: u32, height: u32) -> Vec<ChangedRegion> {
+    let columns = width.div_ceil(DIFF_CELL_SIDE) as usize;
+    let rows = height.div_ceil(DIFF_CELL_SIDE) as usize;
+    let mut cells: Vec<Option<ChangedRegion>> = vec![None; columns * rows];
+    for (i, _) in changed.iter().enumerate().filter(|(_, &c)| c) {
+        let (x, y) = (i as u32 % width, i as u32 / width);
+        let cell = (y / DIFF_CELL_SIDE) as usize * columns + (x / DIFF_CELL_SIDE) as usize;
+        let pixel = ChangedRegion::pixel(x, y);
+        match &mut cells[cell] {
+            Some(region) => region.merge(&pixel),
+            empty => *empty = Some(pixel),
+        }
+    }
+
+    let mut regions = Vec::new();
+    for start in 0..cells.len() {
+        let Some(mut region) = cells[start].take() else {
+            continue;
+        };
+        let mut pending = vec![start];
+        while let Some(cell) = pending.pop() {
+            let (column, row) = ((cell % columns) as isize, (cell / columns) as isize);
+            for (dx, dy) in [(-1, -1), (0, -1), (1, -1), (-1, 0), (1, 0), (-1, 1), (0, 1), (1, 1)] {
+                let (c, r) = (column + dx, row + dy);
+                if c < 0 || r < 0 || c >= columns as isize || r >= rows as isize {
+                    continue;
+                }
+                let neighbour = r as usize * columns + c as usize;
+                if let Some(other) = cells[neighbour].take() {
+                    region.merge(&other);
+                    pending.push(neighbour);
+                }
+            }
+        }
+        regions.push(region);
+    }
+    regions.sort_by_key(|region| std::cmp::Reverse(region.pixels));
+    regions
+}
+
+/// The current image dimmed to gray, with changed pixels in red, ignored
+/// areas tinted blue and each region outlined in green, shrunk to fit
+/// DIFF_IMAGE_MAX_SIDE. Returned as base64 PNG.
+fn render_diff(
+    current: &RgbImage,
+    changed: &[bool],
+    ignored: &[bool],
+    regions: &[ChangedRegion],
+) -> Result<String, String> {
+    let (width, height) = current.dimensions();
+    let picture = RgbImage::from_fn(width, height, |x, y| {
+        let i = (y * width + x) as usize;
+        if changed[i] {
+            return Rgb([255, 0, 0]);
+        }
+        let [r, g, b] = current.get_pixel(x, y).0;
+        let luma = (r as u32 * 299 + g as u32 * 587 + b as u32 * 114) / 1000;
+        let gray = (luma / 2 + 64) as u8;
+        if ignored[i] {
+            Rgb([gray / 2, gray / 2, gray])
+        } else {
+            Rgb([gray, gray, gray])
+        }
+    });
+
+    let scale = (DIFF_IMAGE_MAX_SIDE as f64 / width.max(height) as f64).min(1.0);
+    let output_width = ((width as f64 * scale).round() as u32).max(1);
+    let output_height = ((height as f64 * scale).round() as u32).max(1);
+    let mut picture = DynamicImage::ImageRgb8(picture)
+        .resize_exact(output_width, output_height, FilterType::Triangle)
+        .to_rgb8();
+
+    let outline = Rgb([0, 255, 0]);
+    for region in regions {
+        let left = ((region.left as f64 * scale) as u32).min(output_width - 1);
+        let top = ((region.top as f64 * scale) as u32).min(output_height - 1);
+        let right = ((region.right as f64 * scale).ceil() as u32).clamp(left + 1, output_width);
+        let bottom = ((region.bottom as f64 * scale).ceil() as u32).clamp(top + 1, output_height);
+        for x in left..right {
+            picture.put_pixel(x, top, outline);
+            picture.put_pixel(x, bottom - 1, outline);
+        }
+        for y in top..bottom {
+            picture.put_pixel(left, y, outline);
+            picture.put_pixel(right - 1, y, outline);
+        }
+    }
+
+    let bytes = encode_image(&DynamicImage::ImageRgb8(picture), CaptureFormat::Png, 0)?;
+    Ok(base64::engine::general_purpose::STANDARD.encode(&bytes))
+}
+
+/// Capture the area and compare it pixel by pixel against `base`, at the
+/// base image's size. A capture of another size is scaled to it when
+/// `scale_mismatch` is set and the shapes agree; otherwise the mismatch is
+/// reported as an invalid `base_field`. Changed regions are reported in
+/// global logical coordinates, largest first.
+pub(crate) fn diff_capture(
+    area: &CaptureArea,
+    base: &DynamicImage,
+    base_field: &str,
+    options: &DiffOptions,
+) -> Result<Diff, ToolError> {
+    let frame = capture_frame(area)?;
+    let (width, height) = (base.width(), base.height());
+    let (frame_width, frame_height) = (frame.image.width(), frame.image.height());
+    let scaled = (frame_width, frame_height) != (width, height);
+    let current = if scaled {
+        let mismatch = format!(
+            "The base image is {}x{} but the capture is {}x{}",
+            width, height, frame_width, frame_height
+        );
+        if !options.scale_mismatch {
+            return Err(ToolError::invalid_field(
+                base_field,
+                format!("{}; pass size_mismatch: \"scale\" to compare them anyway", mismatch),
+            ));
+        }
+        let base_aspect = width as f64 / height as f64;
+        let frame_aspect = frame_width as f64 / frame_height as f64;
+        if (base_aspect / frame_aspect - 1.0).abs() > MAX_ASPECT_DIFFERENCE {
+            return Err(ToolError::invalid_field(
+                base_field,
+                format!("{}, a different shape, so it can't be of the same area", mismatch),
+            ));
+        }
+        frame.image.resize_exact(width, height, FilterType::Triangle).to_rgb8()
+    } else {
+        frame.image.to_rgb8()
+    };
+    let base = base.to_rgb8();
+
+    // Global logical units per compared pixel
+    let unit_x = frame_width as f64 / width as f64 / frame.scale_factor;
+    let unit_y = frame_height as f64 / height as f64 / frame.scale_factor;
+    let to_global = |x: f64, y: f64| (frame.origin_x + x * unit_x, frame.origin_y + y * unit_y);
+
+    let mut ignored = vec![false; (width * height) as usize];
+    for &(x, y, w, h) in &options.ignore {
+        let left = ((x - frame.origin_x) / unit_x).floor().clamp(0.0, width as f64) as u32;
+        let top = ((y - frame.origin_y) / unit_y).floor().clamp(0.0, height as f64) as u32;
+        let right = ((x + w - frame.origin_x) / unit_x).ceil().clamp(0.0, width as f64) as u32;
+        let bottom = ((y + h - frame.origin_y) / unit_y).ceil().clamp(0.0, height as f64) as u32;
+        for row in top..bottom {
+            let start = (row * width) as usize;
+            ignored[start + left as usize..start + right.max(left) as usize].fill(true);
+        }
+    }
+
+    let mut changed = vec![false; ignored.len()];
+    let mut compared_pixels = 0u64;
+    let mut changed_pixels = 0u64;
+    for (i, (a, b)) in base.pixels().zip(current.pixels()).enumerate() {
+        if ignored[i] {
+            continue;
+        }
+        compared_pixels += 1;
+        if a.0.iter().zip(b.0).any(|(a, b)| a.abs_diff(b) > options.channel_tolerance) {
+            changed[i] = true;
+            changed_pixels += 1;
+        }
+    }
+
+    let regions: Vec<ChangedRegion> = changed_regions(&changed, width, height)
+        .into_iter()
+        .filter(|region| region.pixels >= options.min_region_pixels)
+        .collect();
+    let regions_json: Vec<Value> = regions
+        .iter()
+        .map(|region| {
+            let (x, y) = to_global(region.left as f64, region.top as f64);
+            let (right, bottom) = to_global(region.right as f64, region.bottom as f64);
+            json!({
+                "x": (x + right) / 2.0,
+                "y": (y + bottom) / 2.0,
+                "bounds": { "x": x, "y": y, "width": right - x, "height": bottom - y },
+                "changed_pixels": region.pixels
+            })
+        })
+        .collect();
+
+    let changed_percent = if compared_pixels == 0 {
+        0.0
+    } else {
+        (changed_pixels as f64 * 100_000.0 / compared_pixels as f64).round() / 1000.0
+    };
+    let result = json!({
+        "changed": !regions.is_empty(),
+        "changed_percent": changed_percent,
+        "changed_pixels": changed_pixels,
+        "compared_pixels": compared_pixels,
+        "width": width,
+        "height": height,
+        "scaled": scaled,
+        "origin": { "x": frame.origin_x, "y": frame.origin_y },
+        "logical_per_pixel": unit_x,
+        "regions": regions_json
+    });
+    let image = if options.diff_image {
+        Some(render_diff(&current, &changed, &ignored, &regions)?)
+    } else {
+        None
+    };
+    Ok(Diff { result, image })
+}
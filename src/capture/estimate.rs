@@ -0,0 +1,134 @@
+//! Predicting the size of a capture without encoding it.
+
+use image::imageops::FilterType;
+use image::DynamicImage;
+use serde_json::{json, Value};
+
+use crate::capture::{
+    capture_frame, encode_image, zoomed_dimensions, CaptureArea, CaptureFormat, EncodeOptions,
+};
+
+/// estimate_capture samples a grid of ESTIMATE_TILES x ESTIMATE_TILES tiles,
+/// each ESTIMATE_TILE_SIDE output pixels square. 16px tiles line up with
+/// JPEG's 4:2:0 blocks, so a tile costs about the same in the sample as in
+/// the full image.
+const ESTIMATE_TILES: u32 = 16;
+const ESTIMATE_TILE_SIDE: u32 = 16;
+
+/// Range around the estimate that actual sizes fell within when calibrated
+/// against encodes of synthetic flat, gradient, UI, text, photo and noise
+/// images at several sizes and scales: JPEG was within 0.8-1.15x. Lossless
+/// formats overestimate smooth images, since tile seams break up long runs,
+/// WebP's most of all.
+fn estimate_band(format: CaptureFormat) -> (f64, f64) {
+    match format {
+        CaptureFormat::Jpeg | CaptureFormat::WebP { lossless: false } => (0.75, 1.25),
+        CaptureFormat::Png => (0.6, 1.25),
+        CaptureFormat::WebP { lossless: true } => (0.15, 1.25),
+    }
+}
+
+/// The size `img.resize(max_width, max_height, ..)` produces, or the input
+/// size if it already fits. Mirrors the image crate's aspect-preserving fit.
+fn fit_dimensions(width: u32, height: u32, max_width: u32, max_height: u32) -> (u32, u32) {
+    if width <= max_width && height <= max_height {
+        return (width, height);
+    }
+    let ratio = (max_width as f64 / width as f64).min(max_height as f64 / height as f64);
+    (
+        ((width as f64 * ratio).round() as u32).max(1),
+        ((height as f64 * ratio).round() as u32).max(1),
+    )
+}
+
+/// Build a mosaic of tiles sampled evenly across the image, each resampled
+/// to how it would look at the output size.
+fn sample_mosaic(img: &DynamicImage, (output_width, output_height): (u32, u32)) -> DynamicImage {
+    let tile = ESTIMATE_TILE_SIDE;
+    let scale = img.width() as f64 / output_width as f64;
+    let mut mosaic = image::RgbImage::new(ESTIMATE_TILES * tile, ESTIMATE_TILES * tile);
+
+    for j in 0..ESTIMATE_TILES {
+        for i in 0..ESTIMATE_TILES {
+            // Output-space tile origins, spread from edge to edge
+            let ox = i * output_width.saturating_sub(tile) / (ESTIMATE_TILES - 1);
+            let oy = j * output_height.saturating_sub(tile) / (ESTIMATE_TILES - 1);
+            let sx = ((ox as f64 * scale) as u32).min(img.width() - 1);
+            let sy = ((oy as f64 * scale) as u32).min(img.height() - 1);
+            let side = (tile as f64 * scale).round() as u32;
+            let source = img.crop_imm(
+                sx,
+                sy,
+                side.min(img.width() - sx).max(1),
+                side.min(img.height() - sy).max(1),
+            );
+            let source = if source.width() == tile && source.height() == tile {
+                source
+            } else {
+                source.resize_exact(tile, tile, FilterType::Lanczos3)
+            };
+            image::imageops::replace(&mut mosaic, &source.to_rgb8(), (i * tile) as i64, (j * tile) as i64);
+        }
+    }
+    DynamicImage::ImageRgb8(mosaic)
+}
+
+/// Estimate the encoded size of an image scaled to `output` by encoding a
+/// sampled mosaic and scaling by pixel count. The fixed per-file overhead
+/// (headers, tables) is measured with a single blank tile and not scaled.
+/// Returns (low, estimate, high) in bytes.
+fn estimate_encoded_bytes(
+    img: &DynamicImage,
+    output: (u32, u32),
+    format: CaptureFormat,
+    quality: u8,
+) -> Result<(usize, usize, usize), String> {
+    let mosaic = sample_mosaic(img, output);
+    let sample_bytes = encode_image(&mosaic, format, quality)?.len() as f64;
+    let blank = DynamicImage::ImageRgb8(image::RgbImage::new(ESTIMATE_TILE_SIDE, ESTIMATE_TILE_SIDE));
+    let overhead = (encode_image(&blank, format, quality)?.len() as f64).min(sample_bytes);
+
+    let coverage = (output.0 as f64 * output.1 as f64) / (mosaic.width() as f64 * mosaic.height() as f64);
+    let estimate = overhead + (sample_bytes - overhead) * coverage;
+    let (low, high) = estimate_band(format);
+    Ok((
+        (estimate * low) as usize,
+        estimate as usize,
+        (estimate * high) as usize,
+    ))
+}
+
+/// Predict what screen_capture would return for the same arguments without
+/// encoding or transferring the full image: the output size, an encoded
+/// byte range, and whether it would exceed max_bytes.
+pub(crate) fn estimate_capture(area: &CaptureArea, options: &EncodeOptions) -> Result<Value, String> {
+    let frame = capture_frame(area)?;
+    let (width, height) = (frame.image.width(), frame.image.height());
+    let output = zoomed_dimensions(width, height, options)
+        .unwrap_or_else(|| fit_dimensions(width, height, options.max_width, options.max_height));
+    let (low, estimate, high) =
+        estimate_encoded_bytes(&frame.image, output, options.format, options.quality)?;
+
+    let exceeds_max_bytes = options.max_bytes.map(|budget| {
+        if low > budget {
+            "yes"
+        } else if high <= budget {
+            "no"
+        } else {
+            "maybe"
+        }
+    });
+    Ok(json!({
+        "format": options.format.name(),
+        "quality": options.format.is_lossy().then_some(options.quality),
+        "original_width": width,
+        "original_height": height,
+        "output_width": output.0,
+        "output_height": output.1,
+        "bytes": { "low": low, "estimate": estimate, "high": high },
+        "base64_bytes": estimate.div_ceil(3) * 4,
+        "max_bytes": options.max_bytes,
+        "exceeds_max_bytes": exceeds_max_bytes,
+        "screens": frame.screens,
+    }))
+}
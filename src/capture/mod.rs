@@ -0,0 +1,994 @@
+//! Screen capture: grabbing frames, encoding them and everything built
+//! on top of that (change detection, diffs, recordings, template matching,
+//! OCR, assertions).
+
+pub(crate) mod assert;
+pub(crate) mod baseline;
+pub(crate) mod change;
+pub(crate) mod coords;
+pub(crate) mod diff;
+pub(crate) mod estimate;
+pub(crate) mod ocr;
+pub(crate) mod overlay;
+pub(crate) mod record;
+pub(crate) mod resources;
+pub(crate) mod save;
+pub(crate) mod template;
+
+use base64::Engine;
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageFormat};
+use serde_json::{json, Value};
+use std::cell::RefCell;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::backend::{displays, refresh_displays, retry_with_fresh_displays, screen_backend, Display, ScreenRect};
+use crate::capture::overlay::{draw_overlay, Overlay};
+use crate::capture::template::{DEFAULT_MAX_HEIGHT, DEFAULT_MAX_WIDTH};
+use crate::clock::Timestamp;
+use crate::retry::retry_transient;
+use crate::tools::cursor::cursor_position;
+
+/// A single screen capture, optionally cropped to a region, along with what
+/// is needed to map its pixels back to global logical coordinates.
+pub(crate) struct Frame {
+    image: DynamicImage,
+    /// Global logical coordinates of the frame's top-left corner.
+    origin_x: f64,
+    origin_y: f64,
+    /// Physical pixels per logical pixel.
+    scale_factor: f64,
+    /// Whether the requested region was clamped to the screen bounds.
+    clamped: bool,
+    /// Indices of the screens that contributed to the frame.
+    screens: Vec<usize>,
+    timing: CaptureTiming,
+}
+
+/// When a frame was grabbed and how long it took, for lining captures up
+/// against input actions in the audit log.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct CaptureTiming {
+    /// When grabbing the pixels began.
+    pub(crate) captured_at: Timestamp,
+    /// How long grabbing the pixels took.
+    pub(crate) capture_time: Duration,
+    /// Counts captures since the server started, from 1. Crops of one
+    /// capture share its number.
+    pub(crate) sequence: u64,
+}
+
+impl CaptureTiming {
+    /// Timing for a capture that began at `captured_at` and has just
+    /// finished, with the next sequence number.
+    fn since(captured_at: Timestamp) -> Self {
+        static SEQUENCE: AtomicU64 = AtomicU64::new(0);
+        CaptureTiming {
+            captured_at,
+            capture_time: captured_at.elapsed(),
+            sequence: SEQUENCE.fetch_add(1, Ordering::Relaxed) + 1,
+        }
+    }
+}
+
+impl Frame {
+    pub(crate) fn timing(&self) -> CaptureTiming {
+        self.timing
+    }
+
+    /// Convert a physical pixel position within the frame to global logical
+    /// coordinates, i.e. the coordinate system the mouse tools use.
+    fn to_global(&self, px: f64, py: f64) -> (f64, f64) {
+        (
+            self.origin_x + px / self.scale_factor,
+            self.origin_y + py / self.scale_factor,
+        )
+    }
+}
+
+/// Check a logical region against a screen's logical size. Negative origins
+/// and empty regions are rejected; extents past the screen edge are clamped,
+/// or rejected when `strict` is set. Returns the region to capture and
+/// whether it was clamped.
+fn validate_region(
+    region: (i32, i32, u32, u32),
+    screen_width: u32,
+    screen_height: u32,
+    strict: bool,
+) -> Result<(ScreenRect, bool), String> {
+    let (x, y, width, height) = region;
+    let bounds = format!("screen is {}x{}", screen_width, screen_height);
+
+    if x < 0 || y < 0 {
+        return Err(format!("Region origin ({}, {}) is negative; {}", x, y, bounds));
+    }
+    if width == 0 || height == 0 {
+        return Err(format!("Region size {}x{} is empty; {}", width, height, bounds));
+    }
+
+    // Both are non-negative now, so the casts can't wrap
+    let (x, y) = (x as u32, y as u32);
+    if x >= screen_width || y >= screen_height {
+        return Err(format!("Region origin ({}, {}) is outside the screen; {}", x, y, bounds));
+    }
+
+    let clamped_width = width.min(screen_width - x);
+    let clamped_height = height.min(screen_height - y);
+    let clamped = clamped_width != width || clamped_height != height;
+    if clamped && strict {
+        return Err(format!(
+            "Region {}x{} at ({}, {}) extends past the screen edge; {}",
+            width, height, x, y, bounds
+        ));
+    }
+
+    Ok(((x, y, clamped_width, clamped_height), clamped))
+}
+
+/// Which part of the desktop to capture.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum CaptureArea {
+    /// One screen, optionally cropped to a region relative to that screen.
+    Screen {
+        index: Option<usize>,
+        region: Option<(i32, i32, u32, u32)>,
+        strict_bounds: bool,
+    },
+    /// A rectangle in global (virtual desktop) coordinates, which may span
+    /// several screens. Without a region, the whole virtual desktop.
+    Global {
+        region: Option<(i32, i32, u32, u32)>,
+        strict_bounds: bool,
+    },
+}
+
+pub(crate) fn capture_frame(area: &CaptureArea) -> Result<Frame, String> {
+    retry_with_fresh_displays(|| match *area {
+        CaptureArea::Screen {
+            index,
+            region,
+            strict_bounds,
+        } => capture_screen_frame(index, region, strict_bounds),
+        CaptureArea::Global {
+            region,
+            strict_bounds,
+        } => capture_global_frame(region, strict_bounds),
+    })
+}
+
+fn capture_screen_image(screen: &Display) -> Result<DynamicImage, String> {
+    let image = retry_transient("Capture", || screen_backend().capture(screen))?;
+    Ok(DynamicImage::ImageRgba8(image))
+}
+
+/// Capture just a logical rectangle of a screen, which avoids allocating a
+/// full-screen image when polling a small region.
+pub(crate) fn capture_screen_area(screen: &Display, rect: ScreenRect) -> Result<DynamicImage, String> {
+    let image = retry_transient("Capture", || screen_backend().capture_area(screen, rect))?;
+    Ok(DynamicImage::ImageRgba8(image))
+}
+
+/// Crop a logical rectangle out of a capture taken at the given scale factor.
+fn crop_logical(img: &DynamicImage, rect: ScreenRect, scale_factor: f64) -> DynamicImage {
+    let (x, y, w, h) = rect;
+    let physical_x = (x as f64 * scale_factor) as u32;
+    let physical_y = (y as f64 * scale_factor) as u32;
+    let physical_w = (w as f64 * scale_factor) as u32;
+    let physical_h = (h as f64 * scale_factor) as u32;
+    img.crop_imm(physical_x, physical_y, physical_w, physical_h)
+}
+
+fn capture_screen_frame(
+    screen_index: Option<usize>,
+    region: Option<(i32, i32, u32, u32)>,
+    strict_bounds: bool,
+) -> Result<Frame, String> {
+    // Capture screenshot
+    let screens = displays()?;
+    let idx = screen_index.unwrap_or(0);
+    let screen = screens.get(idx).ok_or_else(|| {
+        format!("Screen index {} not found. Available screens: 0-{}", idx, screens.len().saturating_sub(1))
+    })?;
+    
+    // Get scale factor for coordinate conversion (logical -> physical pixels)
+    // This ensures region coordinates match what get_screen_info reports
+    let scale_factor = screen.scale_factor as f64;
+
+    let mut origin_x = screen.x as f64;
+    let mut origin_y = screen.y as f64;
+    let mut clamped = false;
+
+    // Capture only the region if one is specified, in logical coordinates
+    // (as reported by get_screen_info)
+    let captured_at = Timestamp::now();
+    let img = if let Some(region) = region {
+        let (rect, was_clamped) = validate_region(region, screen.width, screen.height, strict_bounds)?;
+        clamped = was_clamped;
+        origin_x += rect.0 as f64;
+        origin_y += rect.1 as f64;
+        capture_screen_area(screen, rect)?
+    } else {
+        capture_screen_image(screen)?
+    };
+
+    Ok(Frame {
+        image: img,
+        origin_x,
+        origin_y,
+        scale_factor,
+        clamped,
+        screens: vec![idx],
+        timing: CaptureTiming::since(captured_at),
+    })
+}
+
+/// Crop a region out of a frame captured of the whole of `area`, as if the
+/// region had been captured on its own: relative to the screen for a screen
+/// area, in global coordinates for a global one, and clamped or rejected
+/// at the frame's edge by the area's strict_bounds. Lets several regions
+/// share one capture.
+pub(crate) fn crop_frame(frame: &Frame, area: &CaptureArea, region: (i32, i32, u32, u32)) -> Result<Frame, String> {
+    let (x, y, width, height) = region;
+    let (x, y, strict_bounds) = match *area {
+        CaptureArea::Screen { strict_bounds, .. } => (x, y, strict_bounds),
+        CaptureArea::Global { strict_bounds, .. } => (
+            x - frame.origin_x.round() as i32,
+            y - frame.origin_y.round() as i32,
+            strict_bounds,
+        ),
+    };
+    let frame_width = (frame.image.width() as f64 / frame.scale_factor).round() as u32;
+    let frame_height = (frame.image.height() as f64 / frame.scale_factor).round() as u32;
+    let (rect, clamped) = validate_region((x, y, width, height), frame_width, frame_height, strict_bounds)?;
+    Ok(Frame {
+        image: crop_logical(&frame.image, rect, frame.scale_factor),
+        origin_x: frame.origin_x + rect.0 as f64,
+        origin_y: frame.origin_y + rect.1 as f64,
+        scale_factor: frame.scale_factor,
+        clamped,
+        screens: frame.screens.clone(),
+        timing: frame.timing,
+    })
+}
+
+/// Bounding box (left, top, right, bottom) of the virtual desktop in global
+/// logical coordinates.
+pub(crate) fn desktop_bounds(screens: &[Display]) -> (i64, i64, i64, i64) {
+    let left = screens.iter().map(|s| s.x as i64).min().unwrap_or(0);
+    let top = screens.iter().map(|s| s.y as i64).min().unwrap_or(0);
+    let right = screens
+        .iter()
+        .map(|s| s.x as i64 + s.width as i64)
+        .max()
+        .unwrap_or(0);
+    let bottom = screens
+        .iter()
+        .map(|s| s.y as i64 + s.height as i64)
+        .max()
+        .unwrap_or(0);
+    (left, top, right, bottom)
+}
+
+/// Capture a rectangle in global coordinates by capturing every screen it
+/// intersects and stitching the pieces together. The result uses the highest
+/// scale factor among the contributing screens; areas no screen covers are
+/// left transparent.
+fn capture_global_frame(
+    region: Option<(i32, i32, u32, u32)>,
+    strict_bounds: bool,
+) -> Result<Frame, String> {
+    let screens = displays()?;
+    if screens.is_empty() {
+        return Err("No screens found".to_string());
+    }
+
+    let (left, top, right, bottom) = desktop_bounds(&screens);
+
+    let (rx, ry, rw, rh) = match region {
+        Some((x, y, w, h)) => (x as i64, y as i64, w as i64, h as i64),
+        None => (left, top, right - left, bottom - top),
+    };
+    let bounds = format!(
+        "virtual desktop spans ({}, {}) to ({}, {})",
+        left, top, right, bottom
+    );
+    if rw <= 0 || rh <= 0 {
+        return Err(format!("Region size {}x{} is empty; {}", rw, rh, bounds));
+    }
+
+    // Clamp the rectangle to the virtual desktop
+    let (x0, y0) = (rx.max(left), ry.max(top));
+    let (x1, y1) = ((rx + rw).min(right), (ry + rh).min(bottom));
+    if x0 >= x1 || y0 >= y1 {
+        return Err(format!(
+            "Region {}x{} at ({}, {}) doesn't intersect any screen; {}",
+            rw, rh, rx, ry, bounds
+        ));
+    }
+    let clamped = (x0, y0, x1, y1) != (rx, ry, rx + rw, ry + rh);
+    if clamped && strict_bounds {
+        return Err(format!(
+            "Region {}x{} at ({}, {}) extends past the desktop edge; {}",
+            rw, rh, rx, ry, bounds
+        ));
+    }
+
+    let intersecting: Vec<(usize, &Display)> = screens
+        .iter()
+        .enumerate()
+        .filter(|(_, info)| {
+            (info.x as i64) < x1
+                && (info.y as i64) < y1
+                && info.x as i64 + info.width as i64 > x0
+                && info.y as i64 + info.height as i64 > y0
+        })
+        .collect();
+
+    let scale_factor = intersecting
+        .iter()
+        .map(|(_, s)| s.scale_factor as f64)
+        .fold(1.0, f64::max);
+    let canvas_w = ((x1 - x0) as f64 * scale_factor).round() as u32;
+    let canvas_h = ((y1 - y0) as f64 * scale_factor).round() as u32;
+    let mut canvas = image::RgbaImage::new(canvas_w, canvas_h);
+
+    let captured_at = Timestamp::now();
+    for (_, info) in &intersecting {
+        let (sx, sy) = (info.x as i64, info.y as i64);
+
+        // Intersection in global logical coordinates
+        let ix0 = x0.max(sx);
+        let iy0 = y0.max(sy);
+        let ix1 = x1.min(sx + info.width as i64);
+        let iy1 = y1.min(sy + info.height as i64);
+
+        let rect = (
+            (ix0 - sx) as u32,
+            (iy0 - sy) as u32,
+            (ix1 - ix0) as u32,
+            (iy1 - iy0) as u32,
+        );
+        // Only the part of the screen in the region is captured
+        let mut piece = if rect == (0, 0, info.width, info.height) {
+            capture_screen_image(info)?
+        } else {
+            capture_screen_area(info, rect)?
+        };
+
+        // Bring every piece to the canvas scale
+        let target_w = ((ix1 - ix0) as f64 * scale_factor).round() as u32;
+        let target_h = ((iy1 - iy0) as f64 * scale_factor).round() as u32;
+        if piece.width() != target_w || piece.height() != target_h {
+            piece = piece.resize_exact(target_w, target_h, FilterType::Lanczos3);
+        }
+
+        let dest_x = ((ix0 - x0) as f64 * scale_factor).round() as i64;
+        let dest_y = ((iy0 - y0) as f64 * scale_factor).round() as i64;
+        match piece.as_rgba8() {
+            Some(rgba) => image::imageops::replace(&mut canvas, rgba, dest_x, dest_y),
+            None => image::imageops::replace(&mut canvas, &piece.to_rgba8(), dest_x, dest_y),
+        }
+    }
+
+    Ok(Frame {
+        image: DynamicImage::ImageRgba8(canvas),
+        origin_x: x0 as f64,
+        origin_y: y0 as f64,
+        scale_factor,
+        clamped,
+        screens: intersecting.iter().map(|(i, _)| *i).collect(),
+        timing: CaptureTiming::since(captured_at),
+    })
+}
+
+/// Output encoding for screen captures.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum CaptureFormat {
+    Jpeg,
+    Png,
+    WebP { lossless: bool },
+}
+
+impl CaptureFormat {
+    pub(crate) fn parse(name: &str, lossless: bool) -> Result<Self, String> {
+        match name.to_lowercase().as_str() {
+            "jpeg" | "jpg" => Ok(CaptureFormat::Jpeg),
+            "png" => Ok(CaptureFormat::Png),
+            "webp" => Ok(CaptureFormat::WebP { lossless }),
+            other => Err(format!(
+                "Unsupported format: {} (expected \"jpeg\", \"png\" or \"webp\")",
+                other
+            )),
+        }
+    }
+
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            CaptureFormat::Jpeg => "jpeg",
+            CaptureFormat::Png => "png",
+            CaptureFormat::WebP { .. } => "webp",
+        }
+    }
+
+    pub(crate) fn mime_type(self) -> &'static str {
+        match self {
+            CaptureFormat::Jpeg => "image/jpeg",
+            CaptureFormat::Png => "image/png",
+            CaptureFormat::WebP { .. } => "image/webp",
+        }
+    }
+
+    /// Whether quality affects the encoded size.
+    pub(crate) fn is_lossy(self) -> bool {
+        matches!(self, CaptureFormat::Jpeg | CaptureFormat::WebP { lossless: false })
+    }
+}
+
+/// How a capture over max_width/max_height is downscaled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum DownscaleFilter {
+    /// Sharp and good for photos, but smears small fonts somewhat.
+    Lanczos3,
+    /// Keeps small text crisper.
+    CatmullRom,
+    /// Soft and fast.
+    Triangle,
+    /// Drops pixels, for pixel art and terminals.
+    Nearest,
+}
+
+impl DownscaleFilter {
+    pub(crate) const NAMES: [&'static str; 4] = ["lanczos3", "catmullrom", "triangle", "nearest"];
+
+    pub(crate) fn parse(name: &str) -> Option<DownscaleFilter> {
+        match name {
+            "lanczos3" => Some(DownscaleFilter::Lanczos3),
+            "catmullrom" => Some(DownscaleFilter::CatmullRom),
+            "triangle" => Some(DownscaleFilter::Triangle),
+            "nearest" => Some(DownscaleFilter::Nearest),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            DownscaleFilter::Lanczos3 => "lanczos3",
+            DownscaleFilter::CatmullRom => "catmullrom",
+            DownscaleFilter::Triangle => "triangle",
+            DownscaleFilter::Nearest => "nearest",
+        }
+    }
+
+    fn filter_type(self) -> FilterType {
+        match self {
+            DownscaleFilter::Lanczos3 => FilterType::Lanczos3,
+            DownscaleFilter::CatmullRom => FilterType::CatmullRom,
+            DownscaleFilter::Triangle => FilterType::Triangle,
+            DownscaleFilter::Nearest => FilterType::Nearest,
+        }
+    }
+}
+
+/// The unsharp mask `sharpen` applies: a light one, enough to firm up
+/// downscaled text without haloing.
+const SHARPEN_SIGMA: f32 = 0.6;
+const SHARPEN_THRESHOLD: i32 = 2;
+
+/// How a zoomed capture is magnified.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ZoomFilter {
+    /// Each pixel becomes a block, for pixel-accurate inspection.
+    Nearest,
+    /// Smooth magnification, easier on text.
+    Lanczos,
+}
+
+impl ZoomFilter {
+    pub(crate) const NAMES: [&'static str; 2] = ["nearest", "lanczos"];
+
+    pub(crate) fn parse(name: &str) -> Option<ZoomFilter> {
+        match name {
+            "nearest" => Some(ZoomFilter::Nearest),
+            "lanczos" => Some(ZoomFilter::Lanczos),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            ZoomFilter::Nearest => "nearest",
+            ZoomFilter::Lanczos => "lanczos",
+        }
+    }
+
+    fn filter_type(self) -> FilterType {
+        match self {
+            ZoomFilter::Nearest => FilterType::Nearest,
+            ZoomFilter::Lanczos => FilterType::Lanczos3,
+        }
+    }
+}
+
+/// The size a capture of width x height is magnified to by `zoom`, or None
+/// if it isn't magnified. max_width/max_height still bound the output, so
+/// the zoom actually applied can be less than asked for; a capture already
+/// over them is downscaled as usual instead.
+pub(crate) fn zoomed_dimensions(width: u32, height: u32, options: &EncodeOptions) -> Option<(u32, u32)> {
+    let fit = (options.max_width as f64 / width as f64).min(options.max_height as f64 / height as f64);
+    let scale = options.zoom.min(fit);
+    if scale <= 1.0 {
+        return None;
+    }
+    Some((
+        ((width as f64 * scale).round() as u32).clamp(1, options.max_width),
+        ((height as f64 * scale).round() as u32).clamp(1, options.max_height),
+    ))
+}
+
+/// Encode an image in the given format. Quality applies to JPEG and lossy
+/// WebP.
+fn encode_image(img: &DynamicImage, format: CaptureFormat, quality: u8) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::new();
+    encode_image_into(img, format, quality, &mut bytes)?;
+    Ok(bytes)
+}
+
+/// encode_image, replacing the contents of `bytes` but keeping its
+/// allocation.
+fn encode_image_into(img: &DynamicImage, format: CaptureFormat, quality: u8, bytes: &mut Vec<u8>) -> Result<(), String> {
+    bytes.clear();
+    let image_format = match format {
+        CaptureFormat::Jpeg => {
+            // Converting to RGB first is quicker than encoding RGBA pixels
+            // in place
+            let mut encoder = image::codecs::jpeg::JpegEncoder::new_with_quality(&mut *bytes, quality.clamp(1, 100));
+            let encoded = match img {
+                DynamicImage::ImageRgb8(rgb) => encoder.encode_image(rgb),
+                other => encoder.encode_image(&other.to_rgb8()),
+            };
+            return encoded.map_err(|e| format!("Failed to encode JPEG: {:?}", e));
+        }
+        CaptureFormat::Png => ImageFormat::Png,
+        CaptureFormat::WebP { lossless: true } => ImageFormat::WebP,
+        CaptureFormat::WebP { lossless: false } => {
+            *bytes = encode_lossy_webp(img, quality)?;
+            return Ok(());
+        }
+    };
+
+    let mut cursor = std::io::Cursor::new(bytes);
+    img.write_to(&mut cursor, image_format)
+        .map_err(|e| format!("Failed to encode {}: {:?}", format.name().to_uppercase(), e))
+}
+
+thread_local! {
+    /// Where encode_frame encodes to. It's base64'd straight out of here,
+    /// and keeps its capacity, so polling doesn't allocate a fresh buffer
+    /// for every capture.
+    static ENCODE_BUFFER: RefCell<Vec<u8>> = const { RefCell::new(Vec::new()) };
+}
+
+/// The image crate only encodes lossless WebP, so lossy output goes through
+/// libwebp when built with the `webp-lossy` feature.
+#[cfg(feature = "webp-lossy")]
+fn encode_lossy_webp(img: &DynamicImage, quality: u8) -> Result<Vec<u8>, String> {
+    let rgba = img.to_rgba8();
+    let encoded = webp::Encoder::from_rgba(rgba.as_raw(), rgba.width(), rgba.height())
+        .encode(quality.clamp(1, 100) as f32);
+    Ok(encoded.to_vec())
+}
+
+#[cfg(not(feature = "webp-lossy"))]
+fn encode_lossy_webp(_img: &DynamicImage, _quality: u8) -> Result<Vec<u8>, String> {
+    Err("Lossy WebP is not available in this build (rebuild with --features webp-lossy). Pass lossless: true for lossless WebP, or use jpeg.".to_string())
+}
+
+/// Shape of the synthetic cursor drawn by `include_cursor`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum CursorStyle {
+    Arrow,
+    Crosshair,
+}
+
+/// A synthetic cursor to composite onto a capture, since screen captures
+/// don't include the real one.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct CursorMarker {
+    pub(crate) style: CursorStyle,
+    pub(crate) color: [u8; 3],
+}
+
+/// Size of the cursor marker in output pixels.
+const CURSOR_MARKER_SIZE: f64 = 16.0;
+
+/// The classic pointer outline, in units of 1/16 of the marker size, with
+/// the hotspot at the origin.
+const ARROW_POLYGON: &[(f64, f64)] = &[
+    (0.0, 0.0),
+    (0.0, 15.0),
+    (4.0, 11.5),
+    (6.5, 17.0),
+    (9.0, 16.0),
+    (6.5, 10.5),
+    (11.0, 10.5),
+];
+
+fn point_in_polygon(px: f64, py: f64, polygon: &[(f64, f64)]) -> bool {
+    let mut inside = false;
+    let mut j = polygon.len() - 1;
+    for i in 0..polygon.len() {
+        let ((xi, yi), (xj, yj)) = (polygon[i], polygon[j]);
+        if (yi > py) != (yj > py) && px < (xj - xi) * (py - yi) / (yj - yi) + xi {
+            inside = !inside;
+        }
+        j = i;
+    }
+    inside
+}
+
+fn distance_to_segment(px: f64, py: f64, (ax, ay): (f64, f64), (bx, by): (f64, f64)) -> f64 {
+    let (dx, dy) = (bx - ax, by - ay);
+    let t = (((px - ax) * dx + (py - ay) * dy) / (dx * dx + dy * dy)).clamp(0.0, 1.0);
+    (px - ax - t * dx).hypot(py - ay - t * dy)
+}
+
+/// Draw the marker with its hotspot at physical pixel (`hx`, `hy`) of the
+/// frame. `unit` is the size of 1/16 of the marker in physical pixels. The
+/// marker is outlined in black or white, whichever contrasts with `color`.
+fn draw_cursor_marker(frame: &mut DynamicImage, hx: f64, hy: f64, unit: f64, marker: &CursorMarker) {
+    let [r, g, b] = marker.color;
+    let luminance = 0.299 * r as f64 + 0.587 * g as f64 + 0.114 * b as f64;
+    let outline = if luminance > 128.0 { [0, 0, 0] } else { [255, 255, 255] };
+
+    // Which color, if any, a point (in marker units relative to the hotspot)
+    // should be painted.
+    let paint = |mx: f64, my: f64| -> Option<[u8; 3]> {
+        match marker.style {
+            CursorStyle::Arrow => {
+                let near_edge = (0..ARROW_POLYGON.len()).any(|i| {
+                    let next = ARROW_POLYGON[(i + 1) % ARROW_POLYGON.len()];
+                    distance_to_segment(mx, my, ARROW_POLYGON[i], next) <= 0.75
+                });
+                if near_edge {
+                    Some(outline)
+                } else if point_in_polygon(mx, my, ARROW_POLYGON) {
+                    Some(marker.color)
+                } else {
+                    None
+                }
+            }
+            CursorStyle::Crosshair => {
+                let (ax, ay) = (mx.abs(), my.abs());
+                let on_arm = |along: f64, across: f64| along <= 8.0 && across <= 0.75;
+                let near_arm = |along: f64, across: f64| along <= 9.0 && across <= 1.75;
+                if on_arm(ax, ay) || on_arm(ay, ax) {
+                    Some(marker.color)
+                } else if near_arm(ax, ay) || near_arm(ay, ax) {
+                    Some(outline)
+                } else {
+                    None
+                }
+            }
+        }
+    };
+
+    let (min, max) = match marker.style {
+        CursorStyle::Arrow => ((-1.0, -1.0), (12.0, 18.0)),
+        CursorStyle::Crosshair => ((-9.0, -9.0), (9.0, 9.0)),
+    };
+    let (width, height) = (frame.width() as f64, frame.height() as f64);
+    let x0 = (hx + min.0 * unit).floor().clamp(0.0, width) as u32;
+    let y0 = (hy + min.1 * unit).floor().clamp(0.0, height) as u32;
+    let x1 = (hx + max.0 * unit).ceil().clamp(0.0, width) as u32;
+    let y1 = (hy + max.1 * unit).ceil().clamp(0.0, height) as u32;
+
+    // Captures are already RGBA, so this paints in place without copying
+    if frame.as_rgba8().is_none() {
+        *frame = DynamicImage::ImageRgba8(frame.to_rgba8());
+    }
+    let Some(rgba) = frame.as_mut_rgba8() else {
+        return;
+    };
+    for y in y0..y1 {
+        for x in x0..x1 {
+            let mx = (x as f64 + 0.5 - hx) / unit;
+            let my = (y as f64 + 0.5 - hy) / unit;
+            if let Some([r, g, b]) = paint(mx, my) {
+                rgba.put_pixel(x, y, image::Rgba([r, g, b, 255]));
+            }
+        }
+    }
+}
+
+/// Composite the cursor onto a frame at its current position, sized so
+/// that it's still legible after downscaling by `downscale`. Returns the
+/// cursor's global position, or None if it isn't within the frame (e.g.
+/// it's on another screen).
+fn composite_cursor(frame: &mut Frame, downscale: f64, marker: &CursorMarker) -> Option<(f64, f64)> {
+    let (x, y) = cursor_position()?;
+    let hx = (x - frame.origin_x) * frame.scale_factor;
+    let hy = (y - frame.origin_y) * frame.scale_factor;
+    let (width, height) = (frame.image.width() as f64, frame.image.height() as f64);
+    if hx < 0.0 || hy < 0.0 || hx >= width || hy >= height {
+        return None;
+    }
+
+    let unit = CURSOR_MARKER_SIZE / 16.0 * downscale.max(frame.scale_factor);
+    draw_cursor_marker(&mut frame.image, hx, hy, unit, marker);
+    Some((x, y))
+}
+
+/// An encoded screenshot.
+pub(crate) struct Screenshot {
+    /// Base64-encoded image data.
+    pub(crate) data: String,
+    /// Encoded size in bytes, before base64.
+    pub(crate) bytes: usize,
+    /// Whether the requested region was clamped to the screen bounds.
+    pub(crate) clamped: bool,
+    /// Indices of the screens that contributed to the image.
+    pub(crate) screens: Vec<usize>,
+    /// Global position of the cursor marker, if one was drawn.
+    pub(crate) cursor: Option<(f64, f64)>,
+    /// What overlay was drawn, if one was asked for.
+    pub(crate) overlay: Option<Value>,
+    /// Size of the captured image before downscaling, in physical pixels.
+    pub(crate) original_width: u32,
+    pub(crate) original_height: u32,
+    /// Size of the encoded image.
+    pub(crate) output_width: u32,
+    pub(crate) output_height: u32,
+    /// Global logical coordinates of the image's top-left corner.
+    pub(crate) origin_x: f64,
+    pub(crate) origin_y: f64,
+    /// Physical pixels per logical pixel of the captured screen(s).
+    scale_factor: f64,
+    /// Quality the image was encoded at, after any max_bytes fitting.
+    pub(crate) quality: u8,
+    pub(crate) timing: CaptureTiming,
+    /// How long downscaling, drawing and encoding took.
+    pub(crate) encode_time: Duration,
+}
+
+impl Screenshot {
+    /// Output size relative to the captured size (1.0 when not downscaled).
+    pub(crate) fn scale_ratio(&self) -> f64 {
+        self.output_width as f64 / self.original_width.max(1) as f64
+    }
+
+    /// Global logical units per output pixel, so that an image position
+    /// (px, py) is at (origin_x + px * this, origin_y + py * this).
+    pub(crate) fn logical_per_pixel(&self) -> f64 {
+        1.0 / (self.scale_ratio() * self.scale_factor)
+    }
+}
+
+/// Lowest quality `max_bytes` fitting will go down to.
+const MIN_FIT_QUALITY: u8 = 10;
+
+/// Smallest side `max_bytes` fitting will shrink an image to.
+const MIN_FIT_SIDE: u32 = 64;
+
+/// Cap on re-encode passes when fitting `max_bytes`.
+const MAX_FIT_PASSES: usize = 8;
+
+/// Encode into `bytes`, then if the result is over `max_bytes`, re-encode
+/// at lower quality (lossy formats) and then smaller dimensions until it
+/// fits. Returns the image that was encoded and the quality used.
+fn encode_within_budget(
+    img: DynamicImage,
+    format: CaptureFormat,
+    quality: u8,
+    max_bytes: Option<usize>,
+    bytes: &mut Vec<u8>,
+) -> Result<(DynamicImage, u8), String> {
+    encode_image_into(&img, format, quality, bytes)?;
+    let Some(budget) = max_bytes else {
+        return Ok((img, quality));
+    };
+
+    let (mut img, mut quality) = (img, quality);
+    for _ in 0..MAX_FIT_PASSES {
+        if bytes.len() <= budget {
+            return Ok((img, quality));
+        }
+        // Encoded size scales roughly with quality and with pixel count
+        let ratio = budget as f64 / bytes.len() as f64;
+        if format.is_lossy() && quality > MIN_FIT_QUALITY {
+            let target = (quality as f64 * ratio.sqrt() * 0.9) as u8;
+            quality = target.clamp(MIN_FIT_QUALITY, quality - 1);
+        } else if img.width().min(img.height()) > MIN_FIT_SIDE {
+            let shrink = (ratio.sqrt() * 0.9).clamp(0.25, 0.9);
+            let width = ((img.width() as f64 * shrink) as u32).max(MIN_FIT_SIDE);
+            let height = ((img.height() as f64 * shrink) as u32).max(MIN_FIT_SIDE);
+            img = img.resize(width, height, FilterType::Lanczos3);
+        } else {
+            break;
+        }
+        encode_image_into(&img, format, quality, bytes)?;
+    }
+
+    if bytes.len() <= budget {
+        return Ok((img, quality));
+    }
+    let settings = if format.is_lossy() {
+        format!("{}x{}, quality {}", img.width(), img.height(), quality)
+    } else {
+        format!("{}x{}; a lossy format would be smaller", img.width(), img.height())
+    };
+    Err(format!(
+        "Capture doesn't fit in max_bytes {}: still {} bytes at {}",
+        budget,
+        bytes.len(),
+        settings
+    ))
+}
+
+/// How a capture is scaled and encoded.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct EncodeOptions {
+    pub(crate) max_width: u32,
+    pub(crate) max_height: u32,
+    pub(crate) quality: u8,
+    pub(crate) format: CaptureFormat,
+    /// Budget for the encoded size, see encode_within_budget.
+    pub(crate) max_bytes: Option<usize>,
+    /// Magnification applied after cropping, 1.0 for none. See
+    /// zoomed_dimensions.
+    pub(crate) zoom: f64,
+    pub(crate) zoom_filter: ZoomFilter,
+    pub(crate) filter: DownscaleFilter,
+    /// Apply an unsharp mask after resizing.
+    pub(crate) sharpen: bool,
+}
+
+impl EncodeOptions {
+    /// Default downscaling, encoded as PNG.
+    pub(crate) fn png() -> Self {
+        EncodeOptions {
+            max_width: DEFAULT_MAX_WIDTH,
+            max_height: DEFAULT_MAX_HEIGHT,
+            quality: 80,
+            format: CaptureFormat::Png,
+            max_bytes: None,
+            zoom: 1.0,
+            zoom_filter: ZoomFilter::Nearest,
+            filter: DownscaleFilter::Lanczos3,
+            sharpen: false,
+        }
+    }
+}
+
+/// Capture, downscale and encode a screenshot, optionally with a cursor
+/// marker composited in.
+pub(crate) fn capture_screenshot(
+    area: &CaptureArea,
+    options: &EncodeOptions,
+    cursor: Option<&CursorMarker>,
+) -> Result<Screenshot, String> {
+    encode_frame(capture_frame(area)?, options, cursor, None)
+}
+
+/// Downscale (or zoom) and encode a captured frame. An overlay is drawn
+/// after resizing, so its lines and labels stay crisp.
+pub(crate) fn encode_frame(
+    mut frame: Frame,
+    options: &EncodeOptions,
+    cursor: Option<&CursorMarker>,
+    overlay: Option<&Overlay>,
+) -> Result<Screenshot, String> {
+    let EncodeOptions {
+        max_width,
+        max_height,
+        quality,
+        format,
+        max_bytes,
+        zoom_filter,
+        filter,
+        sharpen,
+        ..
+    } = *options;
+    let start = Instant::now();
+
+    let downscale = (frame.image.width() as f64 / max_width as f64)
+        .max(frame.image.height() as f64 / max_height as f64)
+        .max(1.0);
+    let cursor = cursor.and_then(|marker| composite_cursor(&mut frame, downscale, marker));
+    let img = frame.image;
+    let (original_width, original_height) = (img.width(), img.height());
+
+    // Resize if needed
+    let resized = if let Some((width, height)) = zoomed_dimensions(img.width(), img.height(), options) {
+        img.resize_exact(width, height, zoom_filter.filter_type())
+    } else if img.width() > max_width || img.height() > max_height {
+        img.resize(max_width, max_height, filter.filter_type())
+    } else {
+        img
+    };
+    let mut resized = if sharpen {
+        resized.unsharpen(SHARPEN_SIGMA, SHARPEN_THRESHOLD)
+    } else {
+        resized
+    };
+    let overlay = match overlay {
+        Some(overlay) => {
+            let logical_per_pixel = original_width as f64 / resized.width() as f64 / frame.scale_factor;
+            Some(draw_overlay(
+                &mut resized,
+                (frame.origin_x, frame.origin_y),
+                logical_per_pixel,
+                frame.screens.first().copied(),
+                overlay,
+            )?)
+        }
+        None => None,
+    };
+
+    let (bytes, base64_str, resized, quality) = ENCODE_BUFFER.with(|buffer| {
+        let mut buffer = buffer.borrow_mut();
+        let (resized, quality) = encode_within_budget(resized, format, quality, max_bytes, &mut buffer)?;
+        let base64_str = base64::engine::general_purpose::STANDARD.encode(buffer.as_slice());
+        Ok::<_, String>((buffer.len(), base64_str, resized, quality))
+    })?;
+
+    Ok(Screenshot {
+        data: base64_str,
+        bytes,
+        clamped: frame.clamped,
+        screens: frame.screens,
+        cursor,
+        overlay,
+        original_width,
+        original_height,
+        output_width: resized.width(),
+        output_height: resized.height(),
+        origin_x: frame.origin_x,
+        origin_y: frame.origin_y,
+        scale_factor: frame.scale_factor,
+        quality,
+        timing: frame.timing,
+        encode_time: start.elapsed(),
+    })
+}
+
+/// The screens as get_screen_info reports them, enumerated afresh with
+/// `refresh` or else from the cached list.
+pub(crate) fn get_screen_info(refresh: bool) -> Result<Value, String> {
+    let screens = if refresh { refresh_displays()? } else { displays()? };
+
+    let screen_info: Vec<Value> = screens
+        .iter()
+        .enumerate()
+        .map(|(i, info)| {
+            let scale = info.scale_factor as f64;
+            json!({
+                "index": i,
+                "id": info.id,
+                "x": info.x,
+                "y": info.y,
+                "width": info.width,
+                "height": info.height,
+                "scale_factor": info.scale_factor,
+                "is_primary": info.is_primary,
+                "logical": {
+                    "x": info.x,
+                    "y": info.y,
+                    "width": info.width,
+                    "height": info.height
+                },
+                "physical": {
+                    "x": (info.x as f64 * scale).round(),
+                    "y": (info.y as f64 * scale).round(),
+                    "width": (info.width as f64 * scale).round(),
+                    "height": (info.height as f64 * scale).round()
+                },
+            })
+        })
+        .collect();
+
+    Ok(json!({ 
+        "screens": screen_info,
+        "count": screens.len()
+    }))
+}
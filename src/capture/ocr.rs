@@ -0,0 +1,190 @@
+//! Reading text off the screen with Tesseract.
+
+#[cfg(feature = "ocr")]
+use image::imageops::FilterType;
+use image::DynamicImage;
+#[cfg(feature = "ocr")]
+use image::ImageFormat;
+use serde_json::{json, Value};
+#[cfg(feature = "ocr")]
+use std::io::Write;
+#[cfg(feature = "ocr")]
+use std::thread;
+
+use crate::capture::{capture_frame, CaptureArea, Frame};
+use crate::tools::output::ToolOutput;
+
+/// Captures are upscaled by this factor before recognition; Tesseract is
+/// much more reliable on screen text at this size.
+const OCR_UPSCALE: u32 = 2;
+
+/// One recognized line of text, in frame pixel coordinates.
+struct OcrLine {
+    text: String,
+    /// Mean word confidence, 0-100.
+    confidence: f64,
+    left: u32,
+    top: u32,
+    width: u32,
+    height: u32,
+}
+
+/// Recognize text in an image with the Tesseract CLI (`tesseract`, or the
+/// binary named by `DESKTOP_MCP_TESSERACT`). Only built with the `ocr`
+/// feature.
+#[cfg(feature = "ocr")]
+fn ocr_image(img: &DynamicImage, lang: Option<&str>) -> Result<Vec<OcrLine>, String> {
+    use std::process::{Command, Stdio};
+
+    let upscaled = img.resize_exact(
+        img.width() * OCR_UPSCALE,
+        img.height() * OCR_UPSCALE,
+        FilterType::CatmullRom,
+    );
+    let mut png = Vec::new();
+    upscaled
+        .write_to(&mut std::io::Cursor::new(&mut png), ImageFormat::Png)
+        .map_err(|e| format!("Failed to encode image for OCR: {}", e))?;
+
+    let program = std::env::var("DESKTOP_MCP_TESSERACT").unwrap_or_else(|_| "tesseract".to_string());
+    let mut command = Command::new(&program);
+    command.args(["stdin", "stdout"]);
+    if let Some(lang) = lang {
+        command.args(["-l", lang]);
+    }
+    let mut child = command
+        .arg("tsv")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Failed to run {}: {} (is Tesseract installed?)", program, e))?;
+
+    // Feed stdin from another thread so a full stdout pipe can't deadlock us
+    let mut stdin = child.stdin.take().ok_or("Failed to open tesseract stdin")?;
+    let writer = thread::spawn(move || stdin.write_all(&png));
+    let output = child
+        .wait_with_output()
+        .map_err(|e| format!("Failed to wait for {}: {}", program, e))?;
+    let _ = writer.join();
+    if !output.status.success() {
+        return Err(format!(
+            "{} failed: {}",
+            program,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    Ok(parse_tesseract_tsv(&String::from_utf8_lossy(&output.stdout)))
+}
+
+#[cfg(not(feature = "ocr"))]
+fn ocr_image(_img: &DynamicImage, _lang: Option<&str>) -> Result<Vec<OcrLine>, String> {
+    Err("OCR is not available in this build (rebuild with --features ocr)".to_string())
+}
+
+/// Group the word rows of Tesseract's TSV output into lines, scaling boxes
+/// back down from the upscaled image.
+#[cfg_attr(not(feature = "ocr"), allow(dead_code))]
+fn parse_tesseract_tsv(tsv: &str) -> Vec<OcrLine> {
+    /// Words sharing a (block, paragraph, line) key.
+    struct Group {
+        key: (u32, u32, u32),
+        words: Vec<String>,
+        confidences: Vec<f64>,
+        bounds: [u32; 4],
+    }
+    let mut groups: Vec<Group> = Vec::new();
+
+    for row in tsv.lines().skip(1) {
+        let cols: Vec<&str> = row.split('\t').collect();
+        if cols.len() < 12 || cols[0] != "5" {
+            continue;
+        }
+        let text = cols[11].trim();
+        if text.is_empty() {
+            continue;
+        }
+        let num = |i: usize| cols[i].parse::<u32>().unwrap_or(0);
+        let key = (num(2), num(3), num(4));
+        let (left, top) = (num(6), num(7));
+        let (right, bottom) = (left + num(8), top + num(9));
+        let confidence = cols[10].parse::<f64>().unwrap_or(-1.0);
+
+        let group = match groups.iter().position(|g| g.key == key) {
+            Some(i) => &mut groups[i],
+            None => {
+                groups.push(Group {
+                    key,
+                    words: Vec::new(),
+                    confidences: Vec::new(),
+                    bounds: [left, top, right, bottom],
+                });
+                groups.last_mut().unwrap()
+            }
+        };
+        group.words.push(text.to_string());
+        // Negative confidence marks rows Tesseract didn't score
+        if confidence >= 0.0 {
+            group.confidences.push(confidence);
+        }
+        let b = &mut group.bounds;
+        *b = [b[0].min(left), b[1].min(top), b[2].max(right), b[3].max(bottom)];
+    }
+
+    groups
+        .into_iter()
+        .map(|group| {
+            let [left, top, right, bottom] = group.bounds;
+            OcrLine {
+                text: group.words.join(" "),
+                confidence: if group.confidences.is_empty() {
+                    0.0
+                } else {
+                    group.confidences.iter().sum::<f64>() / group.confidences.len() as f64
+                },
+                left: left / OCR_UPSCALE,
+                top: top / OCR_UPSCALE,
+                width: (right - left).div_ceil(OCR_UPSCALE),
+                height: (bottom - top).div_ceil(OCR_UPSCALE),
+            }
+        })
+        .collect()
+}
+
+/// Recognize the text in a frame. Returns the full text (one line per
+/// recognized line) and the lines with bounds in global logical coordinates.
+pub(crate) fn recognize_frame(frame: &Frame, lang: Option<&str>) -> Result<(String, Vec<Value>), String> {
+    let lines = ocr_image(&frame.image, lang)?;
+    let text = lines
+        .iter()
+        .map(|line| line.text.as_str())
+        .collect::<Vec<_>>()
+        .join("\n");
+    let lines = lines
+        .iter()
+        .map(|line| {
+            let (x, y) = frame.to_global(line.left as f64, line.top as f64);
+            json!({
+                "text": line.text,
+                "confidence": (line.confidence * 10.0).round() / 10.0,
+                "x": x.round() as i64,
+                "y": y.round() as i64,
+                "width": (line.width as f64 / frame.scale_factor).round() as i64,
+                "height": (line.height as f64 / frame.scale_factor).round() as i64,
+            })
+        })
+        .collect();
+    Ok((text, lines))
+}
+
+/// Capture a screen or region and read the text in it.
+pub(crate) fn read_text(area: &CaptureArea, lang: Option<&str>) -> Result<ToolOutput, String> {
+    let frame = capture_frame(area)?;
+    let (text, lines) = recognize_frame(&frame, lang)?;
+    Ok(ToolOutput::json(&json!({
+        "text": text,
+        "lines": lines,
+        "screens": frame.screens,
+    })))
+}
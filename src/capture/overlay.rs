@@ -0,0 +1,281 @@
+//! A labeled coordinate grid and marker points drawn onto a capture, so a
+//! model can read coordinates off the image instead of estimating them.
+//! Labels are in the coordinates the mouse tools take, never in image
+//! pixels.
+
+use image::{DynamicImage, Rgba, RgbaImage};
+use serde_json::{json, Value};
+
+use crate::backend::displays;
+use crate::capture::{draw_cursor_marker, CursorMarker, CursorStyle};
+
+/// Automatic grid spacings to choose from, in logical pixels.
+const GRID_STEPS: [f64; 9] = [10.0, 20.0, 25.0, 50.0, 100.0, 200.0, 250.0, 500.0, 1000.0];
+
+/// An automatic grid's lines are at least this many output pixels apart.
+const MIN_AUTO_GRID_GAP: f64 = 60.0;
+
+/// Smallest grid spacing that may be asked for, in logical pixels.
+pub(crate) const MIN_GRID_SPACING: f64 = 5.0;
+
+/// Most marker points.
+pub(crate) const MAX_OVERLAY_MARKERS: usize = 50;
+
+/// Longest marker label, in characters.
+pub(crate) const MAX_MARKER_LABEL: usize = 32;
+
+/// Glyphs are 3x5 bitmaps drawn at this many output pixels per dot.
+const GLYPH_SCALE: u32 = 2;
+
+const GRID_COLOR: [u8; 3] = [255, 0, 255];
+const GRID_ALPHA: f64 = 0.5;
+const LABEL_TEXT: [u8; 3] = [255, 255, 255];
+const LABEL_BACKGROUND: [u8; 3] = [0, 0, 0];
+const MARKER_COLOR: [u8; 3] = [255, 0, 0];
+
+/// A point to highlight, in the overlay's coordinates.
+#[derive(Debug, Clone)]
+pub(crate) struct OverlayPoint {
+    pub(crate) x: f64,
+    pub(crate) y: f64,
+    /// Shown next to the point; its coordinates if None.
+    pub(crate) label: Option<String>,
+}
+
+/// What to draw over a capture.
+#[derive(Debug, Clone)]
+pub(crate) struct Overlay {
+    pub(crate) grid: bool,
+    /// Grid spacing in logical pixels, or None to pick one that suits the
+    /// image size.
+    pub(crate) grid_spacing: Option<f64>,
+    pub(crate) markers: Vec<OverlayPoint>,
+    /// Label in coordinates relative to the captured screen (what the mouse
+    /// tools take with screen_index) rather than global ones.
+    pub(crate) screen_relative: bool,
+}
+
+/// Rows of a 3x5 glyph, the top bit of the low three on the left. Lower
+/// case is drawn as upper case, and characters without a glyph as '?'.
+fn glyph(c: char) -> [u8; 5] {
+    match c.to_ascii_uppercase() {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b001, 0b010, 0b010],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        'A' => [0b010, 0b101, 0b111, 0b101, 0b101],
+        'B' => [0b110, 0b101, 0b110, 0b101, 0b110],
+        'C' => [0b011, 0b100, 0b100, 0b100, 0b011],
+        'D' => [0b110, 0b101, 0b101, 0b101, 0b110],
+        'E' => [0b111, 0b100, 0b110, 0b100, 0b111],
+        'F' => [0b111, 0b100, 0b110, 0b100, 0b100],
+        'G' => [0b011, 0b100, 0b101, 0b101, 0b011],
+        'H' => [0b101, 0b101, 0b111, 0b101, 0b101],
+        'I' => [0b111, 0b010, 0b010, 0b010, 0b111],
+        'J' => [0b001, 0b001, 0b001, 0b101, 0b010],
+        'K' => [0b101, 0b101, 0b110, 0b101, 0b101],
+        'L' => [0b100, 0b100, 0b100, 0b100, 0b111],
+        'M' => [0b101, 0b111, 0b111, 0b101, 0b101],
+        'N' => [0b110, 0b101, 0b101, 0b101, 0b101],
+        'O' => [0b010, 0b101, 0b101, 0b101, 0b010],
+        'P' => [0b110, 0b101, 0b110, 0b100, 0b100],
+        'Q' => [0b010, 0b101, 0b101, 0b110, 0b011],
+        'R' => [0b110, 0b101, 0b110, 0b101, 0b101],
+        'S' => [0b011, 0b100, 0b010, 0b001, 0b110],
+        'T' => [0b111, 0b010, 0b010, 0b010, 0b010],
+        'U' => [0b101, 0b101, 0b101, 0b101, 0b111],
+        'V' => [0b101, 0b101, 0b101, 0b101, 0b010],
+        'W' => [0b101, 0b101, 0b111, 0b111, 0b101],
+        'X' => [0b101, 0b101, 0b010, 0b101, 0b101],
+        'Y' => [0b101, 0b101, 0b010, 0b010, 0b010],
+        'Z' => [0b111, 0b001, 0b010, 0b100, 0b111],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        ',' => [0b000, 0b000, 0b000, 0b010, 0b100],
+        ':' => [0b000, 0b010, 0b000, 0b010, 0b000],
+        '_' => [0b000, 0b000, 0b000, 0b000, 0b111],
+        '(' => [0b001, 0b010, 0b010, 0b010, 0b001],
+        ')' => [0b100, 0b010, 0b010, 0b010, 0b100],
+        '/' => [0b001, 0b001, 0b010, 0b100, 0b100],
+        '#' => [0b101, 0b111, 0b101, 0b111, 0b101],
+        ' ' => [0b000; 5],
+        _ => [0b111, 0b001, 0b011, 0b000, 0b010],
+    }
+}
+
+/// Size in output pixels of a label, including its one-dot padding.
+fn label_size(text: &str) -> (u32, u32) {
+    let characters = text.chars().count() as u32;
+    ((characters * 4 + 1) * GLYPH_SCALE, 7 * GLYPH_SCALE)
+}
+
+/// Draw text in a dark box with its top-left corner at (x, y), moved in
+/// as needed to stay within the image.
+fn draw_label(image: &mut RgbaImage, x: i64, y: i64, text: &str) {
+    let (width, height) = label_size(text);
+    let x = x.clamp(0, (image.width() as i64 - width as i64).max(0)) as u32;
+    let y = y.clamp(0, (image.height() as i64 - height as i64).max(0)) as u32;
+    let [r, g, b] = LABEL_BACKGROUND;
+    for py in y..(y + height).min(image.height()) {
+        for px in x..(x + width).min(image.width()) {
+            image.put_pixel(px, py, Rgba([r, g, b, 255]));
+        }
+    }
+    let [r, g, b] = LABEL_TEXT;
+    for (i, c) in text.chars().enumerate() {
+        let left = x + (1 + i as u32 * 4) * GLYPH_SCALE;
+        for (row, bits) in glyph(c).iter().enumerate() {
+            for column in 0..3 {
+                if bits & (0b100 >> column) == 0 {
+                    continue;
+                }
+                for dy in 0..GLYPH_SCALE {
+                    for dx in 0..GLYPH_SCALE {
+                        let px = left + column * GLYPH_SCALE + dx;
+                        let py = y + (1 + row as u32) * GLYPH_SCALE + dy;
+                        if px < image.width() && py < image.height() {
+                            image.put_pixel(px, py, Rgba([r, g, b, 255]));
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Blend the grid color into a pixel.
+fn blend_grid(image: &mut RgbaImage, x: u32, y: u32) {
+    let pixel = image.get_pixel_mut(x, y);
+    for (channel, grid) in pixel.0.iter_mut().zip(GRID_COLOR) {
+        *channel = (*channel as f64 * (1.0 - GRID_ALPHA) + grid as f64 * GRID_ALPHA).round() as u8;
+    }
+}
+
+/// A coordinate as shown in a label: whole numbers without a fraction.
+fn format_coordinate(value: f64) -> String {
+    if value.fract() == 0.0 {
+        format!("{}", value as i64)
+    } else {
+        format!("{:.1}", value)
+    }
+}
+
+/// Draw the overlay onto an output image whose top-left corner is at
+/// global logical (origin_x, origin_y), `logical_per_pixel` global units
+/// per image pixel. `screen` is the captured screen, for screen-relative
+/// labels. Returns a description of what was drawn for the result.
+pub(crate) fn draw_overlay(
+    picture: &mut DynamicImage,
+    (origin_x, origin_y): (f64, f64),
+    logical_per_pixel: f64,
+    screen: Option<usize>,
+    overlay: &Overlay,
+) -> Result<Value, String> {
+    // Where label coordinates are measured from, in global coordinates
+    let (base_x, base_y) = match (overlay.screen_relative, screen) {
+        (true, Some(index)) => {
+            let screens = displays()?;
+            let display = screens
+                .get(index)
+                .ok_or_else(|| format!("Screen index {} not found", index))?;
+            (display.x as f64, display.y as f64)
+        }
+        _ => (0.0, 0.0),
+    };
+    let mut image = picture.to_rgba8();
+    let (width, height) = image.dimensions();
+    let to_image = |x: f64, y: f64| ((x + base_x - origin_x) / logical_per_pixel, (y + base_y - origin_y) / logical_per_pixel);
+    let mut described = json!({
+        "coordinates": if overlay.screen_relative { "screen" } else { "global" }
+    });
+
+    if overlay.grid {
+        let step = overlay.grid_spacing.unwrap_or_else(|| {
+            GRID_STEPS
+                .iter()
+                .copied()
+                .find(|step| step / logical_per_pixel >= MIN_AUTO_GRID_GAP)
+                .unwrap_or(GRID_STEPS[GRID_STEPS.len() - 1])
+        });
+        let gap = step / logical_per_pixel;
+        // The image's extent in label coordinates
+        let left = origin_x - base_x;
+        let top = origin_y - base_y;
+        let right = left + width as f64 * logical_per_pixel;
+        let bottom = top + height as f64 * logical_per_pixel;
+        let widest = format_coordinate((right.abs().max(left.abs()) / step).ceil() * step);
+        // Label every nth line, so labels don't overlap
+        let every_x = ((label_size(&widest).0 + 2 * GLYPH_SCALE) as f64 / gap).ceil().max(1.0) as i64;
+        let every_y = ((label_size("0").1 + 2 * GLYPH_SCALE) as f64 / gap).ceil().max(1.0) as i64;
+
+        let mut labels = Vec::new();
+        let first = (left / step).ceil() as i64;
+        for n in first..=(right / step).floor() as i64 {
+            let px = to_image(n as f64 * step, 0.0).0.round();
+            if px < 0.0 || px >= width as f64 {
+                continue;
+            }
+            for y in 0..height {
+                blend_grid(&mut image, px as u32, y);
+            }
+            if (n - first) % every_x == 0 {
+                labels.push((px as i64 + 2, 0, format_coordinate(n as f64 * step)));
+            }
+        }
+        let first = (top / step).ceil() as i64;
+        for n in first..=(bottom / step).floor() as i64 {
+            let py = to_image(0.0, n as f64 * step).1.round();
+            if py < 0.0 || py >= height as f64 {
+                continue;
+            }
+            for x in 0..width {
+                blend_grid(&mut image, x, py as u32);
+            }
+            if (n - first) % every_y == 0 {
+                labels.push((0, py as i64 + 2, format_coordinate(n as f64 * step)));
+            }
+        }
+        for (x, y, text) in labels {
+            draw_label(&mut image, x, y, &text);
+        }
+        described["grid_spacing"] = json!(step);
+    }
+
+    let mut outside = Vec::new();
+    let marker = CursorMarker {
+        style: CursorStyle::Crosshair,
+        color: MARKER_COLOR,
+    };
+    let mut picture_with_markers = DynamicImage::ImageRgba8(image);
+    let mut labels = Vec::new();
+    for (i, point) in overlay.markers.iter().enumerate() {
+        let (px, py) = to_image(point.x, point.y);
+        if px < 0.0 || py < 0.0 || px >= width as f64 || py >= height as f64 {
+            outside.push(i);
+            continue;
+        }
+        draw_cursor_marker(&mut picture_with_markers, px, py, 1.0, &marker);
+        let text = match &point.label {
+            Some(label) => label.clone(),
+            None => format!("{},{}", format_coordinate(point.x), format_coordinate(point.y)),
+        };
+        labels.push((px as i64 + 10, py as i64 + 10, text));
+    }
+    let mut image = picture_with_markers.to_rgba8();
+    for (x, y, text) in labels {
+        draw_label(&mut image, x, y, &text);
+    }
+    *picture = DynamicImage::ImageRgba8(image);
+
+    if !overlay.markers.is_empty() {
+        described["markers"] = json!(overlay.markers.len());
+        described["markers_outside"] = json!(outside);
+    }
+    Ok(described)
+}
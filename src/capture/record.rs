@@ -0,0 +1,180 @@
+//! Short screen recordings, for screen_record: a few seconds of frames
+//! captured at a fixed rate, downscaled and returned as an animated GIF or
+//! as separate JPEGs.
+
+use base64::Engine;
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::imageops::FilterType;
+use image::{Delay, DynamicImage};
+use serde_json::{json, Value};
+use std::time::{Duration, Instant};
+
+use crate::cancel::sleep_cancellable;
+use crate::capture::{capture_frame, encode_image, CaptureArea, CaptureFormat};
+use crate::tools::output::{ContentItem, ToolError, ToolOutput};
+
+/// Longest recording.
+pub(crate) const MAX_RECORD_DURATION_MS: u64 = 5_000;
+
+/// Highest frame rate.
+pub(crate) const MAX_RECORD_FPS: u64 = 10;
+
+/// Largest width or height of a recorded frame.
+pub(crate) const MAX_RECORD_SIDE: u32 = 1280;
+
+/// Largest encoded recording, GIF or all frames together.
+const MAX_RECORD_BYTES: usize = 5 * 1024 * 1024;
+
+const RECORD_JPEG_QUALITY: u8 = 70;
+
+/// The GIF quantizer's speed, 1 (best colors) to 30 (fastest).
+const GIF_SPEED: i32 = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum RecordFormat {
+    Gif,
+    Frames,
+}
+
+impl RecordFormat {
+    pub(crate) const NAMES: [&'static str; 2] = ["gif", "frames"];
+
+    pub(crate) fn parse(name: &str) -> Option<RecordFormat> {
+        match name {
+            "gif" => Some(RecordFormat::Gif),
+            "frames" => Some(RecordFormat::Frames),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            RecordFormat::Gif => "gif",
+            RecordFormat::Frames => "frames",
+        }
+    }
+}
+
+pub(crate) struct RecordOptions {
+    pub(crate) duration: Duration,
+    pub(crate) fps: u64,
+    pub(crate) max_width: u32,
+    pub(crate) max_height: u32,
+    pub(crate) format: RecordFormat,
+}
+
+fn too_large(bytes: usize) -> ToolError {
+    ToolError::invalid_field(
+        "max_width",
+        format!(
+            "The recording is {} bytes, over the {} byte limit; record a smaller region, for less time, at a lower fps or a smaller max_width",
+            bytes, MAX_RECORD_BYTES
+        ),
+    )
+}
+
+/// Capture the area `fps` times a second for `duration`, downscaling each
+/// frame as it's taken. A capture that runs late delays the next rather
+/// than being skipped, and the result's timestamps (ms since the first
+/// frame) say when each frame was really taken. The wait between frames is
+/// cancellable, and no input lock is held, so other calls carry on.
+pub(crate) fn record_screen(area: &CaptureArea, options: &RecordOptions) -> Result<ToolOutput, ToolError> {
+    let interval = Duration::from_secs_f64(1.0 / options.fps as f64);
+    let count = ((options.duration.as_secs_f64() * options.fps as f64).round() as u32).max(1);
+
+    let start = Instant::now();
+    let mut frames: Vec<(u64, DynamicImage)> = Vec::new();
+    let mut first = None;
+    for i in 0..count {
+        let due = start + interval * i;
+        sleep_cancellable(due.saturating_duration_since(Instant::now()))?;
+        let taken = start.elapsed().as_millis() as u64;
+        let frame = capture_frame(area)?;
+        let captured_width = frame.image.width();
+        let image = if captured_width > options.max_width || frame.image.height() > options.max_height {
+            frame.image.resize(options.max_width, options.max_height, FilterType::Triangle)
+        } else {
+            frame.image
+        };
+        if first.is_none() {
+            let logical_per_pixel = captured_width as f64 / image.width() as f64 / frame.scale_factor;
+            first = Some((frame.origin_x, frame.origin_y, logical_per_pixel, frame.screens));
+        }
+        frames.push((taken, image));
+    }
+    let Some((origin_x, origin_y, logical_per_pixel, screens)) = first else {
+        return Err(ToolError::Platform("No frames were recorded".to_string()));
+    };
+    let (width, height) = (frames[0].1.width(), frames[0].1.height());
+    let timestamps: Vec<u64> = frames.iter().map(|(taken, _)| taken - frames[0].0).collect();
+
+    let mut result = json!({
+        "format": options.format.as_str(),
+        "frame_count": frames.len(),
+        "fps": options.fps,
+        "duration_ms": timestamps.last().copied().unwrap_or(0) + interval.as_millis() as u64,
+        "width": width,
+        "height": height,
+        "timestamps_ms": timestamps,
+        "screens": screens,
+        "origin": { "x": origin_x, "y": origin_y },
+        "logical_per_pixel": logical_per_pixel
+    });
+
+    match options.format {
+        RecordFormat::Gif => {
+            let mut bytes = Vec::new();
+            {
+                let mut encoder = GifEncoder::new_with_speed(&mut bytes, GIF_SPEED);
+                encoder
+                    .set_repeat(Repeat::Infinite)
+                    .map_err(|e| format!("Failed to encode GIF: {}", e))?;
+                for (i, (_, picture)) in frames.iter().enumerate() {
+                    // Each frame shows until the next was taken
+                    let shown = match timestamps.get(i + 1) {
+                        Some(next) => Duration::from_millis(next - timestamps[i]),
+                        None => interval,
+                    };
+                    let frame = image::Frame::from_parts(picture.to_rgba8(), 0, 0, Delay::from_saturating_duration(shown));
+                    encoder
+                        .encode_frame(frame)
+                        .map_err(|e| format!("Failed to encode GIF: {}", e))?;
+                }
+            }
+            if bytes.len() > MAX_RECORD_BYTES {
+                return Err(too_large(bytes.len()));
+            }
+            result["bytes"] = json!(bytes.len());
+            result["mimeType"] = json!("image/gif");
+            Ok(ToolOutput::json(&result).with(ContentItem::Image {
+                data: base64::engine::general_purpose::STANDARD.encode(&bytes),
+                mime_type: "image/gif".to_string(),
+            }))
+        }
+        RecordFormat::Frames => {
+            let mut total = 0;
+            let mut images = Vec::new();
+            let mut described: Vec<Value> = Vec::new();
+            for (i, (_, picture)) in frames.iter().enumerate() {
+                let bytes = encode_image(picture, CaptureFormat::Jpeg, RECORD_JPEG_QUALITY)?;
+                total += bytes.len();
+                if total > MAX_RECORD_BYTES {
+                    return Err(too_large(total));
+                }
+                described.push(json!({ "index": i, "t_ms": timestamps[i], "bytes": bytes.len() }));
+                images.push(base64::engine::general_purpose::STANDARD.encode(&bytes));
+            }
+            result["bytes"] = json!(total);
+            result["mimeType"] = json!(CaptureFormat::Jpeg.mime_type());
+            result["frames"] = json!(described);
+            let mut output = ToolOutput::json(&result);
+            for data in images {
+                output = output.with(ContentItem::Image {
+                    data,
+                    mime_type: CaptureFormat::Jpeg.mime_type().to_string(),
+                });
+            }
+            Ok(output)
+        }
+    }
+}
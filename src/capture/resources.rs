@@ -0,0 +1,144 @@
+//! Captures kept in memory as MCP resources, for `save_to: "resource"`:
+//! each gets a `screenshot://<id>` URI that clients fetch with
+//! resources/read instead of receiving the image in the tool result, and
+//! that compare_screens can diff against. Only the most recently stored or
+//! used captures are kept.
+
+use serde_json::{json, Value};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use crate::capture::CaptureArea;
+use crate::clock::format_rfc3339;
+use crate::protocol::send_notification;
+
+/// How many captures are kept.
+const MAX_STORED_CAPTURES: usize = 20;
+
+const URI_SCHEME: &str = "screenshot://";
+
+struct StoredCapture {
+    id: u64,
+    /// Base64-encoded image data.
+    data: String,
+    mime_type: String,
+    bytes: usize,
+    width: u32,
+    height: u32,
+    taken: SystemTime,
+    /// What was captured, so that the same area can be captured again.
+    area: CaptureArea,
+}
+
+impl StoredCapture {
+    fn uri(&self) -> String {
+        format!("{}{}", URI_SCHEME, self.id)
+    }
+}
+
+/// Least recently used first.
+static STORE: Mutex<VecDeque<StoredCapture>> = Mutex::new(VecDeque::new());
+
+/// Keep a capture, evicting the least recently used beyond
+/// MAX_STORED_CAPTURES, and tell the client the list changed. Returns its
+/// URI.
+pub(crate) fn store_capture(
+    data: String,
+    mime_type: &str,
+    bytes: usize,
+    width: u32,
+    height: u32,
+    area: CaptureArea,
+) -> String {
+    static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+    let capture = StoredCapture {
+        id: NEXT_ID.fetch_add(1, Ordering::SeqCst),
+        data,
+        mime_type: mime_type.to_string(),
+        bytes,
+        width,
+        height,
+        taken: SystemTime::now(),
+        area,
+    };
+    let uri = capture.uri();
+    {
+        let mut store = STORE.lock().unwrap();
+        store.push_back(capture);
+        while store.len() > MAX_STORED_CAPTURES {
+            store.pop_front();
+        }
+    }
+    send_notification(&json!({
+        "jsonrpc": "2.0",
+        "method": "notifications/resources/list_changed"
+    }));
+    uri
+}
+
+/// The stored captures, for resources/list, most recent first.
+pub(crate) fn list_resources() -> Value {
+    let store = STORE.lock().unwrap();
+    let resources: Vec<Value> = store
+        .iter()
+        .rev()
+        .map(|capture| {
+            json!({
+                "uri": capture.uri(),
+                "name": format!("Screenshot {}", capture.id),
+                "description": format!(
+                    "{}x{} capture taken at {}",
+                    capture.width,
+                    capture.height,
+                    format_rfc3339(capture.taken)
+                ),
+                "mimeType": capture.mime_type,
+                "size": capture.bytes
+            })
+        })
+        .collect();
+    json!({ "resources": resources })
+}
+
+/// Look up a stored capture by URI, or by its bare id when `bare_id` is
+/// set, marking it as recently used.
+fn use_capture<T>(uri: &str, bare_id: bool, f: impl FnOnce(&StoredCapture) -> T) -> Result<T, String> {
+    let id = uri
+        .strip_prefix(URI_SCHEME)
+        .or(bare_id.then_some(uri))
+        .and_then(|id| id.parse::<u64>().ok())
+        .ok_or_else(|| format!("Unknown resource: {}", uri))?;
+    let mut store = STORE.lock().unwrap();
+    let index = store.iter().position(|capture| capture.id == id).ok_or_else(|| {
+        format!(
+            "Resource not found: {} (only the last {} captures are kept)",
+            uri, MAX_STORED_CAPTURES
+        )
+    })?;
+    let capture = store.remove(index).unwrap();
+    let result = f(&capture);
+    store.push_back(capture);
+    Ok(result)
+}
+
+/// A stored capture's contents, for resources/read, marking it as recently
+/// used. Errors if the URI isn't one of the stored captures.
+pub(crate) fn read_resource(uri: &str) -> Result<Value, String> {
+    use_capture(uri, false, |capture| {
+        json!({
+            "contents": [{
+                "uri": capture.uri(),
+                "mimeType": capture.mime_type,
+                "blob": capture.data
+            }]
+        })
+    })
+}
+
+/// A stored capture's base64 image data and the area it was taken of, by
+/// `screenshot://` URI or bare id.
+pub(crate) fn stored_capture(id: &str) -> Result<(String, CaptureArea), String> {
+    use_capture(id, true, |capture| (capture.data.clone(), capture.area))
+}
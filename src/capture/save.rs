@@ -0,0 +1,142 @@
+//! Writing captures to files, for `save_to: "file"`, so that a local client
+//! can read them directly instead of receiving base64 through the model's
+//! context. Files go in a managed directory, from the config file's
+//! `[capture_files]` section or else a desktop-mcp directory under the
+//! system temp directory. Files the server names itself are pruned by
+//! count and age; files given an explicit path are left alone.
+
+use base64::Engine;
+use serde_json::{json, Value};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::capture::CaptureFormat;
+use crate::config::loaded_config;
+
+/// Default number of server-named files kept.
+const DEFAULT_MAX_COUNT: usize = 50;
+
+/// Default age past which server-named files are removed.
+const DEFAULT_MAX_AGE_SECS: u64 = 60 * 60;
+
+/// Prefix of the files the server names, and prunes.
+const FILE_PREFIX: &str = "capture-";
+
+/// The directory captures are saved in.
+pub(crate) fn capture_dir() -> PathBuf {
+    loaded_config()
+        .config
+        .capture_files
+        .dir
+        .clone()
+        .unwrap_or_else(|| std::env::temp_dir().join("desktop-mcp"))
+}
+
+/// The directory and pruning limits, for initialize.
+pub(crate) fn capture_files_info() -> Value {
+    let config = &loaded_config().config.capture_files;
+    json!({
+        "dir": capture_dir().display().to_string(),
+        "max_count": config.max_count.unwrap_or(DEFAULT_MAX_COUNT),
+        "max_age_secs": config.max_age_secs.unwrap_or(DEFAULT_MAX_AGE_SECS)
+    })
+}
+
+/// Where to write a capture: `requested` within the capture directory, or
+/// a new server-chosen name. Absolute paths and `..` are refused, and the
+/// extension must match the format; one is added if missing.
+fn target_path(requested: Option<&str>, format: CaptureFormat) -> Result<PathBuf, String> {
+    let dir = capture_dir();
+    let Some(requested) = requested else {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+        let millis = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis();
+        let name = format!(
+            "{}{}-{}.{}",
+            FILE_PREFIX,
+            millis,
+            NEXT.fetch_add(1, Ordering::SeqCst),
+            format.name()
+        );
+        return Ok(dir.join(name));
+    };
+
+    let relative = Path::new(requested);
+    if requested.is_empty() || !relative.components().all(|c| matches!(c, Component::Normal(_))) {
+        return Err(format!(
+            "path must be a relative path within the capture directory, without \"..\": {}",
+            requested
+        ));
+    }
+    let mut path = dir.join(relative);
+    match path.extension().and_then(|e| e.to_str()) {
+        None => {
+            path.set_extension(format.name());
+        }
+        Some(extension) => {
+            if CaptureFormat::parse(extension, false).map(|f| f.name()) != Ok(format.name()) {
+                return Err(format!(
+                    "path's extension .{} doesn't match format {}",
+                    extension,
+                    format.name()
+                ));
+            }
+        }
+    }
+    Ok(path)
+}
+
+/// Write a base64-encoded capture to a file, returning its path. Checks
+/// `requested` before anything is written.
+pub(crate) fn save_capture(data: &str, format: CaptureFormat, requested: Option<&str>) -> Result<PathBuf, String> {
+    let path = target_path(requested, format)?;
+    let bytes = base64::engine::general_purpose::STANDARD
+        .decode(data)
+        .map_err(|e| format!("Failed to decode capture: {}", e))?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+    }
+    fs::write(&path, bytes).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    if requested.is_none() {
+        prune();
+    }
+    Ok(path)
+}
+
+/// Check an explicit path without writing anything, so a bad one fails
+/// before the capture is taken.
+pub(crate) fn check_path(requested: &str, format: CaptureFormat) -> Result<(), String> {
+    target_path(Some(requested), format).map(|_| ())
+}
+
+/// Remove server-named files beyond the configured count or age, oldest
+/// first.
+fn prune() {
+    let config = &loaded_config().config.capture_files;
+    let max_count = config.max_count.unwrap_or(DEFAULT_MAX_COUNT);
+    let max_age = Duration::from_secs(config.max_age_secs.unwrap_or(DEFAULT_MAX_AGE_SECS));
+
+    let Ok(entries) = fs::read_dir(capture_dir()) else {
+        return;
+    };
+    let mut files: Vec<(SystemTime, PathBuf)> = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_name().to_string_lossy().starts_with(FILE_PREFIX))
+        .filter_map(|entry| {
+            let metadata = entry.metadata().ok().filter(|m| m.is_file())?;
+            Some((metadata.modified().ok()?, entry.path()))
+        })
+        .collect();
+    // Newest first
+    files.sort_by_key(|&(modified, _)| std::cmp::Reverse(modified));
+    let now = SystemTime::now();
+    for (i, (modified, path)) in files.iter().enumerate() {
+        let expired = now.duration_since(*modified).is_ok_and(|age| age > max_age);
+        if i >= max_count || expired {
+            if let Err(e) = fs::remove_file(path) {
+                log::debug!("Failed to remove old capture {}: {}", path.display(), e);
+            }
+        }
+    }
+}
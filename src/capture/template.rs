@@ -0,0 +1,495 @@
+//! Finding a template image on screen.
+
+use base64::Engine;
+use image::imageops::FilterType;
+use image::DynamicImage;
+use serde_json::{json, Value};
+use std::time::{Duration, Instant};
+
+use crate::cancel::sleep_cancellable;
+use crate::capture::{capture_frame, CaptureArea, Frame};
+
+/// Default `screen_capture` output bounds, used to guess how much a template
+/// cropped from a previous capture was downscaled.
+pub(crate) const DEFAULT_MAX_WIDTH: u32 = 1280;
+pub(crate) const DEFAULT_MAX_HEIGHT: u32 = 720;
+
+/// Templates are searched on a reduced image first; this is the smallest
+/// template side (in pixels) the coarse pass is allowed to shrink to.
+const COARSE_TEMPLATE_SIDE: usize = 16;
+
+/// Upper bound on matches returned when `all` is requested.
+const MAX_MATCHES: usize = 50;
+
+/// A grayscale image with f32 samples, used for correlation.
+struct GrayF32 {
+    width: usize,
+    height: usize,
+    data: Vec<f32>,
+}
+
+impl GrayF32 {
+    fn from_image(img: &DynamicImage) -> Self {
+        let mut gray = GrayF32 {
+            width: 0,
+            height: 0,
+            data: Vec::new(),
+        };
+        gray.load(img);
+        gray
+    }
+
+    /// Replace the contents with `img`, reusing the sample buffer.
+    fn load(&mut self, img: &DynamicImage) {
+        self.width = img.width() as usize;
+        self.height = img.height() as usize;
+        self.data.clear();
+        match img.as_rgba8() {
+            // Captures are RGBA; convert directly rather than via a luma copy
+            Some(rgba) => self.data.extend(rgba.pixels().map(|p| {
+                0.2126 * p[0] as f32 + 0.7152 * p[1] as f32 + 0.0722 * p[2] as f32
+            })),
+            None => self.data.extend(img.to_luma8().into_raw().into_iter().map(f32::from)),
+        }
+    }
+
+    /// Box-average downsample by an integer factor.
+    fn downsample(&self, factor: usize) -> Self {
+        let width = self.width / factor;
+        let height = self.height / factor;
+        let norm = (factor * factor) as f32;
+        let mut data = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                let mut sum = 0.0;
+                for dy in 0..factor {
+                    let row = (y * factor + dy) * self.width + x * factor;
+                    sum += self.data[row..row + factor].iter().sum::<f32>();
+                }
+                data.push(sum / norm);
+            }
+        }
+        GrayF32 {
+            width,
+            height,
+            data,
+        }
+    }
+}
+
+/// Summed-area tables of pixel values and squared pixel values, giving O(1)
+/// window mean/variance for normalized cross-correlation.
+struct IntegralImage {
+    stride: usize,
+    sum: Vec<f64>,
+    sum_sq: Vec<f64>,
+}
+
+impl IntegralImage {
+    fn new(img: &GrayF32) -> Self {
+        let mut integral = IntegralImage {
+            stride: 0,
+            sum: Vec::new(),
+            sum_sq: Vec::new(),
+        };
+        integral.rebuild(img);
+        integral
+    }
+
+    /// Recompute the tables for `img`, reusing their buffers.
+    fn rebuild(&mut self, img: &GrayF32) {
+        let stride = img.width + 1;
+        let len = stride * (img.height + 1);
+        self.stride = stride;
+        // The first row and column must be zero; the rest is overwritten
+        self.sum.clear();
+        self.sum.resize(len, 0.0);
+        self.sum_sq.clear();
+        self.sum_sq.resize(len, 0.0);
+        let (sum, sum_sq) = (&mut self.sum, &mut self.sum_sq);
+        for y in 0..img.height {
+            let mut row_sum = 0.0;
+            let mut row_sum_sq = 0.0;
+            for x in 0..img.width {
+                let v = img.data[y * img.width + x] as f64;
+                row_sum += v;
+                row_sum_sq += v * v;
+                let i = (y + 1) * stride + x + 1;
+                sum[i] = sum[i - stride] + row_sum;
+                sum_sq[i] = sum_sq[i - stride] + row_sum_sq;
+            }
+        }
+    }
+
+    fn window(&self, x: usize, y: usize, w: usize, h: usize) -> (f64, f64) {
+        let s = self.stride;
+        let (a, b, c, d) = (y * s + x, y * s + x + w, (y + h) * s + x, (y + h) * s + x + w);
+        (
+            self.sum[d] - self.sum[b] - self.sum[c] + self.sum[a],
+            self.sum_sq[d] - self.sum_sq[b] - self.sum_sq[c] + self.sum_sq[a],
+        )
+    }
+}
+
+/// A template with its mean removed, ready for correlation.
+struct PreparedTemplate {
+    width: usize,
+    height: usize,
+    data: Vec<f32>,
+    norm: f64,
+}
+
+impl PreparedTemplate {
+    fn new(img: &GrayF32) -> Self {
+        let mean = img.data.iter().map(|&v| v as f64).sum::<f64>() / img.data.len() as f64;
+        let data: Vec<f32> = img.data.iter().map(|&v| (v as f64 - mean) as f32).collect();
+        let norm = data.iter().map(|&v| (v as f64) * (v as f64)).sum::<f64>().sqrt();
+        PreparedTemplate {
+            width: img.width,
+            height: img.height,
+            data,
+            norm,
+        }
+    }
+}
+
+/// Zero-mean normalized cross-correlation of the template placed at (x, y).
+/// Returns a score in [-1, 1]; 1 is a perfect match.
+fn ncc_at(img: &GrayF32, integral: &IntegralImage, tmpl: &PreparedTemplate, x: usize, y: usize) -> f32 {
+    let n = (tmpl.width * tmpl.height) as f64;
+    let (sum, sum_sq) = integral.window(x, y, tmpl.width, tmpl.height);
+    let variance = sum_sq - sum * sum / n;
+    if variance <= 1e-6 || tmpl.norm <= 1e-6 {
+        return 0.0;
+    }
+
+    // The template is zero-mean, so correlating against raw pixels is the
+    // same as correlating against the mean-subtracted window.
+    let mut dot = 0.0f32;
+    for ty in 0..tmpl.height {
+        let img_row = &img.data[(y + ty) * img.width + x..][..tmpl.width];
+        let tmpl_row = &tmpl.data[ty * tmpl.width..][..tmpl.width];
+        dot += img_row.iter().zip(tmpl_row).map(|(a, b)| a * b).sum::<f32>();
+    }
+
+    (dot as f64 / (variance.sqrt() * tmpl.norm)) as f32
+}
+
+/// A template match in the captured frame's physical pixel space.
+#[derive(Debug, Clone, Copy)]
+struct TemplateMatch {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+    score: f32,
+    scale: f32,
+}
+
+impl TemplateMatch {
+    fn overlaps(&self, other: &TemplateMatch) -> bool {
+        let ix = (self.x + self.width).min(other.x + other.width) as i64 - self.x.max(other.x) as i64;
+        let iy = (self.y + self.height).min(other.y + other.height) as i64 - self.y.max(other.y) as i64;
+        if ix <= 0 || iy <= 0 {
+            return false;
+        }
+        let smaller = (self.width * self.height).min(other.width * other.height) as i64;
+        ix * iy * 2 > smaller
+    }
+}
+
+/// Keep the best-scoring match from every cluster of overlapping matches.
+fn suppress_overlaps(mut matches: Vec<TemplateMatch>) -> Vec<TemplateMatch> {
+    matches.sort_by(|a, b| b.score.total_cmp(&a.score));
+    let mut kept: Vec<TemplateMatch> = Vec::new();
+    for m in matches {
+        if !kept.iter().any(|k| k.overlaps(&m)) {
+            kept.push(m);
+        }
+    }
+    kept
+}
+
+/// Search for a template at a single scale. A coarse pass over a downsampled
+/// copy of both images finds candidates, which are then refined at full
+/// resolution. Returns candidates scoring at least `threshold` plus the best
+/// candidate overall, so callers can report how close a miss was.
+fn search_scale(
+    haystack: &GrayF32,
+    integral: &IntegralImage,
+    template: &GrayF32,
+    scale: f32,
+    threshold: f32,
+) -> Vec<TemplateMatch> {
+    let tmpl = PreparedTemplate::new(template);
+    let factor = (template.width.min(template.height) / COARSE_TEMPLATE_SIDE).clamp(1, 8);
+
+    let make_match = |x: usize, y: usize, t: &PreparedTemplate, score: f32| TemplateMatch {
+        x: x as u32,
+        y: y as u32,
+        width: t.width as u32,
+        height: t.height as u32,
+        score,
+        scale,
+    };
+
+    // Coarse pass: score every position in the reduced image
+    let (coarse_hay, coarse_integral, coarse_tmpl) = if factor > 1 {
+        let hay = haystack.downsample(factor);
+        let integral = IntegralImage::new(&hay);
+        let tmpl = PreparedTemplate::new(&template.downsample(factor));
+        (Some(hay), Some(integral), Some(tmpl))
+    } else {
+        (None, None, None)
+    };
+    let hay = coarse_hay.as_ref().unwrap_or(haystack);
+    let hay_integral = coarse_integral.as_ref().unwrap_or(integral);
+    let coarse_tmpl = coarse_tmpl.as_ref().unwrap_or(&tmpl);
+
+    if coarse_tmpl.width > hay.width || coarse_tmpl.height > hay.height {
+        return Vec::new();
+    }
+
+    // Coarse scores are noisier, so admit candidates a little below threshold
+    let coarse_threshold = if factor > 1 { threshold - 0.2 } else { threshold };
+    let mut candidates = Vec::new();
+    let mut best = make_match(0, 0, coarse_tmpl, f32::MIN);
+    for y in 0..=hay.height - coarse_tmpl.height {
+        for x in 0..=hay.width - coarse_tmpl.width {
+            let score = ncc_at(hay, hay_integral, coarse_tmpl, x, y);
+            if score > best.score {
+                best = make_match(x, y, coarse_tmpl, score);
+            }
+            if score >= coarse_threshold {
+                candidates.push(make_match(x, y, coarse_tmpl, score));
+            }
+        }
+    }
+    candidates.push(best);
+    let mut candidates = suppress_overlaps(candidates);
+    candidates.truncate(MAX_MATCHES * 2);
+
+    if factor == 1 {
+        return candidates;
+    }
+
+    // Refine each candidate in a small full-resolution neighborhood
+    let max_x = haystack.width - tmpl.width;
+    let max_y = haystack.height - tmpl.height;
+    let refined = candidates
+        .into_iter()
+        .map(|c| {
+            let cx = c.x as usize * factor;
+            let cy = c.y as usize * factor;
+            let mut best = make_match(cx.min(max_x), cy.min(max_y), &tmpl, f32::MIN);
+            for y in cy.saturating_sub(factor)..=(cy + factor).min(max_y) {
+                for x in cx.saturating_sub(factor)..=(cx + factor).min(max_x) {
+                    let score = ncc_at(haystack, integral, &tmpl, x, y);
+                    if score > best.score {
+                        best = make_match(x, y, &tmpl, score);
+                    }
+                }
+            }
+            best
+        })
+        .collect();
+
+    suppress_overlaps(refined)
+}
+
+/// A template prepared at each search scale, with working buffers that are
+/// reused across searches so that polling doesn't reallocate them per frame.
+struct TemplateSearch {
+    templates: Vec<(f32, GrayF32)>,
+    haystack: GrayF32,
+    integral: IntegralImage,
+}
+
+impl TemplateSearch {
+    fn new(template: &DynamicImage, scales: &[f32]) -> Self {
+        let templates = scales
+            .iter()
+            .filter_map(|&scale| {
+                let width = (template.width() as f32 * scale).round() as u32;
+                let height = (template.height() as f32 * scale).round() as u32;
+                if width < 4 || height < 4 {
+                    return None;
+                }
+                let scaled = if (scale - 1.0).abs() < f32::EPSILON {
+                    GrayF32::from_image(template)
+                } else {
+                    GrayF32::from_image(&template.resize_exact(width, height, FilterType::Triangle))
+                };
+                Some((scale, scaled))
+            })
+            .collect();
+        let haystack = GrayF32::from_image(&DynamicImage::new_luma8(0, 0));
+        let integral = IntegralImage::new(&haystack);
+        TemplateSearch {
+            templates,
+            haystack,
+            integral,
+        }
+    }
+
+    /// Find the template in `haystack` at each scale. The result is sorted
+    /// best-first and always contains the best candidate, even if it falls
+    /// below `threshold`.
+    fn run(&mut self, haystack: &DynamicImage, threshold: f32) -> Vec<TemplateMatch> {
+        self.haystack.load(haystack);
+        self.integral.rebuild(&self.haystack);
+
+        let mut matches = Vec::new();
+        for (scale, template) in &self.templates {
+            if template.width > self.haystack.width || template.height > self.haystack.height {
+                continue;
+            }
+            matches.extend(search_scale(&self.haystack, &self.integral, template, *scale, threshold));
+        }
+
+        suppress_overlaps(matches)
+    }
+}
+
+pub(crate) fn decode_base64_image(data: &str) -> Result<DynamicImage, String> {
+    // Accept data URLs as well as bare base64
+    let data = data.split_once("base64,").map_or(data, |(_, d)| d).trim();
+    let bytes = base64::engine::general_purpose::STANDARD
+        .decode(data)
+        .map_err(|e| format!("Invalid base64 image: {}", e))?;
+    image::load_from_memory(&bytes).map_err(|e| format!("Failed to decode image: {}", e))
+}
+
+/// Locate a base64-encoded template on screen. Matches are returned in
+/// global logical coordinates, ready to pass to the mouse tools.
+/// Scales to search at. Without a hint, native size plus the ratio a
+/// default screen_capture of a frame this size would have been downscaled
+/// by, since templates are usually cropped from an earlier capture.
+fn search_scales(scale: Option<f32>, frame: &Frame) -> Result<Vec<f32>, String> {
+    match scale {
+        Some(s) if s > 0.0 => Ok(vec![s]),
+        Some(_) => Err("scale must be positive".to_string()),
+        None => {
+            let (w, h) = (frame.image.width() as f32, frame.image.height() as f32);
+            let ratio = (w / DEFAULT_MAX_WIDTH as f32).max(h / DEFAULT_MAX_HEIGHT as f32);
+            if ratio > 1.05 {
+                Ok(vec![1.0, ratio])
+            } else {
+                Ok(vec![1.0])
+            }
+        }
+    }
+}
+
+/// Describe template matches in global coordinates: the best match (if it
+/// meets the threshold) at the top level, and every match if `all` is set.
+fn match_result(frame: &Frame, matches: &[TemplateMatch], threshold: f32, scales: &[f32], all: bool) -> Value {
+    let best_score = matches.first().map(|m| m.score).unwrap_or(0.0);
+
+    let to_json = |m: &TemplateMatch| {
+        let (x, y) = frame.to_global(m.x as f64, m.y as f64);
+        let (cx, cy) = frame.to_global(
+            m.x as f64 + m.width as f64 / 2.0,
+            m.y as f64 + m.height as f64 / 2.0,
+        );
+        json!({
+            "x": cx,
+            "y": cy,
+            "bounds": {
+                "x": x,
+                "y": y,
+                "width": m.width as f64 / frame.scale_factor,
+                "height": m.height as f64 / frame.scale_factor
+            },
+            "score": m.score,
+            "scale": m.scale
+        })
+    };
+
+    let found: Vec<&TemplateMatch> = matches.iter().filter(|m| m.score >= threshold).collect();
+    let mut result = match found.first() {
+        Some(best) => {
+            let mut r = to_json(best);
+            r["found"] = json!(true);
+            r
+        }
+        None => json!({ "found": false, "best_score": best_score }),
+    };
+    result["threshold"] = json!(threshold);
+    result["scales_searched"] = json!(scales);
+    if all {
+        result["matches"] = json!(found.iter().take(MAX_MATCHES).map(|m| to_json(m)).collect::<Vec<_>>());
+    }
+    result
+}
+
+pub(crate) fn find_image(
+    template: &str,
+    screen_index: Option<usize>,
+    region: Option<(i32, i32, u32, u32)>,
+    threshold: f32,
+    scale: Option<f32>,
+    all: bool,
+) -> Result<Value, String> {
+    let template = decode_base64_image(template)?;
+    let frame = capture_frame(&CaptureArea::Screen {
+        index: screen_index,
+        region,
+        strict_bounds: false,
+    })?;
+
+    let scales = search_scales(scale, &frame)?;
+    let matches = TemplateSearch::new(&template, &scales).run(&frame.image, threshold);
+    Ok(match_result(&frame, &matches, threshold, &scales, all))
+}
+
+/// Upper bound on how long wait_for_image may block.
+pub(crate) const MAX_WAIT_FOR_IMAGE_TIMEOUT_MS: u64 = 120_000;
+
+/// Poll the area until the template appears or the timeout passes. Not
+/// finding it is an expected outcome, reported as `found: false` rather than
+/// an error. The prepared template and search buffers are reused across
+/// polls, and a region is captured on its own rather than cropped from a
+/// full-screen image.
+pub(crate) fn wait_for_image(
+    template: &str,
+    area: &CaptureArea,
+    threshold: f32,
+    scale: Option<f32>,
+    poll_interval: Duration,
+    timeout: Duration,
+) -> Result<Value, String> {
+    let start = Instant::now();
+    let template = decode_base64_image(template)?;
+    let mut frame = capture_frame(area)?;
+    let scales = search_scales(scale, &frame)?;
+    let mut search = TemplateSearch::new(&template, &scales);
+    let mut best_score = f32::MIN;
+    let mut polls = 0;
+
+    loop {
+        polls += 1;
+        let matches = search.run(&frame.image, threshold);
+        best_score = best_score.max(matches.first().map_or(f32::MIN, |m| m.score));
+        if matches.first().is_some_and(|m| m.score >= threshold) {
+            let mut result = match_result(&frame, &matches, threshold, &scales, false);
+            result["waited_ms"] = json!(start.elapsed().as_millis() as u64);
+            result["polls"] = json!(polls);
+            return Ok(result);
+        }
+
+        let remaining = timeout.saturating_sub(start.elapsed());
+        if remaining.is_zero() {
+            return Ok(json!({
+                "found": false,
+                "timed_out": true,
+                "waited_ms": start.elapsed().as_millis() as u64,
+                "polls": polls,
+                "best_score": best_score.max(0.0),
+                "threshold": threshold
+            }));
+        }
+        sleep_cancellable(poll_interval.min(remaining))?;
+        frame = capture_frame(area)?;
+    }
+}
@@ -0,0 +1,83 @@
+//! Timestamps on both the wall clock and the server's monotonic clock.
+
+use serde_json::{json, Value};
+use std::cell::Cell;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// A point in time on both the wall clock and the server's monotonic clock,
+/// so that results can be lined up against application logs.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Timestamp {
+    wall: SystemTime,
+    monotonic: Duration,
+}
+
+impl Timestamp {
+    pub(crate) fn now() -> Self {
+        let (_, started) = server_start();
+        Timestamp {
+            wall: SystemTime::now(),
+            monotonic: started.elapsed(),
+        }
+    }
+
+    /// The wall-clock time, formatted as by format_rfc3339.
+    pub(crate) fn rfc3339(self) -> String {
+        format_rfc3339(self.wall)
+    }
+
+    /// Monotonic time since this timestamp was taken.
+    pub(crate) fn elapsed(self) -> Duration {
+        server_start().1.elapsed().saturating_sub(self.monotonic)
+    }
+
+    pub(crate) fn to_json(self) -> Value {
+        json!({
+            "timestamp": format_rfc3339(self.wall),
+            "monotonic_ms": self.monotonic.as_millis() as u64
+        })
+    }
+}
+
+/// Wall-clock and monotonic time at server start. Monotonic times in
+/// results count from here.
+pub(crate) fn server_start() -> (SystemTime, Instant) {
+    static START: OnceLock<(SystemTime, Instant)> = OnceLock::new();
+    *START.get_or_init(|| (SystemTime::now(), Instant::now()))
+}
+
+/// Format a time as RFC 3339 in UTC with millisecond precision.
+pub(crate) fn format_rfc3339(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since_epoch.as_secs();
+    let (days, secs_of_day) = ((secs / 86400) as i64, secs % 86400);
+
+    // Civil date from days since the epoch (Howard Hinnant's algorithm)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        secs_of_day / 3600,
+        secs_of_day / 60 % 60,
+        secs_of_day % 60,
+        since_epoch.subsec_millis()
+    )
+}
+
+thread_local! {
+    /// Time of the first event simulated during the current tool call, which
+    /// runs on its own thread.
+    pub(crate) static FIRST_EVENT: Cell<Option<Timestamp>> = const { Cell::new(None) };
+}
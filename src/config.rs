@@ -0,0 +1,342 @@
+//! Defaults for capture and input arguments, and restrictions on screens and
+//! tools, read at startup from a TOML file: the one given with `--config`,
+//! or else `$XDG_CONFIG_HOME/desktop-mcp/config.toml` (by default
+//! `~/.config/desktop-mcp/config.toml`) if it exists.
+//!
+//! ```toml
+//! [capture]
+//! quality = 60
+//! max_width = 1920
+//! max_height = 1080
+//! format = "webp"
+//!
+//! [input]
+//! type_delay_ms = 10
+//! click_interval_ms = 80
+//! drag_duration_ms = 800
+//! keyboard_layout = "de"
+//!
+//! [safety]
+//! screen_indices = [0]
+//! allow_tools = ["screen_capture", "get_screen_info"]
+//! deny_tools = ["type_text"]
+//!
+//! [limits]
+//! max_events_per_second = 200
+//! max_type_text_length = 1000
+//! max_sequence_steps = 20
+//! max_key_hold_ms = 30000
+//!
+//! [failsafe]
+//! corner = "top-left"
+//! size = 5
+//! dwell_ms = 200
+//!
+//! [audit]
+//! path = "/var/log/desktop-mcp/audit.jsonl"
+//! max_bytes = 10485760
+//!
+//! [retry]
+//! attempts = 5
+//! backoff_ms = 50
+//!
+//! [timeouts]
+//! default_ms = 20000
+//! tools = { screen_capture = 10000, type_text = 120000 }
+//!
+//! [capture_files]
+//! dir = "/tmp/desktop-mcp"
+//! max_count = 50
+//! max_age_secs = 3600
+//!
+//! [baselines]
+//! dir = "/srv/desktop-mcp/baselines"
+//! ```
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+use crate::capture::baseline::baselines_dir;
+use crate::capture::save::capture_files_info;
+use crate::input::failsafe::Corner;
+use crate::tools::output::ToolError;
+use crate::tools::{find_tool, Tool};
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct Config {
+    capture: CaptureDefaults,
+    pub(crate) input: InputDefaults,
+    pub(crate) safety: Safety,
+    pub(crate) limits: Limits,
+    pub(crate) failsafe: Failsafe,
+    pub(crate) audit: Audit,
+    pub(crate) retry: Retry,
+    pub(crate) timeouts: Timeouts,
+    pub(crate) capture_files: CaptureFiles,
+    pub(crate) baselines: Baselines,
+}
+
+/// For screen_capture and estimate_capture.
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+struct CaptureDefaults {
+    quality: Option<u64>,
+    max_width: Option<u64>,
+    max_height: Option<u64>,
+    format: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct InputDefaults {
+    type_delay_ms: Option<u64>,
+    click_interval_ms: Option<u64>,
+    drag_duration_ms: Option<u64>,
+    /// Instead of the detected layout; see input/layout.rs.
+    pub(crate) keyboard_layout: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct Safety {
+    /// Only these screens may be captured or addressed by index.
+    pub(crate) screen_indices: Option<Vec<u64>>,
+    /// Below `--allow-tools` and `DESKTOP_MCP_ALLOW_TOOLS` in precedence.
+    pub(crate) allow_tools: Option<Vec<String>>,
+    /// Below `--deny-tools` and `DESKTOP_MCP_DENY_TOOLS` in precedence.
+    pub(crate) deny_tools: Option<Vec<String>>,
+    /// Refuse input tools while the session is locked (default: true).
+    /// Kiosks whose session reports itself locked while still taking input
+    /// turn this off.
+    pub(crate) check_session_lock: Option<bool>,
+}
+
+/// Limits on input, refused with `throttled`; see tools/limits.rs.
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct Limits {
+    /// Simulated events in any one second, across all calls.
+    pub(crate) max_events_per_second: Option<usize>,
+    /// Characters in one type_text, paste_text or set_text_field.
+    pub(crate) max_type_text_length: Option<usize>,
+    /// Steps in one run_sequence.
+    pub(crate) max_sequence_steps: Option<usize>,
+    /// Longest key_hold, in milliseconds.
+    pub(crate) max_key_hold_ms: Option<u64>,
+}
+
+/// The failsafe; see input/failsafe.rs.
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct Failsafe {
+    /// Off unless given.
+    pub(crate) corner: Option<Corner>,
+    /// Size of the corner region, in logical pixels.
+    pub(crate) size: Option<u32>,
+    /// How long the cursor must stay there.
+    pub(crate) dwell_ms: Option<u64>,
+}
+
+/// The audit log; see audit.rs.
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct Audit {
+    /// Used when `--audit-log` isn't given.
+    pub(crate) path: Option<PathBuf>,
+    /// Size at which the log is rotated.
+    pub(crate) max_bytes: Option<u64>,
+}
+
+/// Retrying captures and simulated events that fail; see retry.rs.
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct Retry {
+    /// Tries per capture or event, including the first.
+    pub(crate) attempts: Option<u32>,
+    /// Wait before the first retry, doubled for each one after.
+    pub(crate) backoff_ms: Option<u64>,
+}
+
+/// How long tool calls may run before they're given up on; see
+/// tools/timeout.rs.
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct Timeouts {
+    /// For tools that don't declare a longer one of their own.
+    pub(crate) default_ms: Option<u64>,
+    /// By tool name, over both the default and the tool's own.
+    pub(crate) tools: BTreeMap<String, u64>,
+}
+
+/// Captures saved with `save_to: "file"`; see capture/save.rs.
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct CaptureFiles {
+    /// Where they're written.
+    pub(crate) dir: Option<PathBuf>,
+    /// How many server-named files are kept.
+    pub(crate) max_count: Option<usize>,
+    /// How long server-named files are kept.
+    pub(crate) max_age_secs: Option<u64>,
+}
+
+/// Named baselines saved by baseline_save; see capture/baseline.rs.
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct Baselines {
+    /// Where they're kept, instead of `baselines` next to the config file.
+    pub(crate) dir: Option<PathBuf>,
+}
+
+/// Each config key, as section and name, with the tools and argument it
+/// supplies a default for.
+const DEFAULTS: &[(&str, &str, &[&str], &str)] = &[
+    ("capture", "quality", &["screen_capture", "estimate_capture"], "quality"),
+    ("capture", "max_width", &["screen_capture", "estimate_capture"], "max_width"),
+    ("capture", "max_height", &["screen_capture", "estimate_capture"], "max_height"),
+    ("capture", "format", &["screen_capture", "estimate_capture"], "format"),
+    ("input", "type_delay_ms", &["type_text"], "delay_ms"),
+    ("input", "click_interval_ms", &["mouse_click"], "interval_ms"),
+    ("input", "drag_duration_ms", &["mouse_drag"], "duration_ms"),
+    ("input", "keyboard_layout", &["type_text"], "layout"),
+];
+
+/// The config in effect and where it came from.
+#[derive(Debug, Default)]
+pub(crate) struct LoadedConfig {
+    /// The file read, if any.
+    pub(crate) path: Option<PathBuf>,
+    pub(crate) config: Config,
+}
+
+static CONFIG: OnceLock<LoadedConfig> = OnceLock::new();
+
+/// `$XDG_CONFIG_HOME/desktop-mcp`, or `~/.config/desktop-mcp`.
+pub(crate) fn config_dir() -> Option<PathBuf> {
+    let base = match std::env::var_os("XDG_CONFIG_HOME").filter(|v| !v.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+    };
+    Some(base.join("desktop-mcp"))
+}
+
+/// Where the config file is looked for when `--config` isn't given.
+fn default_config_path() -> Option<PathBuf> {
+    Some(config_dir()?.join("config.toml"))
+}
+
+/// Read the config file at `path`, or at the default location if None and
+/// there is one there. A file that can't be read or parsed is an error. Only
+/// the first call has any effect; without one, nothing is configured.
+pub fn load_config(path: Option<PathBuf>) -> Result<(), String> {
+    let path = match path {
+        Some(path) => path,
+        None => match default_config_path().filter(|p| p.is_file()) {
+            Some(path) => path,
+            None => {
+                let _ = CONFIG.set(LoadedConfig::default());
+                return Ok(());
+            }
+        },
+    };
+    let text = std::fs::read_to_string(&path)
+        .map_err(|e| format!("Failed to read config file {}: {}", path.display(), e))?;
+    let config: Config =
+        toml::from_str(&text).map_err(|e| format!("Invalid config file {}: {}", path.display(), e))?;
+    let _ = CONFIG.set(LoadedConfig {
+        path: Some(path),
+        config,
+    });
+    Ok(())
+}
+
+pub(crate) fn loaded_config() -> &'static LoadedConfig {
+    CONFIG.get_or_init(LoadedConfig::default)
+}
+
+/// Fill in the configured defaults for arguments the call left out, and
+/// check any screen it addresses against `screen_indices`. Capture tools
+/// called without a screen_index get the first allowed screen, unless they
+/// name a stored capture, whose screen was checked when it was taken.
+pub(crate) fn apply_config(tool: &dyn Tool, args: &Value) -> Result<Value, ToolError> {
+    let config = &loaded_config().config;
+    let mut args = args.clone();
+    let Some(map) = args.as_object_mut() else {
+        return Ok(args);
+    };
+
+    let values = serde_json::to_value(config).unwrap_or_default();
+    for (section, key, tools, arg) in DEFAULTS {
+        let value = &values[section][key];
+        if !value.is_null() && tools.contains(&tool.name()) && !map.contains_key(*arg) {
+            map.insert(arg.to_string(), value.clone());
+        }
+    }
+
+    if let Some(allowed) = &config.safety.screen_indices {
+        if map.get("coordinates").and_then(|v| v.as_str()) == Some("global") {
+            return Err(ToolError::PermissionDenied(
+                "Global coordinates are not allowed while the config file restricts screens".to_string(),
+            ));
+        }
+        match map.get("screen_index").and_then(|v| v.as_u64()) {
+            Some(index) if !allowed.contains(&index) => {
+                return Err(ToolError::PermissionDenied(format!(
+                    "Screen index {} is not allowed by the config file (allowed: {:?})",
+                    index, allowed
+                )));
+            }
+            Some(_) => {}
+            None if tool.schema()["properties"].get("screen_index").is_some() && !map.contains_key("capture_id") => {
+                let first = allowed
+                    .first()
+                    .ok_or_else(|| ToolError::PermissionDenied("No screens are allowed by the config file".to_string()))?;
+                map.insert("screen_index".to_string(), json!(first));
+            }
+            None => {}
+        }
+    }
+    Ok(args)
+}
+
+/// The defaults in effect, from the config file or else the tools' own, and
+/// the safety settings.
+pub(crate) fn effective_config() -> Value {
+    let loaded = loaded_config();
+    let values = serde_json::to_value(&loaded.config).unwrap_or_default();
+    let mut result = json!({
+        "path": loaded.path.as_ref().map(|p| p.display().to_string()),
+        "searched": default_config_path().map(|p| p.display().to_string()),
+        "capture": {},
+        "input": {},
+        "safety": values["safety"],
+        "limits": values["limits"],
+        "failsafe": values["failsafe"],
+        "audit": values["audit"],
+        "retry": values["retry"],
+        "timeouts": values["timeouts"],
+        "capture_files": capture_files_info(),
+        "baselines": { "dir": baselines_dir().ok().map(|dir| dir.display().to_string()) },
+        "from_config": []
+    });
+    for (section, key, tools, arg) in DEFAULTS {
+        let configured = &values[section][key];
+        let value = if configured.is_null() {
+            find_tool(tools[0])
+                .map(|tool| tool.schema()["properties"][arg]["default"].clone())
+                .unwrap_or(Value::Null)
+        } else {
+            result["from_config"]
+                .as_array_mut()
+                .unwrap()
+                .push(json!(format!("{}.{}", section, key)));
+            configured.clone()
+        };
+        result[section][key] = value;
+    }
+    result
+}
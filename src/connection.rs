@@ -0,0 +1,240 @@
+//! Newline-delimited JSON-RPC connections: stdio, and the sockets
+//! `--listen` accepts so that several local clients can share one server.
+//! Each connection is a session of its own.
+
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crate::cancel::{cancel_session, InFlight};
+use crate::dump::dump_received;
+use crate::http::token_matches;
+use crate::input::physical::release_session_input;
+use crate::protocol::{handle_request_catching_panics, parse_request};
+use crate::session::{enter_session, Session};
+
+/// Serve requests on stdin, answering on stdout, until stdin closes.
+pub fn serve_stdio() {
+    let session = Session::open(
+        "stdio",
+        Box::new(|json| {
+            let mut stdout = io::stdout().lock();
+            writeln!(stdout, "{}", json)?;
+            stdout.flush()
+        }),
+    );
+    serve_lines(io::stdin().lock(), session);
+}
+
+/// Answer newline-delimited requests from `reader` in `session` until it
+/// closes. Calls still running are then cancelled, and any keys and buttons
+/// the session left held are released.
+fn serve_lines(reader: impl BufRead, session: Arc<Session>) {
+    enter_session(Some(session.clone()));
+    for line in reader.lines() {
+        let line = match line {
+            Ok(l) => l,
+            // A line that isn't UTF-8; the next one may be fine
+            Err(e) if e.kind() == io::ErrorKind::InvalidData => {
+                log::error!("Error reading from {}: {}", session.label(), e);
+                continue;
+            }
+            // The client went away without reading everything
+            Err(e) if e.kind() == io::ErrorKind::ConnectionReset => break,
+            Err(e) => {
+                log::error!("Error reading from {}: {}", session.label(), e);
+                break;
+            }
+        };
+
+        if line.trim().is_empty() {
+            continue;
+        }
+        dump_received(session.label(), &line);
+
+        let request = match parse_request(&line) {
+            Ok(request) => request,
+            Err(response) => {
+                log::warn!("Rejected malformed message: {}", line);
+                session.send(&response);
+                continue;
+            }
+        };
+
+        // Tool calls run on their own threads so that a long one (a slow
+        // drag, a wait) doesn't hold up other requests; input tools still
+        // take turns through INPUT_LOCK, across sessions too. Responses may
+        // be written out of order, which is fine since they carry the
+        // request id. The call is registered for cancellation before its
+        // thread starts.
+        if request.method == "tools/call" {
+            let in_flight = request.id.as_ref().map(InFlight::register);
+            let session = session.clone();
+            thread::spawn(move || {
+                enter_session(Some(session.clone()));
+                if let Some(in_flight) = &in_flight {
+                    in_flight.enter();
+                }
+                if let Some(response) = handle_request_catching_panics(&request) {
+                    session.send(&response);
+                }
+            });
+        } else if let Some(response) = handle_request_catching_panics(&request) {
+            session.send(&response);
+        }
+    }
+
+    cancel_session(session.id());
+    let released = release_session_input(session.id());
+    if !released.is_empty() {
+        log::info!("Released {} left held by {}", released.join(", "), session.label());
+    }
+    enter_session(None);
+}
+
+/// How long a TCP client has to send the token after connecting.
+const TOKEN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Accept newline-delimited JSON-RPC connections on `spec`,
+/// `unix:<path>` or `tcp:<host>:<port>`, until the process exits. TCP is
+/// limited to loopback, and since any local user can reach that, each TCP
+/// client must first send `token` on a line of its own.
+pub fn serve_listen(spec: &str, token: Option<String>) -> Result<(), String> {
+    if let Some(path) = spec.strip_prefix("unix:") {
+        return listen_unix(path);
+    }
+    let Some(addr) = spec.strip_prefix("tcp:") else {
+        return Err(format!("--listen takes unix:<path> or tcp:<host>:<port>, not {}", spec));
+    };
+    let addr: SocketAddr = addr
+        .parse()
+        .map_err(|e| format!("Invalid --listen address {}: {}", addr, e))?;
+    if !addr.ip().is_loopback() {
+        return Err(format!(
+            "--listen tcp: only binds to loopback; use --http for other hosts, not {}",
+            addr
+        ));
+    }
+    let token: Arc<str> = token
+        .filter(|t| !t.is_empty())
+        .ok_or("--listen tcp: needs an auth token: set DESKTOP_MCP_LISTEN_TOKEN or pass --listen-token")?
+        .into();
+    let listener = TcpListener::bind(addr).map_err(|e| format!("Failed to listen on {}: {}", addr, e))?;
+    log::info!("Listening for connections on tcp:{}", addr);
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                let token = token.clone();
+                thread::spawn(move || serve_tcp(stream, &token));
+            }
+            Err(e) => log::warn!("Failed to accept connection: {}", e),
+        }
+    }
+    Ok(())
+}
+
+/// Serve a TCP connection once its first line has matched the token. A
+/// client that sends the wrong one, or nothing in time, is disconnected.
+fn serve_tcp(stream: TcpStream, token: &str) {
+    let peer = stream
+        .peer_addr()
+        .map_or_else(|_| "unknown peer".to_string(), |addr| addr.to_string());
+    let Ok(writer) = stream.try_clone() else {
+        return;
+    };
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    let _ = reader.get_ref().set_read_timeout(Some(TOKEN_TIMEOUT));
+    if reader.read_line(&mut line).is_err() || !token_matches(line.trim(), token) {
+        log::warn!("Refused connection from tcp:{}: missing or wrong token", peer);
+        return;
+    }
+    let _ = reader.get_ref().set_read_timeout(None);
+    serve_socket(format!("tcp:{}", peer), reader, writer);
+}
+
+/// Serve one accepted socket as a session, logging when it opens and
+/// closes.
+fn serve_socket(label: String, reader: impl BufRead, writer: impl Write + Send + 'static) {
+    log::info!("Connection from {}", label);
+    let writer = Mutex::new(writer);
+    let session = Session::open(
+        label.clone(),
+        Box::new(move |json| {
+            let mut writer = writer.lock().unwrap();
+            writeln!(writer, "{}", json)?;
+            writer.flush()
+        }),
+    );
+    serve_lines(reader, session);
+    log::info!("Connection from {} closed", label);
+}
+
+/// Bind a Unix socket at `path` that only this user can connect to. It's
+/// bound inside a directory only this user can enter and restricted there,
+/// then moved into place, so there's no moment when others could connect.
+#[cfg(unix)]
+fn bind_private(path: &std::path::Path) -> Result<std::os::unix::net::UnixListener, String> {
+    use std::fs::{DirBuilder, Permissions};
+    use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+    use std::os::unix::net::UnixListener;
+
+    let name = path.file_name().ok_or_else(|| format!("{} isn't a file path", path.display()))?;
+    let dir = path
+        .with_file_name(format!(".{}.{}", name.to_string_lossy(), std::process::id()));
+    DirBuilder::new()
+        .mode(0o700)
+        .create(&dir)
+        .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    let staged = dir.join("socket");
+    let bound = (|| {
+        let listener =
+            UnixListener::bind(&staged).map_err(|e| format!("Failed to listen on {}: {}", path.display(), e))?;
+        std::fs::set_permissions(&staged, Permissions::from_mode(0o600))
+            .map_err(|e| format!("Failed to restrict {}: {}", path.display(), e))?;
+        std::fs::rename(&staged, path).map_err(|e| format!("Failed to move socket to {}: {}", path.display(), e))?;
+        Ok(listener)
+    })();
+    let _ = std::fs::remove_file(&staged);
+    let _ = std::fs::remove_dir(&dir);
+    bound
+}
+
+#[cfg(unix)]
+fn listen_unix(path: &str) -> Result<(), String> {
+    use std::os::unix::net::UnixStream;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    // A socket left by a server that has exited is replaced; a live one
+    // isn't
+    if std::path::Path::new(path).exists() {
+        if UnixStream::connect(path).is_ok() {
+            return Err(format!("{} is already in use by another server", path));
+        }
+        std::fs::remove_file(path).map_err(|e| format!("Failed to remove stale socket {}: {}", path, e))?;
+    }
+    let listener = bind_private(std::path::Path::new(path))?;
+    log::info!("Listening for connections on unix:{}", path);
+
+    static NEXT: AtomicU64 = AtomicU64::new(1);
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                let label = format!("unix:{} #{}", path, NEXT.fetch_add(1, Ordering::SeqCst));
+                let Ok(writer) = stream.try_clone() else {
+                    continue;
+                };
+                thread::spawn(move || serve_socket(label, BufReader::new(stream), writer));
+            }
+            Err(e) => log::warn!("Failed to accept connection: {}", e),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn listen_unix(_path: &str) -> Result<(), String> {
+    Err("--listen unix: needs Unix domain sockets, which this platform lacks; use tcp:127.0.0.1:<port>".to_string())
+}
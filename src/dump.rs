@@ -0,0 +1,65 @@
+//! `--dump-protocol`: every message received and sent, verbatim, for
+//! debugging clients. Unlike the log, base64 payloads are written in full,
+//! since that's often where interop bugs are, so the file is readable only
+//! by its owner.
+
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use crate::clock::format_rfc3339;
+
+static DUMP: Mutex<Option<File>> = Mutex::new(None);
+
+/// Append every message to the file at `path` from now on, one per line:
+/// a timestamp, `<-` for received or `->` for sent, the peer, then the
+/// message as it was read or written.
+pub fn open_protocol_dump(path: &Path) -> Result<(), String> {
+    let mut options = OpenOptions::new();
+    options.create(true).append(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let file = options
+        .open(path)
+        .map_err(|e| format!("Failed to open protocol dump {}: {}", path.display(), e))?;
+    // The mode only applies to a new file; tighten an existing one too
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        file.set_permissions(std::fs::Permissions::from_mode(0o600))
+            .map_err(|e| format!("Failed to restrict protocol dump {}: {}", path.display(), e))?;
+    }
+    *DUMP.lock().unwrap() = Some(file);
+    log::warn!(
+        "Dumping protocol messages to {}, including screenshots in full",
+        path.display()
+    );
+    Ok(())
+}
+
+/// Record a message received from `peer`.
+pub(crate) fn dump_received(peer: &str, message: &str) {
+    dump("<-", peer, message);
+}
+
+/// Record a message sent to `peer`.
+pub(crate) fn dump_sent(peer: &str, message: &str) {
+    dump("->", peer, message);
+}
+
+fn dump(direction: &str, peer: &str, message: &str) {
+    let mut dump = DUMP.lock().unwrap();
+    let Some(file) = dump.as_mut() else {
+        return;
+    };
+    let line = format!("{} {} {} {}\n", format_rfc3339(SystemTime::now()), direction, peer, message);
+    // The whole line in one write, so lines from concurrent calls don't mix
+    if let Err(e) = file.write_all(line.as_bytes()) {
+        log::warn!("Failed to write protocol dump: {}", e);
+    }
+}
@@ -0,0 +1,610 @@
+//! The Streamable HTTP transport, for clients that can't spawn the server,
+//! e.g. one inside a container or VM. Every message goes to one endpoint,
+//! `/mcp`: requests are POSTed and answered with JSON, or with an SSE
+//! stream for tool calls when the client accepts one, and a GET opens an
+//! SSE stream for server notifications. Every request must carry the
+//! bearer token, since whoever holds it controls the desktop.
+//!
+//! The transport has one session at a time: initialize starts a new one,
+//! replacing any other, and later requests must name it in
+//! `Mcp-Session-Id`. Sessions on other transports are unaffected.
+
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::cell::RefCell;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread;
+use std::time::Duration;
+
+use crate::cancel::{cancel_session, InFlight};
+use crate::dump::{dump_received, dump_sent};
+use crate::input::physical::release_session_input;
+use crate::protocol::{handle_request_catching_panics, parse_request};
+use crate::session::{enter_session, Session};
+
+/// The one path the transport answers on.
+const ENDPOINT: &str = "/mcp";
+
+/// Largest request body accepted, enough for find_image templates.
+const MAX_BODY_BYTES: usize = 32 * 1024 * 1024;
+
+/// Largest request line plus headers accepted.
+const MAX_HEADER_BYTES: usize = 16 * 1024;
+
+/// Most connections served at once; more are refused with 503. SSE streams
+/// each hold one.
+const MAX_CONNECTIONS: usize = 32;
+
+/// How long an idle keep-alive connection is held open.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// How often an SSE stream with nothing to send gets a comment, so that
+/// proxies don't close it.
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Port used when `--http` names only a host.
+const DEFAULT_PORT: u16 = 8931;
+
+/// The bearer token every request must carry.
+static TOKEN: OnceLock<String> = OnceLock::new();
+
+/// Connections being served.
+static CONNECTIONS: AtomicUsize = AtomicUsize::new(0);
+
+/// The current session and its id, once a client has initialized.
+static SESSION: Mutex<Option<(String, Arc<Session>)>> = Mutex::new(None);
+
+/// The current session's open GET streams, which server notifications are
+/// sent to.
+static STREAMS: Mutex<Vec<Sender<String>>> = Mutex::new(Vec::new());
+
+thread_local! {
+    /// The SSE stream of the tool call running on this thread, which
+    /// notifications it causes are sent on.
+    static CALL_STREAM: RefCell<Option<Sender<String>>> = const { RefCell::new(None) };
+}
+
+/// The SSE stream of the tool call running on this thread, for handing the
+/// call to another thread with enter_call_stream.
+pub(crate) fn call_stream() -> Option<Sender<String>> {
+    CALL_STREAM.with(|stream| stream.borrow().clone())
+}
+
+pub(crate) fn enter_call_stream(stream: Option<Sender<String>>) {
+    CALL_STREAM.with(|s| *s.borrow_mut() = stream);
+}
+
+/// Where `--http` binds: "port" or "host:port". A bare port or a missing
+/// port binds to localhost.
+fn parse_addr(addr: &str) -> Result<SocketAddr, String> {
+    if let Ok(port) = addr.parse::<u16>() {
+        return Ok(SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), port));
+    }
+    if let Ok(ip) = addr.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>() {
+        return Ok(SocketAddr::new(ip, DEFAULT_PORT));
+    }
+    let with_port = if addr.contains(':') {
+        addr.to_string()
+    } else {
+        format!("{}:{}", addr, DEFAULT_PORT)
+    };
+    with_port
+        .to_socket_addrs()
+        .map_err(|e| format!("Invalid --http address {}: {}", addr, e))?
+        .next()
+        .ok_or_else(|| format!("Invalid --http address {}: no addresses", addr))
+}
+
+/// Serve MCP over HTTP on `addr` until the process exits, requiring
+/// `token` on every request. Fails if there's no token or the address
+/// can't be bound.
+pub fn serve_http(addr: &str, token: Option<String>) -> Result<(), String> {
+    let token = token.filter(|t| !t.is_empty()).ok_or(
+        "--http needs an auth token: set DESKTOP_MCP_HTTP_TOKEN or pass --http-token",
+    )?;
+    let addr = parse_addr(addr)?;
+    let listener = TcpListener::bind(addr).map_err(|e| format!("Failed to listen on {}: {}", addr, e))?;
+    let _ = TOKEN.set(token);
+
+    log::info!("Serving MCP over HTTP at http://{}{}", addr, ENDPOINT);
+    if !addr.ip().is_loopback() {
+        log::warn!(
+            "Listening on {}, which isn't loopback: anyone who can reach it and has the token controls this desktop",
+            addr.ip()
+        );
+    }
+
+    for stream in listener.incoming() {
+        match stream {
+            Ok(mut stream) => match ConnectionSlot::take() {
+                Some(slot) => {
+                    thread::spawn(move || {
+                        serve_connection(stream);
+                        drop(slot);
+                    });
+                }
+                None => {
+                    log::warn!("Refused HTTP connection: already serving {}", MAX_CONNECTIONS);
+                    let _ = Response::error(503, "Too many connections").write_to(&mut stream, false);
+                }
+            },
+            Err(e) => log::warn!("Failed to accept HTTP connection: {}", e),
+        }
+    }
+    Ok(())
+}
+
+/// A place among the MAX_CONNECTIONS served at once, given up when dropped.
+struct ConnectionSlot;
+
+impl ConnectionSlot {
+    fn take() -> Option<Self> {
+        CONNECTIONS
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| (n < MAX_CONNECTIONS).then_some(n + 1))
+            .ok()
+            .map(|_| ConnectionSlot)
+    }
+}
+
+impl Drop for ConnectionSlot {
+    fn drop(&mut self) {
+        CONNECTIONS.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Write a message for the HTTP session with this id: on the SSE stream of
+/// the tool call that caused it, or else, while it's the current session,
+/// to every open GET stream. Messages with no stream to go to are dropped,
+/// as the transport allows.
+fn send_to_streams(session_id: &str, message: &str) -> io::Result<()> {
+    let sent_on_call = CALL_STREAM.with(|stream| {
+        stream
+            .borrow()
+            .as_ref()
+            .is_some_and(|stream| stream.send(message.to_string()).is_ok())
+    });
+    let current = SESSION.lock().unwrap().as_ref().is_some_and(|(id, _)| id == session_id);
+    if !sent_on_call && current {
+        STREAMS.lock().unwrap().retain(|stream| stream.send(message.to_string()).is_ok());
+    }
+    Ok(())
+}
+
+/// One parsed HTTP request.
+struct Request {
+    method: String,
+    path: String,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl Request {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn accepts_event_stream(&self) -> bool {
+        self.header("Accept").is_some_and(|accept| accept.contains("text/event-stream"))
+    }
+
+    fn keep_alive(&self) -> bool {
+        !self.header("Connection").is_some_and(|c| c.eq_ignore_ascii_case("close"))
+    }
+}
+
+/// A response to write. SSE responses are written separately.
+struct Response {
+    status: u16,
+    headers: Vec<(&'static str, String)>,
+    body: String,
+}
+
+impl Response {
+    fn new(status: u16) -> Self {
+        Response {
+            status,
+            headers: Vec::new(),
+            body: String::new(),
+        }
+    }
+
+    fn json(status: u16, body: String) -> Self {
+        Response::new(status).header("Content-Type", "application/json").body(body)
+    }
+
+    /// A plain-text error, logged at debug level.
+    fn error(status: u16, message: impl Into<String>) -> Self {
+        let message = message.into();
+        log::debug!("HTTP {}: {}", status, message);
+        Response::new(status).header("Content-Type", "text/plain; charset=utf-8").body(message)
+    }
+
+    fn header(mut self, name: &'static str, value: impl Into<String>) -> Self {
+        self.headers.push((name, value.into()));
+        self
+    }
+
+    fn body(mut self, body: String) -> Self {
+        self.body = body;
+        self
+    }
+
+    fn write_to(&self, stream: &mut TcpStream, keep_alive: bool) -> io::Result<()> {
+        let mut head = format!("HTTP/1.1 {} {}\r\n", self.status, reason_phrase(self.status));
+        for (name, value) in &self.headers {
+            head.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        head.push_str(&format!("Content-Length: {}\r\n", self.body.len()));
+        head.push_str(if keep_alive { "Connection: keep-alive\r\n" } else { "Connection: close\r\n" });
+        head.push_str("\r\n");
+        stream.write_all(head.as_bytes())?;
+        stream.write_all(self.body.as_bytes())?;
+        stream.flush()
+    }
+}
+
+fn reason_phrase(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        202 => "Accepted",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        411 => "Length Required",
+        413 => "Payload Too Large",
+        431 => "Request Header Fields Too Large",
+        503 => "Service Unavailable",
+        _ => "Internal Server Error",
+    }
+}
+
+/// What to do with a request once it's been checked.
+enum Reply {
+    Plain(Response),
+    /// An SSE stream fed by the receiver until its senders are dropped.
+    Stream(Receiver<String>),
+}
+
+/// Answer requests on one connection until it's closed, idle for too long
+/// or handed over to an SSE stream.
+fn serve_connection(stream: TcpStream) {
+    let peer = stream
+        .peer_addr()
+        .map_or_else(|_| "unknown peer".to_string(), |addr| addr.to_string());
+    log::info!("HTTP connection from {}", peer);
+    let _ = stream.set_read_timeout(Some(IDLE_TIMEOUT));
+    let Ok(mut writer) = stream.try_clone() else {
+        return;
+    };
+    let mut reader = BufReader::new(stream);
+    loop {
+        let mut request = match read_head(&mut reader) {
+            Ok(Some(request)) => request,
+            Ok(None) => break,
+            Err(response) => {
+                let _ = response.write_to(&mut writer, false);
+                break;
+            }
+        };
+        // Refused before the body is read, so that a client without the
+        // token can't make the server buffer one
+        let checked = check_access(&request, &peer).and_then(|()| read_body(&mut reader, &mut request));
+        if let Err(response) = checked {
+            let _ = response.write_to(&mut writer, false);
+            break;
+        }
+        let keep_alive = request.keep_alive();
+        let result = match handle(&request, &peer) {
+            Reply::Plain(response) => response.write_to(&mut writer, keep_alive),
+            Reply::Stream(receiver) => {
+                stream_events(&mut writer, receiver);
+                break;
+            }
+        };
+        if result.is_err() || !keep_alive {
+            break;
+        }
+    }
+    log::debug!("HTTP connection from {} closed", peer);
+}
+
+/// Read one request's line and headers, leaving its body to read_body.
+/// None when the client closed the connection or went idle before sending
+/// anything; an error response if it's malformed.
+fn read_head(reader: &mut BufReader<TcpStream>) -> Result<Option<Request>, Response> {
+    let mut head = Vec::new();
+    loop {
+        let mut line = String::new();
+        match reader.by_ref().take(MAX_HEADER_BYTES as u64).read_line(&mut line) {
+            Ok(0) if head.is_empty() => return Ok(None),
+            Ok(0) => return Err(Response::error(400, "Incomplete request")),
+            Ok(_) => {}
+            Err(_) if head.is_empty() => return Ok(None),
+            Err(e) => return Err(Response::error(400, format!("Failed to read request: {}", e))),
+        }
+        if !line.ends_with('\n') {
+            return Err(Response::error(431, "Request line or header too long"));
+        }
+        let line = line.trim_end_matches(['\r', '\n']).to_string();
+        if line.is_empty() {
+            if head.is_empty() {
+                // Tolerate blank lines between requests
+                continue;
+            }
+            break;
+        }
+        head.push(line);
+        if head.iter().map(|l| l.len()).sum::<usize>() > MAX_HEADER_BYTES {
+            return Err(Response::error(431, "Headers too long"));
+        }
+    }
+
+    let mut parts = head[0].split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        return Err(Response::error(400, "Malformed request line"));
+    };
+    let path = target.split('?').next().unwrap_or_default().to_string();
+    let headers: Vec<(String, String)> = head[1..]
+        .iter()
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+        .collect();
+    Ok(Some(Request {
+        method: method.to_string(),
+        path,
+        headers,
+        body: Vec::new(),
+    }))
+}
+
+/// Read the body its Content-Length announces into `request`.
+fn read_body(reader: &mut BufReader<TcpStream>, request: &mut Request) -> Result<(), Response> {
+    if request.header("Transfer-Encoding").is_some() {
+        return Err(Response::error(411, "Chunked request bodies aren't supported; send Content-Length"));
+    }
+    let length = match request.header("Content-Length") {
+        Some(length) => length
+            .parse::<usize>()
+            .map_err(|_| Response::error(400, "Invalid Content-Length"))?,
+        None => 0,
+    };
+    if length > MAX_BODY_BYTES {
+        return Err(Response::error(413, format!("Request body over {} bytes", MAX_BODY_BYTES)));
+    }
+    request.body = vec![0; length];
+    reader
+        .read_exact(&mut request.body)
+        .map_err(|e| Response::error(400, format!("Failed to read request body: {}", e)))
+}
+
+/// Check a request's path, origin and token from its headers alone.
+fn check_access(request: &Request, peer: &str) -> Result<(), Response> {
+    if request.path != ENDPOINT {
+        return Err(Response::error(404, format!("Not found; the MCP endpoint is {}", ENDPOINT)));
+    }
+    // Browsers send Origin; refuse pages that aren't local, against DNS
+    // rebinding
+    if let Some(origin) = request.header("Origin") {
+        if !local_origin(origin) {
+            log::warn!("Refused HTTP request from {} with origin {}", peer, origin);
+            return Err(Response::error(403, "Origin not allowed"));
+        }
+    }
+    if !authorized(request) {
+        log::warn!("Refused HTTP request from {}: missing or wrong token", peer);
+        return Err(Response::error(401, "Missing or wrong bearer token").header("WWW-Authenticate", "Bearer"));
+    }
+    Ok(())
+}
+
+/// Check a request's session, then act on it.
+fn handle(request: &Request, peer: &str) -> Reply {
+    match request.method.as_str() {
+        "POST" => handle_post(request, peer),
+        "GET" => {
+            if let Err(response) = check_session(request) {
+                return Reply::Plain(response);
+            }
+            if !request.accepts_event_stream() {
+                return Reply::Plain(Response::error(405, "GET opens an SSE stream; accept text/event-stream"));
+            }
+            let (sender, receiver) = mpsc::channel();
+            STREAMS.lock().unwrap().push(sender);
+            Reply::Stream(receiver)
+        }
+        "DELETE" => {
+            if let Err(response) = check_session(request) {
+                return Reply::Plain(response);
+            }
+            log::info!("HTTP session ended by {}", peer);
+            replace_session(None);
+            Reply::Plain(Response::new(200))
+        }
+        _ => Reply::Plain(Response::error(405, "Use POST, GET or DELETE").header("Allow", "GET, POST, DELETE")),
+    }
+}
+
+/// Whether an Origin header names this machine.
+fn local_origin(origin: &str) -> bool {
+    let host = origin.split("://").nth(1).unwrap_or(origin);
+    let host = match host.strip_prefix('[') {
+        Some(rest) => rest.split(']').next().unwrap_or_default(),
+        None => host.split(':').next().unwrap_or_default(),
+    };
+    host.eq_ignore_ascii_case("localhost") || host.parse::<IpAddr>().is_ok_and(|ip| ip.is_loopback())
+}
+
+/// Whether the request carries the token, compared without an early exit.
+fn authorized(request: &Request) -> bool {
+    let Some(token) = TOKEN.get() else {
+        return false;
+    };
+    let given = request
+        .header("Authorization")
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .unwrap_or_default()
+        .trim();
+    token_matches(given, token)
+}
+
+/// Whether `given` is `token`, compared without an early exit.
+pub(crate) fn token_matches(given: &str, token: &str) -> bool {
+    // Comparing digests keeps the comparison's length fixed
+    let (given, expected) = (Sha256::digest(given.as_bytes()), Sha256::digest(token.as_bytes()));
+    given.iter().zip(expected.iter()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+/// Check the request names the current session, if there is one, and
+/// return it.
+fn check_session(request: &Request) -> Result<Option<Arc<Session>>, Response> {
+    let current = SESSION.lock().unwrap();
+    let Some((id, session)) = current.as_ref() else {
+        return Ok(None);
+    };
+    match request.header("Mcp-Session-Id") {
+        None => Err(Response::error(400, "Missing Mcp-Session-Id")),
+        Some(given) if given == id => Ok(Some(session.clone())),
+        Some(_) => Err(Response::error(404, "Unknown session; initialize again")),
+    }
+}
+
+/// End the current session, if any, and make `next` current. The old
+/// session's calls are cancelled, what it held is released and its
+/// streams are closed.
+fn replace_session(next: Option<(String, Arc<Session>)>) {
+    let previous = std::mem::replace(&mut *SESSION.lock().unwrap(), next);
+    STREAMS.lock().unwrap().clear();
+    if let Some((_, session)) = previous {
+        cancel_session(session.id());
+        let released = release_session_input(session.id());
+        if !released.is_empty() {
+            log::info!("Released {} left held by {}", released.join(", "), session.label());
+        }
+    }
+}
+
+/// A new session id: 128 bits from the OS's random number generator, as
+/// hex. Knowing the id is enough to use the session, so it mustn't be
+/// derivable from anything else on the host.
+fn new_session_id() -> Result<String, String> {
+    let mut bytes = [0u8; 16];
+    getrandom::fill(&mut bytes).map_err(|e| format!("Couldn't generate a session id: {}", e))?;
+    Ok(bytes.iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+/// A POSTed JSON-RPC message: a request is answered, in an SSE stream if
+/// it's a tool call and the client accepts one; notifications and
+/// responses are acknowledged with 202.
+fn handle_post(request: &Request, peer: &str) -> Reply {
+    let body = String::from_utf8_lossy(&request.body);
+    let label = format!("http:{}", peer);
+    dump_received(&label, &body);
+    let rpc = match parse_request(&body) {
+        Ok(rpc) => rpc,
+        Err(response) => {
+            // A response to a server request also lands here; the server
+            // sends none, so there's nothing to do with it
+            let value: Value = serde_json::from_str(&body).unwrap_or_default();
+            if value.get("result").is_some() || value.get("error").is_some() {
+                return Reply::Plain(Response::new(202));
+            }
+            let json = serde_json::to_string(&response).unwrap();
+            dump_sent(&label, &json);
+            return Reply::Plain(Response::json(400, json));
+        }
+    };
+
+    // initialize starts a session, which becomes current once it succeeds
+    let (session, new_id) = if rpc.method == "initialize" {
+        let id = match new_session_id() {
+            Ok(id) => id,
+            Err(e) => {
+                log::error!("{}", e);
+                return Reply::Plain(Response::new(500));
+            }
+        };
+        let stream_id = id.clone();
+        let session = Session::open(
+            label.clone(),
+            Box::new(move |message| send_to_streams(&stream_id, message)),
+        );
+        (Some(session), Some(id))
+    } else {
+        match check_session(request) {
+            Ok(session) => (session, None),
+            Err(response) => return Reply::Plain(response),
+        }
+    };
+    enter_session(session.clone());
+
+    if rpc.method == "tools/call" && rpc.id.is_some() && request.accepts_event_stream() {
+        let (sender, receiver) = mpsc::channel();
+        let in_flight = rpc.id.as_ref().map(InFlight::register);
+        thread::spawn(move || {
+            enter_session(session);
+            if let Some(in_flight) = &in_flight {
+                in_flight.enter();
+            }
+            CALL_STREAM.with(|stream| *stream.borrow_mut() = Some(sender.clone()));
+            if let Some(response) = handle_request_catching_panics(&rpc) {
+                let json = serde_json::to_string(&response).unwrap();
+                dump_sent(&label, &json);
+                let _ = sender.send(json);
+            }
+            CALL_STREAM.with(|stream| stream.borrow_mut().take());
+        });
+        return Reply::Stream(receiver);
+    }
+
+    let in_flight = match rpc.method.as_str() {
+        "tools/call" => rpc.id.as_ref().map(InFlight::register),
+        _ => None,
+    };
+    if let Some(in_flight) = &in_flight {
+        in_flight.enter();
+    }
+    let response = handle_request_catching_panics(&rpc);
+    drop(in_flight);
+    enter_session(None);
+    let Some(response) = response else {
+        return Reply::Plain(Response::new(202));
+    };
+
+    let json = serde_json::to_string(&response).unwrap();
+    dump_sent(&label, &json);
+    let mut reply = Response::json(200, json);
+    if let (Some(id), Some(session)) = (new_id, session) {
+        log::info!("HTTP session {} started", id);
+        reply = reply.header("Mcp-Session-Id", id.clone());
+        replace_session(Some((id, session)));
+    }
+    Reply::Plain(reply)
+}
+
+/// Write each message from `receiver` as an SSE event, with keep-alive
+/// comments between, until the senders are gone or the client leaves.
+fn stream_events(writer: &mut TcpStream, receiver: Receiver<String>) {
+    let head = "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\nConnection: close\r\n\r\n";
+    if writer.write_all(head.as_bytes()).and_then(|_| writer.flush()).is_err() {
+        return;
+    }
+    loop {
+        let event = match receiver.recv_timeout(KEEPALIVE_INTERVAL) {
+            Ok(message) => format!("event: message\ndata: {}\n\n", message),
+            Err(RecvTimeoutError::Timeout) => ": keepalive\n\n".to_string(),
+            Err(RecvTimeoutError::Disconnected) => return,
+        };
+        if writer.write_all(event.as_bytes()).and_then(|_| writer.flush()).is_err() {
+            return;
+        }
+    }
+}
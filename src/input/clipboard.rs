@@ -0,0 +1,47 @@
+//! Reading and writing the clipboard as text.
+
+#[cfg(target_os = "macos")]
+use std::io::Write;
+
+#[cfg(target_os = "macos")]
+pub(crate) fn clipboard_read() -> Result<String, String> {
+    let output = std::process::Command::new("pbpaste")
+        .output()
+        .map_err(|e| format!("Failed to run pbpaste: {}", e))?;
+    if !output.status.success() {
+        return Err(format!("pbpaste failed: {}", output.status));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+#[cfg(target_os = "macos")]
+pub(crate) fn clipboard_write(text: &str) -> Result<(), String> {
+    use std::process::{Command, Stdio};
+
+    let mut child = Command::new("pbcopy")
+        .stdin(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Failed to run pbcopy: {}", e))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin
+            .write_all(text.as_bytes())
+            .map_err(|e| format!("Failed to write to pbcopy: {}", e))?;
+    }
+    let status = child
+        .wait()
+        .map_err(|e| format!("Failed to wait for pbcopy: {}", e))?;
+    if !status.success() {
+        return Err(format!("pbcopy failed: {}", status));
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "macos"))]
+pub(crate) fn clipboard_read() -> Result<String, String> {
+    Err("Clipboard access is only supported on macOS".to_string())
+}
+
+#[cfg(not(target_os = "macos"))]
+pub(crate) fn clipboard_write(_text: &str) -> Result<(), String> {
+    Err("Clipboard access is only supported on macOS".to_string())
+}
@@ -0,0 +1,136 @@
+//! The failsafe, as in PyAutoGUI: holding the real cursor in a corner of
+//! any screen stops the automation. Calls in flight are cancelled and fail
+//! with `failsafe_triggered`, and input tools are refused until
+//! failsafe_reset. Only physical movement counts; the physical input
+//! listener filters out the events the server simulates itself.
+
+use rdev::EventType;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::backend::{screen_backend, Display};
+use crate::cancel::cancel_all;
+use crate::config::loaded_config;
+use crate::input::physical::{listener_error, subscribe_physical_input};
+
+/// Default size of the corner region, in logical pixels.
+const DEFAULT_SIZE: f64 = 5.0;
+
+/// Default time the cursor must stay in the corner.
+const DEFAULT_DWELL_MS: u64 = 200;
+
+/// How long the screen layout is trusted before it's enumerated again.
+const DISPLAY_REFRESH: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum Corner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+impl Corner {
+    fn name(self) -> &'static str {
+        match self {
+            Corner::TopLeft => "top-left",
+            Corner::TopRight => "top-right",
+            Corner::BottomLeft => "bottom-left",
+            Corner::BottomRight => "bottom-right",
+        }
+    }
+}
+
+static TRIGGERED: AtomicBool = AtomicBool::new(false);
+
+/// Whether the failsafe has gone off and not yet been reset.
+pub(crate) fn failsafe_triggered() -> bool {
+    TRIGGERED.load(Ordering::SeqCst)
+}
+
+/// Allow input tools again. Returns whether the failsafe had gone off.
+pub(crate) fn reset_failsafe() -> bool {
+    TRIGGERED.swap(false, Ordering::SeqCst)
+}
+
+/// Start watching the cursor, if the config file's `[failsafe]` section
+/// names a corner.
+pub fn start_failsafe() {
+    let config = &loaded_config().config.failsafe;
+    let Some(corner) = config.corner else {
+        return;
+    };
+    let size = config.size.map_or(DEFAULT_SIZE, |s| s as f64);
+    let dwell = Duration::from_millis(config.dwell_ms.unwrap_or(DEFAULT_DWELL_MS));
+    match subscribe_physical_input() {
+        Ok(rx) => {
+            log::info!("Failsafe: hold the cursor in the {} corner of a screen to stop", corner.name());
+            thread::spawn(move || watch(rx, corner, size, dwell));
+        }
+        Err(e) => log::warn!("Failsafe disabled: {}", e),
+    }
+}
+
+/// Whether a global logical point is within `size` of `corner` of `display`.
+fn in_corner(display: &Display, corner: Corner, size: f64, x: f64, y: f64) -> bool {
+    let (left, top) = (x - display.x as f64, y - display.y as f64);
+    let (width, height) = (display.width as f64, display.height as f64);
+    if left < 0.0 || top < 0.0 || left >= width || top >= height {
+        return false;
+    }
+    let near_x = match corner {
+        Corner::TopLeft | Corner::BottomLeft => left < size,
+        Corner::TopRight | Corner::BottomRight => left >= width - size,
+    };
+    let near_y = match corner {
+        Corner::TopLeft | Corner::TopRight => top < size,
+        Corner::BottomLeft | Corner::BottomRight => top >= height - size,
+    };
+    near_x && near_y
+}
+
+/// Follow real mouse movement, going off once the cursor has stayed in the
+/// corner for `dwell`. The cursor usually stops dead there, so the wait for
+/// the next event is cut short at the deadline.
+fn watch(rx: Receiver<EventType>, corner: Corner, size: f64, dwell: Duration) {
+    let mut displays: Vec<Display> = Vec::new();
+    let mut refreshed: Option<Instant> = None;
+    let mut entered: Option<Instant> = None;
+    loop {
+        let event = match entered {
+            Some(at) => match rx.recv_timeout(dwell.saturating_sub(at.elapsed())) {
+                Ok(event) => Some(event),
+                Err(RecvTimeoutError::Timeout) => None,
+                Err(RecvTimeoutError::Disconnected) => break,
+            },
+            None => match rx.recv() {
+                Ok(event) => Some(event),
+                Err(_) => break,
+            },
+        };
+
+        if let Some(EventType::MouseMove { x, y }) = event {
+            if refreshed.is_none_or(|at| at.elapsed() > DISPLAY_REFRESH) {
+                displays = screen_backend().displays().unwrap_or_default();
+                refreshed = Some(Instant::now());
+            }
+            let inside = displays.iter().any(|d| in_corner(d, corner, size, x, y));
+            entered = if inside { entered.or(Some(Instant::now())) } else { None };
+        }
+
+        if entered.is_some_and(|at| at.elapsed() >= dwell) {
+            if !TRIGGERED.swap(true, Ordering::SeqCst) {
+                log::warn!("Failsafe triggered; input tools are refused until failsafe_reset");
+            }
+            cancel_all();
+            // Wait for the cursor to move again rather than spinning on a
+            // zero timeout
+            entered = None;
+        }
+    }
+    log::warn!("Failsafe stopped: {}", listener_error());
+}
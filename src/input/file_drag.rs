@@ -0,0 +1,134 @@
+//! Dragging a file onto a drop target, as if from Finder. Simulated mouse
+//! events alone can't start a drag that carries a file, so on macOS a small
+//! helper (JavaScript for Automation, run by `osascript`) puts up a window
+//! under the drag's start point that begins a native dragging session with
+//! the file when it's pressed; the drag itself is then simulated as usual.
+
+use std::path::Path;
+
+use crate::input::DragTiming;
+
+/// Drag `path` from `from` to `to`, dropping it there. The file must
+/// already have been checked to exist. A dry run simulates the drag
+/// without starting the helper.
+#[cfg(target_os = "macos")]
+pub(crate) fn do_drag_file(path: &Path, from: (f64, f64), to: (f64, f64), timing: DragTiming) -> Result<(), String> {
+    use std::io::{BufRead, BufReader};
+    use std::process::{Child, Command, Stdio};
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::Duration;
+
+    use rdev::Button;
+
+    use crate::backend::displays;
+    use crate::cancel::sleep_cancellable;
+    use crate::input::do_mouse_drag;
+    use crate::tools::limits::dry_run;
+
+    /// How long the helper gets to put its window up; starting osascript
+    /// and loading AppKit takes a second or so.
+    const HELPER_READY_TIMEOUT: Duration = Duration::from_secs(10);
+
+    /// How long the helper is kept after the drop, so the target can finish
+    /// reading the file from the drag.
+    const DROP_SETTLE: Duration = Duration::from_millis(500);
+
+    /// The helper, stopped however the drag ends.
+    struct Helper(Child);
+
+    impl Drop for Helper {
+        fn drop(&mut self) {
+            let _ = self.0.kill();
+            let _ = self.0.wait();
+        }
+    }
+
+    // Called with the path and the start point in Cocoa's coordinates,
+    // which run up from the bottom of the main screen. Prints "ready" once
+    // its window is up, then runs until it's killed.
+    const SCRIPT: &str = r#"
+ObjC.import('Cocoa');
+function run(argv) {
+    var path = argv[0], x = parseFloat(argv[1]), y = parseFloat(argv[2]), size = 32;
+    var app = $.NSApplication.sharedApplication;
+    app.setActivationPolicy($.NSApplicationActivationPolicyAccessory);
+    ObjC.registerSubclass({
+        name: 'DesktopMcpFileDragSource',
+        superclass: 'NSView',
+        protocols: ['NSDraggingSource'],
+        methods: {
+            'acceptsFirstMouse:': {
+                types: ['bool', ['id']],
+                implementation: function (event) { return true; }
+            },
+            'mouseDown:': {
+                types: ['void', ['id']],
+                implementation: function (event) {
+                    var item = $.NSDraggingItem.alloc.initWithPasteboardWriter($.NSURL.fileURLWithPath(path));
+                    item.setDraggingFrameContents(this.bounds, $.NSWorkspace.sharedWorkspace.iconForFile(path));
+                    this.beginDraggingSessionWithItemsEventSource($.NSArray.arrayWithObject(item), event, this);
+                }
+            },
+            'draggingSession:sourceOperationMaskForDraggingContext:': {
+                types: ['unsigned long', ['id', 'long']],
+                implementation: function (session, context) {
+                    return $.NSDragOperationCopy | $.NSDragOperationLink | $.NSDragOperationGeneric;
+                }
+            }
+        }
+    });
+    var window = $.NSWindow.alloc.initWithContentRectStyleMaskBackingDefer(
+        $.NSMakeRect(x - size / 2, y - size / 2, size, size),
+        $.NSWindowStyleMaskBorderless, $.NSBackingStoreBuffered, false);
+    window.level = $.NSStatusWindowLevel;
+    window.contentView = $.DesktopMcpFileDragSource.alloc.initWithFrame($.NSMakeRect(0, 0, size, size));
+    window.orderFrontRegardless;
+    $.NSFileHandle.fileHandleWithStandardOutput.writeData($('ready\n').dataUsingEncoding($.NSUTF8StringEncoding));
+    app.run;
+}
+"#;
+
+    if dry_run() {
+        return do_mouse_drag(from, to, Button::Left, timing, None, &[]);
+    }
+
+    let main_height = displays()?
+        .iter()
+        .find(|d| d.is_primary)
+        .map(|d| d.height as f64)
+        .ok_or("No main screen to place the drag source on")?;
+    let child = Command::new("osascript")
+        .args(["-l", "JavaScript", "-e", SCRIPT, "--"])
+        .arg(path)
+        .arg(from.0.to_string())
+        .arg((main_height - from.1).to_string())
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| format!("Failed to start osascript: {}", e))?;
+    let mut helper = Helper(child);
+
+    let stdout = helper.0.stdout.take().ok_or("osascript has no output")?;
+    let (ready_tx, ready_rx) = mpsc::channel();
+    thread::spawn(move || {
+        let ready = BufReader::new(stdout).lines().map_while(Result::ok).any(|line| line == "ready");
+        let _ = ready_tx.send(ready);
+    });
+    match ready_rx.recv_timeout(HELPER_READY_TIMEOUT) {
+        Ok(true) => {}
+        Ok(false) => return Err("The drag source helper exited before it was ready".to_string()),
+        Err(_) => return Err("The drag source helper didn't start in time".to_string()),
+    }
+
+    do_mouse_drag(from, to, Button::Left, timing, None, &[])?;
+    sleep_cancellable(DROP_SETTLE)?;
+    drop(helper);
+    Ok(())
+}
+
+#[cfg(not(target_os = "macos"))]
+pub(crate) fn do_drag_file(_path: &Path, _from: (f64, f64), _to: (f64, f64), _timing: DragTiming) -> Result<(), String> {
+    Err("Dragging files is only supported on macOS".to_string())
+}
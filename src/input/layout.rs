@@ -0,0 +1,331 @@
+//! Keyboard layouts: which key types each character on the active layout,
+//! detecting that layout, and inferring it by typing a probe and reading it
+//! back.
+
+use rdev::Key;
+use serde_json::{json, Value};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use crate::input::clipboard::{clipboard_read, clipboard_write};
+use crate::input::{do_key_tap, do_type_text, shortcut_modifier, TypingRhythm};
+
+/// The character keys, in the order of the rows in LAYOUTS: the number row
+/// from the key left of 1, then the three letter rows, each from the left.
+/// IntlBackslash is the extra key left of Z on ISO keyboards.
+const KEYS: [Key; 48] = [
+    Key::BackQuote, Key::Num1, Key::Num2, Key::Num3, Key::Num4, Key::Num5, Key::Num6,
+    Key::Num7, Key::Num8, Key::Num9, Key::Num0, Key::Minus, Key::Equal,
+    Key::KeyQ, Key::KeyW, Key::KeyE, Key::KeyR, Key::KeyT, Key::KeyY, Key::KeyU,
+    Key::KeyI, Key::KeyO, Key::KeyP, Key::LeftBracket, Key::RightBracket, Key::BackSlash,
+    Key::KeyA, Key::KeyS, Key::KeyD, Key::KeyF, Key::KeyG, Key::KeyH, Key::KeyJ,
+    Key::KeyK, Key::KeyL, Key::SemiColon, Key::Quote,
+    Key::IntlBackslash, Key::KeyZ, Key::KeyX, Key::KeyC, Key::KeyV, Key::KeyB, Key::KeyN,
+    Key::KeyM, Key::Comma, Key::Dot, Key::Slash,
+];
+
+/// A layout type_text can type on: what each of KEYS produces alone and
+/// with Shift, one character per key, with a space for keys that produce
+/// nothing or a dead key. Characters needing AltGr or Option aren't typed.
+#[derive(Debug)]
+pub(crate) struct KeyboardLayout {
+    pub(crate) name: &'static str,
+    unshifted: &'static str,
+    shifted: &'static str,
+}
+
+/// Supported layouts, PC variants, the first being the fallback.
+pub(crate) const LAYOUTS: &[KeyboardLayout] = &[
+    KeyboardLayout {
+        name: "us",
+        unshifted: "`1234567890-=qwertyuiop[]\\asdfghjkl;' zxcvbnm,./",
+        shifted: "~!@#$%^&*()_+QWERTYUIOP{}|ASDFGHJKL:\" ZXCVBNM<>?",
+    },
+    KeyboardLayout {
+        name: "uk",
+        unshifted: "`1234567890-=qwertyuiop[]#asdfghjkl;'\\zxcvbnm,./",
+        shifted: "¬!\"£$%^&*()_+QWERTYUIOP{}~ASDFGHJKL:@|ZXCVBNM<>?",
+    },
+    KeyboardLayout {
+        name: "de",
+        unshifted: " 1234567890ß qwertzuiopü+#asdfghjklöä<yxcvbnm,.-",
+        shifted: "°!\"§$%&/()=? QWERTZUIOPÜ*'ASDFGHJKLÖÄ>YXCVBNM;:_",
+    },
+    KeyboardLayout {
+        name: "fr",
+        unshifted: "²&é\"'(-è_çà)=azertyuiop $*qsdfghjklmù<wxcvbn,;:!",
+        shifted: " 1234567890°+AZERTYUIOP £µQSDFGHJKLM%>WXCVBN?./§",
+    },
+];
+
+impl KeyboardLayout {
+    pub(crate) fn named(name: &str) -> Option<&'static KeyboardLayout> {
+        LAYOUTS.iter().find(|layout| layout.name == name)
+    }
+
+    /// The key that types `c`, and whether it needs Shift.
+    pub(crate) fn key_for(&self, c: char) -> (Option<Key>, bool) {
+        match c {
+            ' ' => return (Some(Key::Space), false),
+            '\n' => return (Some(Key::Return), false),
+            '\t' => return (Some(Key::Tab), false),
+            _ => {}
+        }
+        let find = |row: &str| KEYS.iter().zip(row.chars()).find(|&(_, k)| k == c).map(|(&key, _)| key);
+        match (find(self.unshifted), find(self.shifted)) {
+            (Some(key), _) => (Some(key), false),
+            (None, Some(key)) => (Some(key), true),
+            (None, None) => (None, false),
+        }
+    }
+}
+
+/// The layout to type on and how it was chosen.
+#[derive(Debug)]
+pub(crate) struct ActiveLayout {
+    pub(crate) layout: &'static KeyboardLayout,
+    /// "requested", "detected" or "fallback".
+    pub(crate) source: &'static str,
+    /// The OS's name for the active layout, where it could be read.
+    pub(crate) platform_id: Option<String>,
+    pub(crate) detection_error: Option<String>,
+}
+
+impl ActiveLayout {
+    pub(crate) fn to_json(&self) -> Value {
+        json!({
+            "layout": self.layout.name,
+            "source": self.source,
+            "platform_id": self.platform_id,
+            "detection_error": self.detection_error,
+            "supported": LAYOUTS.iter().map(|l| l.name).collect::<Vec<_>>()
+        })
+    }
+}
+
+/// The layout named by `requested` (from the call or the config file), or
+/// else the OS's active layout, falling back to US with a warning (logged
+/// once) when it can't be detected or isn't supported.
+pub(crate) fn active_layout(requested: Option<&str>) -> Result<ActiveLayout, String> {
+    if let Some(name) = requested {
+        let layout = KeyboardLayout::named(name).ok_or_else(|| format!("Unknown keyboard layout: {}", name))?;
+        return Ok(ActiveLayout {
+            layout,
+            source: "requested",
+            platform_id: None,
+            detection_error: None,
+        });
+    }
+
+    let (platform_id, error) = match platform_layout_id() {
+        Ok(id) => match layout_for_id(&id) {
+            Some(layout) => {
+                return Ok(ActiveLayout {
+                    layout,
+                    source: "detected",
+                    platform_id: Some(id),
+                    detection_error: None,
+                })
+            }
+            None => {
+                let error = format!("layout {} isn't supported", id);
+                (Some(id), error)
+            }
+        },
+        Err(e) => (None, e),
+    };
+    static WARNED: AtomicBool = AtomicBool::new(false);
+    if !WARNED.swap(true, Ordering::SeqCst) {
+        log::warn!("Keyboard layout detection failed ({}); typing as US", error);
+    }
+    Ok(ActiveLayout {
+        layout: &LAYOUTS[0],
+        source: "fallback",
+        platform_id,
+        detection_error: Some(error),
+    })
+}
+
+/// Map an OS layout identifier to a supported layout.
+fn layout_for_id(id: &str) -> Option<&'static KeyboardLayout> {
+    let name = match id.trim_start_matches("com.apple.keylayout.") {
+        // macOS
+        "US" | "ABC" | "USExtended" => "us",
+        "British" | "British-PC" => "uk",
+        "German" | "Austrian" | "ABC-QWERTZ" => "de",
+        "French" | "French-PC" | "French-numerical" | "ABC-AZERTY" => "fr",
+        // XKB layout names
+        "gb" => "uk",
+        // Windows language identifiers
+        "0409" => "us",
+        "0809" => "uk",
+        "0407" | "0c07" => "de",
+        "040c" => "fr",
+        other => other,
+    };
+    KeyboardLayout::named(name)
+}
+
+/// The current keyboard layout's input source ID, e.g.
+/// com.apple.keylayout.French. Read from the HIToolbox preferences rather
+/// than the Text Input Sources API, which must run on the main thread.
+#[cfg(target_os = "macos")]
+fn platform_layout_id() -> Result<String, String> {
+    let output = std::process::Command::new("defaults")
+        .args(["read", "com.apple.HIToolbox", "AppleCurrentKeyboardLayoutInputSourceID"])
+        .output()
+        .map_err(|e| format!("Failed to run defaults: {}", e))?;
+    if !output.status.success() {
+        return Err("No current keyboard layout in the HIToolbox preferences".to_string());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// The language identifier of the foreground window's keyboard layout, as
+/// four hex digits.
+#[cfg(target_os = "windows")]
+fn platform_layout_id() -> Result<String, String> {
+    #[link(name = "user32")]
+    extern "system" {
+        fn GetForegroundWindow() -> isize;
+        fn GetWindowThreadProcessId(window: isize, process_id: *mut u32) -> u32;
+        fn GetKeyboardLayout(thread_id: u32) -> isize;
+    }
+
+    let layout = unsafe {
+        let thread = GetWindowThreadProcessId(GetForegroundWindow(), std::ptr::null_mut());
+        GetKeyboardLayout(thread)
+    };
+    if layout == 0 {
+        return Err("GetKeyboardLayout failed".to_string());
+    }
+    Ok(format!("{:04x}", layout as usize & 0xFFFF))
+}
+
+/// The first XKB layout, from setxkbmap.
+#[cfg(not(any(target_os = "macos", target_os = "windows")))]
+fn platform_layout_id() -> Result<String, String> {
+    let output = std::process::Command::new("setxkbmap")
+        .arg("-query")
+        .output()
+        .map_err(|e| format!("Failed to run setxkbmap: {}", e))?;
+    if !output.status.success() {
+        return Err(format!("setxkbmap failed: {}", output.status));
+    }
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .find_map(|line| line.strip_prefix("layout:"))
+        .and_then(|layouts| layouts.trim().split(',').next())
+        .map(str::to_string)
+        .ok_or_else(|| "setxkbmap reported no layout".to_string())
+}
+
+/// Characters typed by probe_layout, all unshifted keys whose output differs
+/// between common layouts. They're typed space-separated so that a dead key
+/// is flushed by the following space rather than swallowing the next probe.
+const LAYOUT_PROBE: &[char] = &['q', 'w', 'z', 'y', 'a', 'm', '1', '2', '-', ';', ',', '.', '/'];
+
+/// What each probe key produces on known layouts, in LAYOUT_PROBE order.
+/// Entries listing several characters accept any of them (PC and Mac
+/// variants differ on some punctuation).
+const KNOWN_LAYOUTS: &[(&str, &[&str])] = &[
+    ("us", &["q", "w", "z", "y", "a", "m", "1", "2", "-", ";", ",", ".", "/"]),
+    ("de", &["q", "w", "y", "z", "a", "m", "1", "2", "ß", "ö", ",", ".", "-"]),
+    ("fr", &["a", "z", "w", "y", "q", ",", "&", "é", ")", "m", ";", ":", "!="]),
+];
+
+/// Infer the layout family from what each probe key produced.
+fn infer_layout(read_back: &[String]) -> &'static str {
+    let matches = |expected: &[&str]| {
+        read_back.len() == expected.len()
+            && read_back
+                .iter()
+                .zip(expected)
+                .all(|(got, accepted)| got.chars().count() == 1 && got.chars().all(|c| accepted.contains(c)))
+    };
+    if let Some((name, _)) = KNOWN_LAYOUTS.iter().find(|(_, expected)| matches(expected)) {
+        return name;
+    }
+
+    // Fall back to the letter swaps that identify the family
+    let got = |c: char| {
+        LAYOUT_PROBE
+            .iter()
+            .position(|&p| p == c)
+            .and_then(|i| read_back.get(i))
+            .map(String::as_str)
+    };
+    if got('q') == Some("a") && got('a') == Some("q") && got('w') == Some("z") {
+        "azerty"
+    } else if got('y') == Some("z") && got('z') == Some("y") {
+        "qwertz"
+    } else {
+        "unknown"
+    }
+}
+
+/// Type the probe string into the focused field, read it back through
+/// select + copy, and infer the keyboard layout from the difference. The
+/// probe text is deleted and the clipboard restored afterwards.
+///
+/// Selection uses arrow-key shortcuts rather than select-all, since the key
+/// that types "a" on US layouts types "q" on AZERTY, and Cmd+Q would quit
+/// the focused app.
+pub(crate) fn probe_layout() -> Result<Value, String> {
+    let saved_clipboard = clipboard_read();
+    // A sentinel, so that a copy that didn't happen is noticed
+    clipboard_write("")?;
+
+    let probe: String = LAYOUT_PROBE
+        .iter()
+        .map(|c| c.to_string())
+        .collect::<Vec<_>>()
+        .join(" ");
+    let read_back = (|| {
+        // The probe characters are US key positions
+        do_type_text(&format!("{} ", probe), &TypingRhythm::fixed(20), &LAYOUTS[0])?;
+        thread::sleep(Duration::from_millis(100));
+        if cfg!(target_os = "macos") {
+            let modifiers = ["meta".to_string(), "shift".to_string()];
+            do_key_tap("up", &modifiers)?;
+            do_key_tap("left", &modifiers)?;
+        } else {
+            do_key_tap("home", &["control".to_string(), "shift".to_string()])?;
+        }
+        do_key_tap("c", &[shortcut_modifier().to_string()])?;
+        thread::sleep(Duration::from_millis(200));
+        let copied = clipboard_read()?;
+        do_key_tap("backspace", &[])?;
+        Ok::<_, String>(copied)
+    })();
+
+    let clipboard_restored = match &saved_clipboard {
+        Ok(text) => clipboard_write(text).is_ok(),
+        Err(_) => false,
+    };
+    let read_back = read_back?;
+    if read_back.is_empty() {
+        return Err(
+            "Couldn't read back the probe text; focus an empty text field before probing".to_string(),
+        );
+    }
+
+    let tokens: Vec<String> = read_back.split_whitespace().map(String::from).collect();
+    let mismatches: Vec<Value> = LAYOUT_PROBE
+        .iter()
+        .enumerate()
+        .filter_map(|(i, &sent)| {
+            let got = tokens.get(i);
+            (got != Some(&sent.to_string())).then(|| json!({ "sent": sent, "got": got }))
+        })
+        .collect();
+
+    Ok(json!({
+        "layout": infer_layout(&tokens),
+        "typed": probe,
+        "read_back": read_back.trim_end(),
+        "mismatches": mismatches,
+        "clipboard_restored": clipboard_restored
+    }))
+}
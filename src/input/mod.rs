@@ -0,0 +1,1418 @@
+//! Simulated mouse and keyboard input.
+
+mod clipboard;
+pub(crate) mod failsafe;
+pub(crate) mod file_drag;
+pub(crate) mod layout;
+pub(crate) mod physical;
+
+use rdev::{Button, EventType, Key};
+use serde_json::{json, Value};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::backend::displays;
+use crate::cancel::{check_cancelled, sleep_cancellable};
+use crate::capture::desktop_bounds;
+use crate::input::clipboard::{clipboard_read, clipboard_write};
+use crate::input::layout::KeyboardLayout;
+use crate::input::physical::{send_drag_move, send_event, subscribe_physical_input};
+use crate::tools::cursor::cursor_position;
+use crate::tools::limits::dry_run;
+use crate::tools::parse_modifiers;
+
+pub(crate) fn do_mouse_move(x: f64, y: f64) -> Result<(), String> {
+    send_event(&EventType::MouseMove { x, y }).map_err(|e| format!("Mouse move failed: {:?}", e))
+}
+
+/// Button names the mouse tools accept. "x1" and "x2" are other names for
+/// back and forward.
+pub(crate) const MOUSE_BUTTONS: &[&str] = &["left", "right", "middle", "back", "forward", "x1", "x2"];
+
+/// The side buttons as rdev simulates and reports them: XBUTTON1 and
+/// XBUTTON2 on Windows, and X11's buttons 8 and 9 elsewhere, which the
+/// macOS and portal backends translate.
+#[cfg(target_os = "windows")]
+pub(crate) const BACK_BUTTON: Button = Button::Unknown(1);
+#[cfg(target_os = "windows")]
+pub(crate) const FORWARD_BUTTON: Button = Button::Unknown(2);
+#[cfg(not(target_os = "windows"))]
+pub(crate) const BACK_BUTTON: Button = Button::Unknown(8);
+#[cfg(not(target_os = "windows"))]
+pub(crate) const FORWARD_BUTTON: Button = Button::Unknown(9);
+
+/// The button with one of the MOUSE_BUTTONS names, ignoring case and
+/// surrounding space. Anything else is an error rather than a guess, since
+/// clicking the wrong button can't be undone.
+pub(crate) fn mouse_button(name: &str) -> Result<Button, String> {
+    match name.trim().to_lowercase().as_str() {
+        "left" => Ok(Button::Left),
+        "right" => Ok(Button::Right),
+        "middle" => Ok(Button::Middle),
+        "back" | "x1" => Ok(BACK_BUTTON),
+        "forward" | "x2" => Ok(FORWARD_BUTTON),
+        _ => Err(format!(
+            "Unknown mouse button {:?}: expected one of {}",
+            name,
+            MOUSE_BUTTONS.join(", ")
+        )),
+    }
+}
+
+/// A button's name, as mouse_button takes it, for reporting physical clicks.
+pub(crate) fn button_name(button: Button) -> String {
+    match button {
+        Button::Left => "left".to_string(),
+        Button::Right => "right".to_string(),
+        Button::Middle => "middle".to_string(),
+        BACK_BUTTON => "back".to_string(),
+        FORWARD_BUTTON => "forward".to_string(),
+        Button::Unknown(code) => format!("unknown({})", code),
+    }
+}
+
+/// Upper bound on mouse_click's click_count.
+const MAX_CLICK_COUNT: u64 = 10;
+
+/// Upper bound on mouse_click's interval_ms and press_duration_ms.
+const MAX_CLICK_TIMING_MS: u64 = 2_000;
+
+/// How many times to click and how long each click takes, in milliseconds.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct ClickOptions {
+    count: u64,
+    /// Gap between a release and the next press.
+    interval_ms: u64,
+    /// How long each press is held.
+    press_ms: u64,
+}
+
+impl Default for ClickOptions {
+    fn default() -> Self {
+        ClickOptions {
+            count: 1,
+            interval_ms: 50,
+            press_ms: 10,
+        }
+    }
+}
+
+impl ClickOptions {
+    /// Reads click_count, interval_ms and press_duration_ms, falling back to
+    /// the deprecated double_click flag when click_count is absent.
+    pub(crate) fn parse(args: &Value) -> Self {
+        let defaults = ClickOptions::default();
+        let count = args
+            .get("click_count")
+            .and_then(|v| v.as_u64())
+            .unwrap_or_else(|| {
+                match args.get("double_click").and_then(|v| v.as_bool()) {
+                    Some(true) => 2,
+                    _ => 1,
+                }
+            })
+            .clamp(1, MAX_CLICK_COUNT);
+        let timing = |name: &str, default: u64| {
+            args.get(name)
+                .and_then(|v| v.as_u64())
+                .unwrap_or(default)
+                .min(MAX_CLICK_TIMING_MS)
+        };
+        ClickOptions {
+            count,
+            interval_ms: timing("interval_ms", defaults.interval_ms),
+            press_ms: timing("press_duration_ms", defaults.press_ms),
+        }
+    }
+
+    /// How long the clicks take: every press, and the gaps between them.
+    pub(crate) fn duration(self) -> Duration {
+        Duration::from_millis(self.count * self.press_ms + (self.count - 1) * self.interval_ms)
+    }
+
+    pub(crate) fn to_json(self) -> Value {
+        json!({
+            "click_count": self.count,
+            "interval_ms": self.interval_ms,
+            "press_duration_ms": self.press_ms
+        })
+    }
+}
+
+pub(crate) fn do_mouse_click(
+    x: Option<f64>,
+    y: Option<f64>,
+    button: Button,
+    clicks: ClickOptions,
+    modifiers: &[String],
+) -> Result<(), String> {
+    // Move if coordinates provided
+    if let (Some(x), Some(y)) = (x, y) {
+        do_mouse_move(x, y)?;
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    with_modifiers(modifiers, || {
+        for i in 0..clicks.count {
+            if i > 0 {
+                sleep_cancellable(Duration::from_millis(clicks.interval_ms))?;
+            }
+            check_cancelled()?;
+            send_event(&EventType::ButtonPress(button))
+                .map_err(|e| format!("Button press failed: {:?}", e))?;
+            // Once pressed, the button is released even if the call is
+            // cancelled mid-press
+            let held = sleep_cancellable(Duration::from_millis(clicks.press_ms));
+            send_event(&EventType::ButtonRelease(button))
+                .map_err(|e| format!("Button release failed: {:?}", e))?;
+            held?;
+        }
+
+        Ok(())
+    })
+}
+
+fn do_drag_move(x: f64, y: f64, button: Button) -> Result<(), String> {
+    send_drag_move(x, y, button).map_err(|e| format!("Mouse move failed: {:?}", e))
+}
+
+/// Upper bound on mouse_drag's hold_after_press_ms and
+/// hold_before_release_ms.
+pub(crate) const MAX_DRAG_HOLD_MS: u64 = 10_000;
+
+/// Upper bound on mouse_drag's duration_ms.
+pub(crate) const MAX_DRAG_MS: u64 = 60_000;
+
+/// Distance between interpolated positions of a straight mouse_drag, in
+/// logical pixels.
+const DRAG_STEP_PX: f64 = 8.0;
+
+/// Bounds on the number of moves in a straight mouse_drag.
+const MIN_DRAG_STEPS: usize = 20;
+const MAX_DRAG_STEPS: usize = 500;
+
+/// Timing of a mouse_drag, in milliseconds.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct DragTiming {
+    /// How long the move from start to end takes.
+    pub(crate) duration_ms: u64,
+    /// Pause after pressing, before moving; some apps only start a drag
+    /// once the button has been held briefly.
+    pub(crate) hold_after_press_ms: u64,
+    /// Dwell at the end before releasing; many drop targets only accept a
+    /// drop once the cursor has hovered over them.
+    pub(crate) hold_before_release_ms: u64,
+}
+
+pub(crate) fn do_mouse_drag(
+    (from_x, from_y): (f64, f64),
+    (to_x, to_y): (f64, f64),
+    button: Button,
+    timing: DragTiming,
+    humanize: Option<&Humanize>,
+    modifiers: &[String],
+) -> Result<(), String> {
+    // Move to start position
+    do_mouse_move(from_x, from_y)?;
+    thread::sleep(Duration::from_millis(20));
+
+    with_modifiers(modifiers, || {
+        // Press button
+        send_event(&EventType::ButtonPress(button))
+            .map_err(|e| format!("Button press failed: {:?}", e))?;
+
+        // Once pressed, the button is released however the drag ends
+        let dragged = (|| -> Result<(), String> {
+            sleep_cancellable(Duration::from_millis(20 + timing.hold_after_press_ms))?;
+
+            // Interpolate drag movement, in steps of a few pixels so that long
+            // drags don't jump
+            let path: Vec<(f64, f64)> = match humanize {
+                Some(humanize) => humanize.path((from_x, from_y), (to_x, to_y), timing.duration_ms),
+                None => {
+                    let distance = (to_x - from_x).hypot(to_y - from_y);
+                    let steps = ((distance / DRAG_STEP_PX).ceil() as usize).clamp(MIN_DRAG_STEPS, MAX_DRAG_STEPS);
+                    (0..=steps)
+                        .map(|i| {
+                            let t = i as f64 / steps as f64;
+                            (from_x + (to_x - from_x) * t, from_y + (to_y - from_y) * t)
+                        })
+                        .collect()
+                }
+            };
+            // Sleep to each step's scheduled time rather than a whole number of
+            // milliseconds per step, which rounds short drags down to nothing
+            let steps = (path.len() - 1).max(1) as u32;
+            let duration = Duration::from_millis(timing.duration_ms);
+            let start = Instant::now();
+            for (i, (x, y)) in path.into_iter().enumerate().skip(1) {
+                do_drag_move(x, y, button)?;
+                let due = start + duration * i as u32 / steps;
+                sleep_cancellable(due.saturating_duration_since(Instant::now()))?;
+            }
+
+            sleep_cancellable(Duration::from_millis(timing.hold_before_release_ms))
+        })();
+
+        // Release button
+        let release = send_event(&EventType::ButtonRelease(button))
+            .map_err(|e| format!("Button release failed: {:?}", e));
+        dragged?;
+        release
+    })
+}
+
+/// Upper bound on the number of points in a mouse_drag_path.
+pub(crate) const MAX_DRAG_PATH_POINTS: usize = 1_000;
+
+/// Upper bound on a mouse_drag_path's total duration.
+pub(crate) const MAX_DRAG_PATH_MS: u64 = 60_000;
+
+/// Drag through a series of waypoints: press at the first, move through
+/// each in turn, and release at the last. `segment_ms[i]` is how long the
+/// move from point i to i + 1 takes; each segment is interpolated in steps
+/// of about SMOOTH_MOVE_STEP_MS. The button is released even if a move
+/// fails. Returns the number of move events emitted.
+pub(crate) fn do_mouse_drag_path(
+    points: &[(f64, f64)],
+    segment_ms: &[u64],
+    button: Button,
+    modifiers: &[String],
+) -> Result<usize, String> {
+    let (start_x, start_y) = points[0];
+
+    do_mouse_move(start_x, start_y)?;
+    thread::sleep(Duration::from_millis(20));
+
+    with_modifiers(modifiers, || {
+        send_event(&EventType::ButtonPress(button))
+            .map_err(|e| format!("Button press failed: {:?}", e))?;
+        thread::sleep(Duration::from_millis(20));
+
+        let moves = (|| -> Result<usize, String> {
+            let mut moves = 0;
+            for (segment, &duration_ms) in points.windows(2).zip(segment_ms) {
+                let ((x0, y0), (x1, y1)) = (segment[0], segment[1]);
+                let steps = (duration_ms / SMOOTH_MOVE_STEP_MS).max(1);
+                let step_delay = Duration::from_millis(duration_ms / steps);
+                for i in 1..=steps {
+                    let t = i as f64 / steps as f64;
+                    do_drag_move(x0 + (x1 - x0) * t, y0 + (y1 - y0) * t, button)?;
+                    moves += 1;
+                    sleep_cancellable(step_delay)?;
+                }
+            }
+            Ok(moves)
+        })();
+
+        let release = send_event(&EventType::ButtonRelease(button))
+            .map_err(|e| format!("Button release failed: {:?}", e));
+        let moves = moves?;
+        release?;
+        Ok(moves)
+    })
+}
+
+/// Upper bound on mouse_long_press's hold_ms.
+pub(crate) const MAX_LONG_PRESS_MS: u64 = 10_000;
+
+/// How often the position is reasserted during a long press.
+const LONG_PRESS_REASSERT_MS: u64 = 100;
+
+/// Press a button at (x, y), hold it for `hold_ms`, then release. While
+/// held, the same position is re-posted periodically (as a zero-distance
+/// drag) so that apps tracking the press see a stationary pointer rather
+/// than drift. A physical Escape ends the hold early. The button is always
+/// released once it has been pressed. Returns how long it was actually held
+/// and whether the hold was cut short.
+pub(crate) fn do_mouse_long_press(x: f64, y: f64, button: Button, hold_ms: u64) -> Result<(Duration, bool), String> {
+    // Escape aborts the hold; without a listener, the hold just runs its
+    // course
+    let physical = subscribe_physical_input().ok();
+
+    do_mouse_move(x, y)?;
+    thread::sleep(Duration::from_millis(10));
+
+    send_event(&EventType::ButtonPress(button)).map_err(|e| format!("Button press failed: {:?}", e))?;
+    let pressed = Instant::now();
+    let deadline = pressed + Duration::from_millis(hold_ms);
+
+    let hold = (|| -> Result<bool, String> {
+        loop {
+            check_cancelled()?;
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Ok(false);
+            }
+            let wait = remaining.min(Duration::from_millis(LONG_PRESS_REASSERT_MS));
+            let aborted = match &physical {
+                Some(rx) => match rx.recv_timeout(wait) {
+                    Ok(EventType::KeyPress(Key::Escape)) => true,
+                    Ok(_) => continue,
+                    Err(mpsc::RecvTimeoutError::Timeout) => false,
+                    // The listener died; keep holding without it
+                    Err(mpsc::RecvTimeoutError::Disconnected) => {
+                        thread::sleep(wait);
+                        false
+                    }
+                },
+                None => {
+                    thread::sleep(wait);
+                    false
+                }
+            };
+            if aborted {
+                return Ok(true);
+            }
+            do_drag_move(x, y, button)?;
+        }
+    })();
+
+    let held = pressed.elapsed();
+    let release =
+        send_event(&EventType::ButtonRelease(button)).map_err(|e| format!("Button release failed: {:?}", e));
+    let aborted = hold?;
+    release?;
+    Ok((held, aborted))
+}
+
+/// A small seedable PRNG (SplitMix64), so humanized paths are reproducible.
+struct Rng(u64);
+
+impl Rng {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in [lo, hi).
+    fn range(&mut self, lo: f64, hi: f64) -> f64 {
+        lo + (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64 * (hi - lo)
+    }
+}
+
+/// A seed for when the caller doesn't supply one.
+fn random_seed() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_nanos() as u64)
+}
+
+/// Minimum-jerk easing: slow start, fast middle, decelerating finish.
+fn ease(t: f64) -> f64 {
+    t * t * t * (10.0 - 15.0 * t + 6.0 * t * t)
+}
+
+fn cubic_bezier(p: [(f64, f64); 4], t: f64) -> (f64, f64) {
+    let u = 1.0 - t;
+    let (a, b, c, d) = (u * u * u, 3.0 * u * u * t, 3.0 * u * t * t, t * t * t);
+    (
+        a * p[0].0 + b * p[1].0 + c * p[2].0 + d * p[3].0,
+        a * p[0].1 + b * p[1].1 + c * p[2].1 + d * p[3].1,
+    )
+}
+
+/// Points along a human-looking path from `from` to `to`, one per step: a
+/// gently curved Bézier with eased velocity that overshoots the target
+/// slightly and then corrects. The first point is `from` and the last is
+/// exactly `to`; every point is clamped to `bounds` (left, top, right,
+/// bottom).
+fn humanized_path(
+    from: (f64, f64),
+    to: (f64, f64),
+    steps: usize,
+    bounds: (f64, f64, f64, f64),
+    rng: &mut Rng,
+) -> Vec<(f64, f64)> {
+    let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+    let distance = dx.hypot(dy);
+    if distance < 1.0 || steps < 2 {
+        return vec![from, to];
+    }
+    let (ux, uy) = (dx / distance, dy / distance);
+    let (px, py) = (-uy, ux);
+
+    // Overshoot a few pixels past the target, slightly off-axis
+    let overshoot = (distance * rng.range(0.02, 0.06)).min(12.0);
+    let drift = rng.range(-0.3, 0.3) * overshoot;
+    let past = (
+        to.0 + ux * overshoot + px * drift,
+        to.1 + uy * overshoot + py * drift,
+    );
+
+    // Control points bowed to one side of the straight line
+    let bow = distance * rng.range(0.05, 0.2) * if rng.range(0.0, 1.0) < 0.5 { -1.0 } else { 1.0 };
+    let c1 = (
+        from.0 + dx * rng.range(0.2, 0.4) + px * bow,
+        from.1 + dy * rng.range(0.2, 0.4) + py * bow,
+    );
+    let c2 = (
+        from.0 + dx * rng.range(0.6, 0.8) + px * bow * 0.5,
+        from.1 + dy * rng.range(0.6, 0.8) + py * bow * 0.5,
+    );
+
+    // Most of the time goes to the main movement, the rest to correcting
+    let correction_steps = (steps / 6).max(1);
+    let main_steps = steps - correction_steps;
+    let (left, top, right, bottom) = bounds;
+    let clamp = |(x, y): (f64, f64)| (x.clamp(left, right), y.clamp(top, bottom));
+
+    let mut path = vec![from];
+    for i in 1..=main_steps {
+        let t = ease(i as f64 / main_steps as f64);
+        path.push(clamp(cubic_bezier([from, c1, c2, past], t)));
+    }
+    for i in 1..correction_steps {
+        let t = ease(i as f64 / correction_steps as f64);
+        path.push(clamp((past.0 + (to.0 - past.0) * t, past.1 + (to.1 - past.1) * t)));
+    }
+    path.push(to);
+    path
+}
+
+/// Humanized paths are sent as one event per this many milliseconds.
+const HUMANIZED_STEP_MS: u64 = 10;
+
+/// Humanized path settings: the seed, and the usable (left, top, right,
+/// bottom) bounds of the virtual desktop.
+pub(crate) struct Humanize {
+    pub(crate) seed: u64,
+    bounds: (f64, f64, f64, f64),
+}
+
+impl Humanize {
+    pub(crate) fn new(seed: Option<u64>) -> Result<Self, String> {
+        let screens = displays()?;
+        let (left, top, right, bottom) = desktop_bounds(&screens);
+        Ok(Humanize {
+            seed: seed.unwrap_or_else(random_seed),
+            bounds: (left as f64, top as f64, (right - 1) as f64, (bottom - 1) as f64),
+        })
+    }
+
+    fn path(&self, from: (f64, f64), to: (f64, f64), duration_ms: u64) -> Vec<(f64, f64)> {
+        let steps = (duration_ms / HUMANIZED_STEP_MS).max(2) as usize;
+        humanized_path(from, to, steps, self.bounds, &mut Rng(self.seed))
+    }
+}
+
+/// Move along a humanized path from the current cursor position. The
+/// duration scales with distance unless given. Falls back to a direct move
+/// where the cursor position can't be read; returns whether the move was
+/// humanized. Stops between steps if the call is cancelled.
+pub(crate) fn do_humanized_move(x: f64, y: f64, humanize: &Humanize, duration_ms: Option<u64>) -> Result<bool, String> {
+    let Some(from) = cursor_position() else {
+        do_mouse_move(x, y)?;
+        return Ok(false);
+    };
+    let distance = (x - from.0).hypot(y - from.1);
+    let duration_ms = duration_ms.unwrap_or((150.0 + distance / 2.0).min(1200.0) as u64);
+    for (px, py) in humanize.path(from, (x, y), duration_ms).into_iter().skip(1) {
+        do_mouse_move(px, py)?;
+        sleep_cancellable(Duration::from_millis(HUMANIZED_STEP_MS))?;
+    }
+    Ok(true)
+}
+
+/// Upper bound on a smooth mouse_move's duration.
+pub(crate) const MAX_SMOOTH_MOVE_MS: u64 = 10_000;
+
+/// Upper bound on a smooth mouse_move's steps.
+pub(crate) const MAX_SMOOTH_MOVE_STEPS: u64 = 1_000;
+
+/// Default time between intermediate positions of a smooth move.
+pub(crate) const SMOOTH_MOVE_STEP_MS: u64 = 10;
+
+/// Velocity profile of a smooth mouse_move.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Easing {
+    Linear,
+    EaseInOut,
+}
+
+impl Easing {
+    pub(crate) fn parse(name: &str) -> Result<Self, String> {
+        match name {
+            "linear" => Ok(Easing::Linear),
+            "ease_in_out" => Ok(Easing::EaseInOut),
+            other => Err(format!(
+                "Unknown easing: {} (expected \"linear\" or \"ease_in_out\")",
+                other
+            )),
+        }
+    }
+
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Easing::Linear => "linear",
+            Easing::EaseInOut => "ease_in_out",
+        }
+    }
+
+    fn apply(self, t: f64) -> f64 {
+        match self {
+            Easing::Linear => t,
+            Easing::EaseInOut => ease(t),
+        }
+    }
+}
+
+/// Move in a straight line from the current cursor position over
+/// `duration_ms`, in `steps` evenly timed moves. Falls back to a direct move
+/// where the cursor position can't be read; returns whether the move was
+/// interpolated. Stops between steps if the call is cancelled.
+pub(crate) fn do_smooth_move(x: f64, y: f64, duration_ms: u64, steps: u64, easing: Easing) -> Result<bool, String> {
+    let Some(from) = cursor_position() else {
+        do_mouse_move(x, y)?;
+        return Ok(false);
+    };
+
+    let start = Instant::now();
+    for i in 1..=steps {
+        let t = easing.apply(i as f64 / steps as f64);
+        do_mouse_move(from.0 + (x - from.0) * t, from.1 + (y - from.1) * t)?;
+        if i < steps {
+            // Sleep to each step's scheduled time so slow event posting
+            // doesn't stretch the move
+            let due = start + Duration::from_millis(duration_ms * i / steps);
+            sleep_cancellable(due.saturating_duration_since(Instant::now()))?;
+        }
+    }
+    Ok(true)
+}
+
+pub(crate) fn parse_key(key_str: &str) -> Option<Key> {
+    match key_str.to_lowercase().as_str() {
+        "a" => Some(Key::KeyA),
+        "b" => Some(Key::KeyB),
+        "c" => Some(Key::KeyC),
+        "d" => Some(Key::KeyD),
+        "e" => Some(Key::KeyE),
+        "f" => Some(Key::KeyF),
+        "g" => Some(Key::KeyG),
+        "h" => Some(Key::KeyH),
+        "i" => Some(Key::KeyI),
+        "j" => Some(Key::KeyJ),
+        "k" => Some(Key::KeyK),
+        "l" => Some(Key::KeyL),
+        "m" => Some(Key::KeyM),
+        "n" => Some(Key::KeyN),
+        "o" => Some(Key::KeyO),
+        "p" => Some(Key::KeyP),
+        "q" => Some(Key::KeyQ),
+        "r" => Some(Key::KeyR),
+        "s" => Some(Key::KeyS),
+        "t" => Some(Key::KeyT),
+        "u" => Some(Key::KeyU),
+        "v" => Some(Key::KeyV),
+        "w" => Some(Key::KeyW),
+        "x" => Some(Key::KeyX),
+        "y" => Some(Key::KeyY),
+        "z" => Some(Key::KeyZ),
+        "0" => Some(Key::Num0),
+        "1" => Some(Key::Num1),
+        "2" => Some(Key::Num2),
+        "3" => Some(Key::Num3),
+        "4" => Some(Key::Num4),
+        "5" => Some(Key::Num5),
+        "6" => Some(Key::Num6),
+        "7" => Some(Key::Num7),
+        "8" => Some(Key::Num8),
+        "9" => Some(Key::Num9),
+        "enter" | "return" => Some(Key::Return),
+        "tab" => Some(Key::Tab),
+        "space" | " " => Some(Key::Space),
+        "backspace" => Some(Key::Backspace),
+        "delete" => Some(Key::Delete),
+        "escape" | "esc" => Some(Key::Escape),
+        "up" | "uparrow" => Some(Key::UpArrow),
+        "down" | "downarrow" => Some(Key::DownArrow),
+        "left" | "leftarrow" => Some(Key::LeftArrow),
+        "right" | "rightarrow" => Some(Key::RightArrow),
+        "home" => Some(Key::Home),
+        "end" => Some(Key::End),
+        "pageup" => Some(Key::PageUp),
+        "pagedown" => Some(Key::PageDown),
+        "f1" => Some(Key::F1),
+        "f2" => Some(Key::F2),
+        "f3" => Some(Key::F3),
+        "f4" => Some(Key::F4),
+        "f5" => Some(Key::F5),
+        "f6" => Some(Key::F6),
+        "f7" => Some(Key::F7),
+        "f8" => Some(Key::F8),
+        "f9" => Some(Key::F9),
+        "f10" => Some(Key::F10),
+        "f11" => Some(Key::F11),
+        "f12" => Some(Key::F12),
+        "capslock" => Some(Key::CapsLock),
+        "shift" => Some(Key::ShiftLeft),
+        "control" | "ctrl" => Some(Key::ControlLeft),
+        "alt" | "option" => Some(Key::Alt),
+        "meta" | "command" | "cmd" | "super" | "win" => Some(Key::MetaLeft),
+        "-" | "minus" => Some(Key::Minus),
+        "=" | "equal" => Some(Key::Equal),
+        "[" | "leftbracket" => Some(Key::LeftBracket),
+        "]" | "rightbracket" => Some(Key::RightBracket),
+        "\\" | "backslash" => Some(Key::BackSlash),
+        ";" | "semicolon" => Some(Key::SemiColon),
+        "'" | "quote" => Some(Key::Quote),
+        "," | "comma" => Some(Key::Comma),
+        "." | "dot" | "period" => Some(Key::Dot),
+        "/" | "slash" => Some(Key::Slash),
+        "`" | "backquote" | "grave" => Some(Key::BackQuote),
+        "kp0" => Some(or_mac_code(Key::Kp0, 82)),
+        "kp1" => Some(or_mac_code(Key::Kp1, 83)),
+        "kp2" => Some(or_mac_code(Key::Kp2, 84)),
+        "kp3" => Some(or_mac_code(Key::Kp3, 85)),
+        "kp4" => Some(or_mac_code(Key::Kp4, 86)),
+        "kp5" => Some(or_mac_code(Key::Kp5, 87)),
+        "kp6" => Some(or_mac_code(Key::Kp6, 88)),
+        "kp7" => Some(or_mac_code(Key::Kp7, 89)),
+        "kp8" => Some(or_mac_code(Key::Kp8, 91)),
+        "kp9" => Some(or_mac_code(Key::Kp9, 92)),
+        // Windows has no separate keypad Enter key code
+        "kpenter" | "kpreturn" => Some(if cfg!(target_os = "windows") {
+            Key::Return
+        } else {
+            or_mac_code(Key::KpReturn, 76)
+        }),
+        "kpplus" => Some(or_mac_code(Key::KpPlus, 69)),
+        "kpminus" => Some(or_mac_code(Key::KpMinus, 78)),
+        "kpmultiply" => Some(or_mac_code(Key::KpMultiply, 67)),
+        "kpdivide" => Some(or_mac_code(Key::KpDivide, 75)),
+        "kpdecimal" => Some(or_mac_code(Key::KpDelete, 65)),
+        // On Mac keyboards these are Help/Clear and F13-F15, at the same positions
+        "insert" | "ins" => Some(or_mac_code(Key::Insert, 114)),
+        "printscreen" | "prtsc" => Some(or_mac_code(Key::PrintScreen, 105)),
+        "scrolllock" => Some(or_mac_code(Key::ScrollLock, 107)),
+        "pause" => Some(or_mac_code(Key::Pause, 113)),
+        "numlock" => Some(or_mac_code(Key::NumLock, 71)),
+        "menu" | "contextmenu" | "apps" => Some(native_key(110, 135, 0x5D)),
+        "rshift" => Some(Key::ShiftRight),
+        "rctrl" | "rcontrol" => Some(Key::ControlRight),
+        "ralt" | "roption" => Some(Key::AltGr),
+        "rmeta" | "rcommand" | "rcmd" | "rsuper" | "rwin" => Some(if cfg!(target_os = "macos") {
+            Key::MetaRight
+        } else {
+            native_key(54, 134, 0x5C)
+        }),
+        name => native_key_code(name).map(Key::Unknown),
+    }
+}
+
+/// Names of the media keys, which macOS delivers as system-defined events
+/// rather than key codes.
+const MEDIA_KEYS: &[&str] = &[
+    "volumeup",
+    "volumedown",
+    "volumemute",
+    "playpause",
+    "nexttrack",
+    "prevtrack",
+    "stop",
+];
+
+/// Native key codes for keys rdev has no variant for: F13-F24 and, outside
+/// macOS, the media keys.
+#[cfg(target_os = "macos")]
+fn native_key_code(name: &str) -> Option<u32> {
+    // Virtual key codes; Mac keyboards stop at F20
+    match name {
+        "f13" => Some(105),
+        "f14" => Some(107),
+        "f15" => Some(113),
+        "f16" => Some(106),
+        "f17" => Some(64),
+        "f18" => Some(79),
+        "f19" => Some(80),
+        "f20" => Some(90),
+        _ => None,
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn native_key_code(name: &str) -> Option<u32> {
+    // Virtual-key codes
+    match name {
+        "f13" => Some(0x7C),
+        "f14" => Some(0x7D),
+        "f15" => Some(0x7E),
+        "f16" => Some(0x7F),
+        "f17" => Some(0x80),
+        "f18" => Some(0x81),
+        "f19" => Some(0x82),
+        "f20" => Some(0x83),
+        "f21" => Some(0x84),
+        "f22" => Some(0x85),
+        "f23" => Some(0x86),
+        "f24" => Some(0x87),
+        "volumemute" => Some(0xAD),
+        "volumedown" => Some(0xAE),
+        "volumeup" => Some(0xAF),
+        "nexttrack" => Some(0xB0),
+        "prevtrack" => Some(0xB1),
+        "stop" => Some(0xB2),
+        "playpause" => Some(0xB3),
+        _ => None,
+    }
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "windows")))]
+fn native_key_code(name: &str) -> Option<u32> {
+    // X11 keycodes (evdev codes plus 8)
+    match name {
+        "f13" => Some(191),
+        "f14" => Some(192),
+        "f15" => Some(193),
+        "f16" => Some(194),
+        "f17" => Some(195),
+        "f18" => Some(196),
+        "f19" => Some(197),
+        "f20" => Some(198),
+        "f21" => Some(199),
+        "f22" => Some(200),
+        "f23" => Some(201),
+        "f24" => Some(202),
+        "volumemute" => Some(121),
+        "volumedown" => Some(122),
+        "volumeup" => Some(123),
+        "nexttrack" => Some(171),
+        "playpause" => Some(172),
+        "prevtrack" => Some(173),
+        "stop" => Some(174),
+        _ => None,
+    }
+}
+
+/// Look up a key by name, explaining why when it can't be sent.
+pub(crate) fn resolve_key(key_str: &str) -> Result<Key, String> {
+    parse_key(key_str).ok_or_else(|| {
+        if cfg!(target_os = "macos") && MEDIA_KEYS.contains(&key_str.to_lowercase().as_str()) {
+            format!("Media keys can't be sent on macOS: {}", key_str)
+        } else {
+            format!("Unknown key: {}", key_str)
+        }
+    })
+}
+
+/// rdev's macOS backend has no key codes for the keypad or the PC-only
+/// keys, so on macOS send those by their virtual key code instead.
+fn or_mac_code(key: Key, mac_code: u32) -> Key {
+    if cfg!(target_os = "macos") {
+        Key::Unknown(mac_code)
+    } else {
+        key
+    }
+}
+
+/// A key rdev has no variant for on some platforms, by its native key code:
+/// macOS virtual key code, X11 keycode, Windows virtual-key code.
+fn native_key(mac_code: u32, x11_code: u32, windows_code: u32) -> Key {
+    Key::Unknown(if cfg!(target_os = "macos") {
+        mac_code
+    } else if cfg!(target_os = "windows") {
+        windows_code
+    } else {
+        x11_code
+    })
+}
+
+pub(crate) fn get_modifier_key(modifier: &str) -> Option<Key> {
+    match modifier.to_lowercase().as_str() {
+        "shift" => Some(Key::ShiftLeft),
+        "control" | "ctrl" => Some(Key::ControlLeft),
+        "alt" | "option" => Some(Key::Alt),
+        "meta" | "command" | "cmd" | "super" | "win" => Some(Key::MetaLeft),
+        _ => None,
+    }
+}
+
+/// Hold modifier keys around an action, releasing them in reverse order
+/// afterwards even if the action fails. Unknown modifiers are rejected
+/// before anything is pressed.
+fn with_modifiers<T>(
+    modifiers: &[String],
+    action: impl FnOnce() -> Result<T, String>,
+) -> Result<T, String> {
+    let keys = modifiers
+        .iter()
+        .map(|m| get_modifier_key(m).ok_or_else(|| format!("Unknown modifier: {}", m)))
+        .collect::<Result<Vec<Key>, String>>()?;
+    if keys.is_empty() {
+        return action();
+    }
+
+    // Every key is released even if an earlier release fails; the first
+    // failure is reported
+    let release = |pressed: &[Key]| -> Result<(), String> {
+        let mut result = Ok(());
+        for key in pressed.iter().rev() {
+            if let Err(e) = send_event(&EventType::KeyRelease(*key)) {
+                result = result.and(Err(format!("Modifier release failed: {:?}", e)));
+            }
+        }
+        result
+    };
+
+    for (i, key) in keys.iter().enumerate() {
+        if let Err(e) = send_event(&EventType::KeyPress(*key)) {
+            let _ = release(&keys[..i]);
+            return Err(format!("Modifier press failed: {:?}", e));
+        }
+    }
+    thread::sleep(Duration::from_millis(10));
+
+    let result = action();
+    thread::sleep(Duration::from_millis(10));
+    let released = release(&keys);
+    let value = result?;
+    released?;
+    Ok(value)
+}
+
+fn do_key_tap(key_str: &str, modifiers: &[String]) -> Result<(), String> {
+    tap_key(resolve_key(key_str)?, modifiers)
+}
+
+pub(crate) fn tap_key(key: Key, modifiers: &[String]) -> Result<(), String> {
+    with_modifiers(modifiers, || {
+        send_event(&EventType::KeyPress(key)).map_err(|e| format!("Key press failed: {:?}", e))?;
+        thread::sleep(Duration::from_millis(10));
+        send_event(&EventType::KeyRelease(key)).map_err(|e| format!("Key release failed: {:?}", e))
+    })
+}
+
+/// Tap `key` `repeat` times, waiting `delay` between taps, with `modifiers`
+/// held across the whole run as they are during a real auto-repeat. Stops
+/// if the call is cancelled, and the modifiers are released however the
+/// run ends. Returns the number of taps.
+pub(crate) fn tap_key_repeated(key: Key, modifiers: &[String], repeat: u64, delay: Duration) -> Result<u64, String> {
+    with_modifiers(modifiers, || {
+        let mut taps = 0;
+        while taps < repeat {
+            if taps > 0 {
+                sleep_cancellable(delay)?;
+            }
+            send_event(&EventType::KeyPress(key))
+                .map_err(|e| format!("Key press failed after {} of {} taps: {:?}", taps, repeat, e))?;
+            thread::sleep(Duration::from_millis(10));
+            send_event(&EventType::KeyRelease(key))
+                .map_err(|e| format!("Key release failed after {} of {} taps: {:?}", taps, repeat, e))?;
+            taps += 1;
+        }
+        Ok(taps)
+    })
+}
+
+/// Whether `key` is a modifier, which key_hold only holds on request since
+/// a stuck one changes every later key press.
+pub(crate) fn is_modifier_key(key: Key) -> bool {
+    matches!(
+        key,
+        Key::ShiftLeft
+            | Key::ShiftRight
+            | Key::ControlLeft
+            | Key::ControlRight
+            | Key::Alt
+            | Key::AltGr
+            | Key::MetaLeft
+            | Key::MetaRight
+    )
+}
+
+/// Press `key` with `modifiers` held, keep it down for `duration`, then
+/// release. The wait checks for cancellation (which the failsafe triggers
+/// too) as it goes, and the key and modifiers are released however it
+/// ends. Returns how long the key was actually held.
+pub(crate) fn do_key_hold(key: Key, modifiers: &[String], duration: Duration) -> Result<Duration, String> {
+    with_modifiers(modifiers, || {
+        send_event(&EventType::KeyPress(key)).map_err(|e| format!("Key press failed: {:?}", e))?;
+        let pressed = Instant::now();
+        let wait = sleep_cancellable(duration);
+        let held = pressed.elapsed();
+        let release =
+            send_event(&EventType::KeyRelease(key)).map_err(|e| format!("Key release failed: {:?}", e));
+        wait?;
+        release?;
+        Ok(held)
+    })
+}
+
+/// Upper bound on the total number of taps in a key_sequence, counting
+/// repeats.
+pub(crate) const MAX_KEY_SEQUENCE_TAPS: u64 = 1000;
+
+/// Upper bound on key_sequence's delay_ms.
+pub(crate) const MAX_KEY_SEQUENCE_DELAY_MS: u64 = 5000;
+
+/// One element of a key_sequence: a key, the modifiers held around it and
+/// how many times to tap it.
+pub(crate) struct KeyStep {
+    name: String,
+    key: Key,
+    modifiers: Vec<String>,
+    pub(crate) repeat: u64,
+}
+
+impl KeyStep {
+    /// Parse a plain key name or a {key, modifiers, repeat} object,
+    /// checking the key and modifiers exist and the repeat is in range so
+    /// nothing is sent for a sequence that would fail part-way through.
+    pub(crate) fn parse(step: &Value) -> Result<Self, String> {
+        let (name, modifiers, repeat) = match step {
+            Value::String(name) => (name.clone(), Vec::new(), 1),
+            Value::Object(_) => (
+                step.get("key")
+                    .and_then(|v| v.as_str())
+                    .ok_or("Missing key")?
+                    .to_string(),
+                parse_modifiers(step),
+                step.get("repeat").and_then(|v| v.as_u64()).unwrap_or(1),
+            ),
+            _ => return Err("Expected a key name or {key, modifiers, repeat}".to_string()),
+        };
+        if repeat > MAX_KEY_SEQUENCE_TAPS {
+            return Err(format!("repeat is {} (max {})", repeat, MAX_KEY_SEQUENCE_TAPS));
+        }
+        let key = resolve_key(&name)?;
+        if let Some(unknown) = modifiers.iter().find(|m| get_modifier_key(m).is_none()) {
+            return Err(format!("Unknown modifier: {}", unknown));
+        }
+        Ok(KeyStep {
+            name,
+            key,
+            modifiers,
+            repeat,
+        })
+    }
+}
+
+/// Tap each step's key in order, waiting `delay` between taps and stopping
+/// there if the call is cancelled. On failure, reports the index of the
+/// failing step; modifiers are released either way. Returns the number of
+/// taps sent.
+pub(crate) fn do_key_sequence(steps: &[KeyStep], delay: Duration) -> Result<u64, String> {
+    let mut taps = 0;
+    for (i, step) in steps.iter().enumerate() {
+        for _ in 0..step.repeat {
+            if taps > 0 {
+                sleep_cancellable(delay)?;
+            }
+            tap_key(step.key, &step.modifiers).map_err(|e| {
+                format!(
+                    "keys[{}] ({}) failed after {} taps: {}",
+                    i, step.name, taps, e
+                )
+            })?;
+            taps += 1;
+        }
+    }
+    Ok(taps)
+}
+
+/// Pauses between type_text keystrokes: a fixed delay, or one drawn from
+/// [min_ms, max_ms] per character. Humanized, sentence ends and sometimes
+/// other punctuation are followed by a longer pause. Randomness comes from
+/// the seed, so a rhythm can be replayed exactly.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct TypingRhythm {
+    pub(crate) min_ms: u64,
+    pub(crate) max_ms: u64,
+    pub(crate) humanize: bool,
+    pub(crate) seed: u64,
+}
+
+impl TypingRhythm {
+    pub(crate) fn fixed(delay_ms: u64) -> Self {
+        TypingRhythm {
+            min_ms: delay_ms,
+            max_ms: delay_ms,
+            humanize: false,
+            seed: 0,
+        }
+    }
+
+    /// A rhythm around `delay_ms`. `jitter_ms` spreads it either way, and
+    /// `min_ms`/`max_ms` set either end directly. Humanized without a range,
+    /// delays vary by half the delay either way.
+    pub(crate) fn new(
+        delay_ms: u64,
+        jitter_ms: Option<u64>,
+        min_ms: Option<u64>,
+        max_ms: Option<u64>,
+        humanize: bool,
+        seed: Option<u64>,
+    ) -> Result<Self, String> {
+        let jitter = jitter_ms.unwrap_or(if humanize && min_ms.is_none() && max_ms.is_none() {
+            delay_ms / 2
+        } else {
+            0
+        });
+        let min_ms = min_ms.unwrap_or(delay_ms.saturating_sub(jitter));
+        let max_ms = max_ms.unwrap_or(delay_ms + jitter);
+        if min_ms > max_ms {
+            return Err(format!(
+                "min_delay_ms ({}) is above max_delay_ms ({})",
+                min_ms, max_ms
+            ));
+        }
+        Ok(TypingRhythm {
+            min_ms,
+            max_ms,
+            humanize,
+            seed: seed.unwrap_or_else(random_seed),
+        })
+    }
+
+    /// Whether delays vary, so that the seed matters.
+    pub(crate) fn is_random(&self) -> bool {
+        self.humanize || self.min_ms < self.max_ms
+    }
+
+    /// The pause after typing `c`.
+    fn delay_after(&self, c: char, rng: &mut Rng) -> u64 {
+        let mut delay = if self.min_ms < self.max_ms {
+            rng.range(self.min_ms as f64, self.max_ms as f64 + 1.0) as u64
+        } else {
+            self.min_ms
+        };
+        if self.humanize {
+            match c {
+                '.' | '!' | '?' | '\n' => delay += rng.range(150.0, 450.0) as u64,
+                ',' | ';' | ':' if rng.range(0.0, 1.0) < 0.5 => delay += rng.range(60.0, 200.0) as u64,
+                _ => {}
+            }
+        }
+        delay
+    }
+}
+
+/// What do_type_text actually typed.
+#[derive(Debug, Default)]
+pub(crate) struct TypedText {
+    /// The characters emitted as key events.
+    typed: String,
+    /// Characters with no key mapping, by character index in the input.
+    skipped: Vec<(usize, char)>,
+    /// Time taken, including the pause after the last character.
+    elapsed: Duration,
+}
+
+impl TypedText {
+    /// Lengths are counted over what was typed, in the units a caller might
+    /// compare against: Unicode scalar values, UTF-16 code units and UTF-8
+    /// bytes.
+    pub(crate) fn to_json(&self, requested: &str) -> Value {
+        json!({
+            "chars": self.typed.chars().count(),
+            "utf16_units": self.typed.encode_utf16().count(),
+            "bytes": self.typed.len(),
+            "requested_chars": requested.chars().count(),
+            "complete": self.skipped.is_empty(),
+            "elapsed_ms": self.elapsed.as_millis() as u64,
+            "skipped": self.skipped.iter().map(|&(index, c)| json!({
+                "index": index,
+                "char": c.to_string(),
+                "codepoint": format!("U+{:04X}", c as u32)
+            })).collect::<Vec<_>>()
+        })
+    }
+}
+
+/// The first character of `text` that no key produces on `layout`, with
+/// its character index.
+pub(crate) fn first_untypable(text: &str, layout: &KeyboardLayout) -> Option<(usize, char)> {
+    text.chars().enumerate().find(|&(_, c)| layout.key_for(c).0.is_none())
+}
+
+/// Type text key by key, choosing keys for `layout`. Characters without a
+/// key on it are skipped and reported rather than failing the whole call.
+pub(crate) fn do_type_text(text: &str, rhythm: &TypingRhythm, layout: &KeyboardLayout) -> Result<TypedText, String> {
+    let mut result = TypedText::default();
+    let mut rng = Rng(rhythm.seed);
+    let start = Instant::now();
+    for (index, c) in text.chars().enumerate() {
+        check_cancelled()?;
+        let (key, needs_shift) = layout.key_for(c);
+
+        if let Some(k) = key {
+            if needs_shift {
+                send_event(&EventType::KeyPress(Key::ShiftLeft))
+                    .map_err(|e| format!("Shift press failed: {:?}", e))?;
+                thread::sleep(Duration::from_millis(5));
+            }
+
+            // Shift is released even if the key fails
+            let tapped = (|| -> Result<(), String> {
+                send_event(&EventType::KeyPress(k)).map_err(|e| format!("Key press failed: {:?}", e))?;
+                thread::sleep(Duration::from_millis(5));
+                send_event(&EventType::KeyRelease(k)).map_err(|e| format!("Key release failed: {:?}", e))
+            })();
+
+            if needs_shift {
+                thread::sleep(Duration::from_millis(5));
+                let release = send_event(&EventType::KeyRelease(Key::ShiftLeft))
+                    .map_err(|e| format!("Shift release failed: {:?}", e));
+                tapped?;
+                release?;
+            } else {
+                tapped?;
+            }
+
+            result.typed.push(c);
+            sleep_cancellable(Duration::from_millis(rhythm.delay_after(c, &mut rng)))?;
+        } else {
+            result.skipped.push((index, c));
+        }
+    }
+
+    result.elapsed = start.elapsed();
+    Ok(result)
+}
+
+/// The modifier for standard shortcuts such as copy and paste: Cmd on
+/// macOS, Ctrl elsewhere.
+pub(crate) fn shortcut_modifier() -> &'static str {
+    if cfg!(target_os = "macos") {
+        "meta"
+    } else {
+        "control"
+    }
+}
+
+/// Upper bound on paste_text's settle_ms.
+pub(crate) const MAX_PASTE_SETTLE_MS: u64 = 5_000;
+
+/// Paste `text` by putting it on the clipboard and sending the paste
+/// shortcut, then waiting `settle` for the app to read it. With `restore`,
+/// the clipboard's previous text is put back afterwards, even if the paste
+/// failed; a failure to restore is returned rather than failing the paste.
+/// A dry run leaves the clipboard alone.
+pub(crate) fn do_paste_text(text: &str, settle: Duration, restore: bool) -> Result<Option<String>, String> {
+    let paste = || do_key_tap("v", &[shortcut_modifier().to_string()]);
+    if dry_run() {
+        paste()?;
+        return Ok(None);
+    }
+
+    let saved = restore.then(clipboard_read);
+    clipboard_write(text)?;
+    let pasted = (|| {
+        paste()?;
+        sleep_cancellable(settle)
+    })();
+
+    let restore_error = match saved {
+        Some(Ok(saved)) => clipboard_write(&saved).err(),
+        Some(Err(e)) => Some(format!("Couldn't read the clipboard to restore it: {}", e)),
+        None => None,
+    };
+    pasted?;
+    Ok(restore_error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mouse_button_rejects_typos_listing_every_name() {
+        for typo in ["rigth", "lef", "midle", "x3", ""] {
+            let error = mouse_button(typo).unwrap_err();
+            assert!(error.contains(&format!("{:?}", typo)), "{}", error);
+            assert!(error.ends_with(&MOUSE_BUTTONS.join(", ")), "{}", error);
+        }
+    }
+
+    #[test]
+    fn mouse_button_accepts_every_listed_name() {
+        for name in MOUSE_BUTTONS {
+            assert!(mouse_button(name).is_ok(), "{}", name);
+        }
+        assert_eq!(mouse_button(" Middle ").unwrap(), Button::Middle);
+        assert_eq!(mouse_button("x1").unwrap(), BACK_BUTTON);
+        assert_eq!(mouse_button("X2").unwrap(), FORWARD_BUTTON);
+        assert_eq!(button_name(mouse_button("back").unwrap()), "back");
+    }
+
+    const DESKTOP: (f64, f64, f64, f64) = (0.0, 0.0, 1919.0, 1079.0);
+
+    #[test]
+    fn humanized_paths_start_and_end_at_the_requested_points() {
+        for seed in 0..50 {
+            let path = humanized_path((100.0, 900.0), (1500.0, 200.0), 40, DESKTOP, &mut Rng(seed));
+            assert_eq!(path.len(), 41, "seed {}", seed);
+            assert_eq!(path[0], (100.0, 900.0), "seed {}", seed);
+            assert_eq!(path[path.len() - 1], (1500.0, 200.0), "seed {}", seed);
+        }
+    }
+
+    #[test]
+    fn humanized_paths_stay_on_the_desktop_when_clamped() {
+        // Toward a corner and along an edge, where the overshoot and the
+        // bow would otherwise leave the desktop
+        let (left, top, right, bottom) = DESKTOP;
+        for (from, to) in [
+            ((960.0, 540.0), (right, bottom)),
+            ((960.0, 540.0), (left, top)),
+            ((10.0, top), (1900.0, top)),
+            ((right, 10.0), (right, 1070.0)),
+        ] {
+            for seed in 0..50 {
+                let path = humanized_path(from, to, 40, DESKTOP, &mut Rng(seed));
+                assert_eq!((path[0], path[path.len() - 1]), (from, to), "seed {}", seed);
+                for &(x, y) in &path {
+                    assert!(
+                        (left..=right).contains(&x) && (top..=bottom).contains(&y),
+                        "({}, {}) off the desktop going {:?} to {:?}, seed {}",
+                        x,
+                        y,
+                        from,
+                        to,
+                        seed
+                    );
+                }
+            }
+        }
+    }
+
+    fn typed(text: &str) -> Value {
+        let typed = TypedText {
+            typed: text.to_string(),
+            ..Default::default()
+        };
+        typed.to_json(text)
+    }
+
+    #[test]
+    fn typed_text_is_counted_in_every_unit() {
+        for (text, chars, utf16_units, bytes) in [
+            ("abc", 3, 3, 3),
+            // Thumbs up with a skin tone: two scalars, each a surrogate pair
+            ("\u{1F44D}\u{1F3FD}", 2, 4, 8),
+            // A family joined by zero-width joiners
+            ("\u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}", 5, 8, 18),
+            // e with a combining acute accent, and precomposed é
+            ("e\u{301}", 2, 2, 3),
+            ("\u{E9}", 1, 1, 2),
+            // CJK in the basic plane, and an extension B ideograph outside it
+            ("日本語", 3, 3, 9),
+            ("\u{2000B}", 1, 2, 4),
+        ] {
+            let result = typed(text);
+            assert_eq!(result["chars"], json!(chars), "{:?}", text);
+            assert_eq!(result["utf16_units"], json!(utf16_units), "{:?}", text);
+            assert_eq!(result["bytes"], json!(bytes), "{:?}", text);
+            assert_eq!(result["requested_chars"], json!(chars), "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn skipped_characters_are_reported_by_character_index() {
+        let requested = "a\u{1F44D}e\u{301}日";
+        let typed = TypedText {
+            typed: "ae".to_string(),
+            skipped: vec![(1, '\u{1F44D}'), (3, '\u{301}'), (4, '日')],
+            ..Default::default()
+        };
+        let result = typed.to_json(requested);
+        assert_eq!(result["chars"], json!(2));
+        assert_eq!(result["requested_chars"], json!(5));
+        assert_eq!(result["complete"], json!(false));
+        assert_eq!(
+            result["skipped"],
+            json!([
+                { "index": 1, "char": "\u{1F44D}", "codepoint": "U+1F44D" },
+                { "index": 3, "char": "\u{301}", "codepoint": "U+0301" },
+                { "index": 4, "char": "日", "codepoint": "U+65E5" }
+            ])
+        );
+    }
+
+    /// "f1-f24" as f1 to f24; other names as they are.
+    fn expand_range(name: &str) -> Vec<String> {
+        let Some((first, last)) = name.split_once('-') else {
+            return vec![name.to_string()];
+        };
+        let prefix = first.trim_end_matches(|c: char| c.is_ascii_digit());
+        let number = |s: &str| s[prefix.len()..].parse::<u32>().unwrap();
+        (number(first)..=number(last)).map(|n| format!("{}{}", prefix, n)).collect()
+    }
+
+    /// The key names key_tap's schema documents for this platform, read
+    /// from its description: ranges expanded, group labels such as
+    /// "keypad" dropped and notes on macOS applied.
+    fn documented_key_names() -> Vec<String> {
+        let schema = crate::tools::find_tool("key_tap").unwrap().schema();
+        let description = schema["properties"]["key"]["description"].as_str().unwrap().to_string();
+        let (_, list) = description.split_once("): ").unwrap();
+        let mut names = Vec::new();
+        // The first clause describes characters rather than naming keys
+        for clause in list.split("; ").skip(1) {
+            if cfg!(target_os = "macos") && clause.contains("(not on macOS)") {
+                continue;
+            }
+            for item in clause.split(", ").flat_map(|item| item.split(" and ")) {
+                let (item, note) = match item.split_once(" (") {
+                    Some((item, note)) => (item, Some(note.trim_end_matches(')'))),
+                    None => (item, None),
+                };
+                let item = match note.and_then(|note| note.strip_suffix(" on macOS")) {
+                    Some(macos) if cfg!(target_os = "macos") => macos,
+                    _ => item,
+                };
+                names.extend(expand_range(item.rsplit(' ').next().unwrap()));
+            }
+        }
+        names
+    }
+
+    #[test]
+    fn every_documented_key_name_parses() {
+        let names = documented_key_names();
+        for expected in ["enter", "f12", "kp9", "kpdecimal", "menu", "rmeta"] {
+            assert!(names.iter().any(|n| n == expected), "{} missing from {:?}", expected, names);
+        }
+        for name in &names {
+            assert!(parse_key(name).is_some(), "{} is documented but doesn't parse", name);
+            assert!(parse_key(&name.to_uppercase()).is_some(), "{} doesn't parse in upper case", name);
+        }
+        // Letters, digits and punctuation, from the first clause
+        for c in ('a'..='z').chain('0'..='9').chain("-=[]\\;',./`".chars()) {
+            assert!(parse_key(&c.to_string()).is_some(), "{:?} doesn't parse", c);
+        }
+    }
+
+    #[test]
+    fn unknown_keys_are_named_in_the_error() {
+        assert_eq!(resolve_key("kp10").unwrap_err(), "Unknown key: kp10");
+        assert_eq!(resolve_key("f25").unwrap_err(), "Unknown key: f25");
+    }
+
+    /// The pauses do_type_text would make typing `text` with `rhythm`.
+    fn delays(rhythm: &TypingRhythm, text: &str) -> Vec<u64> {
+        let mut rng = Rng(rhythm.seed);
+        text.chars().map(|c| rhythm.delay_after(c, &mut rng)).collect()
+    }
+
+    #[test]
+    fn the_same_seed_gives_the_same_typing_rhythm() {
+        let text = "Hello, world. Is this typed the same way twice? Yes; every time!";
+        let rhythm = |seed| TypingRhythm::new(60, Some(40), None, None, true, Some(seed)).unwrap();
+        for seed in [0, 1, 42, u64::MAX] {
+            assert_eq!(delays(&rhythm(seed), text), delays(&rhythm(seed), text), "seed {}", seed);
+        }
+        assert_ne!(delays(&rhythm(1), text), delays(&rhythm(2), text));
+
+        // Without humanizing, only the range varies the delays
+        let plain = |seed| TypingRhythm::new(60, None, Some(20), Some(100), false, Some(seed)).unwrap();
+        let first = delays(&plain(7), text);
+        assert_eq!(first, delays(&plain(7), text));
+        assert!(first.iter().all(|delay| (20..=100).contains(delay)), "{:?}", first);
+    }
+}
@@ -0,0 +1,364 @@
+//! Observing the physical mouse and keyboard, and telling their events
+//! apart from the ones we simulate.
+
+use rdev::{Button, EventType, Key};
+use serde_json::json;
+use std::cell::Cell;
+use std::collections::VecDeque;
+use std::sync::{mpsc, Mutex, Once, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::backend::{displays, input_backend, Display};
+use crate::cancel::{check_cancelled, CANCEL_POLL_INTERVAL};
+use crate::capture::coords::get_mouse_position;
+use crate::capture::{capture_screenshot, CaptureArea, CaptureFormat, EncodeOptions};
+use crate::clock::{Timestamp, FIRST_EVENT};
+use crate::config::loaded_config;
+use crate::input::button_name;
+use crate::retry::retry_transient;
+use crate::session::current_session_id;
+use crate::tools::output::{ContentItem, ToolOutput};
+use crate::window::window_at_point;
+
+// ============================================================================
+// Physical Input Observation
+// ============================================================================
+
+/// How long a simulated event is remembered, so that the listener can tell
+/// our own events apart from the same event coming from real hardware.
+const SYNTHETIC_EVENT_TTL: Duration = Duration::from_millis(250);
+
+static SYNTHETIC_EVENTS: Mutex<VecDeque<(EventType, Instant)>> = Mutex::new(VecDeque::new());
+static PHYSICAL_SUBSCRIBERS: Mutex<Vec<mpsc::Sender<EventType>>> = Mutex::new(Vec::new());
+static LISTENER_ERROR: Mutex<Option<String>> = Mutex::new(None);
+static LISTENER_STARTED: Once = Once::new();
+
+/// When each event in the last second was sent, for max_events_per_second.
+static RECENT_SENDS: Mutex<VecDeque<Instant>> = Mutex::new(VecDeque::new());
+
+thread_local! {
+    /// Whether the call running on this thread had an event refused by
+    /// max_events_per_second.
+    pub(crate) static THROTTLED: Cell<bool> = const { Cell::new(false) };
+
+    /// Number of events simulated on this thread, so a call can tell
+    /// whether it moved the cursor itself.
+    pub(crate) static SENT_EVENTS: Cell<u64> = const { Cell::new(0) };
+}
+
+/// Releases owed for keys and buttons that have been pressed and not yet
+/// released, with the session that pressed them, so input left held by a
+/// failed step or a closed connection can be let go.
+static PENDING_RELEASES: Mutex<Vec<(u64, EventType)>> = Mutex::new(Vec::new());
+
+/// Simulate an input event, remembering it so that the physical input
+/// listener ignores the echo. All input simulation should go through here
+/// or send_drag_move.
+pub(crate) fn send_event(event: &EventType) -> Result<(), rdev::SimulateError> {
+    check_rate(event)?;
+    note_sent(event);
+    let sent = retry_transient("Simulating input", || input_backend().simulate(event));
+    if sent.is_err() && matches!(event, EventType::KeyRelease(_) | EventType::ButtonRelease(_)) {
+        // The key or button is still held, so the release is still owed
+        PENDING_RELEASES.lock().unwrap().push((current_session_id(), *event));
+    }
+    sent
+}
+
+/// Move the cursor with `button` held, as part of a drag, as send_event.
+pub(crate) fn send_drag_move(x: f64, y: f64, button: Button) -> Result<(), rdev::SimulateError> {
+    let event = EventType::MouseMove { x, y };
+    check_rate(&event)?;
+    note_sent(&event);
+    retry_transient("Simulating input", || input_backend().drag_move(x, y, button))
+}
+
+/// Refuse an event that would go over `max_events_per_second`, flagging
+/// the call as throttled. Releases always go through, so that nothing is
+/// left held.
+fn check_rate(event: &EventType) -> Result<(), rdev::SimulateError> {
+    let Some(max) = loaded_config().config.limits.max_events_per_second else {
+        return Ok(());
+    };
+    if matches!(event, EventType::KeyRelease(_) | EventType::ButtonRelease(_)) {
+        return Ok(());
+    }
+    let mut recent = RECENT_SENDS.lock().unwrap();
+    let now = Instant::now();
+    while recent
+        .front()
+        .is_some_and(|t| now.duration_since(*t) >= Duration::from_secs(1))
+    {
+        recent.pop_front();
+    }
+    if recent.len() >= max {
+        THROTTLED.set(true);
+        return Err(rdev::SimulateError);
+    }
+    recent.push_back(now);
+    Ok(())
+}
+
+/// Record an event about to be simulated: what it leaves held, its echo
+/// for the listener to ignore, and the call's first event time.
+fn note_sent(event: &EventType) {
+    {
+        let mut pending = PENDING_RELEASES.lock().unwrap();
+        let release = match *event {
+            EventType::KeyPress(key) | EventType::KeyRelease(key) => Some(EventType::KeyRelease(key)),
+            EventType::ButtonPress(button) | EventType::ButtonRelease(button) => {
+                Some(EventType::ButtonRelease(button))
+            }
+            _ => None,
+        };
+        if let Some(release) = release {
+            pending.retain(|(_, e)| *e != release);
+            if matches!(event, EventType::KeyPress(_) | EventType::ButtonPress(_)) {
+                pending.push((current_session_id(), release));
+            }
+        }
+    }
+    {
+        let mut recent = SYNTHETIC_EVENTS.lock().unwrap();
+        let now = Instant::now();
+        while recent
+            .front()
+            .is_some_and(|(_, t)| now.duration_since(*t) > SYNTHETIC_EVENT_TTL)
+        {
+            recent.pop_front();
+        }
+        recent.push_back((*event, now));
+    }
+    if FIRST_EVENT.get().is_none() {
+        FIRST_EVENT.set(Some(Timestamp::now()));
+    }
+    SENT_EVENTS.set(SENT_EVENTS.get() + 1);
+}
+
+/// Release every key and button the current session's simulated input
+/// still holds, most recently pressed first. Returns what was released.
+pub(crate) fn release_held_input() -> Vec<String> {
+    release_session_input(current_session_id())
+}
+
+/// Release what the session with this id still holds, as
+/// release_held_input, e.g. once its connection has closed.
+pub(crate) fn release_session_input(session_id: u64) -> Vec<String> {
+    let pending: Vec<EventType> = {
+        let mut pending = PENDING_RELEASES.lock().unwrap();
+        let (ours, others): (Vec<_>, Vec<_>) = std::mem::take(&mut *pending)
+            .into_iter()
+            .partition(|&(id, _)| id == session_id);
+        *pending = others;
+        ours.into_iter().map(|(_, release)| release).collect()
+    };
+    pending
+        .into_iter()
+        .rev()
+        .filter(|release| send_event(release).is_ok())
+        .map(|release| match release {
+            EventType::KeyRelease(key) => format!("{:?}", key),
+            EventType::ButtonRelease(button) => format!("{} button", button_name(button)),
+            other => format!("{:?}", other),
+        })
+        .collect()
+}
+
+fn same_event(a: &EventType, b: &EventType) -> bool {
+    match (a, b) {
+        (EventType::MouseMove { x: ax, y: ay }, EventType::MouseMove { x: bx, y: by }) => {
+            (ax - bx).abs() < 1.0 && (ay - by).abs() < 1.0
+        }
+        _ => a == b,
+    }
+}
+
+/// Whether an observed event is the echo of one we simulated. Each simulated
+/// event is matched at most once.
+fn is_synthetic(event: &EventType) -> bool {
+    let mut recent = SYNTHETIC_EVENTS.lock().unwrap();
+    let now = Instant::now();
+    let pos = recent
+        .iter()
+        .position(|(e, t)| now.duration_since(*t) <= SYNTHETIC_EVENT_TTL && same_event(e, event));
+    match pos {
+        Some(pos) => {
+            recent.remove(pos);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Subscribe to events from real hardware, as observed by the input
+/// backend. We only ever observe events, never intercept them.
+pub(crate) fn subscribe_physical_input() -> Result<mpsc::Receiver<EventType>, String> {
+    input_backend().start_listener()?;
+    let (tx, rx) = mpsc::channel();
+    PHYSICAL_SUBSCRIBERS.lock().unwrap().push(tx);
+    Ok(rx)
+}
+
+/// Hand an event observed on real hardware to every subscriber, unless
+/// it's the echo of one we simulated. Returns whether anyone is subscribed.
+pub(crate) fn deliver_physical(event: EventType) -> bool {
+    let mut subscribers = PHYSICAL_SUBSCRIBERS.lock().unwrap();
+    if !is_synthetic(&event) {
+        subscribers.retain(|tx| tx.send(event).is_ok());
+    }
+    !subscribers.is_empty()
+}
+
+/// Start the global listener thread that feeds deliver_physical from the
+/// OS, on first use. Fails if the OS won't let us listen.
+pub(crate) fn start_system_listener() -> Result<(), String> {
+    LISTENER_STARTED.call_once(|| {
+        thread::spawn(|| {
+            let result = rdev::listen(|event| {
+                deliver_physical(event.event_type);
+            });
+            if let Err(e) = result {
+                log::error!("Input listener failed: {:?}", e);
+                *LISTENER_ERROR.lock().unwrap() = Some(format!("{:?}", e));
+                // Dropping the senders disconnects every waiting subscriber
+                PHYSICAL_SUBSCRIBERS.lock().unwrap().clear();
+            }
+        });
+    });
+
+    match LISTENER_ERROR.lock().unwrap().as_ref() {
+        Some(e) => Err(listener_unavailable(e)),
+        None => Ok(()),
+    }
+}
+
+fn listener_unavailable(error: &str) -> String {
+    format!(
+        "Physical input listener unavailable ({}). On macOS, grant Input Monitoring/Accessibility permission.",
+        error
+    )
+}
+
+pub(crate) fn listener_error() -> String {
+    listener_unavailable(LISTENER_ERROR.lock().unwrap().as_deref().unwrap_or("stopped"))
+}
+
+/// Map right-hand modifier variants onto the left-hand ones, so chords match
+/// whichever side the user presses.
+pub(crate) fn normalize_modifier(key: Key) -> Key {
+    match key {
+        Key::ShiftRight => Key::ShiftLeft,
+        Key::ControlRight => Key::ControlLeft,
+        Key::MetaRight => Key::MetaLeft,
+        Key::AltGr => Key::Alt,
+        other => other,
+    }
+}
+
+// ============================================================================
+// User Click Picker
+// ============================================================================
+
+/// Upper bound on how long wait_for_user_click may block.
+pub(crate) const MAX_USER_CLICK_TIMEOUT_MS: u64 = 120_000;
+
+/// wait_for_user_click listens to the real mouse, so it's opt-in via
+/// `DESKTOP_MCP_ENABLE_USER_CLICK=1`.
+pub(crate) fn user_click_enabled() -> bool {
+    static ENABLED: OnceLock<bool> = OnceLock::new();
+    *ENABLED.get_or_init(|| {
+        std::env::var("DESKTOP_MCP_ENABLE_USER_CLICK")
+            .is_ok_and(|v| matches!(v.trim(), "1" | "true" | "yes"))
+    })
+}
+
+/// Find the screen containing a global logical point.
+pub(crate) fn screen_at_point(x: f64, y: f64) -> Result<Option<(usize, Display)>, String> {
+    let screens = displays()?;
+    Ok(screens.into_iter().enumerate().find(|(_, info)| {
+        x >= info.x as f64
+            && y >= info.y as f64
+            && x < info.x as f64 + info.width as f64
+            && y < info.y as f64 + info.height as f64
+    }))
+}
+
+/// Wait for the user to click a physical mouse button and report where.
+/// The click is only observed, never swallowed, so it still reaches whatever
+/// is under the cursor. A physical Escape cancels the wait.
+pub(crate) fn wait_for_user_click(timeout_ms: u64, capture_size: Option<u32>) -> Result<ToolOutput, String> {
+    let rx = subscribe_physical_input()?;
+    let start = Instant::now();
+    let deadline = start + Duration::from_millis(timeout_ms);
+
+    // Button events carry no position, so track the pointer from move events,
+    // seeded with the current position where the platform can report it.
+    let mut position = get_mouse_position()
+        .ok()
+        .and_then(|p| Some((p.get("x")?.as_f64()?, p.get("y")?.as_f64()?)));
+
+    let button = loop {
+        check_cancelled()?;
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        match rx.recv_timeout(remaining.min(CANCEL_POLL_INTERVAL)) {
+            Ok(EventType::MouseMove { x, y }) => position = Some((x, y)),
+            Ok(EventType::ButtonPress(button)) => break button,
+            Ok(EventType::KeyPress(Key::Escape)) => {
+                return Ok(ToolOutput::json(&json!({
+                    "clicked": false,
+                    "cancelled": true,
+                    "waited_ms": start.elapsed().as_millis() as u64
+                })));
+            }
+            Ok(_) => {}
+            Err(mpsc::RecvTimeoutError::Timeout) if Instant::now() < deadline => {}
+            Err(mpsc::RecvTimeoutError::Timeout) => {
+                return Ok(ToolOutput::json(&json!({
+                    "clicked": false,
+                    "timed_out": true,
+                    "waited_ms": start.elapsed().as_millis() as u64
+                })));
+            }
+            Err(mpsc::RecvTimeoutError::Disconnected) => return Err(listener_error()),
+        }
+    };
+
+    let (x, y) = position.ok_or("Click observed but the pointer position is unknown")?;
+    let screen = screen_at_point(x, y)?;
+    let result = json!({
+        "clicked": true,
+        "x": x,
+        "y": y,
+        "button": button_name(button),
+        "screen_index": screen.as_ref().map(|(i, _)| *i),
+        // Null where windows can't be listed
+        "window": window_at_point(x, y).ok().flatten().map(|w| w.to_json()),
+        "waited_ms": start.elapsed().as_millis() as u64
+    });
+
+    let mut output = ToolOutput::json(&result);
+    if let (Some(size), Some((index, info))) = (capture_size, screen) {
+        // Capture a square around the click, relative to its screen
+        let half = size as f64 / 2.0;
+        let region_x = (x - info.x as f64 - half).max(0.0) as i32;
+        let region_y = (y - info.y as f64 - half).max(0.0) as i32;
+        let width = size.min(info.width.saturating_sub(region_x as u32));
+        let height = size.min(info.height.saturating_sub(region_y as u32));
+        let screenshot = capture_screenshot(
+            &CaptureArea::Screen {
+                index: Some(index),
+                region: Some((region_x, region_y, width, height)),
+                strict_bounds: false,
+            },
+            &EncodeOptions::png(),
+            None,
+        )?;
+        output = output.with(ContentItem::Image {
+            data: screenshot.data,
+            mime_type: CaptureFormat::Png.mime_type().to_string(),
+        });
+    }
+
+    Ok(output)
+}
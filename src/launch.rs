@@ -0,0 +1,156 @@
+//! Launching applications and opening URLs and files with the platform's
+//! opener. Everything is spawned directly, never through a shell, so
+//! arguments are passed as-is.
+
+use std::path::Path;
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long an opener (`open`, `xdg-open`) gets to hand over to the app.
+/// Some xdg-open setups run the app in the foreground, so it's left running
+/// after this rather than waited for.
+const OPENER_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// What launch_app started.
+#[derive(Debug)]
+pub(crate) struct Launched {
+    /// The app's process, where it's known. `open -a` doesn't report it, so
+    /// on macOS it's looked up by name afterwards.
+    pub(crate) pid: Option<u32>,
+    /// An instance was already running and was brought forward instead of
+    /// a new one being started.
+    pub(crate) reused: bool,
+}
+
+/// Reject text that could be taken for an option by the program it's
+/// passed to, or that can't be passed at all.
+fn check_argument(what: &str, text: &str) -> Result<(), String> {
+    if text.is_empty() {
+        return Err(format!("{} is empty", what));
+    }
+    if text.contains('\0') {
+        return Err(format!("{} contains a NUL character", what));
+    }
+    if text.starts_with('-') {
+        return Err(format!("{} can't start with '-': {}", what, text));
+    }
+    Ok(())
+}
+
+/// Start `child`'s wait on a thread of its own, so that it doesn't linger
+/// as a zombie once it exits.
+fn detach(mut child: Child) -> u32 {
+    let pid = child.id();
+    thread::spawn(move || {
+        let _ = child.wait();
+    });
+    pid
+}
+
+/// Wait up to `timeout` for a helper program to exit, failing if it
+/// reports an error. One still running after that is left to finish on its
+/// own and counts as having succeeded, so a helper that stays around (an
+/// opener running the app in the foreground) can't hold up the call.
+pub(crate) fn wait_briefly(mut child: Child, program: &str, timeout: Duration) -> Result<(), String> {
+    let start = Instant::now();
+    loop {
+        match child.try_wait() {
+            Ok(Some(status)) if status.success() => return Ok(()),
+            Ok(Some(status)) => return Err(format!("{} failed ({})", program, status)),
+            Ok(None) if start.elapsed() >= timeout => {
+                detach(child);
+                return Ok(());
+            }
+            Ok(None) => thread::sleep(Duration::from_millis(50)),
+            Err(e) => return Err(format!("Failed to wait for {}: {}", program, e)),
+        }
+    }
+}
+
+/// Start `program` directly, with no input and its output discarded.
+pub(crate) fn spawn(program: &str, args: &[String]) -> Result<Child, String> {
+    Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| format!("Failed to start {}: {}", program, e))
+}
+
+/// Processes named `name` (case-insensitive), or None where that can't be
+/// told.
+#[cfg(unix)]
+fn running_pids(name: &str) -> Option<Vec<u32>> {
+    let output = Command::new("pgrep").args(["-i", "-x", "--", name]).output().ok()?;
+    // pgrep exits 1 when nothing matches, and 2 or more on errors
+    if output.status.code().is_none_or(|code| code > 1) {
+        return None;
+    }
+    Some(
+        String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .filter_map(|line| line.trim().parse().ok())
+            .collect(),
+    )
+}
+
+#[cfg(not(unix))]
+fn running_pids(_name: &str) -> Option<Vec<u32>> {
+    None
+}
+
+/// Launch `app` with `args`. A path is run directly. On macOS a bare name
+/// is opened as an application with `open -a`, which brings an instance
+/// already running forward rather than starting another; elsewhere it's
+/// looked up on PATH and run.
+pub(crate) fn launch_app(app: &str, args: &[String]) -> Result<Launched, String> {
+    check_argument("app", app)?;
+    for (i, arg) in args.iter().enumerate() {
+        if arg.contains('\0') {
+            return Err(format!("args[{}] contains a NUL character", i));
+        }
+    }
+
+    let is_path = app.contains(std::path::MAIN_SEPARATOR) || app.contains('/');
+    if is_path || !cfg!(target_os = "macos") {
+        if is_path && !Path::new(app).is_file() {
+            return Err(format!("No executable at {}", app));
+        }
+        let child = spawn(app, args)?;
+        return Ok(Launched {
+            pid: Some(detach(child)),
+            reused: false,
+        });
+    }
+
+    let before = running_pids(app);
+    let mut open_args = vec!["-a".to_string(), app.to_string()];
+    if !args.is_empty() {
+        open_args.push("--args".to_string());
+        open_args.extend(args.iter().cloned());
+    }
+    wait_briefly(spawn("open", &open_args)?, "open", OPENER_TIMEOUT).map_err(|e| format!("Couldn't launch {}: {}", app, e))?;
+
+    let reused = before.as_ref().is_some_and(|pids| !pids.is_empty());
+    let pid = match (&before, running_pids(app)) {
+        (Some(before), Some(after)) if reused => after.into_iter().find(|pid| before.contains(pid)),
+        (Some(before), Some(after)) => after.into_iter().find(|pid| !before.contains(pid)),
+        _ => None,
+    };
+    Ok(Launched { pid, reused })
+}
+
+/// Open a URL or file with its default application.
+pub(crate) fn open_with_default_app(target: &str) -> Result<(), String> {
+    check_argument("target", target)?;
+    #[cfg(target_os = "macos")]
+    let (program, args) = ("open", vec![target.to_string()]);
+    #[cfg(target_os = "windows")]
+    let (program, args) = ("rundll32", vec!["url.dll,FileProtocolHandler".to_string(), target.to_string()]);
+    #[cfg(not(any(target_os = "macos", target_os = "windows")))]
+    let (program, args) = ("xdg-open", vec![target.to_string()]);
+
+    wait_briefly(spawn(program, &args)?, program, OPENER_TIMEOUT).map_err(|e| format!("Couldn't open {}: {}", target, e))
+}
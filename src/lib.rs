@@ -0,0 +1,94 @@
+//! desktop-mcp: an MCP server that lets an agent see the screen and drive
+//! the mouse and keyboard. The binary is a thin wrapper that serves
+//! handle_request over stdio, sockets or HTTP; everything else lives here.
+
+#![recursion_limit = "256"]
+
+mod accessibility;
+mod audit;
+pub mod backend;
+mod cancel;
+mod capture;
+mod clock;
+mod config;
+mod connection;
+mod dump;
+mod http;
+mod input;
+mod launch;
+mod logging;
+mod notify;
+mod process;
+mod protocol;
+mod retry;
+mod session;
+mod system_info;
+mod tools;
+mod wayland;
+mod window;
+
+use serde_json::Value;
+
+use crate::clock::{format_rfc3339, server_start};
+use crate::config::loaded_config;
+use crate::tools::approval::approval_config;
+use crate::tools::availability::{permission_granted, Permission};
+use crate::tools::guard::client_identity;
+
+pub use crate::audit::open_audit_log;
+pub use crate::cancel::InFlight;
+pub use crate::config::load_config;
+pub use crate::connection::{serve_listen, serve_stdio};
+pub use crate::dump::open_protocol_dump;
+pub use crate::http::serve_http;
+pub use crate::input::failsafe::start_failsafe;
+pub use crate::logging::init_logging;
+pub use crate::protocol::{
+    handle_request_catching_panics, parse_request, JsonRpcRequest, JsonRpcResponse,
+};
+pub use crate::tools::availability::set_tool_filter;
+pub use crate::tools::limits::set_dry_run;
+pub use crate::wayland::start_wayland_input;
+
+/// The tools tools/list would advertise right now, to a client on the
+/// latest protocol revision.
+pub fn list_tools() -> Value {
+    tools::get_tools(protocol::ProtocolVersion::latest())
+}
+
+/// Log the version, the client being served and anything that limits which
+/// tools are offered.
+pub fn log_startup() {
+    log::info!("desktop-mcp v{} starting...", env!("CARGO_PKG_VERSION"));
+    let (started_at, _) = server_start();
+    log::info!("Started at {}", format_rfc3339(started_at));
+
+    if let Some(path) = &loaded_config().path {
+        log::info!("Config file: {}", path.display());
+    }
+
+    let client = client_identity();
+    log::info!("Client process chain: {:?}", client.pids);
+    if !client.patterns.is_empty() {
+        log::info!("Client window patterns: {}", client.patterns.join(", "));
+    }
+
+    for permission in Permission::ALL {
+        if !permission_granted(permission) {
+            log::warn!(
+                "Missing {} permission; tools needing it are disabled. Grant it in {}",
+                permission.name(),
+                permission.settings_pane()
+            );
+        }
+    }
+
+    let approval = approval_config();
+    if !approval.tools.is_empty() {
+        log::info!(
+            "Physical approval ({}) required for: {}",
+            approval.chord,
+            approval.tools.join(", ")
+        );
+    }
+}
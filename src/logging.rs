@@ -0,0 +1,129 @@
+//! Diagnostics on stderr, or in a file with `--log-file`. stdout carries the
+//! protocol, so nothing else may be written there.
+
+use log::{LevelFilter, Log, Metadata, Record};
+use serde_json::{json, Value};
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use crate::clock::format_rfc3339;
+
+/// Size at which the log file is rotated.
+const LOG_FILE_MAX_BYTES: u64 = 10 * 1024 * 1024;
+
+/// Strings at least this long that are all base64 are left out of logged
+/// request and response bodies.
+const MIN_ELIDED_BASE64: usize = 256;
+
+/// The log file, when logging to one.
+struct LogFile {
+    path: PathBuf,
+    file: File,
+    bytes: u64,
+}
+
+impl LogFile {
+    fn open(path: &Path) -> Result<Self, String> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| format!("Failed to open log file {}: {}", path.display(), e))?;
+        let bytes = file.metadata().map(|m| m.len()).unwrap_or(0);
+        Ok(LogFile {
+            path: path.to_path_buf(),
+            file,
+            bytes,
+        })
+    }
+
+    /// Append a line, first moving the file to `<path>.1` (replacing the
+    /// previous one) if the line would take it past LOG_FILE_MAX_BYTES.
+    fn write_line(&mut self, line: &str) {
+        let len = line.len() as u64 + 1;
+        if self.bytes > 0 && self.bytes + len > LOG_FILE_MAX_BYTES {
+            let mut rotated = self.path.clone().into_os_string();
+            rotated.push(".1");
+            if fs::rename(&self.path, &rotated).is_ok() {
+                if let Ok(reopened) = LogFile::open(&self.path) {
+                    *self = reopened;
+                }
+            }
+        }
+        if self.file.write_all(format!("{}\n", line).as_bytes()).is_ok() {
+            self.bytes += len;
+        }
+    }
+}
+
+struct Logger {
+    file: Mutex<Option<LogFile>>,
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let line = format!(
+            "{} {:<5} {}",
+            format_rfc3339(SystemTime::now()),
+            record.level(),
+            record.args()
+        );
+        match &mut *self.file.lock().unwrap() {
+            Some(file) => file.write_line(&line),
+            None => eprintln!("{}", line),
+        }
+    }
+
+    fn flush(&self) {
+        if let Some(file) = &mut *self.file.lock().unwrap() {
+            let _ = file.file.flush();
+        }
+    }
+}
+
+static LOGGER: Logger = Logger {
+    file: Mutex::new(None),
+};
+
+/// Log messages at `level` and above, each with a timestamp and its level,
+/// to `file` if given and otherwise to stderr. The file is rotated to
+/// `<file>.1` when it reaches 10 MiB.
+pub fn init_logging(level: LevelFilter, file: Option<&Path>) -> Result<(), String> {
+    if let Some(path) = file {
+        *LOGGER.file.lock().unwrap() = Some(LogFile::open(path)?);
+    }
+    // Only fails if a logger is already installed, in which case it's used
+    let _ = log::set_logger(&LOGGER);
+    log::set_max_level(level);
+    Ok(())
+}
+
+/// `value` with long base64 strings, such as images and templates,
+/// replaced by a note of their length, for logging request and response
+/// bodies.
+pub(crate) fn elide_base64(value: &Value) -> Value {
+    match value {
+        Value::String(s) if is_base64(s) => json!(format!("<{} bytes of base64>", s.len())),
+        Value::Array(items) => items.iter().map(elide_base64).collect(),
+        Value::Object(map) => map.iter().map(|(k, v)| (k.clone(), elide_base64(v))).collect(),
+        other => other.clone(),
+    }
+}
+
+fn is_base64(s: &str) -> bool {
+    let data = s.split_once("base64,").map_or(s, |(_, data)| data);
+    data.len() >= MIN_ELIDED_BASE64
+        && data
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'+' | b'/' | b'=' | b'\n' | b'\r'))
+}
@@ -0,0 +1,121 @@
+//! Native desktop notifications, shown through the platform's own tool:
+//! `osascript` on macOS, `notify-send` on Linux and a PowerShell toast on
+//! Windows. The text is passed as arguments or environment variables, never
+//! spliced into a script.
+
+use std::time::Duration;
+
+use crate::launch::{spawn, wait_briefly};
+
+/// How long the notifier gets to post the notification. None of them wait
+/// for it to be dismissed, so this only matters when one hangs.
+const NOTIFIER_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Urgency {
+    Low,
+    Normal,
+    Critical,
+}
+
+impl Urgency {
+    pub(crate) const NAMES: [&'static str; 3] = ["low", "normal", "critical"];
+
+    pub(crate) fn parse(name: &str) -> Option<Urgency> {
+        match name {
+            "low" => Some(Urgency::Low),
+            "normal" => Some(Urgency::Normal),
+            "critical" => Some(Urgency::Critical),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            Urgency::Low => "low",
+            Urgency::Normal => "normal",
+            Urgency::Critical => "critical",
+        }
+    }
+}
+
+/// Show a notification. Fails when the platform has no way to show one,
+/// e.g. no notification daemon is running. `urgency` and `timeout` are
+/// hints only `notify-send` takes; on macOS a critical notification plays
+/// the default sound.
+#[cfg(target_os = "macos")]
+pub(crate) fn show_notification(
+    title: &str,
+    body: &str,
+    urgency: Urgency,
+    _timeout: Option<Duration>,
+) -> Result<(), String> {
+    let sound = if urgency == Urgency::Critical {
+        " sound name \"default\""
+    } else {
+        ""
+    };
+    let script = format!("display notification (item 2 of argv) with title (item 1 of argv){}", sound);
+    let args: Vec<String> = [
+        "-e",
+        "on run argv",
+        "-e",
+        &script,
+        "-e",
+        "end run",
+        "--",
+        title,
+        body,
+    ]
+    .iter()
+    .map(|s| s.to_string())
+    .collect();
+    wait_briefly(spawn("osascript", &args)?, "osascript", NOTIFIER_TIMEOUT)
+}
+
+#[cfg(target_os = "windows")]
+pub(crate) fn show_notification(
+    title: &str,
+    body: &str,
+    _urgency: Urgency,
+    _timeout: Option<Duration>,
+) -> Result<(), String> {
+    use std::process::{Command, Stdio};
+
+    const SCRIPT: &str = r#"
+[Windows.UI.Notifications.ToastNotificationManager, Windows.UI.Notifications, ContentType = WindowsRuntime] | Out-Null
+[Windows.Data.Xml.Dom.XmlDocument, Windows.Data.Xml.Dom.XmlDocument, ContentType = WindowsRuntime] | Out-Null
+$title = [Security.SecurityElement]::Escape($env:DESKTOP_MCP_NOTIFY_TITLE)
+$body = [Security.SecurityElement]::Escape($env:DESKTOP_MCP_NOTIFY_BODY)
+$xml = New-Object Windows.Data.Xml.Dom.XmlDocument
+$xml.LoadXml("<toast><visual><binding template='ToastGeneric'><text>$title</text><text>$body</text></binding></visual></toast>")
+$app = '{1AC14E77-02E7-4E5D-B744-2EB1AE5198B7}\WindowsPowerShell\v1.0\powershell.exe'
+[Windows.UI.Notifications.ToastNotificationManager]::CreateToastNotifier($app).Show([Windows.UI.Notifications.ToastNotification]::new($xml))
+"#;
+    let child = Command::new("powershell")
+        .args(["-NoProfile", "-NonInteractive", "-Command", SCRIPT])
+        .env("DESKTOP_MCP_NOTIFY_TITLE", title)
+        .env("DESKTOP_MCP_NOTIFY_BODY", body)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| format!("Failed to start powershell: {}", e))?;
+    wait_briefly(child, "powershell", NOTIFIER_TIMEOUT)
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "windows")))]
+pub(crate) fn show_notification(
+    title: &str,
+    body: &str,
+    urgency: Urgency,
+    timeout: Option<Duration>,
+) -> Result<(), String> {
+    let mut args = vec!["--urgency".to_string(), urgency.as_str().to_string()];
+    if let Some(timeout) = timeout {
+        args.push("--expire-time".to_string());
+        args.push(timeout.as_millis().to_string());
+    }
+    args.extend(["--".to_string(), title.to_string(), body.to_string()]);
+    wait_briefly(spawn("notify-send", &args)?, "notify-send", NOTIFIER_TIMEOUT)
+}
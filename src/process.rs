@@ -0,0 +1,110 @@
+//! The running processes, read from `ps` (or `tasklist` on Windows) the
+//! same way the self-target guard finds its ancestors.
+
+use serde_json::{json, Value};
+use std::process::Command;
+
+/// A running process.
+#[derive(Debug, Clone)]
+pub(crate) struct ProcessInfo {
+    pub(crate) pid: u32,
+    /// The executable's file name.
+    pub(crate) name: String,
+    /// The executable's full path, where the platform reports it.
+    pub(crate) exe_path: Option<String>,
+}
+
+impl ProcessInfo {
+    pub(crate) fn to_json(&self) -> Value {
+        json!({
+            "pid": self.pid,
+            "name": self.name,
+            "exe_path": self.exe_path
+        })
+    }
+}
+
+fn run(program: &str, args: &[&str]) -> Result<String, String> {
+    let output = Command::new(program)
+        .args(args)
+        .output()
+        .map_err(|e| format!("Failed to run {}: {}", program, e))?;
+    if !output.status.success() {
+        return Err(format!("{} failed: {}", program, output.status));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Every process this user can see, sorted by name and then pid.
+pub(crate) fn list_processes() -> Result<Vec<ProcessInfo>, String> {
+    let mut processes = read_processes()?;
+    processes.sort_by(|a, b| a.name.to_lowercase().cmp(&b.name.to_lowercase()).then(a.pid.cmp(&b.pid)));
+    Ok(processes)
+}
+
+/// On macOS `comm` is the executable's path. On Linux it's the name cut to
+/// 15 characters, so the path is read from /proc where this user may, and
+/// the name taken from it.
+#[cfg(unix)]
+fn read_processes() -> Result<Vec<ProcessInfo>, String> {
+    let own_pid = std::process::id();
+    let mut processes = Vec::new();
+    for line in run("ps", &["-axo", "pid=,comm="])?.lines() {
+        let Some((pid, comm)) = line.trim_start().split_once(' ') else {
+            continue;
+        };
+        let Ok(pid) = pid.parse::<u32>() else {
+            continue;
+        };
+        let comm = comm.trim();
+        if comm.is_empty() || pid == own_pid {
+            continue;
+        }
+        let exe_path = if comm.starts_with('/') {
+            Some(comm.to_string())
+        } else {
+            std::fs::read_link(format!("/proc/{}/exe", pid))
+                .ok()
+                .map(|path| path.to_string_lossy().into_owned())
+        };
+        let name = match &exe_path {
+            Some(path) => path.rsplit('/').next().unwrap_or(path).to_string(),
+            None => comm.to_string(),
+        };
+        processes.push(ProcessInfo { pid, name, exe_path });
+    }
+    Ok(processes)
+}
+
+#[cfg(windows)]
+fn read_processes() -> Result<Vec<ProcessInfo>, String> {
+    let mut processes = Vec::new();
+    // "name","pid","session name","session#","mem usage"
+    for line in run("tasklist", &["/FO", "CSV", "/NH"])?.lines() {
+        let fields: Vec<&str> = line.split("\",\"").map(|f| f.trim_matches('"')).collect();
+        let (Some(name), Some(Ok(pid))) = (fields.first(), fields.get(1).map(|p| p.parse::<u32>())) else {
+            continue;
+        };
+        processes.push(ProcessInfo {
+            pid,
+            name: name.to_string(),
+            exe_path: None,
+        });
+    }
+    Ok(processes)
+}
+
+#[cfg(not(any(unix, windows)))]
+fn read_processes() -> Result<Vec<ProcessInfo>, String> {
+    Err("Listing processes isn't supported on this platform".to_string())
+}
+
+/// Whether `name` names the process: its file name, ignoring case and a
+/// Windows ".exe".
+pub(crate) fn process_name_matches(process: &ProcessInfo, name: &str) -> bool {
+    let strip = |s: &str| {
+        let s = s.to_lowercase();
+        s.strip_suffix(".exe").map(String::from).unwrap_or(s)
+    };
+    strip(&process.name) == strip(name)
+}
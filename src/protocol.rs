@@ -0,0 +1,501 @@
+//! JSON-RPC framing and the MCP methods the server answers.
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::sync::{Mutex, Once};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::backend::refresh_displays;
+use crate::cancel::cancel_request;
+use crate::capture::get_screen_info;
+use crate::capture::resources::{list_resources, read_resource};
+use crate::capture::save::capture_files_info;
+use crate::clock::{format_rfc3339, server_start};
+use crate::logging::elide_base64;
+use crate::session::{broadcast, current_session};
+use crate::tools::limits::restrictions;
+use crate::tools::output::ResultLimits;
+use crate::tools::{find_tool, get_tools, run_tool, server_arguments};
+
+// ============================================================================
+// MCP Protocol Types
+// ============================================================================
+
+/// JSON-RPC 2.0 error codes.
+const PARSE_ERROR_CODE: i32 = -32700;
+const INVALID_REQUEST_CODE: i32 = -32600;
+/// Also used for unknown tools.
+pub(crate) const METHOD_NOT_FOUND_CODE: i32 = -32601;
+const INTERNAL_ERROR_CODE: i32 = -32603;
+pub(crate) const INVALID_PARAMS_CODE: i32 = -32602;
+/// MCP's code for resources/read of an unknown URI.
+const RESOURCE_NOT_FOUND_CODE: i32 = -32002;
+
+#[derive(Debug, Deserialize)]
+pub struct JsonRpcRequest {
+    jsonrpc: String,
+    pub id: Option<Value>,
+    pub method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+#[derive(Debug, Serialize)]
+pub struct JsonRpcResponse {
+    jsonrpc: String,
+    id: Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<JsonRpcError>,
+}
+
+impl JsonRpcResponse {
+    fn error(id: Value, code: i32, message: impl Into<String>) -> Self {
+        JsonRpcResponse {
+            jsonrpc: "2.0".to_string(),
+            id,
+            result: None,
+            error: Some(JsonRpcError {
+                code,
+                message: message.into(),
+                data: None,
+            }),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct JsonRpcError {
+    pub(crate) code: i32,
+    pub(crate) message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) data: Option<Value>,
+}
+
+/// The MCP revisions this server speaks, oldest first, so that later
+/// behaviour can be gated with a comparison.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum ProtocolVersion {
+    V2024_11_05,
+    V2025_03_26,
+    V2025_06_18,
+}
+
+impl ProtocolVersion {
+    const SUPPORTED: [ProtocolVersion; 3] = [
+        ProtocolVersion::V2024_11_05,
+        ProtocolVersion::V2025_03_26,
+        ProtocolVersion::V2025_06_18,
+    ];
+
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            ProtocolVersion::V2024_11_05 => "2024-11-05",
+            ProtocolVersion::V2025_03_26 => "2025-03-26",
+            ProtocolVersion::V2025_06_18 => "2025-06-18",
+        }
+    }
+
+    fn parse(version: &str) -> Option<Self> {
+        Self::SUPPORTED.into_iter().find(|v| v.as_str() == version)
+    }
+
+    pub(crate) fn latest() -> Self {
+        Self::SUPPORTED[Self::SUPPORTED.len() - 1]
+    }
+
+    /// Whether tool definitions can carry `annotations`.
+    pub(crate) fn tool_annotations(self) -> bool {
+        self >= ProtocolVersion::V2025_03_26
+    }
+
+    /// Whether tool results can carry `structuredContent`, and tool
+    /// definitions `outputSchema` and `title`.
+    pub(crate) fn structured_content(self) -> bool {
+        self >= ProtocolVersion::V2025_06_18
+    }
+}
+
+/// The revision the current session agreed at initialize. Clients that
+/// skip initialize get the oldest.
+fn negotiated() -> ProtocolVersion {
+    current_session().map_or(ProtocolVersion::V2024_11_05, |session| session.protocol())
+}
+
+/// The client's requested revision if we speak it, or else the latest we
+/// do, which the client may then refuse. Logs which client connected.
+fn negotiate(params: &Value) -> ProtocolVersion {
+    let requested = params.get("protocolVersion").and_then(|v| v.as_str());
+    let version = requested.and_then(ProtocolVersion::parse).unwrap_or_else(ProtocolVersion::latest);
+    let client = params.get("clientInfo");
+    let field = |name: &str| client.and_then(|c| c.get(name)).and_then(|v| v.as_str()).unwrap_or("unknown");
+    let session = current_session();
+    log::info!(
+        "Client {} {} on {} requested protocol {}; using {}",
+        field("name"),
+        field("version"),
+        session.as_ref().map_or("unknown connection", |session| session.label()),
+        requested.unwrap_or("(none)"),
+        version.as_str()
+    );
+    if let Some(session) = session {
+        session.set_protocol(version);
+    }
+    version
+}
+
+// ============================================================================
+// MCP Protocol Handler
+// ============================================================================
+
+/// Send a server notification to every connected client. They share the
+/// tools and the stored captures, so a change to either concerns them all.
+pub(crate) fn send_notification(message: &Value) {
+    broadcast(message);
+}
+
+/// Parse one line of input as a request. Lines that aren't JSON get a
+/// parse error, and JSON that isn't a valid request gets an invalid
+/// request error carrying its id if it had a usable one.
+pub fn parse_request(line: &str) -> Result<JsonRpcRequest, Box<JsonRpcResponse>> {
+    let request = parse_request_inner(line);
+    if request.is_err() {
+        STATS.lock().unwrap().rejected += 1;
+    }
+    request
+}
+
+fn parse_request_inner(line: &str) -> Result<JsonRpcRequest, Box<JsonRpcResponse>> {
+    let value: Value = serde_json::from_str(line)
+        .map_err(|e| Box::new(JsonRpcResponse::error(Value::Null, PARSE_ERROR_CODE, format!("Parse error: {}", e))))?;
+
+    let id = match value.get("id") {
+        Some(id @ (Value::String(_) | Value::Number(_))) => id.clone(),
+        _ => Value::Null,
+    };
+    let invalid = |message: String| Box::new(JsonRpcResponse::error(id.clone(), INVALID_REQUEST_CODE, message));
+    if !value.is_object() {
+        return Err(invalid("Invalid Request: expected a JSON object".to_string()));
+    }
+    if value.get("id").is_some_and(|id| !(id.is_string() || id.is_number() || id.is_null())) {
+        return Err(invalid("Invalid Request: id must be a string, number or null".to_string()));
+    }
+    let request: JsonRpcRequest =
+        serde_json::from_value(value).map_err(|e| invalid(format!("Invalid Request: {}", e)))?;
+    if request.jsonrpc != "2.0" {
+        return Err(invalid(format!(
+            "Invalid Request: unsupported jsonrpc version \"{}\"",
+            request.jsonrpc
+        )));
+    }
+    Ok(request)
+}
+
+/// Handle a request, returning the response to send. Notifications (no
+/// id) are acted on but never answered.
+fn handle_request(request: &JsonRpcRequest) -> Option<JsonRpcResponse> {
+    let id = request.id.clone().unwrap_or(Value::Null);
+
+    let result = match request.method.as_str() {
+        "initialize" => {
+            let mut result = json!({
+                "protocolVersion": negotiate(&request.params).as_str(),
+                "serverInfo": {
+                    "name": "desktop-mcp",
+                    "version": env!("CARGO_PKG_VERSION")
+                },
+                "capabilities": {
+                    "tools": { "listChanged": true },
+                    "resources": { "listChanged": true }
+                }
+            });
+            // Where screen_capture's save_to: "file" writes
+            result["capabilities"]["experimental"] = json!({
+                "capture_files": capture_files_info(),
+                // Sent when a screen is added, removed, moved or resized
+                "screens_changed": { "notification": "notifications/screens/list_changed" }
+            });
+            // Tell the client up front that input won't behave normally
+            if let Some((restrictions, instructions)) = restrictions() {
+                result["capabilities"]["experimental"]["restrictions"] = restrictions;
+                result["instructions"] = json!(instructions);
+            }
+            Ok(result)
+        }
+
+        "ping" => Ok(json!({})),
+
+        "notifications/initialized" => {
+            watch_tool_list();
+            watch_screens();
+            return None;
+        }
+
+        "notifications/cancelled" => {
+            if let Some(id) = request.params.get("requestId") {
+                if !cancel_request(id) {
+                    log::debug!("Ignoring cancellation of {}, which isn't running", id);
+                }
+            }
+            return None;
+        }
+
+        "notifications/progress" | "notifications/roots/list_changed" => {
+            return None;
+        }
+
+        "tools/list" => Ok(json!({
+            "tools": get_tools(negotiated())
+        })),
+
+        "resources/list" => Ok(list_resources()),
+
+        "resources/templates/list" => Ok(json!({ "resourceTemplates": [] })),
+
+        "resources/read" => {
+            let uri = request.params.get("uri").and_then(|v| v.as_str()).unwrap_or("");
+            read_resource(uri).map_err(|message| JsonRpcError {
+                code: RESOURCE_NOT_FOUND_CODE,
+                message,
+                data: Some(json!({ "uri": uri })),
+            })
+        }
+
+        "tools/call" => {
+            let tool_name = request
+                .params
+                .get("name")
+                .and_then(|v| v.as_str())
+                .unwrap_or("");
+            let arguments = request
+                .params
+                .get("arguments")
+                .cloned()
+                .unwrap_or(json!({}));
+
+            let limits = match find_tool(tool_name) {
+                Some(tool) => ResultLimits::for_call(&server_arguments(tool, &arguments)),
+                None => ResultLimits::default(),
+            };
+            run_tool(tool_name, &arguments)
+                .map(|output| output.to_result(negotiated(), &limits))
+                .map_err(|e| e.to_rpc_error())
+        }
+
+        _ => Err(JsonRpcError {
+            code: METHOD_NOT_FOUND_CODE,
+            message: format!("Method not found: {}", request.method),
+            data: None,
+        }),
+    };
+
+    // Notifications are never answered, even on error
+    request.id.as_ref()?;
+    Some(match result {
+        Ok(result) => JsonRpcResponse {
+            jsonrpc: "2.0".to_string(),
+            id,
+            result: Some(result),
+            error: None,
+        },
+        Err(error) => JsonRpcResponse {
+            jsonrpc: "2.0".to_string(),
+            id,
+            result: None,
+            error: Some(error),
+        },
+    })
+}
+
+/// handle_request, answering with an internal error if it panics so that
+/// the client isn't left waiting.
+pub fn handle_request_catching_panics(request: &JsonRpcRequest) -> Option<JsonRpcResponse> {
+    if log::log_enabled!(log::Level::Debug) {
+        log::debug!("{} request: {}", request.method, elide_base64(&request.params));
+    }
+    let start = Instant::now();
+    let response = std::panic::catch_unwind(|| handle_request(request)).unwrap_or_else(|_| {
+        let id = request.id.clone()?;
+        Some(JsonRpcResponse::error(
+            id,
+            INTERNAL_ERROR_CODE,
+            format!("Internal error handling {}", request.method),
+        ))
+    });
+    log_response(request, response.as_ref(), start.elapsed());
+    record_request(request, response.as_ref());
+    response
+}
+
+/// Log a request's method (and tool), how long it took and how it went at
+/// info, and the response body, without base64 data, at debug.
+/// Notifications are only logged at debug.
+fn log_response(request: &JsonRpcRequest, response: Option<&JsonRpcResponse>, duration: Duration) {
+    let Some(response) = response else {
+        log::debug!("{} handled in {} ms", request.method, duration.as_millis());
+        return;
+    };
+    let method = match request.params.get("name").and_then(|v| v.as_str()) {
+        Some(tool) if request.method == "tools/call" => format!("tools/call {}", tool),
+        _ => request.method.clone(),
+    };
+    let outcome = match (&response.result, &response.error) {
+        (_, Some(error)) => format!("error {}: {}", error.code, error.message),
+        (Some(result), None) if result["isError"] == json!(true) => "failed".to_string(),
+        _ => "ok".to_string(),
+    };
+    log::info!("{} ({}): {} in {} ms", method, response.id, outcome, duration.as_millis());
+    if log::log_enabled!(log::Level::Debug) {
+        let body = serde_json::to_value(response).unwrap_or_default();
+        log::debug!("{} response: {}", method, elide_base64(&body));
+    }
+}
+
+/// How one kind of request has gone since the server started.
+#[derive(Default)]
+struct RequestCounts {
+    count: u64,
+    /// Answered with a JSON-RPC error.
+    errors: u64,
+    /// Tool calls whose result had isError set.
+    failed: u64,
+}
+
+impl RequestCounts {
+    fn add(&mut self, response: Option<&JsonRpcResponse>) {
+        self.count += 1;
+        match response.map(|r| (&r.result, &r.error)) {
+            Some((_, Some(_))) => self.errors += 1,
+            Some((Some(result), None)) if result["isError"] == json!(true) => self.failed += 1,
+            _ => {}
+        }
+    }
+
+    fn to_json(&self) -> Value {
+        json!({
+            "count": self.count,
+            "errors": self.errors,
+            "failed": self.failed,
+            "error_rate": (self.errors + self.failed) as f64 / self.count.max(1) as f64,
+        })
+    }
+}
+
+/// Requests handled per method, and tool calls per tool, for protocol_stats.
+struct RequestStats {
+    methods: BTreeMap<String, RequestCounts>,
+    tools: BTreeMap<String, RequestCounts>,
+    /// Messages that weren't valid JSON-RPC requests.
+    rejected: u64,
+}
+
+static STATS: Mutex<RequestStats> = Mutex::new(RequestStats {
+    methods: BTreeMap::new(),
+    tools: BTreeMap::new(),
+    rejected: 0,
+});
+
+fn record_request(request: &JsonRpcRequest, response: Option<&JsonRpcResponse>) {
+    let mut stats = STATS.lock().unwrap();
+    stats.methods.entry(request.method.clone()).or_default().add(response);
+    if request.method == "tools/call" {
+        if let Some(tool) = request.params.get("name").and_then(|v| v.as_str()) {
+            stats.tools.entry(tool.to_string()).or_default().add(response);
+        }
+    }
+}
+
+/// Counts of requests by method and of tool calls by tool since the server
+/// started, with how many were answered with an error or, for tool calls,
+/// failed. error_rate counts both.
+pub(crate) fn protocol_stats() -> Value {
+    let stats = STATS.lock().unwrap();
+    let mut total = RequestCounts::default();
+    for counts in stats.methods.values() {
+        total.count += counts.count;
+        total.errors += counts.errors;
+        total.failed += counts.failed;
+    }
+    let by_name = |map: &BTreeMap<String, RequestCounts>| -> serde_json::Map<String, Value> {
+        map.iter().map(|(name, counts)| (name.clone(), counts.to_json())).collect()
+    };
+    json!({
+        "started_at": format_rfc3339(server_start().0),
+        "total": total.to_json(),
+        "rejected": stats.rejected,
+        "methods": by_name(&stats.methods),
+        "tools": by_name(&stats.tools),
+    })
+}
+
+/// How often the screens are re-enumerated for
+/// `notifications/screens/list_changed`.
+const SCREEN_POLL_INTERVAL: Duration = Duration::from_secs(3);
+
+/// Watch for screens being added, removed, moved or resized and notify the
+/// client with the new list, as get_screen_info reports it. Polling also
+/// keeps the cached screen list fresh. Started once the client has
+/// initialized.
+fn watch_screens() {
+    static STARTED: Once = Once::new();
+    STARTED.call_once(|| {
+        thread::spawn(|| {
+            let mut last = refresh_displays().ok();
+            loop {
+                thread::sleep(SCREEN_POLL_INTERVAL);
+                // Enumeration can fail briefly mid-reconfiguration; the
+                // next poll picks the change up
+                let Ok(current) = refresh_displays() else {
+                    continue;
+                };
+                if last.as_ref().is_some_and(|last| *last != current) {
+                    log::info!("Screens changed; notifying client");
+                    if let Ok(info) = get_screen_info(false) {
+                        send_notification(&json!({
+                            "jsonrpc": "2.0",
+                            "method": "notifications/screens/list_changed",
+                            "params": info
+                        }));
+                    }
+                }
+                last = Some(current);
+            }
+        });
+    });
+}
+
+/// How often permissions are re-checked for `notifications/tools/list_changed`.
+const TOOL_LIST_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Watch for changes to the available tools (e.g. the user granting
+/// Accessibility while we're running) and notify the client. Started once
+/// the client has initialized.
+fn watch_tool_list() {
+    static STARTED: Once = Once::new();
+    STARTED.call_once(|| {
+        thread::spawn(|| {
+            let names = || -> Vec<Value> {
+                get_tools(ProtocolVersion::latest())
+                    .as_array()
+                    .map(|tools| tools.iter().map(|t| t["name"].clone()).collect())
+                    .unwrap_or_default()
+            };
+            let mut last = names();
+            loop {
+                thread::sleep(TOOL_LIST_POLL_INTERVAL);
+                let current = names();
+                if current != last {
+                    log::info!("Available tools changed; notifying client");
+                    send_notification(&json!({
+                        "jsonrpc": "2.0",
+                        "method": "notifications/tools/list_changed"
+                    }));
+                    last = current;
+                }
+            }
+        });
+    });
+}
@@ -0,0 +1,55 @@
+//! Retrying OS calls that fail transiently, such as a capture right after
+//! a Space switch on macOS or a simulated event under load.
+
+use std::cell::Cell;
+use std::fmt::Debug;
+use std::time::Duration;
+
+use crate::cancel::sleep_cancellable;
+use crate::config::loaded_config;
+
+/// Tries per call, including the first, unless the config file says.
+const DEFAULT_ATTEMPTS: u32 = 3;
+
+/// Wait before the first retry, doubling for each one after, unless the
+/// config file says. With the default attempts that's 300ms in all.
+const DEFAULT_BACKOFF_MS: u64 = 100;
+
+thread_local! {
+    /// Retries made during the tool call running on this thread.
+    pub(crate) static RETRIES: Cell<u32> = const { Cell::new(0) };
+}
+
+/// Run an OS call, retrying it with exponential backoff if it fails.
+/// Arguments are checked before anything reaches the OS, so a failure here
+/// is taken to be transient. Stops retrying once the call is cancelled, and
+/// returns the last error when the attempts run out.
+pub(crate) fn retry_transient<T, E: Debug>(what: &str, mut f: impl FnMut() -> Result<T, E>) -> Result<T, E> {
+    let config = &loaded_config().config.retry;
+    let attempts = config.attempts.unwrap_or(DEFAULT_ATTEMPTS).max(1);
+    let mut backoff = Duration::from_millis(config.backoff_ms.unwrap_or(DEFAULT_BACKOFF_MS));
+    let mut attempt = 1;
+    loop {
+        let error = match f() {
+            Ok(value) => return Ok(value),
+            Err(e) => e,
+        };
+        if attempt >= attempts {
+            return Err(error);
+        }
+        log::warn!(
+            "{} failed (attempt {} of {}): {:?}; retrying in {} ms",
+            what,
+            attempt,
+            attempts,
+            error,
+            backoff.as_millis()
+        );
+        if sleep_cancellable(backoff).is_err() {
+            return Err(error);
+        }
+        RETRIES.set(RETRIES.get() + 1);
+        backoff *= 2;
+        attempt += 1;
+    }
+}
@@ -0,0 +1,99 @@
+//! Client sessions. Each connection (stdio, a `--listen` socket, or the
+//! HTTP transport's session) has its own: the protocol revision it
+//! negotiated, where its messages are written, and the keys and buttons
+//! its calls left held. Input from every session still takes turns through
+//! INPUT_LOCK.
+
+use serde::Serialize;
+use std::cell::RefCell;
+use std::io;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+
+use crate::dump::dump_sent;
+use crate::protocol::ProtocolVersion;
+
+/// Writes one serialized message to the session's client.
+type Output = Box<dyn Fn(&str) -> io::Result<()> + Send + Sync>;
+
+pub(crate) struct Session {
+    id: u64,
+    /// Who the session is with, for logs, e.g. "stdio" or a peer address.
+    label: String,
+    protocol: Mutex<ProtocolVersion>,
+    output: Output,
+}
+
+/// Every open session, for notifications meant for all clients.
+static SESSIONS: Mutex<Vec<Weak<Session>>> = Mutex::new(Vec::new());
+
+thread_local! {
+    /// The session whose request is being handled on this thread.
+    static CURRENT: RefCell<Option<Arc<Session>>> = const { RefCell::new(None) };
+}
+
+impl Session {
+    /// A new session on the oldest protocol revision until it initializes.
+    pub(crate) fn open(label: impl Into<String>, output: Output) -> Arc<Session> {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+        let session = Arc::new(Session {
+            id: NEXT_ID.fetch_add(1, Ordering::SeqCst),
+            label: label.into(),
+            protocol: Mutex::new(ProtocolVersion::V2024_11_05),
+            output,
+        });
+        let mut sessions = SESSIONS.lock().unwrap();
+        sessions.retain(|s| s.strong_count() > 0);
+        sessions.push(Arc::downgrade(&session));
+        session
+    }
+
+    pub(crate) fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub(crate) fn label(&self) -> &str {
+        &self.label
+    }
+
+    pub(crate) fn protocol(&self) -> ProtocolVersion {
+        *self.protocol.lock().unwrap()
+    }
+
+    pub(crate) fn set_protocol(&self, version: ProtocolVersion) {
+        *self.protocol.lock().unwrap() = version;
+    }
+
+    /// Write a message to this session's client. Failures are logged; the
+    /// connection's reader notices a closed connection.
+    pub(crate) fn send(&self, message: &impl Serialize) {
+        let json = serde_json::to_string(message).unwrap();
+        dump_sent(&self.label, &json);
+        if let Err(e) = (self.output)(&json) {
+            log::debug!("Failed to write to {}: {}", self.label, e);
+        }
+    }
+}
+
+/// Make `session` the one requests on this thread belong to.
+pub(crate) fn enter_session(session: Option<Arc<Session>>) {
+    CURRENT.with(|current| *current.borrow_mut() = session);
+}
+
+/// The session of the request being handled on this thread.
+pub(crate) fn current_session() -> Option<Arc<Session>> {
+    CURRENT.with(|current| current.borrow().clone())
+}
+
+/// The current session's id, or 0 outside any session.
+pub(crate) fn current_session_id() -> u64 {
+    CURRENT.with(|current| current.borrow().as_ref().map_or(0, |session| session.id))
+}
+
+/// Send a message to every open session.
+pub(crate) fn broadcast(message: &impl Serialize) {
+    let sessions: Vec<Arc<Session>> = SESSIONS.lock().unwrap().iter().filter_map(Weak::upgrade).collect();
+    for session in sessions {
+        session.send(message);
+    }
+}
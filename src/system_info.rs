@@ -0,0 +1,192 @@
+//! Facts about the machine and session the server runs in, for
+//! get_system_info and the session lock check. Each is None where the
+//! platform can't tell.
+
+use std::process::Command;
+use std::time::Duration;
+
+use crate::wayland::display_session;
+
+fn command_output(program: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(program).args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let text = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (!text.is_empty()).then_some(text)
+}
+
+/// The OS release, e.g. "14.5" on macOS or "Ubuntu 24.04 LTS" on Linux.
+pub(crate) fn os_version() -> Option<String> {
+    if cfg!(target_os = "macos") {
+        command_output("sw_vers", &["-productVersion"])
+    } else if cfg!(target_os = "windows") {
+        command_output("cmd", &["/C", "ver"])
+    } else {
+        let release = std::fs::read_to_string("/etc/os-release").ok()?;
+        release.lines().find_map(|line| {
+            let value = line.strip_prefix("PRETTY_NAME=")?;
+            Some(value.trim_matches('"').to_string())
+        })
+    }
+}
+
+pub(crate) fn hostname() -> Option<String> {
+    command_output("hostname", &[])
+}
+
+/// The user the server runs as.
+pub(crate) fn user_name() -> Option<String> {
+    std::env::var("USER").or_else(|_| std::env::var("USERNAME")).ok()
+}
+
+/// How the desktop is being shown: "aqua" on macOS; "console" or "rdp"
+/// on Windows; "x11" or "wayland" on Linux.
+pub(crate) fn session_type() -> Option<&'static str> {
+    if cfg!(target_os = "macos") {
+        Some("aqua")
+    } else if cfg!(target_os = "windows") {
+        let remote = std::env::var("SESSIONNAME").is_ok_and(|name| name.to_uppercase().starts_with("RDP-"));
+        Some(if remote { "rdp" } else { "console" })
+    } else {
+        display_session()
+    }
+}
+
+/// How long the machine has been up.
+#[cfg(target_os = "macos")]
+pub(crate) fn system_uptime() -> Option<Duration> {
+    // "{ sec = 1718000000, usec = 123456 } Mon Jun 10 ..."
+    let boot = command_output("sysctl", &["-n", "kern.boottime"])?;
+    let secs: u64 = boot.split("sec = ").nth(1)?.split(',').next()?.trim().parse().ok()?;
+    let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).ok()?;
+    now.checked_sub(Duration::from_secs(secs))
+}
+
+#[cfg(target_os = "windows")]
+pub(crate) fn system_uptime() -> Option<Duration> {
+    extern "system" {
+        fn GetTickCount64() -> u64;
+    }
+    Some(Duration::from_millis(unsafe { GetTickCount64() }))
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "windows")))]
+pub(crate) fn system_uptime() -> Option<Duration> {
+    let uptime = std::fs::read_to_string("/proc/uptime").ok()?;
+    let secs: f64 = uptime.split_whitespace().next()?.parse().ok()?;
+    Some(Duration::from_secs_f64(secs))
+}
+
+/// Whether the screen is locked, when simulated input goes nowhere. Read
+/// from the session dictionary's CGSSessionScreenIsLocked, which is only
+/// present while locked.
+#[cfg(target_os = "macos")]
+fn screen_locked() -> Option<bool> {
+    use core_foundation::base::{CFType, TCFType};
+    use core_foundation::boolean::CFBoolean;
+    use core_foundation::dictionary::{CFDictionary, CFDictionaryRef};
+    use core_foundation::string::CFString;
+
+    #[link(name = "CoreGraphics", kind = "framework")]
+    extern "C" {
+        fn CGSessionCopyCurrentDictionary() -> CFDictionaryRef;
+    }
+
+    let session = unsafe { CGSessionCopyCurrentDictionary() };
+    // No dictionary means no window server session, e.g. over ssh
+    if session.is_null() {
+        return None;
+    }
+    let session: CFDictionary<CFString, CFType> = unsafe { CFDictionary::wrap_under_create_rule(session) };
+    let locked = session
+        .find(CFString::new("CGSSessionScreenIsLocked"))
+        .and_then(|v| v.downcast::<CFBoolean>())
+        .is_some_and(bool::from);
+    Some(locked)
+}
+
+/// Whether the screen is locked: the input desktop is then the secure
+/// desktop, which this process can't open or switch to.
+#[cfg(target_os = "windows")]
+fn screen_locked() -> Option<bool> {
+    use std::ffi::c_void;
+
+    const DESKTOP_SWITCHDESKTOP: u32 = 0x0100;
+
+    #[link(name = "user32")]
+    extern "system" {
+        fn OpenInputDesktop(flags: u32, inherit: i32, desired_access: u32) -> *mut c_void;
+        fn SwitchDesktop(desktop: *mut c_void) -> i32;
+        fn CloseDesktop(desktop: *mut c_void) -> i32;
+    }
+
+    let desktop = unsafe { OpenInputDesktop(0, 0, DESKTOP_SWITCHDESKTOP) };
+    if desktop.is_null() {
+        return Some(true);
+    }
+    let switched = unsafe { SwitchDesktop(desktop) } != 0;
+    unsafe {
+        CloseDesktop(desktop);
+    }
+    Some(!switched)
+}
+
+/// Whether the screen is locked, from logind's LockedHint for this session,
+/// which desktops that use logind's locking set.
+#[cfg(not(any(target_os = "macos", target_os = "windows")))]
+fn screen_locked() -> Option<bool> {
+    let session = std::env::var("XDG_SESSION_ID").ok()?;
+    match command_output("loginctl", &["show-session", &session, "-p", "LockedHint", "--value"])?.as_str() {
+        "yes" => Some(true),
+        "no" => Some(false),
+        _ => None,
+    }
+}
+
+/// Whether the screensaver is running. ScreenSaverEngine runs only while
+/// it's shown.
+#[cfg(target_os = "macos")]
+fn screensaver_running() -> Option<bool> {
+    let status = Command::new("pgrep").args(["-x", "ScreenSaverEngine"]).output().ok()?.status;
+    // pgrep exits 1 when nothing matches, and 2 or more on errors
+    match status.code() {
+        Some(0) => Some(true),
+        Some(1) => Some(false),
+        _ => None,
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn screensaver_running() -> Option<bool> {
+    use std::ffi::c_void;
+
+    const SPI_GETSCREENSAVERRUNNING: u32 = 0x0072;
+
+    #[link(name = "user32")]
+    extern "system" {
+        fn SystemParametersInfoW(action: u32, param: u32, value: *mut c_void, update: u32) -> i32;
+    }
+
+    let mut running: i32 = 0;
+    let ok = unsafe { SystemParametersInfoW(SPI_GETSCREENSAVERRUNNING, 0, &mut running as *mut i32 as *mut c_void, 0) };
+    (ok != 0).then_some(running != 0)
+}
+
+/// Linux screensavers that lock set LockedHint, and those that only blank
+/// leave input working, so there's nothing more to check.
+#[cfg(not(any(target_os = "macos", target_os = "windows")))]
+fn screensaver_running() -> Option<bool> {
+    Some(false)
+}
+
+/// Whether the session is locked or the screensaver is running, when
+/// simulated input goes to the lock screen (or its password field) rather
+/// than the desktop. None when the lock state can't be told.
+pub(crate) fn is_session_locked() -> Option<bool> {
+    match (screen_locked(), screensaver_running()) {
+        (Some(true), _) | (_, Some(true)) => Some(true),
+        (None, _) => None,
+        (Some(false), _) => Some(false),
+    }
+}
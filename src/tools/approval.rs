@@ -0,0 +1,135 @@
+//! Requiring a human to approve calls on the physical keyboard.
+
+use rdev::{EventType, Key};
+use std::sync::{mpsc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use crate::input::physical::{listener_error, normalize_modifier, subscribe_physical_input};
+use crate::input::{get_modifier_key, parse_key};
+
+/// Tools that need a human to approve each call by pressing a key chord on
+/// the real keyboard. Configured through environment variables:
+///
+/// - `DESKTOP_MCP_REQUIRE_APPROVAL`: comma-separated tool names (default: none)
+/// - `DESKTOP_MCP_APPROVAL_KEY`: the approval chord (default: `ctrl+shift+y`)
+/// - `DESKTOP_MCP_APPROVAL_TIMEOUT_MS`: how long to wait (default: 30000)
+/// - `DESKTOP_MCP_APPROVAL_WINDOW_MS`: how long an approval stays valid for
+///   further approval-required calls (default: 0, approve every call)
+///
+/// Pressing Escape denies the call.
+pub(crate) struct ApprovalConfig {
+    pub(crate) tools: Vec<String>,
+    pub(crate) chord: String,
+    modifiers: Vec<Key>,
+    key: Key,
+    timeout: Duration,
+    pub(crate) window: Duration,
+}
+
+pub(crate) fn approval_config() -> &'static ApprovalConfig {
+    static CONFIG: OnceLock<ApprovalConfig> = OnceLock::new();
+    CONFIG.get_or_init(|| {
+        let env_ms = |name: &str, default: u64| {
+            std::env::var(name)
+                .ok()
+                .and_then(|v| v.trim().parse().ok())
+                .map(Duration::from_millis)
+                .unwrap_or(Duration::from_millis(default))
+        };
+
+        let tools = std::env::var("DESKTOP_MCP_REQUIRE_APPROVAL")
+            .unwrap_or_default()
+            .split(',')
+            .map(|t| t.trim().to_string())
+            .filter(|t| !t.is_empty())
+            .collect();
+
+        let default_chord = "ctrl+shift+y";
+        let chord = std::env::var("DESKTOP_MCP_APPROVAL_KEY").unwrap_or_else(|_| default_chord.to_string());
+        let (modifiers, key) = parse_chord(&chord).unwrap_or_else(|e| {
+            log::warn!("{}; using {}", e, default_chord);
+            parse_chord(default_chord).unwrap()
+        });
+
+        ApprovalConfig {
+            tools,
+            chord,
+            modifiers,
+            key,
+            timeout: env_ms("DESKTOP_MCP_APPROVAL_TIMEOUT_MS", 30_000),
+            window: env_ms("DESKTOP_MCP_APPROVAL_WINDOW_MS", 0),
+        }
+    })
+}
+
+/// Parse a chord such as "ctrl+shift+y" into its modifiers and final key.
+fn parse_chord(chord: &str) -> Result<(Vec<Key>, Key), String> {
+    let parts: Vec<&str> = chord.split('+').map(str::trim).collect();
+    let (key, modifiers) = parts.split_last().ok_or("Empty key chord")?;
+    let key = parse_key(key).ok_or_else(|| format!("Unknown key in chord {}: {}", chord, key))?;
+    let modifiers = modifiers
+        .iter()
+        .map(|m| get_modifier_key(m).ok_or_else(|| format!("Unknown modifier in chord {}: {}", chord, m)))
+        .collect::<Result<_, _>>()?;
+    Ok((modifiers, key))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum ApprovalOutcome {
+    Approved,
+    Denied,
+    TimedOut,
+}
+
+impl ApprovalOutcome {
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            ApprovalOutcome::Approved => "approved",
+            ApprovalOutcome::Denied => "denied",
+            ApprovalOutcome::TimedOut => "timeout",
+        }
+    }
+}
+
+/// End of the current elevated window, if an approval is still valid.
+pub(crate) static ELEVATED_UNTIL: Mutex<Option<Instant>> = Mutex::new(None);
+
+/// Block until the user presses the approval chord (or Escape) on the
+/// physical keyboard, or the timeout passes. Synthetic key events never
+/// count, so the server can't approve its own actions.
+pub(crate) fn await_approval(tool: &str, config: &ApprovalConfig) -> Result<ApprovalOutcome, String> {
+    let rx = subscribe_physical_input()?;
+    log::warn!(
+        "Waiting for approval of {}: press {} to approve or Escape to deny ({}s)",
+        tool,
+        config.chord,
+        config.timeout.as_secs()
+    );
+
+    let deadline = Instant::now() + config.timeout;
+    let mut held: Vec<Key> = Vec::new();
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        match rx.recv_timeout(remaining) {
+            Ok(EventType::KeyPress(key)) => {
+                let key = normalize_modifier(key);
+                if key == Key::Escape {
+                    return Ok(ApprovalOutcome::Denied);
+                }
+                if key == config.key && config.modifiers.iter().all(|m| held.contains(m)) {
+                    return Ok(ApprovalOutcome::Approved);
+                }
+                if !held.contains(&key) {
+                    held.push(key);
+                }
+            }
+            Ok(EventType::KeyRelease(key)) => {
+                let key = normalize_modifier(key);
+                held.retain(|&h| h != key);
+            }
+            Ok(_) => {}
+            Err(mpsc::RecvTimeoutError::Timeout) => return Ok(ApprovalOutcome::TimedOut),
+            Err(mpsc::RecvTimeoutError::Disconnected) => return Err(listener_error()),
+        }
+    }
+}
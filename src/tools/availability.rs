@@ -0,0 +1,489 @@
+//! Which tools are offered, given the platform, permissions and
+//! configuration.
+
+use std::sync::OnceLock;
+
+use crate::config::loaded_config;
+use crate::input::physical::user_click_enabled;
+use crate::tools::Tool;
+use crate::wayland::input_unavailable_reason;
+
+/// OS permissions that tools depend on.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Permission {
+    Accessibility,
+    ScreenRecording,
+}
+
+impl Permission {
+    pub(crate) const ALL: [Permission; 2] = [Permission::Accessibility, Permission::ScreenRecording];
+
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Permission::Accessibility => "Accessibility",
+            Permission::ScreenRecording => "Screen Recording",
+        }
+    }
+
+    /// Where the user grants it.
+    pub(crate) fn settings_pane(self) -> &'static str {
+        match self {
+            Permission::Accessibility => "System Settings > Privacy & Security > Accessibility",
+            Permission::ScreenRecording => {
+                "System Settings > Privacy & Security > Screen Recording (Screen & System Audio Recording on macOS 15+)"
+            }
+        }
+    }
+
+    /// Granting Screen Recording only takes effect once the process restarts.
+    pub(crate) fn needs_restart(self) -> bool {
+        self == Permission::ScreenRecording
+    }
+}
+
+/// What a built-in tool needs in order to work.
+#[derive(Debug, Default)]
+pub(crate) struct ToolRequirements {
+    pub(crate) macos_only: bool,
+    pub(crate) permissions: &'static [Permission],
+    /// Only offered when DESKTOP_MCP_ENABLE_USER_CLICK is set.
+    pub(crate) user_click: bool,
+    /// Cargo feature the tool needs that this build was compiled without.
+    pub(crate) missing_feature: Option<&'static str>,
+}
+
+/// Allow and deny lists given on the command line, which take precedence
+/// over the environment.
+#[derive(Debug, Clone, Default)]
+struct CommandLineFilter {
+    allow: Option<Vec<String>>,
+    deny: Option<Vec<String>>,
+}
+
+static COMMAND_LINE_FILTER: OnceLock<CommandLineFilter> = OnceLock::new();
+
+/// Restrict the tools offered to `allow` (if given) minus `deny`, in place of
+/// `DESKTOP_MCP_ALLOW_TOOLS` and `DESKTOP_MCP_DENY_TOOLS`. Only the first call
+/// has any effect.
+pub fn set_tool_filter(allow: Option<Vec<String>>, deny: Option<Vec<String>>) {
+    let _ = COMMAND_LINE_FILTER.set(CommandLineFilter { allow, deny });
+}
+
+/// A list of tool names and the option or variable that set it.
+#[derive(Debug, Clone, PartialEq)]
+struct ToolList {
+    names: Vec<String>,
+    source: &'static str,
+}
+
+impl ToolList {
+    fn contains(&self, name: &str) -> bool {
+        self.names.iter().any(|t| t == name)
+    }
+}
+
+/// The platform, permissions and configuration that decide which tools are
+/// offered. Tool names can be filtered with `--allow-tools`,
+/// `DESKTOP_MCP_ALLOW_TOOLS` or the config file's `allow_tools` (only these)
+/// and `--deny-tools`, `DESKTOP_MCP_DENY_TOOLS` or `deny_tools` (never
+/// these), the first given of each taking effect.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Availability {
+    macos: bool,
+    granted: Vec<Permission>,
+    user_click: bool,
+    /// Why input tools can't be offered in a Wayland session.
+    wayland_input: Option<String>,
+    allow: Option<ToolList>,
+    deny: Option<ToolList>,
+}
+
+impl Availability {
+    pub(crate) fn current() -> Self {
+        let cli = COMMAND_LINE_FILTER.get().cloned().unwrap_or_default();
+        let safety = &loaded_config().config.safety;
+        let list = |cli: Option<Vec<String>>, flag: &'static str, var: &'static str, file: &Option<Vec<String>>| {
+            if let Some(names) = cli {
+                return Some(ToolList { names, source: flag });
+            }
+            if let Ok(v) = std::env::var(var) {
+                return Some(ToolList {
+                    names: v
+                        .split(',')
+                        .map(|t| t.trim().to_string())
+                        .filter(|t| !t.is_empty())
+                        .collect(),
+                    source: var,
+                });
+            }
+            file.clone().map(|names| ToolList {
+                names,
+                source: "config file",
+            })
+        };
+        Availability {
+            macos: cfg!(target_os = "macos"),
+            granted: Permission::ALL
+                .into_iter()
+                .filter(|&p| permission_granted(p))
+                .collect(),
+            user_click: user_click_enabled(),
+            wayland_input: input_unavailable_reason(),
+            allow: list(cli.allow, "--allow-tools", "DESKTOP_MCP_ALLOW_TOOLS", &safety.allow_tools),
+            deny: list(cli.deny, "--deny-tools", "DESKTOP_MCP_DENY_TOOLS", &safety.deny_tools),
+        }
+    }
+
+    /// Why a tool isn't available, or None if it is.
+    pub(crate) fn unavailable_reason(&self, tool: &dyn Tool) -> Option<String> {
+        let name = tool.name();
+        if let Some(deny) = self.deny.as_ref().filter(|deny| deny.contains(name)) {
+            return Some(format!("disabled by server configuration ({})", deny.source));
+        }
+        if let Some(allow) = self.allow.as_ref().filter(|allow| !allow.contains(name)) {
+            return Some(format!("disabled by server configuration (not in {})", allow.source));
+        }
+
+        let requirements = tool.requirements();
+        if let Some(feature) = requirements.missing_feature {
+            return Some(format!("not in this build (rebuild with --features {})", feature));
+        }
+        if requirements.macos_only && !self.macos {
+            return Some("only supported on macOS".to_string());
+        }
+        if let Some(reason) = self.wayland_input.as_ref().filter(|_| tool.sends_input()) {
+            return Some(reason.clone());
+        }
+        if requirements.user_click && !self.user_click {
+            return Some("not enabled (set DESKTOP_MCP_ENABLE_USER_CLICK=1)".to_string());
+        }
+        let missing: Vec<Permission> = requirements
+            .permissions
+            .iter()
+            .copied()
+            .filter(|p| !self.granted.contains(p))
+            .collect();
+        if !missing.is_empty() {
+            let noun = if missing.len() == 1 { "permission" } else { "permissions" };
+            let names: Vec<&str> = missing.iter().map(|p| p.name()).collect();
+            let panes: Vec<&str> = missing.iter().map(|p| p.settings_pane()).collect();
+            return Some(format!(
+                "needs the {} {}; grant it in {}{}",
+                names.join(" and "),
+                noun,
+                panes.join(" and "),
+                if missing.iter().any(|p| p.needs_restart()) {
+                    ", then restart the MCP client"
+                } else {
+                    ""
+                }
+            ));
+        }
+        None
+    }
+}
+
+#[cfg(target_os = "macos")]
+pub(crate) fn permission_granted(permission: Permission) -> bool {
+    #[link(name = "ApplicationServices", kind = "framework")]
+    extern "C" {
+        fn AXIsProcessTrusted() -> u8;
+    }
+
+    match permission {
+        Permission::Accessibility => unsafe { AXIsProcessTrusted() != 0 },
+        Permission::ScreenRecording => core_graphics::access::ScreenCaptureAccess.preflight(),
+    }
+}
+
+/// Other platforms don't gate input or capture behind a permission.
+#[cfg(not(target_os = "macos"))]
+pub(crate) fn permission_granted(_permission: Permission) -> bool {
+    true
+}
+
+/// Ask macOS to show its prompt for a permission, which also adds the
+/// process to the list in System Settings. Returns whether it's granted;
+/// the user's answer only shows up in later checks.
+#[cfg(target_os = "macos")]
+pub(crate) fn request_permission(permission: Permission) -> bool {
+    use core_foundation::base::TCFType;
+    use core_foundation::boolean::CFBoolean;
+    use core_foundation::dictionary::CFDictionary;
+    use core_foundation::string::{CFString, CFStringRef};
+
+    #[link(name = "ApplicationServices", kind = "framework")]
+    extern "C" {
+        static kAXTrustedCheckOptionPrompt: CFStringRef;
+        fn AXIsProcessTrustedWithOptions(options: core_foundation::dictionary::CFDictionaryRef) -> u8;
+    }
+
+    match permission {
+        Permission::Accessibility => unsafe {
+            let key = CFString::wrap_under_get_rule(kAXTrustedCheckOptionPrompt);
+            let options = CFDictionary::from_CFType_pairs(&[(key, CFBoolean::true_value())]);
+            AXIsProcessTrustedWithOptions(options.as_concrete_TypeRef()) != 0
+        },
+        Permission::ScreenRecording => core_graphics::access::ScreenCaptureAccess.request(),
+    }
+}
+
+#[cfg(not(target_os = "macos"))]
+pub(crate) fn request_permission(_permission: Permission) -> bool {
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    use crate::tools::output::{ToolError, ToolOutput};
+
+    /// A tool with the given needs, for checking what they're gated on.
+    #[derive(Default)]
+    struct Needs {
+        macos_only: bool,
+        permissions: &'static [Permission],
+        user_click: bool,
+        missing_feature: Option<&'static str>,
+        sends_input: bool,
+    }
+
+    impl Tool for Needs {
+        fn name(&self) -> &str {
+            "needs"
+        }
+
+        fn description(&self) -> &str {
+            ""
+        }
+
+        fn schema(&self) -> Value {
+            json!({ "type": "object", "properties": {} })
+        }
+
+        fn call(&self, _args: &Value) -> Result<ToolOutput, ToolError> {
+            Ok(ToolOutput::text("done"))
+        }
+
+        fn requirements(&self) -> ToolRequirements {
+            ToolRequirements {
+                macos_only: self.macos_only,
+                permissions: self.permissions,
+                user_click: self.user_click,
+                missing_feature: self.missing_feature,
+            }
+        }
+
+        fn sends_input(&self) -> bool {
+            self.sends_input
+        }
+    }
+
+    /// Nothing filtered, user clicks off and no Wayland restriction.
+    fn platform(macos: bool, granted: &[Permission]) -> Availability {
+        Availability {
+            macos,
+            granted: granted.to_vec(),
+            user_click: false,
+            wayland_input: None,
+            allow: None,
+            deny: None,
+        }
+    }
+
+    fn wayland() -> Availability {
+        Availability {
+            wayland_input: Some("input needs the Wayland remote desktop portal".to_string()),
+            ..platform(false, &Permission::ALL)
+        }
+    }
+
+    fn tool_list(names: &[&str], source: &'static str) -> Option<ToolList> {
+        Some(ToolList {
+            names: names.iter().map(|n| n.to_string()).collect(),
+            source,
+        })
+    }
+
+    /// Check that `tool` is offered, if `expected` is None, or else refused
+    /// for a reason containing it.
+    fn check(case: &str, availability: &Availability, tool: &Needs, expected: Option<&str>) {
+        let reason = availability.unavailable_reason(tool);
+        match expected {
+            None => assert_eq!(reason, None, "{}", case),
+            Some(fragment) => assert!(
+                reason.as_deref().is_some_and(|r| r.contains(fragment)),
+                "{}: expected {:?}, got {:?}",
+                case,
+                fragment,
+                reason
+            ),
+        }
+    }
+
+    #[test]
+    fn tools_are_offered_by_platform_and_backend() {
+        use Permission::*;
+        let input = || Needs {
+            sends_input: true,
+            permissions: &[Accessibility],
+            ..Default::default()
+        };
+        let capture = || Needs {
+            permissions: &[ScreenRecording],
+            ..Default::default()
+        };
+        let macos_only = || Needs {
+            macos_only: true,
+            permissions: &[Accessibility],
+            ..Default::default()
+        };
+        let both = || Needs {
+            permissions: &[Accessibility, ScreenRecording],
+            ..Default::default()
+        };
+        // Expected: None if offered, else a fragment of the reason
+        let cases: Vec<(&str, Availability, Needs, Option<&str>)> = vec![
+            ("input on macOS", platform(true, &[Accessibility]), input(), None),
+            (
+                "input on macOS without Accessibility",
+                platform(true, &[]),
+                input(),
+                Some("needs the Accessibility permission"),
+            ),
+            (
+                "input on X11 or Windows",
+                platform(false, &Permission::ALL),
+                input(),
+                None,
+            ),
+            (
+                "input on Wayland",
+                wayland(),
+                input(),
+                Some("Wayland remote desktop portal"),
+            ),
+            ("capture on Wayland", wayland(), capture(), None),
+            ("capture on macOS", platform(true, &[ScreenRecording]), capture(), None),
+            (
+                "capture on macOS without Screen Recording",
+                platform(true, &[Accessibility]),
+                capture(),
+                Some("then restart the MCP client"),
+            ),
+            (
+                "macOS-only tool on macOS",
+                platform(true, &Permission::ALL),
+                macos_only(),
+                None,
+            ),
+            (
+                "macOS-only tool elsewhere",
+                platform(false, &Permission::ALL),
+                macos_only(),
+                Some("only supported on macOS"),
+            ),
+            (
+                "macOS-only tool on Wayland",
+                wayland(),
+                macos_only(),
+                Some("only supported on macOS"),
+            ),
+            (
+                "both permissions missing",
+                platform(true, &[]),
+                both(),
+                Some("needs the Accessibility and Screen Recording permissions"),
+            ),
+            (
+                "missing feature",
+                platform(true, &Permission::ALL),
+                Needs {
+                    missing_feature: Some("ocr"),
+                    ..Default::default()
+                },
+                Some("rebuild with --features ocr"),
+            ),
+            (
+                "user clicks off",
+                platform(true, &Permission::ALL),
+                Needs {
+                    user_click: true,
+                    ..Default::default()
+                },
+                Some("DESKTOP_MCP_ENABLE_USER_CLICK"),
+            ),
+            (
+                "user clicks on",
+                Availability {
+                    user_click: true,
+                    ..platform(true, &Permission::ALL)
+                },
+                Needs {
+                    user_click: true,
+                    ..Default::default()
+                },
+                None,
+            ),
+        ];
+        for (case, availability, tool, expected) in cases {
+            check(case, &availability, &tool, expected);
+        }
+    }
+
+    #[test]
+    fn configuration_filters_apply_before_the_platform() {
+        let cases: Vec<(&str, Availability, Option<&str>)> = vec![
+            (
+                "denied",
+                Availability {
+                    deny: tool_list(&["needs"], "--deny-tools"),
+                    ..platform(true, &Permission::ALL)
+                },
+                Some("disabled by server configuration (--deny-tools)"),
+            ),
+            (
+                "not allowed",
+                Availability {
+                    allow: tool_list(&["other"], "DESKTOP_MCP_ALLOW_TOOLS"),
+                    ..platform(true, &Permission::ALL)
+                },
+                Some("disabled by server configuration (not in DESKTOP_MCP_ALLOW_TOOLS)"),
+            ),
+            (
+                "allowed",
+                Availability {
+                    allow: tool_list(&["needs"], "config file"),
+                    ..platform(true, &Permission::ALL)
+                },
+                None,
+            ),
+            (
+                "allowed and denied",
+                Availability {
+                    allow: tool_list(&["needs"], "config file"),
+                    deny: tool_list(&["needs"], "config file"),
+                    ..platform(true, &Permission::ALL)
+                },
+                Some("disabled by server configuration (config file)"),
+            ),
+            (
+                "denied where it couldn't run anyway",
+                Availability {
+                    deny: tool_list(&["needs"], "--deny-tools"),
+                    ..platform(false, &[])
+                },
+                Some("disabled by server configuration"),
+            ),
+        ];
+        let tool = Needs {
+            macos_only: true,
+            ..Default::default()
+        };
+        for (case, availability, expected) in cases {
+            check(case, &availability, &tool, expected);
+        }
+    }
+}
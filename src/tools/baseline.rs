@@ -0,0 +1,261 @@
+//! Tools that save named reference captures and check the screen against
+//! them later, for regression-style checks.
+
+use serde_json::{json, Value};
+
+use crate::capture::baseline::{
+    baseline_exists, check_name, delete_baseline, list_baselines, load_baseline, save_baseline,
+};
+use crate::capture::diff::diff_capture;
+use crate::capture::template::{DEFAULT_MAX_HEIGHT, DEFAULT_MAX_WIDTH};
+use crate::tools::availability::{Permission, ToolRequirements};
+use crate::tools::output::{ToolError, ToolOutput};
+use crate::tools::screen::{parse_diff_options, parse_region, CompareScreens};
+use crate::tools::Tool;
+
+/// The `name` argument, checked.
+fn baseline_name(args: &Value) -> Result<&str, ToolError> {
+    let name = args
+        .get("name")
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| ToolError::missing("name"))?;
+    check_name(name).map_err(|e| ToolError::invalid_field("name", e))?;
+    Ok(name)
+}
+
+pub(crate) struct BaselineSave;
+
+impl Tool for BaselineSave {
+    fn name(&self) -> &str {
+        "baseline_save"
+    }
+
+    fn description(&self) -> &str {
+        "Capture a screen or region and save it under a name as a reference image, to check against later with baseline_compare. Baselines are kept on disk across restarts, with the screen and region they were taken of."
+    }
+
+    fn schema(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "name": {
+                    "type": "string",
+                    "description": "Name for the baseline: letters, digits, '-', '_' and '.', e.g. \"settings-dialog\""
+                },
+                "screen_index": {
+                    "type": "integer",
+                    "description": "Index of the screen to capture (default: 0)"
+                },
+                "region": {
+                    "type": "object",
+                    "description": "Region to capture in logical pixels (same coordinate system as screen_capture). If not provided, the entire screen.",
+                    "properties": {
+                        "x": { "type": "integer", "description": "X coordinate of top-left corner" },
+                        "y": { "type": "integer", "description": "Y coordinate of top-left corner" },
+                        "width": { "type": "integer", "description": "Width of region" },
+                        "height": { "type": "integer", "description": "Height of region" }
+                    },
+                    "required": ["x", "y", "width", "height"]
+                },
+                "max_width": {
+                    "type": "integer",
+                    "description": "Maximum width of the saved image; larger captures are downscaled (default: 1280)",
+                    "default": 1280,
+                    "minimum": 1
+                },
+                "max_height": {
+                    "type": "integer",
+                    "description": "Maximum height of the saved image (default: 720)",
+                    "default": 720,
+                    "minimum": 1
+                },
+                "overwrite": {
+                    "type": "boolean",
+                    "description": "Replace a baseline of the same name (default: false)",
+                    "default": false
+                }
+            },
+            "required": ["name"]
+        })
+    }
+
+    fn requirements(&self) -> ToolRequirements {
+        ToolRequirements {
+            permissions: &[Permission::ScreenRecording],
+            ..Default::default()
+        }
+    }
+
+    fn call(&self, args: &Value) -> Result<ToolOutput, ToolError> {
+        let name = baseline_name(args)?;
+        let overwrite = args.get("overwrite").and_then(|v| v.as_bool()).unwrap_or(false);
+        if !overwrite && baseline_exists(name)? {
+            return Err(ToolError::invalid_field(
+                "name",
+                format!("Baseline {} already exists; pass overwrite: true to replace it", name),
+            ));
+        }
+        let screen_index = args
+            .get("screen_index")
+            .and_then(|v| v.as_u64())
+            .map(|v| v as usize);
+        let region = parse_region(args).map_err(|e| ToolError::invalid_field("region", e))?;
+        let max_width = args
+            .get("max_width")
+            .and_then(|v| v.as_u64())
+            .unwrap_or(DEFAULT_MAX_WIDTH as u64) as u32;
+        let max_height = args
+            .get("max_height")
+            .and_then(|v| v.as_u64())
+            .unwrap_or(DEFAULT_MAX_HEIGHT as u64) as u32;
+
+        let record = save_baseline(name, screen_index, region, max_width.max(1), max_height.max(1))?;
+        log::info!("Saved baseline {}", name);
+        Ok(ToolOutput::json(&record))
+    }
+}
+
+pub(crate) struct BaselineCompare;
+
+impl Tool for BaselineCompare {
+    fn name(&self) -> &str {
+        "baseline_compare"
+    }
+
+    fn description(&self) -> &str {
+        "Capture the area a baseline was taken of and compare it with the baseline, as compare_screens does. Returns matches, changed_percent as the difference score, and the changed regions in global coordinates, largest first. The screen matches when no changed region is left after the tolerances, or when changed_percent is at most max_changed_percent."
+    }
+
+    fn schema(&self) -> Value {
+        // Takes compare_screens' tolerance arguments
+        let mut schema = CompareScreens.schema();
+        if let Some(properties) = schema["properties"].as_object_mut() {
+            properties.retain(|name, _| {
+                matches!(
+                    name.as_str(),
+                    "channel_tolerance" | "min_region_pixels" | "ignore_regions" | "diff_image"
+                )
+            });
+            properties.insert(
+                "name".to_string(),
+                json!({
+                    "type": "string",
+                    "description": "Name of the baseline, as given to baseline_save"
+                }),
+            );
+            properties.insert(
+                "max_changed_percent".to_string(),
+                json!({
+                    "type": "number",
+                    "description": "Percentage of changed pixels still counted as a match (default: 0, only changes below min_region_pixels)",
+                    "default": 0,
+                    "minimum": 0,
+                    "maximum": 100
+                }),
+            );
+        }
+        schema["required"] = json!(["name"]);
+        schema
+    }
+
+    fn requirements(&self) -> ToolRequirements {
+        ToolRequirements {
+            permissions: &[Permission::ScreenRecording],
+            ..Default::default()
+        }
+    }
+
+    fn read_only(&self) -> bool {
+        true
+    }
+
+    fn call(&self, args: &Value) -> Result<ToolOutput, ToolError> {
+        let name = baseline_name(args)?;
+        let max_changed_percent = args
+            .get("max_changed_percent")
+            .and_then(|v| v.as_f64())
+            .unwrap_or(0.0);
+        let mut options = parse_diff_options(args)?;
+        // Baselines are saved downscaled, so the capture always needs scaling
+        options.scale_mismatch = true;
+
+        let (base, area, record) =
+            load_baseline(name)?.ok_or_else(|| ToolError::NotFound(format!("No baseline named {}", name)))?;
+        let mut diff = diff_capture(&area, &base, "name", &options)?;
+        let changed = diff.result["changed"].as_bool().unwrap_or(false);
+        let changed_percent = diff.result["changed_percent"].as_f64().unwrap_or(0.0);
+        diff.result["name"] = json!(name);
+        diff.result["created"] = record["created"].clone();
+        diff.result["matches"] = json!(!changed || changed_percent <= max_changed_percent);
+        Ok(diff.into_output())
+    }
+}
+
+pub(crate) struct BaselineList;
+
+impl Tool for BaselineList {
+    fn name(&self) -> &str {
+        "baseline_list"
+    }
+
+    fn description(&self) -> &str {
+        "List the saved baselines, sorted by name, each with when it was created, the screen_index and region it was taken of and its saved size."
+    }
+
+    fn schema(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {}
+        })
+    }
+
+    fn read_only(&self) -> bool {
+        true
+    }
+
+    fn call(&self, _args: &Value) -> Result<ToolOutput, ToolError> {
+        let baselines = list_baselines()?;
+        Ok(ToolOutput::json(&json!({
+            "count": baselines.len(),
+            "baselines": baselines
+        })))
+    }
+}
+
+pub(crate) struct BaselineDelete;
+
+impl Tool for BaselineDelete {
+    fn name(&self) -> &str {
+        "baseline_delete"
+    }
+
+    fn description(&self) -> &str {
+        "Delete a saved baseline. Fails with not_found if there's none of that name."
+    }
+
+    fn schema(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "name": {
+                    "type": "string",
+                    "description": "Name of the baseline to delete"
+                }
+            },
+            "required": ["name"]
+        })
+    }
+
+    fn destructive(&self) -> bool {
+        true
+    }
+
+    fn call(&self, args: &Value) -> Result<ToolOutput, ToolError> {
+        let name = baseline_name(args)?;
+        if !delete_baseline(name)? {
+            return Err(ToolError::NotFound(format!("No baseline named {}", name)));
+        }
+        log::info!("Deleted baseline {}", name);
+        Ok(ToolOutput::json(&json!({ "success": true, "name": name })))
+    }
+}
@@ -0,0 +1,100 @@
+//! Serializing input between concurrent calls, and putting the cursor
+//! back after a call.
+
+use serde_json::{json, Value};
+use std::cell::Cell;
+use std::sync::{Mutex, MutexGuard};
+
+use crate::capture::coords::get_mouse_position;
+use crate::input::do_mouse_move;
+use crate::input::physical::SENT_EVENTS;
+use crate::tools::output::{ContentItem, ToolError, ToolOutput};
+use crate::tools::{execute_timed, server_arguments, Tool};
+
+/// Serializes input simulation. Tool calls run concurrently, so tools that
+/// hold the input (see Tool::holds_input) take this for the whole call
+/// (run_sequence for the whole sequence) and their events never interleave
+/// with another call's. Read-only tools run alongside them without it.
+static INPUT_LOCK: Mutex<()> = Mutex::new(());
+
+thread_local! {
+    /// Whether this thread holds INPUT_LOCK, so that a sequence's steps can
+    /// take it again without deadlocking.
+    static HOLDS_INPUT_LOCK: Cell<bool> = const { Cell::new(false) };
+}
+
+/// INPUT_LOCK, held until dropped. Empty if the thread already held it.
+pub(crate) struct InputGuard {
+    guard: Option<MutexGuard<'static, ()>>,
+}
+
+impl Drop for InputGuard {
+    fn drop(&mut self) {
+        if self.guard.is_some() {
+            HOLDS_INPUT_LOCK.set(false);
+        }
+    }
+}
+
+pub(crate) fn lock_input() -> InputGuard {
+    if HOLDS_INPUT_LOCK.get() {
+        return InputGuard { guard: None };
+    }
+    let guard = INPUT_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    HOLDS_INPUT_LOCK.set(true);
+    InputGuard { guard: Some(guard) }
+}
+
+pub(crate) fn cursor_position() -> Option<(f64, f64)> {
+    let pos = get_mouse_position().ok()?;
+    Some((pos.get("x")?.as_f64()?, pos.get("y")?.as_f64()?))
+}
+
+/// Run a tool, putting the cursor back where it was afterwards if asked to
+/// (or by default for read-only tools). Restoration happens on error paths
+/// too. Only a call that sent input itself restores the cursor: if it
+/// moved otherwise, the user or a concurrent call moved it, and it's left
+/// where they put it. A report of the restoration is appended to the
+/// result when the call moved the cursor or can't read it, and when it
+/// passed restore_cursor itself but something else moved the cursor.
+pub(crate) fn execute_restoring_cursor(tool: &dyn Tool, args: &Value) -> Result<ToolOutput, ToolError> {
+    let requested = server_arguments(tool, args)
+        .get("restore_cursor")
+        .and_then(|v| v.as_bool());
+    let restore = requested.unwrap_or(tool.read_only());
+    let _input = tool.holds_input().then(lock_input);
+    if !restore {
+        return execute_timed(tool, args);
+    }
+
+    let saved = cursor_position();
+    let sent_before = SENT_EVENTS.get();
+    let output = execute_timed(tool, args);
+    let sent_input = SENT_EVENTS.get() != sent_before;
+
+    let Some((x, y)) = saved else {
+        if !sent_input {
+            return output;
+        }
+        let report = json!({ "cursor_restored": false, "reason": "The cursor position can't be read on this platform" });
+        return Ok(output?.with(ContentItem::Json(report)));
+    };
+    let moved = cursor_position().is_some_and(|(cx, cy)| (cx - x).abs() >= 0.5 || (cy - y).abs() >= 0.5);
+    if !moved {
+        return output;
+    }
+
+    let report = if sent_input {
+        let _restoring = lock_input();
+        match do_mouse_move(x, y) {
+            Ok(()) => json!({ "cursor_restored": true, "position": { "x": x, "y": y } }),
+            Err(e) => json!({ "cursor_restored": false, "error": e }),
+        }
+    } else if requested.is_some() {
+        json!({ "cursor_restored": false, "reason": "This call didn't move the cursor; something else did" })
+    } else {
+        // Restoring by default, so there's no need to mention it
+        return output;
+    };
+    Ok(output?.with(ContentItem::Json(report)))
+}
@@ -0,0 +1,338 @@
+//! Tools provided by external commands, configured with
+//! `DESKTOP_MCP_EXTERNAL_TOOLS`.
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::io::{self, Write};
+use std::sync::OnceLock;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::tools::limits::dry_run;
+use crate::tools::output::{ToolError, ToolOutput};
+use crate::tools::timeout::TIMEOUT_GRACE;
+use crate::tools::{server_arguments, title_from_name, Tool, BUILTIN_TOOLS};
+
+/// Default time an external tool may run before it's killed.
+const DEFAULT_EXTERNAL_TIMEOUT_MS: u64 = 30_000;
+
+/// Default cap on an external tool's stdout.
+const DEFAULT_EXTERNAL_MAX_OUTPUT: usize = 1024 * 1024;
+
+/// The external tools file, named by `DESKTOP_MCP_EXTERNAL_TOOLS`:
+///
+/// ```json
+/// { "tools": [{
+///     "name": "vpn_toggle",
+///     "title": "Toggle VPN",
+///     "description": "Connect or disconnect the office VPN",
+///     "input_schema": "vpn_toggle.schema.json",
+///     "command": ["/usr/local/bin/vpn-toggle", "--json"],
+///     "timeout_ms": 10000,
+///     "annotations": { "readOnlyHint": false, "destructiveHint": false },
+///     "alongside_input": true
+/// }] }
+/// ```
+///
+/// Relative paths are resolved against the file's directory, while a bare
+/// program name is looked up on PATH. Commands are spawned directly, never
+/// through a shell. Without `annotations`, clients assume the worst of a
+/// tool, and so does the server: it isn't read-only and it holds the input
+/// for its whole run, unless `alongside_input` says the command doesn't
+/// touch the mouse or keyboard.
+#[derive(Debug, Deserialize)]
+struct ExternalToolsFile {
+    tools: Vec<ExternalToolConfig>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ExternalToolConfig {
+    name: String,
+    title: Option<String>,
+    #[serde(default)]
+    description: String,
+    /// Tool annotations, advertised as written.
+    annotations: Option<Value>,
+    /// Path to a JSON file holding the tool's inputSchema.
+    input_schema: Option<String>,
+    command: Vec<String>,
+    timeout_ms: Option<u64>,
+    max_output_bytes: Option<usize>,
+    /// Run alongside input tools rather than holding the input.
+    #[serde(default)]
+    alongside_input: bool,
+}
+
+pub(crate) struct ExternalTool {
+    name: String,
+    title: Option<String>,
+    description: String,
+    annotations: Option<Value>,
+    input_schema: Value,
+    program: std::path::PathBuf,
+    args: Vec<String>,
+    timeout: Duration,
+    max_output_bytes: usize,
+    alongside_input: bool,
+}
+
+impl Tool for ExternalTool {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn title(&self) -> String {
+        self.title.clone().unwrap_or_else(|| title_from_name(&self.name))
+    }
+
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn schema(&self) -> Value {
+        self.input_schema.clone()
+    }
+
+    /// External schemas are advertised as written, without the arguments
+    /// built-in tools gain, and so are annotations.
+    fn definition(&self) -> Value {
+        let mut definition = json!({
+            "name": self.name,
+            "title": self.title(),
+            "description": self.description,
+            "inputSchema": self.input_schema
+        });
+        if let Some(annotations) = &self.annotations {
+            definition["annotations"] = annotations.clone();
+        }
+        definition
+    }
+
+    /// Only if annotated so: an arbitrary command may change anything.
+    fn read_only(&self) -> bool {
+        self.annotations
+            .as_ref()
+            .and_then(|a| a.get("readOnlyHint"))
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false)
+    }
+
+    fn holds_input(&self) -> bool {
+        !self.read_only() && !self.alongside_input
+    }
+
+    /// The command is killed at its own timeout, so the call ends by then.
+    fn timeout(&self, _args: &Value) -> Option<Duration> {
+        Some(self.timeout + TIMEOUT_GRACE)
+    }
+
+    fn call(&self, args: &Value) -> Result<ToolOutput, ToolError> {
+        Ok(run_external_tool(self, args))
+    }
+}
+
+pub(crate) fn external_tools() -> &'static [ExternalTool] {
+    static TOOLS: OnceLock<Vec<ExternalTool>> = OnceLock::new();
+    TOOLS.get_or_init(|| match std::env::var("DESKTOP_MCP_EXTERNAL_TOOLS") {
+        Ok(path) => load_external_tools(std::path::Path::new(&path)).unwrap_or_else(|e| {
+            log::warn!("Failed to load external tools from {}: {}", path, e);
+            Vec::new()
+        }),
+        Err(_) => Vec::new(),
+    })
+}
+
+fn load_external_tools(path: &std::path::Path) -> Result<Vec<ExternalTool>, String> {
+    let contents = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let file: ExternalToolsFile = serde_json::from_str(&contents).map_err(|e| e.to_string())?;
+    let base = path.parent().unwrap_or(std::path::Path::new("."));
+
+    let mut tools: Vec<ExternalTool> = Vec::new();
+    for config in file.tools {
+        if BUILTIN_TOOLS.iter().any(|t| t.name() == config.name) || tools.iter().any(|t| t.name == config.name) {
+            log::warn!("Skipping external tool {}: name already in use", config.name);
+            continue;
+        }
+        let Some((program, args)) = config.command.split_first() else {
+            log::warn!("Skipping external tool {}: empty command", config.name);
+            continue;
+        };
+
+        if config.annotations.as_ref().is_some_and(|a| !a.is_object()) {
+            log::warn!("Skipping external tool {}: annotations must be an object", config.name);
+            continue;
+        }
+
+        let input_schema = match &config.input_schema {
+            Some(schema_path) => {
+                let schema = std::fs::read_to_string(base.join(schema_path))
+                    .map_err(|e| e.to_string())
+                    .and_then(|s| serde_json::from_str(&s).map_err(|e| e.to_string()));
+                match schema {
+                    Ok(schema) => schema,
+                    Err(e) => {
+                        log::warn!("Skipping external tool {}: bad schema {}: {}", config.name, schema_path, e);
+                        continue;
+                    }
+                }
+            }
+            None => json!({ "type": "object", "properties": {} }),
+        };
+
+        log::info!("Registered external tool: {}", config.name);
+        tools.push(ExternalTool {
+            name: config.name,
+            title: config.title,
+            description: config.description,
+            annotations: config.annotations,
+            input_schema,
+            program: if program.contains(['/', std::path::MAIN_SEPARATOR]) {
+                base.join(program)
+            } else {
+                program.into()
+            },
+            args: args.to_vec(),
+            timeout: Duration::from_millis(config.timeout_ms.unwrap_or(DEFAULT_EXTERNAL_TIMEOUT_MS)),
+            max_output_bytes: config.max_output_bytes.unwrap_or(DEFAULT_EXTERNAL_MAX_OUTPUT),
+            alongside_input: config.alongside_input,
+        });
+    }
+
+    Ok(tools)
+}
+
+/// A structured failure result for an external tool.
+fn external_error(tool: &ExternalTool, kind: &str, message: String, extra: Value) -> ToolOutput {
+    let mut result = json!({
+        "error": kind,
+        "error_code": "platform_error",
+        "tool": tool.name,
+        "message": message
+    });
+    if let (Some(result), Some(extra)) = (result.as_object_mut(), extra.as_object()) {
+        result.extend(extra.clone());
+    }
+    ToolOutput {
+        is_error: true,
+        ..ToolOutput::json(&result)
+    }
+}
+
+/// Read at most `limit` bytes from a pipe, draining (and discarding) the rest
+/// so the child never blocks on a full pipe. Returns the bytes and whether
+/// anything was cut off.
+fn read_limited(mut pipe: impl io::Read, limit: usize) -> (Vec<u8>, bool) {
+    let mut kept = Vec::new();
+    let mut truncated = false;
+    let mut chunk = [0u8; 8192];
+    while let Ok(n) = pipe.read(&mut chunk) {
+        if n == 0 {
+            break;
+        }
+        let room = limit.saturating_sub(kept.len());
+        kept.extend_from_slice(&chunk[..n.min(room)]);
+        truncated |= n > room;
+    }
+    (kept, truncated)
+}
+
+/// Run an external tool with the call's arguments as JSON on stdin, less
+/// those the server handled itself. Stdout becomes the result: JSON output
+/// is passed through, anything else is returned as text. A dry run only
+/// reports the command it would have run.
+fn run_external_tool(tool: &ExternalTool, args: &Value) -> ToolOutput {
+    use std::process::{Command, Stdio};
+
+    let handled = server_arguments(tool, args);
+    let mut args = args.clone();
+    if let (Some(args), Some(handled)) = (args.as_object_mut(), handled.as_object()) {
+        args.retain(|name, _| !handled.contains_key(name));
+    }
+
+    if dry_run() {
+        let command: Vec<String> = std::iter::once(tool.program.display().to_string())
+            .chain(tool.args.iter().cloned())
+            .collect();
+        return ToolOutput::json(&json!({
+            "success": true,
+            "command": command,
+            "input": args,
+            "simulated": true
+        }));
+    }
+
+    let mut child = match Command::new(&tool.program)
+        .args(&tool.args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+    {
+        Ok(child) => child,
+        Err(e) => {
+            return external_error(
+                tool,
+                "spawn_failed",
+                format!("Failed to start {}: {}", tool.program.display(), e),
+                json!({}),
+            )
+        }
+    };
+
+    // Feed stdin from a thread so a child that doesn't read it can't block us
+    if let Some(mut stdin) = child.stdin.take() {
+        let input = args.to_string();
+        thread::spawn(move || {
+            let _ = stdin.write_all(input.as_bytes());
+        });
+    }
+    let limit = tool.max_output_bytes;
+    let stdout = child.stdout.take().map(|out| thread::spawn(move || read_limited(out, limit)));
+    let stderr = child.stderr.take().map(|err| thread::spawn(move || read_limited(err, 4096)));
+
+    let start = Instant::now();
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break status,
+            Ok(None) if start.elapsed() >= tool.timeout => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return external_error(
+                    tool,
+                    "timeout",
+                    format!("Timed out after {} ms", tool.timeout.as_millis()),
+                    json!({ "timeout_ms": tool.timeout.as_millis() as u64 }),
+                );
+            }
+            Ok(None) => thread::sleep(Duration::from_millis(10)),
+            Err(e) => return external_error(tool, "wait_failed", e.to_string(), json!({})),
+        }
+    };
+
+    let (stdout, truncated) = stdout.and_then(|h| h.join().ok()).unwrap_or_default();
+    let (stderr, _) = stderr.and_then(|h| h.join().ok()).unwrap_or_default();
+    let stderr = String::from_utf8_lossy(&stderr).trim().to_string();
+
+    if !status.success() {
+        return external_error(
+            tool,
+            "exit_status",
+            format!("Exited with {}", status),
+            json!({ "exit_code": status.code(), "stderr": stderr }),
+        );
+    }
+    if truncated {
+        return external_error(
+            tool,
+            "output_too_large",
+            format!("Output exceeded {} bytes", tool.max_output_bytes),
+            json!({ "max_output_bytes": tool.max_output_bytes }),
+        );
+    }
+
+    let stdout = String::from_utf8_lossy(&stdout);
+    match serde_json::from_str::<Value>(&stdout) {
+        Ok(value) => ToolOutput::json(&value),
+        Err(_) => ToolOutput::text(stdout.trim_end()),
+    }
+}
@@ -0,0 +1,136 @@
+//! Refusing input aimed at the MCP client's own window, at a window other
+//! than the one the call expects, or at a locked session.
+
+use serde_json::{json, Value};
+use std::sync::OnceLock;
+
+use crate::config::loaded_config;
+use crate::system_info::is_session_locked;
+use crate::tools::output::{ToolError, ToolOutput};
+use crate::tools::Tool;
+use crate::window::{frontmost_window, WindowInfo};
+
+/// How the MCP client's windows are recognized: by the process IDs of the
+/// server's ancestors (the client spawned us), plus any case-insensitive
+/// title or app name substrings in `DESKTOP_MCP_CLIENT_WINDOW_PATTERNS`
+/// (comma-separated), for clients whose windows belong to another process.
+pub(crate) struct ClientIdentity {
+    pub(crate) pids: Vec<i64>,
+    pub(crate) patterns: Vec<String>,
+}
+
+pub(crate) fn client_identity() -> &'static ClientIdentity {
+    static IDENTITY: OnceLock<ClientIdentity> = OnceLock::new();
+    IDENTITY.get_or_init(|| ClientIdentity {
+        pids: ancestor_pids(),
+        patterns: std::env::var("DESKTOP_MCP_CLIENT_WINDOW_PATTERNS")
+            .map(|v| {
+                v.split(',')
+                    .map(|p| p.trim().to_lowercase())
+                    .filter(|p| !p.is_empty())
+                    .collect()
+            })
+            .unwrap_or_default(),
+    })
+}
+
+/// The parent chain of this process, nearest first.
+#[cfg(unix)]
+fn ancestor_pids() -> Vec<i64> {
+    let mut pids = Vec::new();
+    let mut pid = std::os::unix::process::parent_id() as i64;
+    // The chain is short; the cap only guards against a misbehaving ps
+    while pid > 1 && pids.len() < 16 {
+        pids.push(pid);
+        pid = std::process::Command::new("ps")
+            .args(["-o", "ppid=", "-p", &pid.to_string()])
+            .output()
+            .ok()
+            .and_then(|out| String::from_utf8_lossy(&out.stdout).trim().parse().ok())
+            .unwrap_or(0);
+    }
+    pids
+}
+
+#[cfg(not(unix))]
+fn ancestor_pids() -> Vec<i64> {
+    Vec::new()
+}
+
+/// Whether a window belongs to the MCP client.
+fn is_client_window(window: &WindowInfo, identity: &ClientIdentity) -> bool {
+    if identity.pids.contains(&window.pid) {
+        return true;
+    }
+    let title = window.title.to_lowercase();
+    let app_name = window.app_name.to_lowercase();
+    identity
+        .patterns
+        .iter()
+        .any(|p| title.contains(p.as_str()) || app_name.contains(p.as_str()))
+}
+
+/// Refuse input tools while the client's own window is frontmost, unless
+/// the call passes `allow_self_target: true`. Where the frontmost window
+/// can't be determined, input is allowed.
+pub(crate) fn check_self_target(tool: &dyn Tool, args: &Value) -> Option<ToolOutput> {
+    if !tool.sends_input()
+        || args
+            .get("allow_self_target")
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false)
+    {
+        return None;
+    }
+    let window = frontmost_window().ok().flatten()?;
+    if !is_client_window(&window, client_identity()) {
+        return None;
+    }
+
+    Some(ToolOutput {
+        is_error: true,
+        ..ToolOutput::json(&json!({
+            "error": "SelfTargetBlocked",
+            "error_code": "permission_denied",
+            "message": format!(
+                "Refusing to send input to the MCP client's own window \"{}\" ({}). Focus the target application first, or pass allow_self_target: true.",
+                window.title, window.app_name
+            ),
+            "window": window.to_json()
+        }))
+    })
+}
+
+/// Refuse a call's input unless the frontmost window's title or app name
+/// contains its `require_focused_window` (case-insensitive). Unlike the
+/// self-target guard, a frontmost window that can't be determined is
+/// refused, since the caller asked for the check.
+pub(crate) fn check_focused_window(args: &Value) -> Result<(), ToolError> {
+    let Some(expected) = args.get("require_focused_window").and_then(|v| v.as_str()) else {
+        return Ok(());
+    };
+    let window = frontmost_window()
+        .map_err(|e| ToolError::PermissionDenied(format!("Can't check the focused window: {}", e)))?
+        .ok_or_else(|| ToolError::PermissionDenied("No window is focused".to_string()))?;
+    let expected_lower = expected.to_lowercase();
+    if window.title.to_lowercase().contains(&expected_lower) || window.app_name.to_lowercase().contains(&expected_lower) {
+        return Ok(());
+    }
+    Err(ToolError::PermissionDenied(format!(
+        "The focused window is \"{}\" ({}), not one matching \"{}\"; nothing was sent",
+        window.title, window.app_name, expected
+    )))
+}
+
+/// Refuse input while the session is locked or the screensaver is running,
+/// unless the config file's `check_session_lock` is false. A session whose
+/// state can't be told is let through.
+pub(crate) fn check_session_unlocked() -> Result<(), ToolError> {
+    if loaded_config().config.safety.check_session_lock == Some(false) {
+        return Ok(());
+    }
+    match is_session_locked() {
+        Some(true) => Err(ToolError::SessionLocked),
+        _ => Ok(()),
+    }
+}
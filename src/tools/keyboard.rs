@@ -0,0 +1,648 @@
+//! Keyboard tools.
+
+use rdev::Key;
+use serde_json::{json, Value};
+use std::time::Duration;
+
+use crate::config::loaded_config;
+use crate::input::layout::{active_layout, probe_layout, LAYOUTS};
+use crate::input::{
+    do_key_hold, do_key_sequence, do_paste_text, do_type_text, first_untypable, is_modifier_key, resolve_key,
+    tap_key_repeated, KeyStep, TypingRhythm, MAX_KEY_SEQUENCE_DELAY_MS, MAX_KEY_SEQUENCE_TAPS, MAX_PASTE_SETTLE_MS,
+};
+use crate::tools::availability::{Permission, ToolRequirements};
+use crate::tools::guard::check_focused_window;
+use crate::tools::limits::{dry_run, max_key_hold_ms};
+use crate::tools::output::{ContentItem, ToolError, ToolOutput};
+use crate::tools::timeout::{duration_timeout, DEFAULT_CALL_TIMEOUT_MS};
+use crate::tools::{parse_modifiers, Tool};
+
+pub(crate) struct KeyTap;
+
+impl Tool for KeyTap {
+    fn name(&self) -> &str {
+        "key_tap"
+    }
+
+    fn description(&self) -> &str {
+        "Press and release a single key, optionally with modifiers, or tap it several times with repeat (e.g. backspace 40 times), keeping the modifiers held throughout. Returns the number of taps."
+    }
+
+    fn schema(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "key": {
+                    "type": "string",
+                    "description": "Key to press (case-insensitive): a letter, digit or punctuation character; enter, tab, space, backspace, delete, escape, up, down, left, right, home, end, pageup, pagedown, insert, f1-f24 (f1-f20 on macOS), capslock, numlock, scrolllock, printscreen, pause, menu; keypad kp0-kp9, kpenter, kpplus, kpminus, kpmultiply, kpdivide, kpdecimal; modifiers shift, ctrl, alt, meta and right-side rshift, rctrl, ralt, rmeta; media keys volumeup, volumedown, volumemute, playpause, nexttrack, prevtrack, stop (not on macOS)"
+                },
+                "raw_code": {
+                    "type": "integer",
+                    "description": "Send this native key code instead of a named key: a macOS virtual key code, Windows virtual-key code or X11 keycode. Takes precedence over key."
+                },
+                "modifiers": {
+                    "type": "array",
+                    "items": {
+                        "type": "string",
+                        "enum": ["shift", "control", "alt", "meta"]
+                    },
+                    "description": "Modifier keys to hold during the key press"
+                },
+                "require_focused_window": {
+                    "type": "string",
+                    "description": "Only send input if the focused window's title or app name contains this (case-insensitive); otherwise fail with permission_denied and send nothing"
+                },
+                "repeat": {
+                    "type": "integer",
+                    "description": format!("Number of taps (default: 1, max: {}). Modifiers stay held across all of them.", MAX_KEY_SEQUENCE_TAPS),
+                    "default": 1,
+                    "minimum": 1,
+                    "maximum": MAX_KEY_SEQUENCE_TAPS
+                },
+                "repeat_delay_ms": {
+                    "type": "integer",
+                    "description": format!("Delay between taps in milliseconds (default: 50, max: {})", MAX_KEY_SEQUENCE_DELAY_MS),
+                    "default": 50,
+                    "minimum": 0,
+                    "maximum": MAX_KEY_SEQUENCE_DELAY_MS
+                }
+            }
+        })
+    }
+
+    fn requirements(&self) -> ToolRequirements {
+        ToolRequirements {
+            permissions: &[Permission::Accessibility],
+            ..Default::default()
+        }
+    }
+
+    fn sends_input(&self) -> bool {
+        true
+    }
+
+    fn call(&self, args: &Value) -> Result<ToolOutput, ToolError> {
+        check_focused_window(args)?;
+        let modifiers = parse_modifiers(args);
+        let repeat = args
+            .get("repeat")
+            .and_then(|v| v.as_u64())
+            .unwrap_or(1)
+            .clamp(1, MAX_KEY_SEQUENCE_TAPS);
+        let delay = Duration::from_millis(
+            args.get("repeat_delay_ms")
+                .and_then(|v| v.as_u64())
+                .unwrap_or(50)
+                .min(MAX_KEY_SEQUENCE_DELAY_MS),
+        );
+
+        // A raw key code is sent as-is, for keys without a name
+        if let Some(code) = args.get("raw_code").and_then(|v| v.as_u64()) {
+            let code = u32::try_from(code)
+                .map_err(|_| ToolError::invalid_field("raw_code", format!("raw_code out of range: {}", code)))?;
+            let taps = tap_key_repeated(Key::Unknown(code), &modifiers, repeat, delay)?;
+            return Ok(ToolOutput::json(&json!({
+                "success": true,
+                "raw_code": code,
+                "modifiers": modifiers,
+                "taps": taps
+            })));
+        }
+
+        let key = args
+            .get("key")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| ToolError::invalid_field("key", "Missing key (or raw_code)"))?;
+
+        let code = resolve_key(key).map_err(|e| ToolError::invalid_field("key", e))?;
+        let taps = tap_key_repeated(code, &modifiers, repeat, delay)?;
+        Ok(ToolOutput::json(&json!({
+            "success": true,
+            "key": key,
+            "modifiers": modifiers,
+            "taps": taps
+        })))
+    }
+}
+
+pub(crate) struct KeyHold;
+
+impl Tool for KeyHold {
+    fn name(&self) -> &str {
+        "key_hold"
+    }
+
+    fn description(&self) -> &str {
+        "Hold a key down for a while, optionally with modifiers, then release it: for games and media apps that act on how long a key is held, e.g. the right arrow for 3 seconds. The key is always released, even if the call is cancelled or the failsafe is triggered. Returns the actual held_ms."
+    }
+
+    fn schema(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "key": {
+                    "type": "string",
+                    "description": "Key to hold, named as in key_tap"
+                },
+                "duration_ms": {
+                    "type": "integer",
+                    "description": format!("How long to hold it, in milliseconds (max: {})", max_key_hold_ms()),
+                    "minimum": 0,
+                    "maximum": max_key_hold_ms()
+                },
+                "modifiers": {
+                    "type": "array",
+                    "items": {
+                        "type": "string",
+                        "enum": ["shift", "control", "alt", "meta"]
+                    },
+                    "description": "Modifier keys to hold along with it"
+                },
+                "allow_modifiers": {
+                    "type": "boolean",
+                    "description": "Allow key to be a modifier itself, such as shift or ctrl (default: false)",
+                    "default": false
+                }
+            },
+            "required": ["key", "duration_ms"]
+        })
+    }
+
+    fn requirements(&self) -> ToolRequirements {
+        ToolRequirements {
+            permissions: &[Permission::Accessibility],
+            ..Default::default()
+        }
+    }
+
+    fn sends_input(&self) -> bool {
+        true
+    }
+
+    fn timeout(&self, args: &Value) -> Option<Duration> {
+        duration_timeout(args, max_key_hold_ms())
+    }
+
+    fn call(&self, args: &Value) -> Result<ToolOutput, ToolError> {
+        let key = args
+            .get("key")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| ToolError::missing("key"))?;
+        let duration_ms = args
+            .get("duration_ms")
+            .and_then(|v| v.as_u64())
+            .ok_or_else(|| ToolError::missing("duration_ms"))?;
+        let modifiers = parse_modifiers(args);
+        let allow_modifiers = args.get("allow_modifiers").and_then(|v| v.as_bool()).unwrap_or(false);
+
+        let code = resolve_key(key).map_err(|e| ToolError::invalid_field("key", e))?;
+        if is_modifier_key(code) && !allow_modifiers {
+            return Err(ToolError::invalid_field(
+                "key",
+                format!(
+                    "{} is a modifier; a stuck one changes every later key press, so pass allow_modifiers: true to hold it",
+                    key
+                ),
+            ));
+        }
+
+        let held = do_key_hold(code, &modifiers, Duration::from_millis(duration_ms))?;
+        Ok(ToolOutput::json(&json!({
+            "success": true,
+            "key": key,
+            "modifiers": modifiers,
+            "duration_ms": duration_ms,
+            "held_ms": held.as_millis() as u64
+        })))
+    }
+}
+
+pub(crate) struct KeySequence;
+
+impl Tool for KeySequence {
+    fn name(&self) -> &str {
+        "key_sequence"
+    }
+
+    fn description(&self) -> &str {
+        "Tap a list of keys or key combinations in order in one call, e.g. [\"tab\", \"tab\", \"space\", \"enter\"] to work through a dialog. Every key is checked before anything is sent. Stops at the first failure and reports its index; modifiers are never left held."
+    }
+
+    fn schema(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "keys": {
+                    "type": "array",
+                    "items": {
+                        "oneOf": [
+                            { "type": "string", "description": "Key name, as in key_tap" },
+                            {
+                                "type": "object",
+                                "properties": {
+                                    "key": { "type": "string", "description": "Key name, as in key_tap" },
+                                    "modifiers": {
+                                        "type": "array",
+                                        "items": {
+                                            "type": "string",
+                                            "enum": ["shift", "control", "alt", "meta"]
+                                        },
+                                        "description": "Modifier keys to hold during the key press"
+                                    },
+                                    "repeat": {
+                                        "type": "integer",
+                                        "description": "How many times to tap it (default: 1)",
+                                        "default": 1,
+                                        "minimum": 1,
+                                        "maximum": MAX_KEY_SEQUENCE_TAPS
+                                    }
+                                },
+                                "required": ["key"]
+                            }
+                        ]
+                    },
+                    "description": "Keys to tap in order. At most 1000 taps in total, counting repeats."
+                },
+                "delay_ms": {
+                    "type": "integer",
+                    "description": "Delay between taps in milliseconds (default: 50, max: 5000)",
+                    "default": 50
+                }
+            },
+            "required": ["keys"]
+        })
+    }
+
+    fn requirements(&self) -> ToolRequirements {
+        ToolRequirements {
+            permissions: &[Permission::Accessibility],
+            ..Default::default()
+        }
+    }
+
+    fn sends_input(&self) -> bool {
+        true
+    }
+
+    /// The usual allowance plus the time spent waiting between taps.
+    fn timeout(&self, args: &Value) -> Option<Duration> {
+        let taps = args
+            .get("keys")
+            .and_then(|v| v.as_array())?
+            .iter()
+            .map(|step| {
+                step.get("repeat")
+                    .and_then(|v| v.as_u64())
+                    .unwrap_or(1)
+                    .min(MAX_KEY_SEQUENCE_TAPS)
+            })
+            .fold(0u64, u64::saturating_add)
+            .min(MAX_KEY_SEQUENCE_TAPS);
+        let delay_ms = args
+            .get("delay_ms")
+            .and_then(|v| v.as_u64())
+            .unwrap_or(50)
+            .min(MAX_KEY_SEQUENCE_DELAY_MS);
+        Some(Duration::from_millis(DEFAULT_CALL_TIMEOUT_MS + taps * delay_ms))
+    }
+
+    fn call(&self, args: &Value) -> Result<ToolOutput, ToolError> {
+        let steps = args
+            .get("keys")
+            .and_then(|v| v.as_array())
+            .ok_or_else(|| ToolError::missing("keys"))?
+            .iter()
+            .enumerate()
+            .map(|(i, step)| {
+                KeyStep::parse(step).map_err(|e| ToolError::invalid_field("keys", format!("keys[{}]: {}", i, e)))
+            })
+            .collect::<Result<Vec<KeyStep>, ToolError>>()?;
+        // Each repeat is at most MAX_KEY_SEQUENCE_TAPS, so this can't overflow
+        let total: u64 = steps.iter().map(|step| step.repeat).sum();
+        if total > MAX_KEY_SEQUENCE_TAPS {
+            return Err(ToolError::invalid_field(
+                "keys",
+                format!("Sequence has {} taps (max {})", total, MAX_KEY_SEQUENCE_TAPS),
+            ));
+        }
+        let delay_ms = args
+            .get("delay_ms")
+            .and_then(|v| v.as_u64())
+            .unwrap_or(50)
+            .min(MAX_KEY_SEQUENCE_DELAY_MS);
+
+        let taps = do_key_sequence(&steps, Duration::from_millis(delay_ms))?;
+        Ok(ToolOutput::json(&json!({
+            "success": true,
+            "steps": steps.len(),
+            "taps": taps,
+            "delay_ms": delay_ms
+        })))
+    }
+}
+
+/// Upper bound on type_text's jitter_ms, min_delay_ms and max_delay_ms.
+const MAX_TYPE_DELAY_MS: u64 = 10_000;
+
+pub(crate) struct TypeText;
+
+impl Tool for TypeText {
+    fn name(&self) -> &str {
+        "type_text"
+    }
+
+    fn description(&self) -> &str {
+        "Type a string of text character by character, choosing keys for the active keyboard layout (detected, or given with layout). The result counts the characters actually typed (chars, utf16_units, bytes), lists any skipped because no key produces them and reports elapsed_ms. For apps that flag perfectly even typing, vary the delays with jitter_ms or min_delay_ms/max_delay_ms, or pass humanize for that plus pauses after punctuation; the result then reports the seed used."
+    }
+
+    fn schema(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "text": {
+                    "type": "string",
+                    "description": "Text to type"
+                },
+                "delay_ms": {
+                    "type": "integer",
+                    "description": "Delay between keystrokes in milliseconds (default: 20)",
+                    "default": 20
+                },
+                "jitter_ms": {
+                    "type": "integer",
+                    "description": "Vary each delay randomly by up to this much either way (default: 0, or half of delay_ms with humanize)",
+                    "minimum": 0,
+                    "maximum": MAX_TYPE_DELAY_MS
+                },
+                "min_delay_ms": {
+                    "type": "integer",
+                    "description": "Shortest delay, overriding delay_ms - jitter_ms",
+                    "minimum": 0,
+                    "maximum": MAX_TYPE_DELAY_MS
+                },
+                "max_delay_ms": {
+                    "type": "integer",
+                    "description": "Longest delay, overriding delay_ms + jitter_ms",
+                    "minimum": 0,
+                    "maximum": MAX_TYPE_DELAY_MS
+                },
+                "humanize": {
+                    "type": "boolean",
+                    "description": "Vary the delays and pause longer after sentence ends and some commas, colons and semicolons (default: false)",
+                    "default": false
+                },
+                "seed": {
+                    "type": "integer",
+                    "description": "Seed for the random delays, to reproduce an earlier rhythm exactly"
+                },
+                "layout": {
+                    "type": "string",
+                    "enum": LAYOUTS.iter().map(|l| l.name).collect::<Vec<_>>(),
+                    "description": "Keyboard layout to type for, overriding detection (see get_keyboard_layout)"
+                },
+                "require_focused_window": {
+                    "type": "string",
+                    "description": "Only send input if the focused window's title or app name contains this (case-insensitive); otherwise fail with permission_denied and send nothing"
+                },
+                "strict": {
+                    "type": "boolean",
+                    "description": "Type nothing if any character has no key, and fail with the index of the first one, e.g. to paste through the clipboard instead (default: false, which skips them)",
+                    "default": false
+                }
+            },
+            "required": ["text"]
+        })
+    }
+
+    fn requirements(&self) -> ToolRequirements {
+        ToolRequirements {
+            permissions: &[Permission::Accessibility],
+            ..Default::default()
+        }
+    }
+
+    fn sends_input(&self) -> bool {
+        true
+    }
+
+    /// The usual allowance plus the time spent waiting between characters.
+    fn timeout(&self, args: &Value) -> Option<Duration> {
+        let chars = args.get("text").and_then(|v| v.as_str())?.chars().count() as u64;
+        let delay_ms = args.get("delay_ms").and_then(|v| v.as_u64()).unwrap_or(20);
+        Some(Duration::from_millis(DEFAULT_CALL_TIMEOUT_MS.saturating_add(chars.saturating_mul(delay_ms))))
+    }
+
+    fn call(&self, args: &Value) -> Result<ToolOutput, ToolError> {
+        let text = args
+            .get("text")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| ToolError::missing("text"))?;
+        let delay_ms = args
+            .get("delay_ms")
+            .and_then(|v| v.as_u64())
+            .unwrap_or(20);
+        let ms = |name: &str| args.get(name).and_then(|v| v.as_u64());
+        let rhythm = TypingRhythm::new(
+            delay_ms,
+            ms("jitter_ms"),
+            ms("min_delay_ms"),
+            ms("max_delay_ms"),
+            args.get("humanize").and_then(|v| v.as_bool()).unwrap_or(false),
+            ms("seed"),
+        )
+        .map_err(|e| ToolError::invalid_field("min_delay_ms", e))?;
+
+        let active = active_layout(args.get("layout").and_then(|v| v.as_str()))
+            .map_err(|e| ToolError::invalid_field("layout", e))?;
+
+        let strict = args.get("strict").and_then(|v| v.as_bool()).unwrap_or(false);
+        if let Some((index, c)) = first_untypable(text, active.layout).filter(|_| strict) {
+            let error = ToolError::invalid_field(
+                "text",
+                format!("No key types {:?} (U+{:04X}) at index {}; nothing was typed", c, c as u32, index),
+            );
+            return Ok(ToolOutput::failure(&error).with(ContentItem::Json(json!({
+                "index": index,
+                "char": c.to_string(),
+                "codepoint": format!("U+{:04X}", c as u32),
+                "chars": 0,
+                "layout": active.layout.name
+            }))));
+        }
+
+        check_focused_window(args)?;
+        let typed = do_type_text(text, &rhythm, active.layout)?;
+        let mut result = typed.to_json(text);
+        result["success"] = json!(true);
+        result["layout"] = json!(active.layout.name);
+        result["layout_source"] = json!(active.source);
+        if rhythm.is_random() {
+            result["seed"] = json!(rhythm.seed);
+        }
+        Ok(ToolOutput::json(&result))
+    }
+}
+
+pub(crate) struct PasteText;
+
+impl Tool for PasteText {
+    fn name(&self) -> &str {
+        "paste_text"
+    }
+
+    fn description(&self) -> &str {
+        "Paste text into the focused app through the clipboard: puts the text on the clipboard, sends Cmd+V, waits settle_ms for the app to read it, then restores the clipboard's previous text unless restore_clipboard is false. Much faster than type_text and exact for any Unicode text, regardless of keyboard layout. The result reports whether the clipboard was restored; a failure to restore doesn't fail the paste."
+    }
+
+    fn schema(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "text": {
+                    "type": "string",
+                    "description": "Text to paste"
+                },
+                "settle_ms": {
+                    "type": "integer",
+                    "description": format!("How long to wait after pasting before restoring the clipboard, so the app has read it (default: 200, max: {})", MAX_PASTE_SETTLE_MS),
+                    "default": 200,
+                    "minimum": 0,
+                    "maximum": MAX_PASTE_SETTLE_MS
+                },
+                "require_focused_window": {
+                    "type": "string",
+                    "description": "Only send input if the focused window's title or app name contains this (case-insensitive); otherwise fail with permission_denied and send nothing"
+                },
+                "restore_clipboard": {
+                    "type": "boolean",
+                    "description": "Put the clipboard's previous text back afterwards (default: true)",
+                    "default": true
+                }
+            },
+            "required": ["text"]
+        })
+    }
+
+    fn requirements(&self) -> ToolRequirements {
+        ToolRequirements {
+            macos_only: true,
+            permissions: &[Permission::Accessibility],
+            ..Default::default()
+        }
+    }
+
+    fn sends_input(&self) -> bool {
+        true
+    }
+
+    fn call(&self, args: &Value) -> Result<ToolOutput, ToolError> {
+        let text = args
+            .get("text")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| ToolError::missing("text"))?;
+        let settle = Duration::from_millis(
+            args.get("settle_ms")
+                .and_then(|v| v.as_u64())
+                .unwrap_or(200)
+                .min(MAX_PASTE_SETTLE_MS),
+        );
+        let restore = args.get("restore_clipboard").and_then(|v| v.as_bool()).unwrap_or(true);
+
+        check_focused_window(args)?;
+        let restore_error = do_paste_text(text, settle, restore)?;
+        let mut result = json!({
+            "success": true,
+            "chars": text.chars().count(),
+            "clipboard_restored": restore && restore_error.is_none() && !dry_run()
+        });
+        if let Some(e) = restore_error {
+            log::warn!("paste_text couldn't restore the clipboard: {}", e);
+            result["restore_error"] = json!(e);
+        }
+        Ok(ToolOutput::json(&result))
+    }
+}
+
+pub(crate) struct GetKeyboardLayout;
+
+impl Tool for GetKeyboardLayout {
+    fn name(&self) -> &str {
+        "get_keyboard_layout"
+    }
+
+    fn description(&self) -> &str {
+        "Get the keyboard layout type_text will type for: the layout, its source (config when set by the server's config file, detected from the OS, or fallback to us when detection failed or found an unsupported layout), the OS's own name for it and the supported layouts. Unlike probe_layout this types nothing."
+    }
+
+    fn schema(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {}
+        })
+    }
+
+    fn read_only(&self) -> bool {
+        true
+    }
+
+    fn call(&self, _args: &Value) -> Result<ToolOutput, ToolError> {
+        let configured = loaded_config().config.input.keyboard_layout.as_deref();
+        let mut active = active_layout(configured).map_err(ToolError::Platform)?;
+        if configured.is_some() {
+            active.source = "config";
+        }
+        Ok(ToolOutput::json(&active.to_json()))
+    }
+}
+
+pub(crate) struct ProbeLayout;
+
+impl Tool for ProbeLayout {
+    fn name(&self) -> &str {
+        "probe_layout"
+    }
+
+    fn description(&self) -> &str {
+        "Check the effective keyboard layout before relying on type_text. Types a short probe of layout-sensitive characters into the focused text field, reads it back via select + copy, deletes it and restores the clipboard. Reports the inferred layout (us, de, fr, azerty, qwertz or unknown) and which characters came out differently. Focus an empty scratch field first and pass confirm: true."
+    }
+
+    fn schema(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "confirm": {
+                    "type": "boolean",
+                    "description": "Must be true: acknowledges that the probe types into the focused field"
+                }
+            },
+            "required": ["confirm"]
+        })
+    }
+
+    fn requirements(&self) -> ToolRequirements {
+        ToolRequirements {
+            macos_only: true,
+            permissions: &[Permission::Accessibility],
+            ..Default::default()
+        }
+    }
+
+    fn sends_input(&self) -> bool {
+        true
+    }
+
+    fn call(&self, args: &Value) -> Result<ToolOutput, ToolError> {
+        if dry_run() {
+            return Err(ToolError::Unavailable(
+                "probe_layout can't run in a dry run: it reads back what it types through the clipboard".to_string(),
+            ));
+        }
+        if !args.get("confirm").and_then(|v| v.as_bool()).unwrap_or(false) {
+            return Err(ToolError::invalid_field(
+                "confirm",
+                "probe_layout types into the focused field; focus an empty scratch field and pass confirm: true",
+            ));
+        }
+        Ok(ToolOutput::json(&probe_layout()?))
+    }
+}
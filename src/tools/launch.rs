@@ -0,0 +1,293 @@
+//! Tools that start applications, open URLs and files, and report what's
+//! running.
+
+use serde_json::{json, Value};
+use std::path::Path;
+
+use crate::launch::{launch_app, open_with_default_app};
+use crate::process::{list_processes, process_name_matches};
+use crate::tools::limits::dry_run;
+use crate::tools::output::{ToolError, ToolOutput};
+use crate::tools::Tool;
+use crate::window::list_windows;
+
+pub(crate) struct LaunchApp;
+
+impl Tool for LaunchApp {
+    fn name(&self) -> &str {
+        "launch_app"
+    }
+
+    fn description(&self) -> &str {
+        "Start an application by name (e.g. \"Firefox\") or executable path, with optional arguments passed as-is, without a shell. On macOS a name is opened with open -a, which brings an instance already running to the front instead (reused: true). Returns the app's pid where it can be found."
+    }
+
+    fn schema(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "app": {
+                    "type": "string",
+                    "description": "Application name, or the path to an executable. On macOS a name is an app bundle name such as \"Safari\"; elsewhere it's a program on PATH such as \"firefox\"."
+                },
+                "args": {
+                    "type": "array",
+                    "items": { "type": "string" },
+                    "description": "Arguments for the application, each passed as a single argument"
+                }
+            },
+            "required": ["app"]
+        })
+    }
+
+    fn destructive(&self) -> bool {
+        true
+    }
+
+    fn call(&self, args: &Value) -> Result<ToolOutput, ToolError> {
+        let app = args
+            .get("app")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| ToolError::missing("app"))?;
+        let app_args = match args.get("args") {
+            None => Vec::new(),
+            Some(value) => value
+                .as_array()
+                .and_then(|items| items.iter().map(|v| v.as_str().map(String::from)).collect::<Option<Vec<_>>>())
+                .ok_or_else(|| ToolError::invalid_field("args", "args must be an array of strings"))?,
+        };
+
+        if dry_run() {
+            return Ok(ToolOutput::json(&json!({
+                "success": true,
+                "app": app,
+                "args": app_args,
+                "simulated": true
+            })));
+        }
+
+        let launched = launch_app(app, &app_args).map_err(|e| ToolError::invalid_field("app", e))?;
+        log::info!("Launched {} (pid {:?}, reused: {})", app, launched.pid, launched.reused);
+        Ok(ToolOutput::json(&json!({
+            "success": true,
+            "app": app,
+            "pid": launched.pid,
+            "reused": launched.reused
+        })))
+    }
+}
+
+/// Whether `url` starts with a URI scheme, e.g. "https:" or "mailto:".
+fn has_scheme(url: &str) -> bool {
+    let Some((scheme, _)) = url.split_once(':') else {
+        return false;
+    };
+    let mut chars = scheme.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic())
+        && chars.all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+}
+
+pub(crate) struct OpenUrl;
+
+impl Tool for OpenUrl {
+    fn name(&self) -> &str {
+        "open_url"
+    }
+
+    fn description(&self) -> &str {
+        "Open a URL (https:, mailto: and so on) with the system's default handler, usually the default browser. For local files use open_path."
+    }
+
+    fn schema(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "url": {
+                    "type": "string",
+                    "description": "The URL to open, including its scheme, e.g. \"https://example.com\""
+                }
+            },
+            "required": ["url"]
+        })
+    }
+
+    fn destructive(&self) -> bool {
+        true
+    }
+
+    fn call(&self, args: &Value) -> Result<ToolOutput, ToolError> {
+        let url = args
+            .get("url")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| ToolError::missing("url"))?;
+        if !has_scheme(url) {
+            return Err(ToolError::invalid_field("url", format!("Not a URL with a scheme: {}", url)));
+        }
+        if url.get(..5).is_some_and(|scheme| scheme.eq_ignore_ascii_case("file:")) {
+            return Err(ToolError::invalid_field("url", "Open local files with open_path"));
+        }
+
+        if dry_run() {
+            return Ok(ToolOutput::json(&json!({ "success": true, "url": url, "simulated": true })));
+        }
+        open_with_default_app(url)?;
+        log::info!("Opened {}", url);
+        Ok(ToolOutput::json(&json!({ "success": true, "url": url })))
+    }
+}
+
+pub(crate) struct OpenPath;
+
+impl Tool for OpenPath {
+    fn name(&self) -> &str {
+        "open_path"
+    }
+
+    fn description(&self) -> &str {
+        "Open a file or folder with its default application, as double-clicking it would. The path must exist; the result reports it made absolute."
+    }
+
+    fn schema(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "path": {
+                    "type": "string",
+                    "description": "The file or folder to open"
+                }
+            },
+            "required": ["path"]
+        })
+    }
+
+    fn destructive(&self) -> bool {
+        true
+    }
+
+    fn call(&self, args: &Value) -> Result<ToolOutput, ToolError> {
+        let path = args
+            .get("path")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| ToolError::missing("path"))?;
+        // Absolute, so it can't be mistaken for an option
+        let path = Path::new(path)
+            .canonicalize()
+            .map_err(|e| ToolError::invalid_field("path", format!("Can't open {}: {}", path, e)))?;
+        let path = path.to_string_lossy();
+
+        if dry_run() {
+            return Ok(ToolOutput::json(&json!({ "success": true, "path": path, "simulated": true })));
+        }
+        open_with_default_app(&path)?;
+        log::info!("Opened {}", path);
+        Ok(ToolOutput::json(&json!({ "success": true, "path": path })))
+    }
+}
+
+pub(crate) struct ListProcesses;
+
+impl Tool for ListProcesses {
+    fn name(&self) -> &str {
+        "list_processes"
+    }
+
+    fn description(&self) -> &str {
+        "List running processes sorted by name, each with pid, name, exe_path (where known) and, on macOS, has_windows and the window_ids it owns, ready for focus_window. Pass name to keep the list short; the unfiltered list is paged by the usual result limits. To check whether an app is running before launching or focusing it, process_exists is quicker."
+    }
+
+    fn schema(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "name": {
+                    "type": "string",
+                    "description": "Only processes whose name contains this (case-insensitive)"
+                }
+            }
+        })
+    }
+
+    fn read_only(&self) -> bool {
+        true
+    }
+
+    fn call(&self, args: &Value) -> Result<ToolOutput, ToolError> {
+        let filter = args.get("name").and_then(|v| v.as_str()).map(str::to_lowercase);
+        let processes: Vec<_> = list_processes()?
+            .into_iter()
+            .filter(|p| filter.as_ref().is_none_or(|f| p.name.to_lowercase().contains(f)))
+            .collect();
+
+        // Window ownership is only known where windows can be listed
+        let windows = list_windows().ok();
+        let processes: Vec<Value> = processes
+            .iter()
+            .map(|process| {
+                let mut json = process.to_json();
+                if let Some(windows) = &windows {
+                    let ids: Vec<u64> = windows
+                        .iter()
+                        .filter(|w| w.pid == process.pid as i64)
+                        .map(|w| w.id)
+                        .collect();
+                    json["has_windows"] = json!(!ids.is_empty());
+                    json["window_ids"] = json!(ids);
+                } else {
+                    json["has_windows"] = Value::Null;
+                    json["window_ids"] = Value::Null;
+                }
+                json
+            })
+            .collect();
+        Ok(ToolOutput::json(&json!({
+            "count": processes.len(),
+            "processes": processes
+        })))
+    }
+}
+
+pub(crate) struct ProcessExists;
+
+impl Tool for ProcessExists {
+    fn name(&self) -> &str {
+        "process_exists"
+    }
+
+    fn description(&self) -> &str {
+        "Check whether a process with exactly this name is running (case-insensitive, .exe optional), e.g. before choosing between focus_window and launch_app. Returns exists and the matching pids."
+    }
+
+    fn schema(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "name": {
+                    "type": "string",
+                    "description": "The process name, e.g. \"Safari\" or \"firefox\""
+                }
+            },
+            "required": ["name"]
+        })
+    }
+
+    fn read_only(&self) -> bool {
+        true
+    }
+
+    fn call(&self, args: &Value) -> Result<ToolOutput, ToolError> {
+        let name = args
+            .get("name")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| ToolError::missing("name"))?;
+        let pids: Vec<u32> = list_processes()?
+            .iter()
+            .filter(|p| process_name_matches(p, name))
+            .map(|p| p.pid)
+            .collect();
+        Ok(ToolOutput::json(&json!({
+            "name": name,
+            "exists": !pids.is_empty(),
+            "pids": pids
+        })))
+    }
+}
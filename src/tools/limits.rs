@@ -0,0 +1,111 @@
+//! Restricted modes: a dry run, where input tools go through the motions
+//! without touching the machine, and limits on how much input a call may
+//! send, from the config file's `[limits]` section.
+
+use serde_json::{json, Value};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::backend::{set_input_backend, DryRunInput};
+use crate::config::loaded_config;
+use crate::tools::output::{ContentItem, ToolError, ToolOutput};
+use crate::tools::Tool;
+
+static DRY_RUN: AtomicBool = AtomicBool::new(false);
+
+/// Log input events instead of sending them. Input tools still check their
+/// arguments, take their time and report success, marked `simulated`;
+/// capture works as usual.
+pub fn set_dry_run() {
+    set_input_backend(DryRunInput);
+    DRY_RUN.store(true, Ordering::SeqCst);
+}
+
+pub(crate) fn dry_run() -> bool {
+    DRY_RUN.load(Ordering::SeqCst)
+}
+
+/// key_hold's cap when `max_key_hold_ms` isn't configured.
+const DEFAULT_MAX_KEY_HOLD_MS: u64 = 30_000;
+
+/// The longest key_hold allowed. It's advertised as the schema's maximum,
+/// so longer holds are rejected as invalid arguments.
+pub(crate) fn max_key_hold_ms() -> u64 {
+    loaded_config().config.limits.max_key_hold_ms.unwrap_or(DEFAULT_MAX_KEY_HOLD_MS)
+}
+
+/// Refuse a call that's over the configured type_text length (which
+/// paste_text and set_text_field share) or
+/// run_sequence step count. The events-per-second limit is applied as
+/// events are sent.
+pub(crate) fn check_limits(tool: &dyn Tool, args: &Value) -> Result<(), ToolError> {
+    let limits = &loaded_config().config.limits;
+    let over = |what: &str, count: usize, max: Option<usize>| match max {
+        Some(max) if count > max => Err(ToolError::Throttled(format!(
+            "{} has {} (max {} by the server's limits)",
+            what, count, max
+        ))),
+        _ => Ok(()),
+    };
+    match tool.name() {
+        "type_text" | "paste_text" | "set_text_field" => {
+            let text = args.get("text").and_then(|v| v.as_str()).unwrap_or_default();
+            over("text", text.chars().count(), limits.max_type_text_length)
+        }
+        "run_sequence" => {
+            let steps = args.get("steps").and_then(|v| v.as_array()).map_or(0, |s| s.len());
+            over("steps", steps, limits.max_sequence_steps)
+        }
+        _ => Ok(()),
+    }
+}
+
+/// The error for a call that had an event refused by
+/// `max_events_per_second`.
+pub(crate) fn rate_limited() -> ToolError {
+    let max = loaded_config().config.limits.max_events_per_second.unwrap_or_default();
+    ToolError::Throttled(format!("Over the server's limit of {} input events per second", max))
+}
+
+/// Mark an input tool's result as simulated, in its first JSON item or
+/// else in one of its own.
+pub(crate) fn mark_simulated(output: &mut ToolOutput) {
+    if let Some(structured) = output.structured_content.as_mut().and_then(|v| v.as_object_mut()) {
+        structured.insert("simulated".to_string(), json!(true));
+    }
+    let item = output.content.iter_mut().find_map(|item| match item {
+        ContentItem::Json(Value::Object(map)) => Some(map),
+        _ => None,
+    });
+    match item {
+        Some(map) => {
+            map.insert("simulated".to_string(), json!(true));
+        }
+        None => output.content.push(ContentItem::Json(json!({ "simulated": true }))),
+    }
+}
+
+/// The restricted modes in effect and a note on them for the model, for
+/// the initialize result, or None if there are none.
+pub(crate) fn restrictions() -> Option<(Value, String)> {
+    let limits = &loaded_config().config.limits;
+    let limited = limits.max_events_per_second.is_some()
+        || limits.max_type_text_length.is_some()
+        || limits.max_sequence_steps.is_some();
+    let mut notes = Vec::new();
+    if dry_run() {
+        notes.push("Dry run: input tools send nothing and report success marked \"simulated\"; capture is real.");
+    }
+    if limited {
+        notes.push("Input is limited; calls over a limit fail with error_code \"throttled\".");
+    }
+    if notes.is_empty() {
+        return None;
+    }
+    let restrictions = json!({
+        "dry_run": dry_run(),
+        "max_events_per_second": limits.max_events_per_second,
+        "max_type_text_length": limits.max_type_text_length,
+        "max_sequence_steps": limits.max_sequence_steps
+    });
+    Some((restrictions, notes.join(" ")))
+}
@@ -0,0 +1,554 @@
+//! The tools the server offers, and the pipeline every call goes through:
+//! availability, argument validation, the self-target guard, physical
+//! approval and cursor restoration.
+
+pub(crate) mod approval;
+pub(crate) mod availability;
+mod baseline;
+pub(crate) mod cursor;
+mod external;
+pub(crate) mod guard;
+mod keyboard;
+mod launch;
+pub(crate) mod limits;
+mod mouse;
+mod notify;
+pub(crate) mod output;
+pub(crate) mod screen;
+mod sequence;
+mod system;
+mod text;
+mod timeout;
+mod validation;
+mod window;
+
+use rdev::Button;
+use serde_json::{json, Value};
+use std::time::{Duration, Instant};
+
+use crate::audit::record_call;
+use crate::backend::{displays, retry_with_fresh_displays};
+use crate::cancel::is_cancelled;
+use crate::clock::{Timestamp, FIRST_EVENT};
+use crate::config::apply_config;
+use crate::input::failsafe::failsafe_triggered;
+use crate::input::mouse_button;
+use crate::input::physical::{release_held_input, THROTTLED};
+use crate::protocol::ProtocolVersion;
+use crate::retry::RETRIES;
+use crate::tools::approval::{approval_config, await_approval, ApprovalOutcome, ELEVATED_UNTIL};
+use crate::tools::availability::{Availability, ToolRequirements};
+use crate::tools::baseline::{BaselineCompare, BaselineDelete, BaselineList, BaselineSave};
+use crate::tools::external::external_tools;
+use crate::tools::guard::{check_self_target, check_session_unlocked};
+use crate::tools::keyboard::{GetKeyboardLayout, KeyHold, KeySequence, KeyTap, PasteText, ProbeLayout, TypeText};
+use crate::tools::launch::{LaunchApp, ListProcesses, OpenPath, OpenUrl, ProcessExists};
+use crate::tools::limits::{check_limits, dry_run, mark_simulated, rate_limited};
+use crate::tools::mouse::{
+    DragFile, GetMousePosition, MouseClick, MouseDrag, MouseDragPath, MouseLongPress, MouseMove,
+    MouseScroll, WaitForUserClick,
+};
+use crate::tools::notify::Notify;
+use crate::tools::output::{ContentItem, ToolError, ToolOutput};
+use crate::tools::screen::{
+    AssertScreen, CaptureAroundCursor, ClickImage, CompareScreens, EstimateCapture, FindImage,
+    GetScreenInfo, ImageToScreenCoords, ReadText, ScreenCapture, ScreenRecord, WaitForElement,
+    WaitForImage, WaitForRegionChange,
+};
+use crate::tools::sequence::RunSequence;
+use crate::tools::system::{
+    CheckPermissions, FailsafeReset, GetAuditTail, GetConfig, GetSystemInfo, ProtocolStats, SelfTest, ServerStatus,
+    Sleep, WaitForUnlock,
+};
+use crate::tools::text::{SelectText, SetTextField};
+use crate::tools::timeout::{execute_with_timeout, DEFAULT_CALL_TIMEOUT_MS, MAX_CALL_TIMEOUT_MS};
+use crate::tools::validation::{debug_check_output, validate_arguments};
+use crate::tools::window::{FocusWindow, GetActiveWindow, WindowManage};
+
+/// A tool the server offers. Built-in tools are unit structs listed in
+/// BUILTIN_TOOLS; external tools are loaded from configuration.
+pub(crate) trait Tool: Sync {
+    fn name(&self) -> &str;
+
+    /// A friendly name for clients to show: the name as words, e.g.
+    /// "Screen capture".
+    fn title(&self) -> String {
+        title_from_name(self.name())
+    }
+
+    fn description(&self) -> &str;
+
+    /// The tool's own arguments, as a JSON Schema object. The arguments every
+    /// built-in tool accepts are added by definition().
+    fn schema(&self) -> Value;
+
+    /// The shape of a successful result's JSON, as a JSON Schema object,
+    /// for tools whose results are returned as structured content too.
+    fn output_schema(&self) -> Option<Value> {
+        None
+    }
+
+    fn call(&self, args: &Value) -> Result<ToolOutput, ToolError>;
+
+    fn requirements(&self) -> ToolRequirements {
+        ToolRequirements::default()
+    }
+
+    /// How long a call with these arguments may run, for tools that can
+    /// legitimately take longer than the default; see tools/timeout.rs.
+    fn timeout(&self, _args: &Value) -> Option<Duration> {
+        None
+    }
+
+    /// Only looks at the screen, so the cursor is put back by default if the
+    /// call moved it.
+    fn read_only(&self) -> bool {
+        false
+    }
+
+    /// Needs the input to itself for the whole call, so it takes turns with
+    /// other such calls through INPUT_LOCK. Read-only tools run alongside
+    /// them.
+    fn holds_input(&self) -> bool {
+        !self.read_only()
+    }
+
+    /// Sends input, so it's subject to the self-target guard.
+    fn sends_input(&self) -> bool {
+        false
+    }
+
+    /// Takes coordinates that can be given relative to a screen.
+    fn screen_relative(&self) -> bool {
+        false
+    }
+
+    /// Can change what's on screen in ways that can't be undone, which
+    /// clients may ask the user to confirm. Anything that sends input can.
+    fn destructive(&self) -> bool {
+        self.sends_input()
+    }
+
+    /// Calling it again with the same arguments has no further effect.
+    fn idempotent(&self) -> bool {
+        self.read_only()
+    }
+
+    /// Hints for clients deciding whether to ask the user before a call.
+    /// Tools that can act on the desktop reach whatever application is in
+    /// front, so they're also marked open-world.
+    fn annotations(&self) -> Value {
+        let mut annotations = json!({
+            "title": self.title(),
+            "readOnlyHint": self.read_only(),
+            "openWorldHint": self.destructive()
+        });
+        // The other hints only mean something for tools that make changes
+        if !self.read_only() {
+            annotations["destructiveHint"] = json!(self.destructive());
+            annotations["idempotentHint"] = json!(self.idempotent());
+        }
+        annotations
+    }
+
+    /// The definition advertised by tools/list. Every built-in tool can put
+    /// the cursor back afterwards, and input tools can opt out of the
+    /// self-target guard.
+    fn definition(&self) -> Value {
+        let read_only = self.read_only();
+        let mut schema = self.schema();
+        let description = if read_only {
+            "Move the cursor back to where it was before the call, if the call moved it (default: true)"
+        } else {
+            "Move the cursor back to where it was before the call (default: false)"
+        };
+        schema["properties"]["restore_cursor"] = json!({
+            "type": "boolean",
+            "description": description,
+            "default": read_only
+        });
+        if self.screen_relative() {
+            schema["properties"]["screen_index"] = json!({
+                "type": "integer",
+                "description": "Interpret coordinates relative to this screen (as in screen_capture and get_screen_info) instead of globally. Coordinates outside the screen are rejected."
+            });
+            schema["properties"]["coordinate_space"] = json!({
+                "type": "string",
+                "enum": ["logical", "physical"],
+                "description": "Units of the coordinates (default: logical). \"physical\": screenshot pixels at full resolution, divided by the screen's scale_factor (see get_screen_info). For a downscaled screenshot, convert with image_to_screen_coords instead.",
+                "default": "logical"
+            });
+        }
+        if schema["properties"].get("screen_index").is_some() {
+            schema["properties"]["screen_id"] = json!({
+                "type": "integer",
+                "description": "The screen's id from get_screen_info, instead of screen_index. An id stays with its display when others are plugged in, unplugged or go to sleep, which can shift indexes.",
+                "minimum": 0
+            });
+        }
+        if self.sends_input() {
+            schema["properties"]["allow_self_target"] = json!({
+                "type": "boolean",
+                "description": "Send input even if the MCP client's own window is frontmost (default: false)",
+                "default": false
+            });
+        }
+        // A tool's own timeout_ms, such as how long to wait for something,
+        // takes precedence; its call is then bounded by Tool::timeout
+        if schema["properties"].get("timeout_ms").is_none() {
+            schema["properties"]["timeout_ms"] = json!({
+                "type": "integer",
+                "description": format!(
+                    "Give up on the call after this many milliseconds, failing with timed_out (default: {}, or longer for tools that need it; max: {})",
+                    DEFAULT_CALL_TIMEOUT_MS, MAX_CALL_TIMEOUT_MS
+                ),
+                "minimum": 1,
+                "maximum": MAX_CALL_TIMEOUT_MS
+            });
+        }
+        schema["properties"]["limit"] = json!({
+            "type": "integer",
+            "description": "Cap on items in the result's main array, its longest top-level array (default: 500)",
+            "minimum": 0
+        });
+        schema["properties"]["offset"] = json!({
+            "type": "integer",
+            "description": "Skip this many items of the result's main array, to page through a truncated result; its truncated marker names the array and gives the next offset",
+            "minimum": 0
+        });
+        schema["additionalProperties"] = json!(false);
+        let mut definition = json!({
+            "name": self.name(),
+            "title": self.title(),
+            "description": self.description(),
+            "inputSchema": schema,
+            "annotations": self.annotations()
+        });
+        if let Some(output_schema) = self.output_schema() {
+            definition["outputSchema"] = output_schema;
+        }
+        definition
+    }
+}
+
+/// The built-in tools, in the order tools/list advertises them.
+static BUILTIN_TOOLS: &[&dyn Tool] = &[
+    &ScreenCapture,
+    &CaptureAroundCursor,
+    &EstimateCapture,
+    &ScreenRecord,
+    &FindImage,
+    &WaitForImage,
+    &ClickImage,
+    &WaitForRegionChange,
+    &CompareScreens,
+    &BaselineSave,
+    &BaselineCompare,
+    &BaselineList,
+    &BaselineDelete,
+    &WaitForElement,
+    &ReadText,
+    &AssertScreen,
+    &GetActiveWindow,
+    &FocusWindow,
+    &WindowManage,
+    &LaunchApp,
+    &OpenUrl,
+    &OpenPath,
+    &ListProcesses,
+    &ProcessExists,
+    &Notify,
+    &MouseMove,
+    &MouseClick,
+    &MouseDragPath,
+    &MouseLongPress,
+    &MouseDrag,
+    &DragFile,
+    &KeyTap,
+    &KeyHold,
+    &KeySequence,
+    &TypeText,
+    &PasteText,
+    &SelectText,
+    &SetTextField,
+    &ProbeLayout,
+    &GetKeyboardLayout,
+    &RunSequence,
+    &GetScreenInfo,
+    &ImageToScreenCoords,
+    &ServerStatus,
+    &ProtocolStats,
+    &GetSystemInfo,
+    &WaitForUnlock,
+    &Sleep,
+    &CheckPermissions,
+    &SelfTest,
+    &GetConfig,
+    &GetAuditTail,
+    &FailsafeReset,
+    &GetMousePosition,
+    &MouseScroll,
+    &WaitForUserClick,
+];
+
+/// Arguments the server handles itself rather than passing to the tool.
+const SERVER_ARGUMENTS: &[&str] = &["restore_cursor", "timeout_ms", "limit", "offset"];
+
+/// The call's arguments the server handles itself, leaving out any the
+/// tool's own schema declares: those are the tool's, as an external tool's
+/// `limit` may be.
+pub(crate) fn server_arguments(tool: &dyn Tool, args: &Value) -> Value {
+    let own = tool.schema();
+    let arguments: serde_json::Map<String, Value> = SERVER_ARGUMENTS
+        .iter()
+        .filter(|name| own["properties"].get(**name).is_none())
+        .filter_map(|name| Some((name.to_string(), args.get(*name)?.clone())))
+        .collect();
+    Value::Object(arguments)
+}
+
+/// Built-in tools followed by external tools.
+fn all_tools() -> impl Iterator<Item = &'static dyn Tool> {
+    BUILTIN_TOOLS
+        .iter()
+        .copied()
+        .chain(external_tools().iter().map(|tool| tool as &dyn Tool))
+}
+
+pub(crate) fn find_tool(name: &str) -> Option<&'static dyn Tool> {
+    all_tools().find(|tool| tool.name() == name)
+}
+
+/// All advertised tools: the available built-ins followed by external
+/// tools, without the fields `version` predates. Tools disabled by
+/// configuration are left out entirely.
+pub(crate) fn get_tools(version: ProtocolVersion) -> Value {
+    let availability = Availability::current();
+    all_tools()
+        .filter(|tool| availability.unavailable_reason(*tool).is_none())
+        .map(|tool| {
+            let mut definition = tool.definition();
+            if let Some(fields) = definition.as_object_mut() {
+                if !version.tool_annotations() {
+                    fields.remove("annotations");
+                }
+                if !version.structured_content() {
+                    fields.remove("title");
+                    fields.remove("outputSchema");
+                }
+            }
+            definition
+        })
+        .collect()
+}
+
+/// "screen_capture" as "Screen capture".
+pub(crate) fn title_from_name(name: &str) -> String {
+    let words = name.replace('_', " ");
+    let mut chars = words.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => words,
+    }
+}
+
+/// Run a tool, first filling in defaults from the config file, checking it
+/// isn't aimed at a locked session, the client's own window or a screen the
+/// config doesn't allow, and obtaining physical approval if it's configured
+/// to need it.
+/// Blocked, denied and timed-out calls return an error result without
+/// running. A call that runs is given up on after its timeout.
+/// Unknown tools and invalid arguments are returned as errors, to be
+/// answered with a JSON-RPC error.
+pub(crate) fn run_tool(name: &str, args: &Value) -> Result<ToolOutput, ToolError> {
+    let tool = find_tool(name).ok_or_else(|| ToolError::UnknownTool(name.to_string()))?;
+    if let Some(reason) = Availability::current().unavailable_reason(tool) {
+        return Ok(ToolOutput::failure(&ToolError::Unavailable(format!(
+            "Tool {} is not available: {}",
+            name, reason
+        ))));
+    }
+    let args = &validate_arguments(tool, args)?;
+    if tool.sends_input() && failsafe_triggered() {
+        return Ok(ToolOutput::failure(&ToolError::Failsafe));
+    }
+    // A dry run sends nothing, so a locked session doesn't stop it
+    if tool.sends_input() && !dry_run() {
+        if let Err(e) = check_session_unlocked() {
+            return Ok(ToolOutput::failure(&e));
+        }
+    }
+    if let Err(e) = check_limits(tool, args) {
+        return Ok(ToolOutput::failure(&e));
+    }
+    let args = &match resolve_screen_id(args) {
+        Ok(args) => args,
+        Err(e) if e.is_protocol_error() => return Err(e),
+        Err(e) => return Ok(ToolOutput::failure(&e)),
+    };
+    let args = &match apply_config(tool, args) {
+        Ok(args) => args,
+        Err(e) => return Ok(ToolOutput::failure(&e)),
+    };
+    if let Some(blocked) = check_self_target(tool, args) {
+        return Ok(blocked);
+    }
+
+    let config = approval_config();
+    if !config.tools.iter().any(|t| t == name) {
+        return execute_with_timeout(tool, args);
+    }
+
+    let start = Instant::now();
+    let elevated = ELEVATED_UNTIL
+        .lock()
+        .unwrap()
+        .is_some_and(|until| Instant::now() < until);
+    let outcome = if elevated {
+        ApprovalOutcome::Approved
+    } else {
+        match await_approval(name, config) {
+            Ok(outcome) => outcome,
+            Err(e) => return Ok(ToolOutput::failure(&ToolError::Platform(e))),
+        }
+    };
+    let approval = json!({
+        "approval": outcome.name(),
+        "tool": name,
+        "waited_ms": start.elapsed().as_millis() as u64,
+        "elevated": elevated
+    });
+
+    if outcome != ApprovalOutcome::Approved {
+        let message = match outcome {
+            ApprovalOutcome::Denied => "Denied by the user",
+            _ => "Timed out waiting for physical approval",
+        };
+        let error = ToolError::PermissionDenied(message.to_string());
+        let mut result = approval;
+        result["error_code"] = json!(error.code());
+        result["message"] = json!(error.message());
+        return Ok(ToolOutput {
+            is_error: true,
+            timestamp: Some(Timestamp::now()),
+            ..ToolOutput::json(&result)
+        });
+    }
+
+    if !elevated && !config.window.is_zero() {
+        *ELEVATED_UNTIL.lock().unwrap() = Some(Instant::now() + config.window);
+    }
+
+    let output = execute_with_timeout(tool, args)?;
+    if output.is_error {
+        return Ok(output);
+    }
+    Ok(output.with(ContentItem::Json(approval)))
+}
+
+/// Run a tool and stamp its result with the time of its first OS event,
+/// falling back to when execution began for tools that emit none. Runtime
+/// failures become isError results; unknown tools and invalid arguments
+/// are returned as errors for the caller to answer at the JSON-RPC level.
+/// A call that fails lets go of any keys and buttons it left held, such as
+/// a key whose release the OS refused; a cancelled call always reports
+/// what it released.
+fn execute_timed(tool: &dyn Tool, args: &Value) -> Result<ToolOutput, ToolError> {
+    let started = Timestamp::now();
+    let start = Instant::now();
+    FIRST_EVENT.take();
+    THROTTLED.take();
+    RETRIES.take();
+    let mut output = match tool.call(args) {
+        Ok(output) => output,
+        Err(e) if e.is_protocol_error() => return Err(e),
+        Err(_) if is_cancelled() || THROTTLED.get() => {
+            let error = if THROTTLED.take() {
+                rate_limited()
+            } else if failsafe_triggered() {
+                ToolError::Failsafe
+            } else {
+                ToolError::Cancelled
+            };
+            ToolOutput::failure(&error).with(ContentItem::Json(json!({ "released": release_held_input() })))
+        }
+        Err(e) => {
+            let released = release_held_input();
+            let output = ToolOutput::failure(&e);
+            if released.is_empty() {
+                output
+            } else {
+                output.with(ContentItem::Json(json!({ "released": released })))
+            }
+        }
+    };
+    output.timestamp = Some(FIRST_EVENT.take().unwrap_or(started));
+    output.retries = RETRIES.take();
+    if !output.is_error && tool.output_schema().is_some() {
+        // The text is kept for clients that predate structured content
+        output.structured_content = output.content.iter().find_map(|item| match item {
+            ContentItem::Json(value) => Some(value.clone()),
+            _ => None,
+        });
+    }
+    if dry_run() && tool.sends_input() && !output.is_error {
+        log::info!("Dry run: {} {}", tool.name(), args);
+        mark_simulated(&mut output);
+    }
+    if let Some(structured) = &output.structured_content {
+        debug_check_output(tool, structured);
+    }
+    record_call(tool.name(), args, &output, started, start.elapsed());
+    Ok(output)
+}
+
+/// Turn a `screen_id` argument into the `screen_index` that display has
+/// right now, so tools only ever deal in indexes. Fails if the display is
+/// no longer connected, listing the ids that are.
+fn resolve_screen_id(args: &Value) -> Result<Value, ToolError> {
+    let mut args = args.clone();
+    let Some(id) = args.get("screen_id").filter(|v| !v.is_null()) else {
+        return Ok(args);
+    };
+    if args.get("screen_index").is_some_and(|v| !v.is_null()) {
+        return Err(ToolError::invalid_field("screen_id", "Pass screen_index or screen_id, not both"));
+    }
+    let id = id
+        .as_u64()
+        .ok_or_else(|| ToolError::invalid_field("screen_id", "screen_id must be a non-negative integer"))?;
+
+    let index = retry_with_fresh_displays(|| {
+        let screens = displays()?;
+        screens.iter().position(|s| s.id as u64 == id).ok_or_else(|| {
+            let ids: Vec<u32> = screens.iter().map(|s| s.id).collect();
+            ToolError::NotFound(format!("Screen id {} not found. Connected screens: {:?}", id, ids))
+        })
+    })?;
+    args["screen_index"] = json!(index);
+    Ok(args)
+}
+
+/// Parse an optional `button`, left by default. An unknown name is an
+/// invalid argument, never a left click.
+pub(crate) fn parse_button(args: &Value) -> Result<Button, ToolError> {
+    match args.get("button").filter(|v| !v.is_null()) {
+        None => Ok(Button::Left),
+        Some(Value::String(name)) => mouse_button(name).map_err(|e| ToolError::invalid_field("button", e)),
+        Some(other) => Err(ToolError::invalid_field(
+            "button",
+            format!("button must be a string, got {}", other),
+        )),
+    }
+}
+
+/// Parse an optional `modifiers` array of key names.
+pub(crate) fn parse_modifiers(args: &Value) -> Vec<String> {
+    args.get("modifiers")
+        .and_then(|v| v.as_array())
+        .map(|arr| {
+            arr.iter()
+                .filter_map(|v| v.as_str().map(String::from))
+                .collect()
+        })
+        .unwrap_or_default()
+}
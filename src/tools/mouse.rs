@@ -0,0 +1,979 @@
+//! Mouse tools.
+
+use rdev::EventType;
+use serde_json::{json, Value};
+use std::fs::File;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::capture::coords::{get_mouse_position, to_global, CoordinateSpace};
+use crate::input::file_drag::do_drag_file;
+use crate::input::physical::{screen_at_point, send_event, wait_for_user_click, MAX_USER_CLICK_TIMEOUT_MS};
+use crate::input::{
+    button_name, do_humanized_move, do_mouse_click, do_mouse_drag, do_mouse_drag_path, do_mouse_long_press,
+    do_mouse_move, do_smooth_move, ClickOptions, DragTiming, Easing, Humanize, MAX_DRAG_HOLD_MS,
+    MAX_DRAG_MS, MAX_DRAG_PATH_MS, MAX_DRAG_PATH_POINTS, MAX_LONG_PRESS_MS, MAX_SMOOTH_MOVE_MS,
+    MAX_SMOOTH_MOVE_STEPS, MOUSE_BUTTONS, SMOOTH_MOVE_STEP_MS,
+};
+use crate::tools::availability::{Permission, ToolRequirements};
+use crate::tools::cursor::cursor_position;
+use crate::tools::output::{ToolError, ToolOutput};
+use crate::tools::timeout::{own_timeout, TIMEOUT_GRACE};
+use crate::tools::{parse_button, parse_modifiers, Tool};
+
+pub(crate) struct MouseMove;
+
+impl Tool for MouseMove {
+    fn name(&self) -> &str {
+        "mouse_move"
+    }
+
+    fn description(&self) -> &str {
+        "Move the mouse cursor to a specific position"
+    }
+
+    fn schema(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "x": { "type": "number", "description": "X coordinate" },
+                "y": { "type": "number", "description": "Y coordinate" },
+                "humanize": {
+                    "type": "boolean",
+                    "description": "Move along a slightly curved path with eased velocity and a small overshoot-and-correct, instead of jumping (default: false). The result reports the seed used.",
+                    "default": false
+                },
+                "seed": {
+                    "type": "integer",
+                    "description": "With humanize, seed for the path, to reproduce an earlier move"
+                },
+                "duration_ms": {
+                    "type": "integer",
+                    "description": "Glide from the current position over this many milliseconds instead of jumping (max: 10000), for apps that ignore teleports. With humanize, defaults to scaling with distance, up to 1200.",
+                    "minimum": 0,
+                    "maximum": MAX_SMOOTH_MOVE_MS
+                },
+                "steps": {
+                    "type": "integer",
+                    "description": "Number of intermediate positions for a gliding move (default: one per 10ms of duration_ms, max: 1000). Ignored with humanize.",
+                    "minimum": 1,
+                    "maximum": MAX_SMOOTH_MOVE_STEPS
+                },
+                "easing": {
+                    "type": "string",
+                    "enum": ["linear", "ease_in_out"],
+                    "description": "Velocity profile of a gliding move (default: linear). Ignored with humanize.",
+                    "default": "linear"
+                }
+            },
+            "required": ["x", "y"]
+        })
+    }
+
+    fn requirements(&self) -> ToolRequirements {
+        ToolRequirements {
+            permissions: &[Permission::Accessibility],
+            ..Default::default()
+        }
+    }
+
+    fn sends_input(&self) -> bool {
+        true
+    }
+
+    fn idempotent(&self) -> bool {
+        true
+    }
+
+    fn screen_relative(&self) -> bool {
+        true
+    }
+
+    fn call(&self, args: &Value) -> Result<ToolOutput, ToolError> {
+        let x = args
+            .get("x")
+            .and_then(|v| v.as_f64())
+            .ok_or_else(|| ToolError::missing("x"))?;
+        let y = args
+            .get("y")
+            .and_then(|v| v.as_f64())
+            .ok_or_else(|| ToolError::missing("y"))?;
+        let (x, y) = to_global(&CoordinateSpace::from_args(args).map_err(ToolError::invalid)?, (x, y))?;
+
+        let duration_ms = args
+            .get("duration_ms")
+            .and_then(|v| v.as_u64())
+            .map(|v| v.min(MAX_SMOOTH_MOVE_MS));
+
+        if !args.get("humanize").and_then(|v| v.as_bool()).unwrap_or(false) {
+            let steps = args
+                .get("steps")
+                .and_then(|v| v.as_u64())
+                .map(|v| v.clamp(1, MAX_SMOOTH_MOVE_STEPS));
+            if duration_ms.is_none() && steps.is_none() {
+                do_mouse_move(x, y)?;
+                return Ok(ToolOutput::json(
+                    &json!({ "success": true, "position": { "x": x, "y": y } }),
+                ));
+            }
+
+            let duration_ms = duration_ms.unwrap_or_else(|| steps.unwrap_or(1) * SMOOTH_MOVE_STEP_MS);
+            let steps = steps.unwrap_or((duration_ms / SMOOTH_MOVE_STEP_MS).clamp(1, MAX_SMOOTH_MOVE_STEPS));
+            let easing = args
+                .get("easing")
+                .and_then(|v| v.as_str())
+                .map(Easing::parse)
+                .transpose()
+                .map_err(|e| ToolError::invalid_field("easing", e))?
+                .unwrap_or(Easing::Linear);
+            let smooth = do_smooth_move(x, y, duration_ms, steps, easing)?;
+            return Ok(ToolOutput::json(&json!({
+                "success": true,
+                "position": { "x": x, "y": y },
+                "smooth": smooth,
+                "duration_ms": duration_ms,
+                "steps": steps,
+                "easing": easing.name()
+            })));
+        }
+
+        let humanize = Humanize::new(args.get("seed").and_then(|v| v.as_u64()))?;
+        let humanized = do_humanized_move(x, y, &humanize, duration_ms)?;
+        Ok(ToolOutput::json(&json!({
+            "success": true,
+            "position": { "x": x, "y": y },
+            "humanized": humanized,
+            "seed": humanize.seed
+        })))
+    }
+}
+
+pub(crate) struct MouseClick;
+
+impl Tool for MouseClick {
+    fn name(&self) -> &str {
+        "mouse_click"
+    }
+
+    fn description(&self) -> &str {
+        "Click the mouse at the current position or a specific location"
+    }
+
+    fn schema(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "x": { "type": "number", "description": "X coordinate (optional, uses current position if not provided)" },
+                "y": { "type": "number", "description": "Y coordinate (optional, uses current position if not provided)" },
+                "button": {
+                    "type": "string",
+                    "enum": MOUSE_BUTTONS,
+                    "description": "Mouse button to click: left (default), right, middle, or the side buttons back and forward (also x1 and x2), e.g. for browser history",
+                    "default": "left"
+                },
+                "click_count": {
+                    "type": "integer",
+                    "description": "Number of clicks: 2 to double-click, 3 to triple-click (select a line or paragraph) (default: 1, max: 10)",
+                    "default": 1,
+                    "minimum": 1,
+                    "maximum": 10
+                },
+                "interval_ms": {
+                    "type": "integer",
+                    "description": "Gap between clicks (default: 50, max: 2000). Must be below the system double-click time for the clicks to register as one multi-click.",
+                    "default": 50,
+                    "maximum": 2000
+                },
+                "press_duration_ms": {
+                    "type": "integer",
+                    "description": "How long each press is held (default: 10, max: 2000)",
+                    "default": 10,
+                    "maximum": 2000
+                },
+                "double_click": {
+                    "type": "boolean",
+                    "description": "Deprecated: use click_count 2. Ignored when click_count is given.",
+                    "default": false
+                },
+                "modifiers": {
+                    "type": "array",
+                    "items": {
+                        "type": "string",
+                        "enum": ["shift", "control", "alt", "meta"]
+                    },
+                    "description": "Modifier keys to hold during the click, e.g. [\"shift\"] to extend a selection or [\"meta\"] to open a link in a new tab"
+                }
+            }
+        })
+    }
+
+    fn output_schema(&self) -> Option<Value> {
+        Some(json!({
+            "type": "object",
+            "properties": {
+                "success": { "type": "boolean" },
+                "button": { "type": "string", "enum": MOUSE_BUTTONS },
+                "click": {
+                    "type": "object",
+                    "properties": {
+                        "click_count": { "type": "integer", "minimum": 1 },
+                        "interval_ms": { "type": "integer", "minimum": 0, "maximum": 2000 },
+                        "press_duration_ms": { "type": "integer", "minimum": 0, "maximum": 2000 }
+                    },
+                    "required": ["click_count", "interval_ms", "press_duration_ms"],
+                    "additionalProperties": false
+                },
+                "modifiers": { "type": "array", "items": { "type": "string" } },
+                "simulated": {
+                    "type": "boolean",
+                    "description": "Present in dry-run mode, where no input was sent"
+                }
+            },
+            "required": ["success", "button", "click", "modifiers"],
+            "additionalProperties": false
+        }))
+    }
+
+    fn requirements(&self) -> ToolRequirements {
+        ToolRequirements {
+            permissions: &[Permission::Accessibility],
+            ..Default::default()
+        }
+    }
+
+    fn sends_input(&self) -> bool {
+        true
+    }
+
+    fn screen_relative(&self) -> bool {
+        true
+    }
+
+    /// Slow multi-clicks can take longer than the default timeout.
+    fn timeout(&self, args: &Value) -> Option<Duration> {
+        Some(ClickOptions::parse(args).duration() + TIMEOUT_GRACE)
+    }
+
+    fn call(&self, args: &Value) -> Result<ToolOutput, ToolError> {
+        let x = args.get("x").and_then(|v| v.as_f64());
+        let y = args.get("y").and_then(|v| v.as_f64());
+        let (x, y) = match (x, y) {
+            (Some(x), Some(y)) => {
+                let (x, y) = to_global(&CoordinateSpace::from_args(args).map_err(ToolError::invalid)?, (x, y))?;
+                (Some(x), Some(y))
+            }
+            _ => (x, y),
+        };
+        let button = parse_button(args)?;
+        let clicks = ClickOptions::parse(args);
+        let modifiers = parse_modifiers(args);
+
+        do_mouse_click(x, y, button, clicks, &modifiers)?;
+        Ok(ToolOutput::json(&json!({
+            "success": true,
+            "button": button_name(button),
+            "click": clicks.to_json(),
+            "modifiers": modifiers
+        })))
+    }
+}
+
+pub(crate) struct MouseDragPath;
+
+impl Tool for MouseDragPath {
+    fn name(&self) -> &str {
+        "mouse_drag_path"
+    }
+
+    fn description(&self) -> &str {
+        "Drag through a series of waypoints: press at the first point, move through each in turn, release at the last. For drawing, gestures and drag-reordering along a path. Reports the number of move events emitted."
+    }
+
+    fn schema(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "points": {
+                    "type": "array",
+                    "description": "Waypoints in global coordinates, at least 2 (max 1000)",
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "x": { "type": "number" },
+                            "y": { "type": "number" }
+                        },
+                        "required": ["x", "y"]
+                    },
+                    "minItems": 2,
+                    "maxItems": MAX_DRAG_PATH_POINTS
+                },
+                "duration_ms": {
+                    "type": "integer",
+                    "description": "Total duration, split across segments by length (default: 500 per segment, up to 60000). The whole path may take at most 60000."
+                },
+                "segment_duration_ms": {
+                    "type": "integer",
+                    "description": "Duration of every segment, instead of duration_ms"
+                },
+                "button": {
+                    "type": "string",
+                    "enum": MOUSE_BUTTONS,
+                    "description": "Mouse button to hold: left (default), right, middle, back (x1) or forward (x2)",
+                    "default": "left"
+                },
+                "modifiers": {
+                    "type": "array",
+                    "items": {
+                        "type": "string",
+                        "enum": ["shift", "control", "alt", "meta"]
+                    },
+                    "description": "Modifier keys to hold for the whole drag"
+                }
+            },
+            "required": ["points"]
+        })
+    }
+
+    fn requirements(&self) -> ToolRequirements {
+        ToolRequirements {
+            permissions: &[Permission::Accessibility],
+            ..Default::default()
+        }
+    }
+
+    fn sends_input(&self) -> bool {
+        true
+    }
+
+    fn screen_relative(&self) -> bool {
+        true
+    }
+
+    fn timeout(&self, _args: &Value) -> Option<Duration> {
+        Some(Duration::from_millis(MAX_DRAG_PATH_MS) + TIMEOUT_GRACE)
+    }
+
+    fn call(&self, args: &Value) -> Result<ToolOutput, ToolError> {
+        let space = CoordinateSpace::from_args(args).map_err(ToolError::invalid)?;
+        let points = args
+            .get("points")
+            .and_then(|v| v.as_array())
+            .ok_or_else(|| ToolError::missing("points"))?
+            .iter()
+            .enumerate()
+            .map(|(i, point)| {
+                let coord = |name: &str| {
+                    point
+                        .get(name)
+                        .and_then(|v| v.as_f64())
+                        .ok_or_else(|| {
+                            ToolError::invalid_field("points", format!("points[{}] needs numeric x and y", i))
+                        })
+                };
+                to_global(&space, (coord("x")?, coord("y")?)).map_err(|e| {
+                    ToolError::OutOfBounds(format!("points[{}]: {}", i, e.message()))
+                })
+            })
+            .collect::<Result<Vec<(f64, f64)>, ToolError>>()?;
+        if points.len() < 2 {
+            return Err(ToolError::invalid_field(
+                "points",
+                format!("points needs at least 2 points (got {})", points.len()),
+            ));
+        }
+        if points.len() > MAX_DRAG_PATH_POINTS {
+            return Err(ToolError::invalid_field(
+                "points",
+                format!("points has {} points (max {})", points.len(), MAX_DRAG_PATH_POINTS),
+            ));
+        }
+        let button = parse_button(args)?;
+        let modifiers = parse_modifiers(args);
+
+        // Split an overall duration across segments by length, or use a
+        // fixed per-segment duration
+        let lengths: Vec<f64> = points
+            .windows(2)
+            .map(|s| (s[1].0 - s[0].0).hypot(s[1].1 - s[0].1))
+            .collect();
+        let segment_ms: Vec<u64> = match args.get("segment_duration_ms").and_then(|v| v.as_u64()) {
+            Some(ms) => vec![ms; lengths.len()],
+            None => {
+                // The default is capped so that a long path accepted by
+                // MAX_DRAG_PATH_POINTS never fails on its own duration
+                let total = args
+                    .get("duration_ms")
+                    .and_then(|v| v.as_u64())
+                    .unwrap_or((500 * lengths.len() as u64).min(MAX_DRAG_PATH_MS)) as f64;
+                let path_length: f64 = lengths.iter().sum();
+                // Each segment ends at its share of the total, rounded, so
+                // the segments add up to exactly the total
+                let mut travelled = 0.0;
+                let mut elapsed = 0;
+                lengths
+                    .iter()
+                    .enumerate()
+                    .map(|(i, length)| {
+                        travelled += length;
+                        let share = if path_length > 0.0 {
+                            travelled / path_length
+                        } else {
+                            (i + 1) as f64 / lengths.len() as f64
+                        };
+                        let end = (total * share).round() as u64;
+                        let ms = end.saturating_sub(elapsed);
+                        elapsed = end;
+                        ms
+                    })
+                    .collect()
+            }
+        };
+        // Saturating, so absurd durations are rejected below rather than
+        // overflowing
+        let total_ms = segment_ms.iter().fold(0u64, |total, &ms| total.saturating_add(ms));
+        if total_ms > MAX_DRAG_PATH_MS {
+            return Err(ToolError::invalid(format!(
+                "Path would take {}ms (max {}ms)",
+                total_ms, MAX_DRAG_PATH_MS
+            )));
+        }
+
+        let moves = do_mouse_drag_path(&points, &segment_ms, button, &modifiers)?;
+        Ok(ToolOutput::json(&json!({
+            "success": true,
+            "points": points.len(),
+            "duration_ms": total_ms,
+            "move_events": moves,
+            "button": button_name(button),
+            "modifiers": modifiers
+        })))
+    }
+}
+
+pub(crate) struct MouseLongPress;
+
+impl Tool for MouseLongPress {
+    fn name(&self) -> &str {
+        "mouse_long_press"
+    }
+
+    fn description(&self) -> &str {
+        "Press and hold a mouse button at a position, then release: for touch-style apps and context menus that need press-and-hold rather than a click. The pointer is held still for the whole press. Pressing Escape on the real keyboard releases early. Returns the actual held_ms."
+    }
+
+    fn schema(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "x": { "type": "number", "description": "X coordinate" },
+                "y": { "type": "number", "description": "Y coordinate" },
+                "button": {
+                    "type": "string",
+                    "enum": MOUSE_BUTTONS,
+                    "description": "Mouse button to hold: left (default), right, middle, back (x1) or forward (x2)",
+                    "default": "left"
+                },
+                "hold_ms": {
+                    "type": "integer",
+                    "description": "How long to hold in milliseconds (default: 800, max: 10000)",
+                    "default": 800,
+                    "maximum": 10000
+                }
+            },
+            "required": ["x", "y"]
+        })
+    }
+
+    fn requirements(&self) -> ToolRequirements {
+        ToolRequirements {
+            permissions: &[Permission::Accessibility],
+            ..Default::default()
+        }
+    }
+
+    fn sends_input(&self) -> bool {
+        true
+    }
+
+    fn screen_relative(&self) -> bool {
+        true
+    }
+
+    fn call(&self, args: &Value) -> Result<ToolOutput, ToolError> {
+        let x = args.get("x").and_then(|v| v.as_f64()).ok_or_else(|| ToolError::missing("x"))?;
+        let y = args.get("y").and_then(|v| v.as_f64()).ok_or_else(|| ToolError::missing("y"))?;
+        let (x, y) = to_global(&CoordinateSpace::from_args(args).map_err(ToolError::invalid)?, (x, y))?;
+        let button = parse_button(args)?;
+        let hold_ms = args
+            .get("hold_ms")
+            .and_then(|v| v.as_u64())
+            .unwrap_or(800)
+            .min(MAX_LONG_PRESS_MS);
+
+        let (held, aborted) = do_mouse_long_press(x, y, button, hold_ms)?;
+        Ok(ToolOutput::json(&json!({
+            "success": !aborted,
+            "button": button_name(button),
+            "hold_ms": hold_ms,
+            "held_ms": held.as_millis() as u64,
+            "aborted": aborted
+        })))
+    }
+}
+
+pub(crate) struct MouseDrag;
+
+impl Tool for MouseDrag {
+    fn name(&self) -> &str {
+        "mouse_drag"
+    }
+
+    fn description(&self) -> &str {
+        "Drag the mouse from one position to another"
+    }
+
+    fn schema(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "from_x": { "type": "number", "description": "Starting X coordinate" },
+                "from_y": { "type": "number", "description": "Starting Y coordinate" },
+                "to_x": { "type": "number", "description": "Ending X coordinate" },
+                "to_y": { "type": "number", "description": "Ending Y coordinate" },
+                "button": {
+                    "type": "string",
+                    "enum": MOUSE_BUTTONS,
+                    "description": "Mouse button to hold during drag: left (default), right, middle, back (x1) or forward (x2)",
+                    "default": "left"
+                },
+                "duration_ms": {
+                    "type": "integer",
+                    "description": "Duration of drag in milliseconds (default: 500, max: 60000)",
+                    "default": 500,
+                    "minimum": 0,
+                    "maximum": MAX_DRAG_MS
+                },
+                "humanize": {
+                    "type": "boolean",
+                    "description": "Drag along a slightly curved path that decelerates and overshoots a little before settling, instead of a straight constant-speed line (default: false). The result reports the seed used.",
+                    "default": false
+                },
+                "seed": {
+                    "type": "integer",
+                    "description": "With humanize, seed for the path, to reproduce an earlier drag"
+                },
+                "modifiers": {
+                    "type": "array",
+                    "items": {
+                        "type": "string",
+                        "enum": ["shift", "control", "alt", "meta"]
+                    },
+                    "description": "Modifier keys to hold for the whole drag, e.g. [\"alt\"] to duplicate or [\"shift\"] to constrain in design tools"
+                },
+                "hold_after_press_ms": {
+                    "type": "integer",
+                    "description": "Pause after pressing the button, before moving (default: 0, max: 10000)",
+                    "default": 0,
+                    "minimum": 0,
+                    "maximum": MAX_DRAG_HOLD_MS
+                },
+                "hold_before_release_ms": {
+                    "type": "integer",
+                    "description": "Dwell over the target before releasing (default: 0, max: 10000). Many drop targets (Finder, Explorer, web boards) only accept a drop after the cursor has hovered briefly; try 200-500.",
+                    "default": 0,
+                    "minimum": 0,
+                    "maximum": MAX_DRAG_HOLD_MS
+                }
+            },
+            "required": ["from_x", "from_y", "to_x", "to_y"]
+        })
+    }
+
+    fn requirements(&self) -> ToolRequirements {
+        ToolRequirements {
+            permissions: &[Permission::Accessibility],
+            ..Default::default()
+        }
+    }
+
+    fn sends_input(&self) -> bool {
+        true
+    }
+
+    fn screen_relative(&self) -> bool {
+        true
+    }
+
+    /// The drag and its holds, plus a grace period.
+    fn timeout(&self, args: &Value) -> Option<Duration> {
+        Some(Duration::from_millis(drag_ms(args, 0)) + TIMEOUT_GRACE)
+    }
+
+    fn call(&self, args: &Value) -> Result<ToolOutput, ToolError> {
+        let from_x = args
+            .get("from_x")
+            .and_then(|v| v.as_f64())
+            .ok_or_else(|| ToolError::missing("from_x"))?;
+        let from_y = args
+            .get("from_y")
+            .and_then(|v| v.as_f64())
+            .ok_or_else(|| ToolError::missing("from_y"))?;
+        let to_x = args
+            .get("to_x")
+            .and_then(|v| v.as_f64())
+            .ok_or_else(|| ToolError::missing("to_x"))?;
+        let to_y = args
+            .get("to_y")
+            .and_then(|v| v.as_f64())
+            .ok_or_else(|| ToolError::missing("to_y"))?;
+        let space = CoordinateSpace::from_args(args).map_err(ToolError::invalid)?;
+        let (from_x, from_y) = to_global(&space, (from_x, from_y))?;
+        let (to_x, to_y) = to_global(&space, (to_x, to_y))?;
+        let button = parse_button(args)?;
+        let duration_ms = args
+            .get("duration_ms")
+            .and_then(|v| v.as_u64())
+            .unwrap_or(500)
+            .min(MAX_DRAG_MS);
+
+        let humanize = if args.get("humanize").and_then(|v| v.as_bool()).unwrap_or(false) {
+            Some(Humanize::new(args.get("seed").and_then(|v| v.as_u64()))?)
+        } else {
+            None
+        };
+
+        let hold = |name: &str| {
+            args.get(name)
+                .and_then(|v| v.as_u64())
+                .unwrap_or(0)
+                .min(MAX_DRAG_HOLD_MS)
+        };
+        let timing = DragTiming {
+            duration_ms,
+            hold_after_press_ms: hold("hold_after_press_ms"),
+            hold_before_release_ms: hold("hold_before_release_ms"),
+        };
+        let modifiers = parse_modifiers(args);
+
+        do_mouse_drag(
+            (from_x, from_y),
+            (to_x, to_y),
+            button,
+            timing,
+            humanize.as_ref(),
+            &modifiers,
+        )?;
+        let mut result = json!({
+            "success": true,
+            "from": { "x": from_x, "y": from_y },
+            "to": { "x": to_x, "y": to_y },
+            "modifiers": modifiers,
+            "hold_after_press_ms": timing.hold_after_press_ms,
+            "hold_before_release_ms": timing.hold_before_release_ms
+        });
+        if let Some(humanize) = &humanize {
+            result["humanized"] = json!(true);
+            result["seed"] = json!(humanize.seed);
+        }
+        Ok(ToolOutput::json(&result))
+    }
+}
+
+/// How long a drag_file or mouse_drag takes at most from its arguments:
+/// duration_ms plus both holds, with hold_before_release_ms defaulting to
+/// `default_release_ms`.
+fn drag_ms(args: &Value, default_release_ms: u64) -> u64 {
+    let ms = |name: &str, default: u64, max: u64| args.get(name).and_then(|v| v.as_u64()).unwrap_or(default).min(max);
+    ms("duration_ms", 500, MAX_DRAG_MS)
+        + ms("hold_after_press_ms", 0, MAX_DRAG_HOLD_MS)
+        + ms("hold_before_release_ms", default_release_ms, MAX_DRAG_HOLD_MS)
+}
+
+/// Time drag_file's helper may take to start, and to settle after the drop.
+const DRAG_FILE_HELPER_ALLOWANCE: Duration = Duration::from_millis(10_500);
+
+/// How far from its target drag_file starts by default when the cursor is
+/// already on the target, in logical pixels.
+const DRAG_FILE_OFFSET_PX: f64 = 100.0;
+
+pub(crate) struct DragFile;
+
+impl Tool for DragFile {
+    fn name(&self) -> &str {
+        "drag_file"
+    }
+
+    fn description(&self) -> &str {
+        "Drag a file onto a drop target, such as a browser upload area, as if it had been dragged from Finder; the file doesn't need to be visible anywhere. The path must exist and be readable. The drag starts at from_x/from_y, by default the cursor (or 100 px beside the target if the cursor is already on it). Whether the target accepted the drop can't be told; check with a screenshot."
+    }
+
+    fn schema(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "path": {
+                    "type": "string",
+                    "description": "The file or folder to drag"
+                },
+                "to_x": { "type": "number", "description": "X coordinate to drop the file at" },
+                "to_y": { "type": "number", "description": "Y coordinate to drop the file at" },
+                "from_x": { "type": "number", "description": "X coordinate to start the drag at (default: the cursor's)" },
+                "from_y": { "type": "number", "description": "Y coordinate to start the drag at (default: the cursor's)" },
+                "duration_ms": {
+                    "type": "integer",
+                    "description": "Duration of the drag in milliseconds (default: 500, max: 60000)",
+                    "default": 500
+                },
+                "hold_before_release_ms": {
+                    "type": "integer",
+                    "description": "Dwell over the target before dropping (default: 300, max: 10000); many drop targets only accept a drop after the cursor has hovered briefly",
+                    "default": 300
+                }
+            },
+            "required": ["path", "to_x", "to_y"]
+        })
+    }
+
+    fn requirements(&self) -> ToolRequirements {
+        ToolRequirements {
+            macos_only: true,
+            permissions: &[Permission::Accessibility],
+            ..Default::default()
+        }
+    }
+
+    fn sends_input(&self) -> bool {
+        true
+    }
+
+    fn screen_relative(&self) -> bool {
+        true
+    }
+
+    /// The drag and its holds, plus the helper's start-up and a grace
+    /// period.
+    fn timeout(&self, args: &Value) -> Option<Duration> {
+        Some(Duration::from_millis(drag_ms(args, 300)) + DRAG_FILE_HELPER_ALLOWANCE + TIMEOUT_GRACE)
+    }
+
+    fn call(&self, args: &Value) -> Result<ToolOutput, ToolError> {
+        let path = args
+            .get("path")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| ToolError::missing("path"))?;
+        let path = Path::new(path)
+            .canonicalize()
+            .map_err(|e| ToolError::invalid_field("path", format!("Can't drag {}: {}", path, e)))?;
+        File::open(&path)
+            .map_err(|e| ToolError::invalid_field("path", format!("Can't read {}: {}", path.display(), e)))?;
+
+        let to_x = args
+            .get("to_x")
+            .and_then(|v| v.as_f64())
+            .ok_or_else(|| ToolError::missing("to_x"))?;
+        let to_y = args
+            .get("to_y")
+            .and_then(|v| v.as_f64())
+            .ok_or_else(|| ToolError::missing("to_y"))?;
+        let space = CoordinateSpace::from_args(args).map_err(ToolError::invalid)?;
+        let to = to_global(&space, (to_x, to_y))?;
+        let from = match (
+            args.get("from_x").and_then(|v| v.as_f64()),
+            args.get("from_y").and_then(|v| v.as_f64()),
+        ) {
+            (Some(x), Some(y)) => to_global(&space, (x, y))?,
+            (None, None) => default_drag_start(to)?,
+            (Some(_), None) => return Err(ToolError::missing("from_y")),
+            (None, Some(_)) => return Err(ToolError::missing("from_x")),
+        };
+        let timing = DragTiming {
+            duration_ms: args
+                .get("duration_ms")
+                .and_then(|v| v.as_u64())
+                .unwrap_or(500)
+                .min(MAX_DRAG_MS),
+            // Give the drag source time to start its session before moving
+            hold_after_press_ms: 100,
+            hold_before_release_ms: args
+                .get("hold_before_release_ms")
+                .and_then(|v| v.as_u64())
+                .unwrap_or(300)
+                .min(MAX_DRAG_HOLD_MS),
+        };
+
+        do_drag_file(&path, from, to, timing)?;
+        log::info!("Dragged {} to ({}, {})", path.display(), to.0, to.1);
+        Ok(ToolOutput::json(&json!({
+            "success": true,
+            "path": path.to_string_lossy(),
+            "from": { "x": from.0, "y": from.1 },
+            "to": { "x": to.0, "y": to.1 },
+            "hold_before_release_ms": timing.hold_before_release_ms
+        })))
+    }
+}
+
+/// Where drag_file starts when not told: the cursor, unless it's already
+/// on the target (where dragging wouldn't move at all), in which case
+/// beside the target, on whichever side is on a screen.
+fn default_drag_start((to_x, to_y): (f64, f64)) -> Result<(f64, f64), String> {
+    if let Some((x, y)) = cursor_position() {
+        if (x - to_x).hypot(y - to_y) >= DRAG_FILE_OFFSET_PX / 2.0 {
+            return Ok((x, y));
+        }
+    }
+    let left = (to_x - DRAG_FILE_OFFSET_PX, to_y);
+    if screen_at_point(left.0, left.1)?.is_some() {
+        Ok(left)
+    } else {
+        Ok((to_x + DRAG_FILE_OFFSET_PX, to_y))
+    }
+}
+
+pub(crate) struct GetMousePosition;
+
+impl Tool for GetMousePosition {
+    fn name(&self) -> &str {
+        "get_mouse_position"
+    }
+
+    fn description(&self) -> &str {
+        "Get the current mouse cursor position"
+    }
+
+    fn schema(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {}
+        })
+    }
+
+    fn requirements(&self) -> ToolRequirements {
+        ToolRequirements {
+            macos_only: true,
+            ..Default::default()
+        }
+    }
+
+    fn read_only(&self) -> bool {
+        true
+    }
+
+    fn call(&self, _args: &Value) -> Result<ToolOutput, ToolError> {
+        Ok(ToolOutput::json(&get_mouse_position()?))
+    }
+}
+
+pub(crate) struct MouseScroll;
+
+impl Tool for MouseScroll {
+    fn name(&self) -> &str {
+        "mouse_scroll"
+    }
+
+    fn description(&self) -> &str {
+        "Scroll the mouse wheel. Positive delta_y scrolls up, negative scrolls down. Positive delta_x scrolls right, negative scrolls left."
+    }
+
+    fn schema(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "delta_x": {
+                    "type": "integer",
+                    "description": "Horizontal scroll amount (positive = right, negative = left)",
+                    "default": 0
+                },
+                "delta_y": {
+                    "type": "integer",
+                    "description": "Vertical scroll amount (positive = up, negative = down)",
+                    "default": 0
+                }
+            }
+        })
+    }
+
+    fn requirements(&self) -> ToolRequirements {
+        ToolRequirements {
+            permissions: &[Permission::Accessibility],
+            ..Default::default()
+        }
+    }
+
+    fn sends_input(&self) -> bool {
+        true
+    }
+
+    fn call(&self, args: &Value) -> Result<ToolOutput, ToolError> {
+        let delta_x = args.get("delta_x").and_then(|v| v.as_i64()).unwrap_or(0);
+        let delta_y = args.get("delta_y").and_then(|v| v.as_i64()).unwrap_or(0);
+
+        send_event(&EventType::Wheel {
+            delta_x,
+            delta_y,
+        })
+        .map_err(|e| format!("Scroll failed: {:?}", e))?;
+
+        Ok(ToolOutput::json(&json!({
+            "success": true,
+            "delta_x": delta_x,
+            "delta_y": delta_y
+        })))
+    }
+}
+
+pub(crate) struct WaitForUserClick;
+
+impl Tool for WaitForUserClick {
+    fn name(&self) -> &str {
+        "wait_for_user_click"
+    }
+
+    fn description(&self) -> &str {
+        "Wait for the human to physically click somewhere and report the global coordinates, button, screen and the window clicked (null where windows can't be listed). The click is observed, not intercepted, so it still reaches the application. Pressing Escape cancels. Blocks until the click, cancellation or timeout."
+    }
+
+    fn schema(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "timeout_ms": {
+                    "type": "integer",
+                    "description": "How long to wait in milliseconds (default: 30000, max: 120000)",
+                    "default": 30000,
+                    "maximum": 120000
+                },
+                "capture_size": {
+                    "type": "integer",
+                    "description": "If set, also return a PNG capture of a square this many logical pixels wide centered on the click"
+                }
+            }
+        })
+    }
+
+    fn requirements(&self) -> ToolRequirements {
+        ToolRequirements {
+            permissions: &[Permission::Accessibility],
+            user_click: true,
+            ..Default::default()
+        }
+    }
+
+    fn timeout(&self, args: &Value) -> Option<Duration> {
+        own_timeout(args, 30_000, MAX_USER_CLICK_TIMEOUT_MS)
+    }
+
+    fn call(&self, args: &Value) -> Result<ToolOutput, ToolError> {
+        let timeout_ms = args
+            .get("timeout_ms")
+            .and_then(|v| v.as_u64())
+            .unwrap_or(30_000)
+            .min(MAX_USER_CLICK_TIMEOUT_MS);
+        let capture_size = args
+            .get("capture_size")
+            .and_then(|v| v.as_u64())
+            .map(|v| v as u32);
+
+        Ok(wait_for_user_click(timeout_ms, capture_size)?)
+    }
+}
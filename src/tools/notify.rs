@@ -0,0 +1,90 @@
+//! Desktop notifications for the user.
+
+use serde_json::{json, Value};
+use std::time::Duration;
+
+use crate::notify::{show_notification, Urgency};
+use crate::tools::limits::dry_run;
+use crate::tools::output::{ToolError, ToolOutput};
+use crate::tools::Tool;
+
+/// Upper bound on a notification's timeout_ms.
+const MAX_NOTIFY_TIMEOUT_MS: u64 = 600_000;
+
+pub(crate) struct Notify;
+
+impl Tool for Notify {
+    fn name(&self) -> &str {
+        "notify"
+    }
+
+    fn description(&self) -> &str {
+        "Show a native desktop notification, e.g. when a long task finishes or needs the user. Returns as soon as the notification is posted, without waiting for it to be dismissed. If the desktop can't show one (no notification daemon), the call still succeeds with delivered: false and the reason."
+    }
+
+    fn schema(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "title": {
+                    "type": "string",
+                    "description": "Notification title"
+                },
+                "body": {
+                    "type": "string",
+                    "description": "Notification text (default: none)"
+                },
+                "urgency": {
+                    "type": "string",
+                    "enum": Urgency::NAMES,
+                    "description": "How urgent the notification is (default: normal). Linux notification daemons may keep critical ones on screen; on macOS critical plays a sound.",
+                    "default": "normal"
+                },
+                "timeout_ms": {
+                    "type": "integer",
+                    "description": "How long the notification stays on screen, where the platform lets it be set (Linux only)",
+                    "minimum": 0,
+                    "maximum": MAX_NOTIFY_TIMEOUT_MS
+                }
+            },
+            "required": ["title"]
+        })
+    }
+
+    fn call(&self, args: &Value) -> Result<ToolOutput, ToolError> {
+        let title = args
+            .get("title")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| ToolError::missing("title"))?;
+        let body = args.get("body").and_then(|v| v.as_str()).unwrap_or_default();
+        let urgency = match args.get("urgency").and_then(|v| v.as_str()) {
+            Some(name) => Urgency::parse(name)
+                .ok_or_else(|| ToolError::invalid_field("urgency", format!("Unknown urgency: {}", name)))?,
+            None => Urgency::Normal,
+        };
+        let timeout = args
+            .get("timeout_ms")
+            .and_then(|v| v.as_u64())
+            .map(|ms| Duration::from_millis(ms.min(MAX_NOTIFY_TIMEOUT_MS)));
+
+        if dry_run() {
+            return Ok(ToolOutput::json(&json!({
+                "success": true,
+                "title": title,
+                "body": body,
+                "urgency": urgency.as_str(),
+                "simulated": true
+            })));
+        }
+
+        // Not being able to notify isn't the caller's error; it's reported
+        // so that the model can fall back to saying so in the chat
+        let mut result = json!({ "success": true, "delivered": true, "urgency": urgency.as_str() });
+        if let Err(e) = show_notification(title, body, urgency, timeout) {
+            log::warn!("Couldn't show notification \"{}\": {}", title, e);
+            result["delivered"] = json!(false);
+            result["reason"] = json!(e);
+        }
+        Ok(ToolOutput::json(&result))
+    }
+}
@@ -0,0 +1,584 @@
+//! Tool results and errors, and how they're serialized.
+
+use serde_json::{json, Value};
+
+use crate::clock::Timestamp;
+use crate::protocol::{JsonRpcError, ProtocolVersion, INVALID_PARAMS_CODE, METHOD_NOT_FOUND_CODE};
+
+/// A single typed item in a tool result's `content` list.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum ContentItem {
+    Text(String),
+    /// JSON, sent as pretty-printed text after applying ResultLimits.
+    Json(Value),
+    Image {
+        data: String,
+        mime_type: String,
+    },
+}
+
+impl ContentItem {
+    fn to_json(&self, limits: &ResultLimits) -> Value {
+        match self {
+            ContentItem::Text(text) => json!({ "type": "text", "text": text }),
+            ContentItem::Json(value) => json!({
+                "type": "text",
+                "text": serde_json::to_string_pretty(&limits.apply(value)).unwrap_or_default()
+            }),
+            ContentItem::Image { data, mime_type } => json!({
+                "type": "image",
+                "data": data,
+                "mimeType": mime_type
+            }),
+        }
+    }
+}
+
+/// The result of a tool call: an ordered list of content items, optional
+/// structured content, and whether the call failed.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct ToolOutput {
+    pub(crate) content: Vec<ContentItem>,
+    pub(crate) structured_content: Option<Value>,
+    pub(crate) is_error: bool,
+    /// When the call's first OS event was emitted, or when it started
+    /// executing if it emitted none.
+    pub(crate) timestamp: Option<Timestamp>,
+    /// How many failed captures or simulated events were retried.
+    pub(crate) retries: u32,
+}
+
+impl ToolOutput {
+    /// A result whose only content is `value` pretty-printed as text.
+    pub(crate) fn json(value: &Value) -> Self {
+        ToolOutput {
+            content: vec![ContentItem::Json(value.clone())],
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn text(text: impl Into<String>) -> Self {
+        ToolOutput {
+            content: vec![ContentItem::Text(text.into())],
+            ..Default::default()
+        }
+    }
+
+    fn error(message: impl std::fmt::Display) -> Self {
+        ToolOutput {
+            content: vec![ContentItem::Text(format!("Error: {}", message))],
+            is_error: true,
+            ..Default::default()
+        }
+    }
+
+    /// A failed call: the error's message as text, as ToolOutput::error,
+    /// followed by its code.
+    pub(crate) fn failure(error: &ToolError) -> Self {
+        ToolOutput::error(error.message()).with(ContentItem::Json(error.details()))
+    }
+
+    pub(crate) fn with(mut self, item: ContentItem) -> Self {
+        self.content.push(item);
+        self
+    }
+
+    /// Serialize as a `tools/call` result for the given protocol revision,
+    /// truncating JSON content to `limits`. Structured content is dropped
+    /// for revisions that predate it, and otherwise sent whole: truncation
+    /// markers would break the tool's declared output schema.
+    pub(crate) fn to_result(&self, protocol_version: ProtocolVersion, limits: &ResultLimits) -> Value {
+        let mut result = json!({
+            "content": self.content.iter().map(|item| item.to_json(limits)).collect::<Vec<_>>()
+        });
+        if let Some(structured) = &self.structured_content {
+            if protocol_version.structured_content() {
+                result["structuredContent"] = structured.clone();
+            }
+        }
+        if self.is_error {
+            result["isError"] = json!(true);
+        }
+        if let Some(timestamp) = &self.timestamp {
+            result["_meta"] = timestamp.to_json();
+        }
+        if self.retries > 0 {
+            result["_meta"]["retries"] = json!(self.retries);
+        }
+        result
+    }
+}
+
+/// Why a tool call failed. Unknown tools and bad arguments are the
+/// caller's mistake and are answered with a JSON-RPC error; everything else
+/// is a failed call, reported as an isError result whose text is followed
+/// by `{"error_code": ...}`.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum ToolError {
+    UnknownTool(String),
+    InvalidParams {
+        field: Option<String>,
+        message: String,
+    },
+    /// Refused: denied approval, aimed at the client's own window, or the
+    /// wrong window is focused.
+    PermissionDenied(String),
+    /// Coordinates outside the screen they're relative to.
+    OutOfBounds(String),
+    /// The thing looked for isn't on screen, or isn't saved.
+    NotFound(String),
+    /// The tool can't run in this build or on this system.
+    Unavailable(String),
+    /// Capture, input simulation or another OS call failed.
+    Platform(String),
+    /// The client cancelled the call with `notifications/cancelled`.
+    Cancelled,
+    /// The call ran past its timeout and was abandoned.
+    TimedOut(String),
+    /// The user held the cursor in the failsafe corner.
+    Failsafe,
+    /// Over one of the server's input limits.
+    Throttled(String),
+    /// The session is locked or the screensaver is running.
+    SessionLocked,
+}
+
+impl ToolError {
+    pub(crate) fn invalid(message: impl Into<String>) -> Self {
+        ToolError::InvalidParams {
+            field: None,
+            message: message.into(),
+        }
+    }
+
+    /// An invalid argument, attributed to `field`.
+    pub(crate) fn invalid_field(field: &str, message: impl Into<String>) -> Self {
+        ToolError::InvalidParams {
+            field: Some(field.to_string()),
+            message: message.into(),
+        }
+    }
+
+    pub(crate) fn missing(field: &str) -> Self {
+        ToolError::invalid_field(field, format!("Missing {}", field))
+    }
+
+    pub(crate) fn code(&self) -> &'static str {
+        match self {
+            ToolError::UnknownTool(_) => "unknown_tool",
+            ToolError::InvalidParams { .. } => "invalid_params",
+            ToolError::PermissionDenied(_) => "permission_denied",
+            ToolError::OutOfBounds(_) => "out_of_bounds",
+            ToolError::NotFound(_) => "not_found",
+            ToolError::Unavailable(_) => "unavailable",
+            ToolError::Platform(_) => "platform_error",
+            ToolError::Cancelled => "cancelled",
+            ToolError::TimedOut(_) => "timed_out",
+            ToolError::Failsafe => "failsafe_triggered",
+            ToolError::Throttled(_) => "throttled",
+            ToolError::SessionLocked => "session_locked",
+        }
+    }
+
+    pub(crate) fn message(&self) -> String {
+        match self {
+            ToolError::UnknownTool(name) => format!("Unknown tool: {}", name),
+            ToolError::InvalidParams { message, .. }
+            | ToolError::PermissionDenied(message)
+            | ToolError::OutOfBounds(message)
+            | ToolError::NotFound(message)
+            | ToolError::Unavailable(message)
+            | ToolError::Platform(message)
+            | ToolError::TimedOut(message)
+            | ToolError::Throttled(message) => message.clone(),
+            ToolError::Cancelled => "Cancelled by the client".to_string(),
+            ToolError::Failsafe => {
+                "Stopped by the failsafe: the cursor was held in a screen corner. Input tools are refused until failsafe_reset is called".to_string()
+            }
+            ToolError::SessionLocked => {
+                "The session is locked or the screensaver is running, so input would go to the lock screen. Call wait_for_unlock to wait for the user to unlock it".to_string()
+            }
+        }
+    }
+
+    /// The JSON-RPC error code for this error: the standard codes for an
+    /// unknown tool and bad arguments, and one from the range JSON-RPC
+    /// leaves to servers for each of the rest, clear of the -32000 to
+    /// -32002 that MCP and its SDKs use.
+    pub(crate) fn rpc_code(&self) -> i32 {
+        match self {
+            ToolError::UnknownTool(_) => METHOD_NOT_FOUND_CODE,
+            ToolError::InvalidParams { .. } => INVALID_PARAMS_CODE,
+            ToolError::PermissionDenied(_) => -32010,
+            ToolError::OutOfBounds(_) => -32011,
+            ToolError::NotFound(_) => -32012,
+            ToolError::Unavailable(_) => -32013,
+            ToolError::Platform(_) => -32014,
+            ToolError::Cancelled => -32015,
+            ToolError::TimedOut(_) => -32016,
+            ToolError::Failsafe => -32017,
+            ToolError::Throttled(_) => -32018,
+            ToolError::SessionLocked => -32019,
+        }
+    }
+
+    /// Whether this is answered with a JSON-RPC error rather than a failed
+    /// tool result.
+    pub(crate) fn is_protocol_error(&self) -> bool {
+        matches!(self, ToolError::UnknownTool(_) | ToolError::InvalidParams { .. })
+    }
+
+    /// The machine-readable part: the code, plus the offending argument for
+    /// invalid params.
+    fn details(&self) -> Value {
+        let mut details = json!({ "error_code": self.code() });
+        if let ToolError::InvalidParams { field: Some(field), .. } = self {
+            details["field"] = json!(field);
+        }
+        details
+    }
+
+    pub(crate) fn to_rpc_error(&self) -> JsonRpcError {
+        JsonRpcError {
+            code: self.rpc_code(),
+            message: self.message(),
+            data: Some(self.details()),
+        }
+    }
+}
+
+/// Errors from the String-returning helpers are runtime failures unless
+/// the call site says otherwise.
+impl From<String> for ToolError {
+    fn from(message: String) -> Self {
+        ToolError::Platform(message)
+    }
+}
+
+/// Default cap on items in a result's primary array.
+const DEFAULT_MAX_RESULT_ITEMS: usize = 500;
+
+/// Default cap on the serialized size of each JSON item in a result.
+const DEFAULT_MAX_RESULT_BYTES: usize = 256 * 1024;
+
+/// Truncation applied to JSON results as they're serialized, so that large
+/// listings don't swamp the client's context. Defaults come from
+/// `DESKTOP_MCP_MAX_RESULT_ITEMS` and `DESKTOP_MCP_MAX_RESULT_BYTES`; any
+/// tool call can override the item cap with `limit` and page with `offset`.
+///
+/// Only the result's primary array is paged: the result itself if it's an
+/// array, otherwise its longest top-level array field. It keeps `max_items`
+/// items from `offset` and gains a trailing
+/// `{"truncated": {"array": P, "omitted": K, "next_offset": N}}` marker,
+/// where P is the array's JSON pointer ("" for the result itself, else
+/// e.g. "/windows"). Nested arrays are left whole. If the result is still
+/// over `max_bytes`, the item cap is halved until it fits.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct ResultLimits {
+    max_items: usize,
+    max_bytes: usize,
+    offset: usize,
+}
+
+impl Default for ResultLimits {
+    fn default() -> Self {
+        let env = |name: &str, default: usize| {
+            std::env::var(name)
+                .ok()
+                .and_then(|v| v.trim().parse().ok())
+                .unwrap_or(default)
+        };
+        ResultLimits {
+            max_items: env("DESKTOP_MCP_MAX_RESULT_ITEMS", DEFAULT_MAX_RESULT_ITEMS),
+            max_bytes: env("DESKTOP_MCP_MAX_RESULT_BYTES", DEFAULT_MAX_RESULT_BYTES),
+            offset: 0,
+        }
+    }
+}
+
+impl ResultLimits {
+    /// Defaults with the call's `limit` and `offset` overrides applied.
+    pub(crate) fn for_call(args: &Value) -> Self {
+        let arg = |name: &str| args.get(name).and_then(|v| v.as_u64()).map(|v| v as usize);
+        let defaults = Self::default();
+        ResultLimits {
+            max_items: arg("limit").unwrap_or(defaults.max_items),
+            offset: arg("offset").unwrap_or(0),
+            ..defaults
+        }
+    }
+
+    fn apply(&self, value: &Value) -> Value {
+        let primary = match value {
+            Value::Array(_) => Some(""),
+            Value::Object(map) => map
+                .iter()
+                .filter_map(|(key, v)| v.as_array().map(|items| (key, items.len())))
+                .max_by_key(|&(_, len)| len)
+                .map(|(key, _)| key.as_str()),
+            _ => None,
+        };
+        let pointer = match value {
+            Value::Array(_) => String::new(),
+            _ => format!("/{}", primary.unwrap_or_default().replace('~', "~0").replace('/', "~1")),
+        };
+
+        let mut max_items = self.max_items;
+        let mut original_size = None;
+        loop {
+            let truncated = match (value, primary) {
+                (Value::Array(items), _) => self.page(items, &pointer, max_items),
+                (Value::Object(map), Some(key)) => {
+                    let mut map = map.clone();
+                    let items = map[key].as_array().cloned().unwrap_or_default();
+                    map[key] = self.page(&items, &pointer, max_items);
+                    Value::Object(map)
+                }
+                (v, _) => v.clone(),
+            };
+
+            let size = serde_json::to_string_pretty(&truncated).map_or(0, |s| s.len());
+            if size <= self.max_bytes {
+                return truncated;
+            }
+            let original_size = *original_size.get_or_insert(size);
+            if max_items == 0 || primary.is_none() {
+                return json!({
+                    "truncated": { "bytes": original_size, "max_bytes": self.max_bytes }
+                });
+            }
+            max_items /= 2;
+        }
+    }
+
+    /// Keep `max_items` items of the primary array from `offset`, marking
+    /// any that follow as omitted.
+    fn page(&self, items: &[Value], pointer: &str, max_items: usize) -> Value {
+        let start = self.offset.min(items.len());
+        let end = start.saturating_add(max_items).min(items.len());
+        let mut kept = items[start..end].to_vec();
+        if end < items.len() {
+            kept.push(json!({
+                "truncated": { "array": pointer, "omitted": items.len() - end, "next_offset": end }
+            }));
+        }
+        Value::Array(kept)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn listing() -> ToolOutput {
+        let windows: Vec<Value> = (0..5).map(|i| json!({ "id": i })).collect();
+        let mut output = ToolOutput::json(&json!({ "windows": windows, "count": 5 }));
+        output.structured_content = Some(json!({ "windows": windows, "count": 5 }));
+        output
+    }
+
+    fn text_json(result: &Value) -> Value {
+        serde_json::from_str(result["content"][0]["text"].as_str().unwrap()).unwrap()
+    }
+
+    fn image(data: &str) -> ContentItem {
+        ContentItem::Image {
+            data: data.to_string(),
+            mime_type: "image/png".to_string(),
+        }
+    }
+
+    fn serialize(output: &ToolOutput) -> Value {
+        output.to_result(ProtocolVersion::V2025_06_18, &ResultLimits::default())
+    }
+
+    #[test]
+    fn text_only_results_are_a_single_text_block() {
+        assert_eq!(
+            serialize(&ToolOutput::text("done")),
+            json!({ "content": [{ "type": "text", "text": "done" }] })
+        );
+        assert_eq!(
+            serialize(&ToolOutput::json(&json!({ "success": true }))),
+            json!({ "content": [{ "type": "text", "text": "{\n  \"success\": true\n}" }] })
+        );
+    }
+
+    #[test]
+    fn content_items_keep_their_order_and_types() {
+        let output = ToolOutput::json(&json!({ "width": 2 })).with(image("AAAA"));
+        assert_eq!(
+            serialize(&output)["content"],
+            json!([
+                { "type": "text", "text": "{\n  \"width\": 2\n}" },
+                { "type": "image", "data": "AAAA", "mimeType": "image/png" }
+            ])
+        );
+
+        let output = ToolOutput {
+            content: vec![image("AAAA"), ContentItem::Text("between".to_string()), image("BBBB")],
+            ..Default::default()
+        };
+        assert_eq!(
+            serialize(&output)["content"],
+            json!([
+                { "type": "image", "data": "AAAA", "mimeType": "image/png" },
+                { "type": "text", "text": "between" },
+                { "type": "image", "data": "BBBB", "mimeType": "image/png" }
+            ])
+        );
+    }
+
+    #[test]
+    fn failures_are_flagged_with_their_code() {
+        let result = serialize(&ToolOutput::failure(&ToolError::NotFound("No match".to_string())));
+        assert_eq!(result["isError"], json!(true));
+        assert_eq!(result["content"][0], json!({ "type": "text", "text": "Error: No match" }));
+        let details: Value = serde_json::from_str(result["content"][1]["text"].as_str().unwrap()).unwrap();
+        assert_eq!(details, json!({ "error_code": "not_found" }));
+        assert!(serialize(&ToolOutput::text("done")).get("isError").is_none());
+    }
+
+    #[test]
+    fn each_error_has_its_own_code_and_json_rpc_code() {
+        let message = || "message".to_string();
+        let cases = [
+            (ToolError::UnknownTool("nope".to_string()), "unknown_tool", -32601),
+            (ToolError::invalid_field("x", "Missing x"), "invalid_params", -32602),
+            (ToolError::PermissionDenied(message()), "permission_denied", -32010),
+            (ToolError::OutOfBounds(message()), "out_of_bounds", -32011),
+            (ToolError::NotFound(message()), "not_found", -32012),
+            (ToolError::Unavailable(message()), "unavailable", -32013),
+            (ToolError::Platform(message()), "platform_error", -32014),
+            (ToolError::Cancelled, "cancelled", -32015),
+            (ToolError::TimedOut(message()), "timed_out", -32016),
+            (ToolError::Failsafe, "failsafe_triggered", -32017),
+            (ToolError::Throttled(message()), "throttled", -32018),
+            (ToolError::SessionLocked, "session_locked", -32019),
+        ];
+        for (error, code, rpc_code) in &cases {
+            assert_eq!(error.code(), *code);
+            let rpc = error.to_rpc_error();
+            assert_eq!(rpc.code, *rpc_code, "{}", code);
+            assert_eq!(rpc.message, error.message());
+            assert_eq!(rpc.data.as_ref().unwrap()["error_code"], json!(code));
+        }
+        assert_eq!(
+            ToolError::invalid_field("x", "Missing x").to_rpc_error().data,
+            Some(json!({ "error_code": "invalid_params", "field": "x" }))
+        );
+        // No two errors share a JSON-RPC code, nor one the server answers
+        // other failures with
+        let mut rpc_codes: Vec<i32> = cases.iter().map(|(_, _, rpc_code)| *rpc_code).collect();
+        rpc_codes.sort();
+        rpc_codes.dedup();
+        assert_eq!(rpc_codes.len(), cases.len());
+        for reserved in [-32700, -32600, -32603, -32000, -32001, -32002] {
+            assert!(!rpc_codes.contains(&reserved), "{}", reserved);
+        }
+    }
+
+    #[test]
+    fn structured_content_depends_on_the_protocol_revision() {
+        let output = listing();
+        for version in [ProtocolVersion::V2024_11_05, ProtocolVersion::V2025_03_26] {
+            let result = output.to_result(version, &ResultLimits::default());
+            assert!(result.get("structuredContent").is_none(), "{:?}", version);
+            assert_eq!(text_json(&result)["count"], json!(5));
+        }
+        let result = output.to_result(ProtocolVersion::V2025_06_18, &ResultLimits::default());
+        assert_eq!(result["structuredContent"]["count"], json!(5));
+        assert_eq!(text_json(&result)["count"], json!(5));
+    }
+
+    #[test]
+    fn pages_through_the_primary_array() {
+        let first = listing().to_result(ProtocolVersion::V2024_11_05, &ResultLimits::for_call(&json!({ "limit": 2 })));
+        let first = text_json(&first);
+        assert_eq!(
+            first["windows"],
+            json!([
+                { "id": 0 },
+                { "id": 1 },
+                { "truncated": { "array": "/windows", "omitted": 3, "next_offset": 2 } }
+            ])
+        );
+        assert_eq!(first["count"], json!(5));
+
+        let next_offset = first["windows"][2]["truncated"]["next_offset"].clone();
+        let args = json!({ "limit": 2, "offset": next_offset });
+        let second = text_json(&listing().to_result(ProtocolVersion::V2024_11_05, &ResultLimits::for_call(&args)));
+        assert_eq!(
+            second["windows"],
+            json!([
+                { "id": 2 },
+                { "id": 3 },
+                { "truncated": { "array": "/windows", "omitted": 1, "next_offset": 4 } }
+            ])
+        );
+
+        let last = text_json(&listing().to_result(
+            ProtocolVersion::V2024_11_05,
+            &ResultLimits::for_call(&json!({ "limit": 2, "offset": 4 })),
+        ));
+        assert_eq!(last["windows"], json!([{ "id": 4 }]));
+    }
+
+    #[test]
+    fn structured_content_is_never_truncated() {
+        let output = listing();
+        let result = output.to_result(ProtocolVersion::V2025_06_18, &ResultLimits::for_call(&json!({ "limit": 1 })));
+        assert_eq!(Some(&result["structuredContent"]), output.structured_content.as_ref());
+        assert_eq!(text_json(&result)["windows"].as_array().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn oversized_results_halve_the_item_cap_until_they_fit() {
+        let items: Vec<Value> = (0..100).map(|i| json!(format!("item {:04}", i))).collect();
+        let limits = ResultLimits {
+            max_items: 100,
+            max_bytes: 400,
+            offset: 0,
+        };
+        let truncated = limits.apply(&json!(items));
+        assert!(serde_json::to_string_pretty(&truncated).unwrap().len() <= 400);
+        let kept = truncated.as_array().unwrap();
+        assert!(kept.len() < 100);
+        assert_eq!(kept[0], json!("item 0000"));
+        let marker = &kept.last().unwrap()["truncated"];
+        assert!(marker["next_offset"].is_number());
+        assert_eq!(marker["array"], json!(""));
+    }
+
+    #[test]
+    fn only_the_primary_array_is_truncated() {
+        let value = json!({
+            "elements": [
+                { "id": 0, "children": [1, 2, 3, 4] },
+                { "id": 1, "children": [] },
+                { "id": 2, "children": [] }
+            ],
+            "roles": ["button", "link"],
+            "count": 3
+        });
+        let limits = ResultLimits {
+            max_items: 1,
+            max_bytes: 1 << 20,
+            offset: 1,
+        };
+        assert_eq!(
+            limits.apply(&value),
+            json!({
+                "elements": [
+                    { "id": 1, "children": [] },
+                    { "truncated": { "array": "/elements", "omitted": 1, "next_offset": 2 } }
+                ],
+                "roles": ["button", "link"],
+                "count": 3
+            })
+        );
+        // Nested arrays of the kept items are whole
+        let limits = ResultLimits { offset: 0, ..limits };
+        assert_eq!(limits.apply(&value)["elements"][0]["children"], json!([1, 2, 3, 4]));
+    }
+}
@@ -0,0 +1,2137 @@
+//! Tools that look at the screen.
+
+use serde_json::{json, Value};
+use std::time::{Duration, Instant};
+
+use crate::accessibility::{
+    wait_for_element, ElementQuery, MAX_WAIT_FOR_ELEMENT_TIMEOUT_MS, MIN_ELEMENT_POLL_INTERVAL_MS,
+};
+use crate::cancel::sleep_cancellable;
+use crate::capture::assert::{assert_screen, parse_color};
+use crate::capture::change::{
+    compare_to_baseline, reset_baseline, wait_for_region_change, MAX_WAIT_FOR_CHANGE_TIMEOUT_MS,
+    MIN_CHANGE_POLL_INTERVAL_MS,
+};
+use crate::capture::coords::image_to_screen_coords;
+use crate::capture::diff::{diff_capture, DiffOptions};
+use crate::capture::estimate::estimate_capture;
+use crate::capture::ocr::read_text;
+use crate::capture::overlay::{
+    Overlay, OverlayPoint, MAX_MARKER_LABEL, MAX_OVERLAY_MARKERS, MIN_GRID_SPACING,
+};
+use crate::capture::record::{
+    record_screen, RecordFormat, RecordOptions, MAX_RECORD_DURATION_MS, MAX_RECORD_FPS, MAX_RECORD_SIDE,
+};
+use crate::capture::resources::{store_capture, stored_capture};
+use crate::capture::save::{check_path, save_capture};
+use crate::capture::template::{
+    decode_base64_image, find_image, wait_for_image, DEFAULT_MAX_HEIGHT, DEFAULT_MAX_WIDTH,
+    MAX_WAIT_FOR_IMAGE_TIMEOUT_MS,
+};
+use crate::capture::{
+    capture_frame, crop_frame, encode_frame, get_screen_info, CaptureArea, CaptureFormat, CaptureTiming,
+    CursorMarker, CursorStyle, DownscaleFilter, EncodeOptions, ZoomFilter,
+};
+use crate::input::physical::screen_at_point;
+use crate::input::{button_name, do_mouse_click, ClickOptions, MOUSE_BUTTONS};
+use crate::tools::availability::{Permission, ToolRequirements};
+use crate::tools::cursor::cursor_position;
+use crate::tools::output::{ContentItem, ToolError, ToolOutput};
+use crate::tools::timeout::{own_timeout, DEFAULT_CALL_TIMEOUT_MS};
+use crate::tools::{parse_button, parse_modifiers, Tool};
+
+pub(crate) struct ScreenCapture;
+
+impl Tool for ScreenCapture {
+    fn name(&self) -> &str {
+        "screen_capture"
+    }
+
+    fn description(&self) -> &str {
+        "Capture a screenshot of a specific screen or region. Returns a base64-encoded JPEG (or PNG) with automatic downscaling for efficiency, or with save_to: \"file\" writes it to the server's capture directory and returns the path instead. For text-heavy screens that get downscaled, pass filter: \"catmullrom\" and sharpen: true to keep small fonts readable; for pixel art or terminals, filter: \"nearest\". overlay_grid: true labels the image with mouse coordinates to read targets off. Use get_screen_info to list available screens."
+    }
+
+    fn schema(&self) -> Value {
+        let format_description = if cfg!(feature = "webp-lossy") {
+            "Output format (default: jpeg). png is lossless and keeps small text and 1px borders crisp, at the cost of a larger payload; check the reported bytes and fall back to jpeg if it's too big. webp at quality ~60 is roughly half the size of jpeg, which suits frequent polling."
+        } else {
+            "Output format (default: jpeg). png is lossless and keeps small text and 1px borders crisp, at the cost of a larger payload; check the reported bytes and fall back to jpeg if it's too big. This build only encodes webp losslessly, so webp needs lossless: true."
+        };
+        json!({
+            "type": "object",
+            "properties": {
+                "screen_index": {
+                    "type": "integer",
+                    "description": "Index of the screen to capture (default: 0, the primary screen). Use get_screen_info to see available screens.",
+                    "default": 0
+                },
+                "region": {
+                    "type": "object",
+                    "description": "Optional region to capture in logical pixels (same coordinate system as get_screen_info). If not provided, captures entire screen.",
+                    "properties": {
+                        "x": { "type": "integer", "description": "X coordinate of top-left corner" },
+                        "y": { "type": "integer", "description": "Y coordinate of top-left corner" },
+                        "width": { "type": "integer", "description": "Width of region" },
+                        "height": { "type": "integer", "description": "Height of region" }
+                    },
+                    "required": ["x", "y", "width", "height"]
+                },
+                "regions": {
+                    "type": "array",
+                    "description": "Several regions to capture at once, instead of region, in the same coordinates. The screen is captured once and cropped for each, and each is returned as its own image, in order, with the encoding settings shared. The result's regions lists each one's label, region and size.",
+                    "minItems": 1,
+                    "maxItems": MAX_CAPTURE_REGIONS,
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "x": { "type": "integer", "description": "X coordinate of top-left corner" },
+                            "y": { "type": "integer", "description": "Y coordinate of top-left corner" },
+                            "width": { "type": "integer", "description": "Width of region" },
+                            "height": { "type": "integer", "description": "Height of region" },
+                            "label": { "type": "string", "description": "Name to tag the region's image with in the result" }
+                        },
+                        "required": ["x", "y", "width", "height"]
+                    }
+                },
+                "max_width": {
+                    "type": "integer",
+                    "description": "Maximum width for downscaling (default: 1280)",
+                    "default": 1280
+                },
+                "max_height": {
+                    "type": "integer",
+                    "description": "Maximum height for downscaling (default: 720)",
+                    "default": 720
+                },
+                "quality": {
+                    "type": "integer",
+                    "description": "JPEG/WebP quality 1-100 (default: 80). Ignored for png and lossless webp.",
+                    "default": 80,
+                    "minimum": 1,
+                    "maximum": 100
+                },
+                "format": {
+                    "type": "string",
+                    "enum": ["jpeg", "png", "webp"],
+                    "description": format_description,
+                    "default": "jpeg"
+                },
+                "lossless": {
+                    "type": "boolean",
+                    "description": "Encode webp losslessly (default: false)",
+                    "default": false
+                },
+                "strict_bounds": {
+                    "type": "boolean",
+                    "description": "Fail if the region extends past the screen edge instead of clamping it (default: false). Clamped captures report clamped: true.",
+                    "default": false
+                },
+                "max_bytes": {
+                    "type": "integer",
+                    "description": "Budget for the encoded image size (before base64, which adds a third). If the capture is over it, quality and then dimensions are lowered until it fits; the result reports the quality and output size used. Fails rather than exceeding the budget."
+                },
+                "filter": {
+                    "type": "string",
+                    "enum": DownscaleFilter::NAMES,
+                    "description": "Filter for downscaling to max_width/max_height (default: lanczos3). For text-heavy screens prefer catmullrom with sharpen: true, which keeps small fonts legible; nearest suits pixel art and terminals; lanczos3 suits photos and general UI.",
+                    "default": "lanczos3"
+                },
+                "sharpen": {
+                    "type": "boolean",
+                    "description": "Apply a light unsharp mask after resizing, which helps downscaled text (default: false)",
+                    "default": false
+                },
+                "zoom": {
+                    "type": "number",
+                    "description": "Magnify the captured region by this factor, e.g. 2 or 3, to make small text readable (default: 1). max_width/max_height still cap the output, so the zoom applied may be less; the result reports both. A capture that is already over them is downscaled as usual.",
+                    "default": 1,
+                    "minimum": 1,
+                    "maximum": 8
+                },
+                "zoom_filter": {
+                    "type": "string",
+                    "enum": ZoomFilter::NAMES,
+                    "description": "How zoom magnifies (default: nearest). \"nearest\" turns each pixel into a block, for pixel-accurate inspection. \"lanczos\" is smoother, easier on text.",
+                    "default": "nearest"
+                },
+                "only_if_changed": {
+                    "type": "boolean",
+                    "description": "Return {changed: false} and no image if this screen/region looks the same as the last time it was returned (default: false). Useful when polling. The first call for an area always returns an image.",
+                    "default": false
+                },
+                "change_threshold": {
+                    "type": "number",
+                    "description": "With only_if_changed, the fraction (0-1) of a 64x64 thumbnail's pixels that must differ to count as changed (default: 0, any visible change)",
+                    "default": 0
+                },
+                "reset_baseline": {
+                    "type": "boolean",
+                    "description": "Forget the stored comparison image for this screen/region first, so the capture is returned (default: false)",
+                    "default": false
+                },
+                "include_cursor": {
+                    "type": "boolean",
+                    "description": "Draw a marker where the mouse cursor is, since captures don't include the real pointer (default: false). The result's cursor field gives its global position, or null if the cursor isn't within the captured area.",
+                    "default": false
+                },
+                "cursor_style": {
+                    "type": "string",
+                    "enum": ["arrow", "crosshair"],
+                    "description": "Shape of the cursor marker (default: arrow)",
+                    "default": "arrow"
+                },
+                "cursor_color": {
+                    "type": "string",
+                    "description": "Fill color of the cursor marker as \"#rrggbb\" (default: #ff0000)",
+                    "default": "#ff0000"
+                },
+                "overlay_grid": {
+                    "type": "boolean",
+                    "description": "Draw a labeled grid over the image to read coordinates off, instead of estimating them from pixels (default: false). Labels are in mouse coordinates (see grid_coordinates), not image pixels.",
+                    "default": false
+                },
+                "grid_spacing": {
+                    "type": "number",
+                    "description": "Grid spacing in logical pixels (default: chosen so lines are at least 60 image pixels apart)",
+                    "minimum": MIN_GRID_SPACING
+                },
+                "grid_coordinates": {
+                    "type": "string",
+                    "enum": ["global", "screen"],
+                    "description": "Coordinates the grid labels and markers use (default: global). \"global\": what the mouse tools take by default. \"screen\": relative to the captured screen, what the mouse tools take with the same screen_index. Only global for coordinates: \"global\" captures.",
+                    "default": "global"
+                },
+                "markers": {
+                    "type": "array",
+                    "description": "Points to highlight with a crosshair and a label, in grid_coordinates, e.g. to check where a click would land before making it",
+                    "maxItems": MAX_OVERLAY_MARKERS,
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "x": { "type": "number" },
+                            "y": { "type": "number" },
+                            "label": {
+                                "type": "string",
+                                "description": "Text shown by the point (default: its coordinates)",
+                                "maxLength": MAX_MARKER_LABEL
+                            }
+                        },
+                        "required": ["x", "y"]
+                    }
+                },
+                "coordinates": {
+                    "type": "string",
+                    "enum": ["screen", "global"],
+                    "description": "How region is interpreted (default: screen). \"screen\": relative to screen_index. \"global\": virtual desktop coordinates, the same ones the mouse tools use; the region may span several monitors and screen_index is ignored. A global capture with no region covers the whole desktop. The result lists which screens contributed.",
+                    "default": "screen"
+                },
+                "save_to": {
+                    "type": "string",
+                    "enum": ["inline", "file", "resource"],
+                    "description": "Where the image goes (default: inline, as base64 in the result). \"file\" writes it to the server's capture directory (announced in initialize) and returns its path and no image, for clients on the same machine. \"resource\" keeps it in memory as an MCP resource and returns its screenshot:// uri, to fetch with resources/read; only the last 20 are kept.",
+                    "default": "inline"
+                },
+                "path": {
+                    "type": "string",
+                    "description": "With save_to: \"file\", a file name or relative path within the capture directory. The extension is added if missing. Without it the server picks a name, and prunes such files by count and age."
+                },
+                "delay_ms": {
+                    "type": "integer",
+                    "description": "Wait this long before capturing, to let a transition started by the previous action settle (default: 0, max: 10000). The result's waited_ms gives the time actually waited.",
+                    "default": 0,
+                    "minimum": 0,
+                    "maximum": MAX_CAPTURE_DELAY_MS
+                }
+            }
+        })
+    }
+
+    /// Either the capture's metadata, or with only_if_changed, a note that
+    /// the screen hasn't changed.
+    fn output_schema(&self) -> Option<Value> {
+        let point = json!({
+            "type": "object",
+            "properties": {
+                "x": { "type": "number" },
+                "y": { "type": "number" }
+            },
+            "required": ["x", "y"],
+            "additionalProperties": false
+        });
+        Some(json!({
+            "type": "object",
+            "oneOf": [
+                {
+                    "type": "object",
+                    "properties": {
+                        "type": { "type": "string", "enum": ["image"] },
+                        "format": { "type": "string", "enum": ["jpeg", "png", "webp"] },
+                        "mimeType": { "type": "string" },
+                        "encoding": {
+                            "type": "string",
+                            "enum": ["base64", "file", "resource"],
+                            "description": "Where the image is: base64 in the image content item, a file at path, or a resource at uri"
+                        },
+                        "bytes": { "type": "integer", "minimum": 0 },
+                        "quality": { "type": ["integer", "null"], "minimum": 1, "maximum": 100 },
+                        "original_width": { "type": "integer", "minimum": 0 },
+                        "original_height": { "type": "integer", "minimum": 0 },
+                        "output_width": { "type": "integer", "minimum": 0 },
+                        "output_height": { "type": "integer", "minimum": 0 },
+                        "scale_ratio": { "type": "number" },
+                        "screen_index": { "type": ["integer", "null"], "minimum": 0 },
+                        "region_applied": { "type": "boolean" },
+                        "clamped": { "type": "boolean" },
+                        "screens": { "type": "array", "items": { "type": "integer", "minimum": 0 } },
+                        "origin": point,
+                        "logical_per_pixel": { "type": "number" },
+                        "filter": { "type": "string", "enum": DownscaleFilter::NAMES },
+                        "sharpened": { "type": "boolean" },
+                        "zoom": { "type": "number" },
+                        "zoom_applied": { "type": "number" },
+                        "zoom_filter": { "type": "string", "enum": ZoomFilter::NAMES },
+                        "changed": { "type": "boolean", "enum": [true] },
+                        "difference": { "type": ["number", "null"] },
+                        "cursor": { "oneOf": [point, { "type": "null" }] },
+                        "overlay": { "type": "object" },
+                        "path": { "type": "string" },
+                        "uri": { "type": "string" },
+                        "captured_at": { "type": "string" },
+                        "capture_seq": { "type": "integer", "minimum": 1 },
+                        "capture_ms": { "type": "integer", "minimum": 0 },
+                        "encode_ms": { "type": "integer", "minimum": 0 },
+                        "waited_ms": { "type": "integer", "minimum": 0 }
+                    },
+                    "required": [
+                        "type", "format", "mimeType", "encoding", "bytes", "original_width", "original_height",
+                        "output_width", "output_height", "scale_ratio", "region_applied", "clamped", "screens",
+                        "origin", "logical_per_pixel", "captured_at", "capture_seq", "capture_ms", "encode_ms"
+                    ],
+                    "additionalProperties": false
+                },
+                {
+                    "type": "object",
+                    "properties": {
+                        "type": { "type": "string", "enum": ["images"] },
+                        "format": { "type": "string", "enum": ["jpeg", "png", "webp"] },
+                        "mimeType": { "type": "string" },
+                        "count": { "type": "integer", "minimum": 1 },
+                        "bytes": { "type": "integer", "minimum": 0 },
+                        "screens": { "type": "array", "items": { "type": "integer", "minimum": 0 } },
+                        "regions": {
+                            "type": "array",
+                            "items": {
+                                "type": "object",
+                                "properties": {
+                                    "label": { "type": ["string", "null"] },
+                                    "region": {
+                                        "type": "object",
+                                        "properties": {
+                                            "x": { "type": "integer" },
+                                            "y": { "type": "integer" },
+                                            "width": { "type": "integer" },
+                                            "height": { "type": "integer" }
+                                        }
+                                    },
+                                    "bytes": { "type": "integer", "minimum": 0 },
+                                    "quality": { "type": ["integer", "null"], "minimum": 1, "maximum": 100 },
+                                    "original_width": { "type": "integer", "minimum": 0 },
+                                    "original_height": { "type": "integer", "minimum": 0 },
+                                    "output_width": { "type": "integer", "minimum": 0 },
+                                    "output_height": { "type": "integer", "minimum": 0 },
+                                    "clamped": { "type": "boolean" },
+                                    "origin": point,
+                                    "logical_per_pixel": { "type": "number" },
+                                    "cursor": { "oneOf": [point, { "type": "null" }] },
+                                    "overlay": { "type": "object" },
+                                    "encode_ms": { "type": "integer", "minimum": 0 }
+                                },
+                                "required": [
+                                    "label", "region", "bytes", "original_width", "original_height", "output_width",
+                                    "output_height", "clamped", "origin", "logical_per_pixel", "encode_ms"
+                                ]
+                            }
+                        },
+                        "captured_at": { "type": "string" },
+                        "capture_seq": { "type": "integer", "minimum": 1 },
+                        "capture_ms": { "type": "integer", "minimum": 0 },
+                        "encode_ms": { "type": "integer", "minimum": 0 },
+                        "waited_ms": { "type": "integer", "minimum": 0 }
+                    },
+                    "required": [
+                        "type", "format", "mimeType", "count", "bytes", "screens", "regions", "captured_at",
+                        "capture_seq", "capture_ms", "encode_ms"
+                    ],
+                    "additionalProperties": false
+                },
+                {
+                    "type": "object",
+                    "properties": {
+                        "changed": { "type": "boolean", "enum": [false] },
+                        "difference": { "type": ["number", "null"] },
+                        "captured_at": { "type": "string" },
+                        "capture_seq": { "type": "integer", "minimum": 1 },
+                        "capture_ms": { "type": "integer", "minimum": 0 },
+                        "waited_ms": { "type": "integer", "minimum": 0 }
+                    },
+                    "required": ["changed", "difference", "captured_at", "capture_seq", "capture_ms"],
+                    "additionalProperties": false
+                }
+            ]
+        }))
+    }
+
+    fn requirements(&self) -> ToolRequirements {
+        ToolRequirements {
+            permissions: &[Permission::ScreenRecording],
+            ..Default::default()
+        }
+    }
+
+    fn read_only(&self) -> bool {
+        true
+    }
+
+    fn call(&self, args: &Value) -> Result<ToolOutput, ToolError> {
+        let area = parse_capture_area(args).map_err(ToolError::invalid)?;
+        let options = parse_encode_options(args).map_err(ToolError::invalid)?;
+        let format = options.format;
+
+        let save_to = args.get("save_to").and_then(|v| v.as_str()).unwrap_or("inline");
+        if !matches!(save_to, "inline" | "file" | "resource") {
+            return Err(ToolError::invalid_field(
+                "save_to",
+                format!("Unknown save_to: {} (expected \"inline\", \"file\" or \"resource\")", save_to),
+            ));
+        }
+        let path = args.get("path").and_then(|v| v.as_str());
+        if let Some(path) = path {
+            if save_to != "file" {
+                return Err(ToolError::invalid_field("path", "path needs save_to: \"file\""));
+            }
+            check_path(path, format).map_err(|e| ToolError::invalid_field("path", e))?;
+        }
+
+        let cursor = if args
+            .get("include_cursor")
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false)
+        {
+            let style = match args.get("cursor_style").and_then(|v| v.as_str()).unwrap_or("arrow") {
+                "arrow" => CursorStyle::Arrow,
+                "crosshair" => CursorStyle::Crosshair,
+                other => {
+                    return Err(ToolError::invalid_field(
+                        "cursor_style",
+                        format!("Unknown cursor_style: {} (expected \"arrow\" or \"crosshair\")", other),
+                    ))
+                }
+            };
+            let color = args
+                .get("cursor_color")
+                .map(parse_color)
+                .transpose()
+                .map_err(|e| ToolError::invalid_field("cursor_color", e))?
+                .unwrap_or([255, 0, 0]);
+            Some(CursorMarker { style, color })
+        } else {
+            None
+        };
+
+        let overlay = parse_overlay(args, &area)?;
+        let delay = Duration::from_millis(
+            args.get("delay_ms")
+                .and_then(|v| v.as_u64())
+                .unwrap_or(0)
+                .min(MAX_CAPTURE_DELAY_MS),
+        );
+        if args.get("regions").is_some_and(|v| !v.is_null()) {
+            return capture_regions(args, &area, &options, cursor.as_ref(), overlay.as_ref(), delay);
+        }
+
+        let only_if_changed = args
+            .get("only_if_changed")
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false);
+        let change_threshold = args
+            .get("change_threshold")
+            .and_then(|v| v.as_f64())
+            .unwrap_or(0.0);
+        if args.get("reset_baseline").and_then(|v| v.as_bool()).unwrap_or(false) {
+            reset_baseline(&area);
+        }
+
+        let waited = delay_capture(delay)?;
+        let frame = capture_frame(&area)?;
+        let difference = if only_if_changed {
+            let difference = compare_to_baseline(&area, &frame, change_threshold);
+            if difference.is_some_and(|d| d <= change_threshold) {
+                let mut result = json!({
+                    "changed": false,
+                    "difference": difference
+                });
+                add_capture_timing(&mut result, frame.timing(), waited);
+                return Ok(ToolOutput::json(&result));
+            }
+            difference
+        } else {
+            None
+        };
+
+        let screenshot = encode_frame(frame, &options, cursor.as_ref(), overlay.as_ref())?;
+
+        let mut result = json!({
+            "type": "image",
+            "format": format.name(),
+            "mimeType": format.mime_type(),
+            "encoding": "base64",
+            "bytes": screenshot.bytes,
+            "quality": format.is_lossy().then_some(screenshot.quality),
+            "original_width": screenshot.original_width,
+            "original_height": screenshot.original_height,
+            "output_width": screenshot.output_width,
+            "output_height": screenshot.output_height,
+            "scale_ratio": screenshot.scale_ratio(),
+            "screen_index": match area {
+                CaptureArea::Screen { .. } => json!(screenshot.screens.first()),
+                CaptureArea::Global { .. } => Value::Null,
+            },
+            "region_applied": parse_region(args).map_err(|e| ToolError::invalid_field("region", e))?.is_some(),
+            "clamped": screenshot.clamped,
+            "screens": screenshot.screens,
+            "origin": { "x": screenshot.origin_x, "y": screenshot.origin_y },
+            "logical_per_pixel": screenshot.logical_per_pixel()
+        });
+        add_capture_timing(&mut result, screenshot.timing, waited);
+        result["encode_ms"] = json!(screenshot.encode_time.as_millis() as u64);
+        if screenshot.scale_ratio() < 1.0 {
+            result["filter"] = json!(options.filter.as_str());
+        }
+        if options.sharpen {
+            result["sharpened"] = json!(true);
+        }
+        if options.zoom > 1.0 {
+            result["zoom"] = json!(options.zoom);
+            result["zoom_applied"] = json!(screenshot.scale_ratio());
+            result["zoom_filter"] = json!(options.zoom_filter.as_str());
+        }
+        if only_if_changed {
+            result["changed"] = json!(true);
+            result["difference"] = json!(difference);
+        }
+        if let Some(overlay) = screenshot.overlay {
+            result["overlay"] = overlay;
+        }
+        if cursor.is_some() {
+            result["cursor"] = match screenshot.cursor {
+                Some((x, y)) => json!({ "x": x, "y": y }),
+                None => Value::Null,
+            };
+        }
+
+        match save_to {
+            "file" => {
+                let saved = save_capture(&screenshot.data, format, path).map_err(ToolError::Platform)?;
+                result["encoding"] = json!("file");
+                result["path"] = json!(saved.display().to_string());
+                return Ok(ToolOutput::json(&result));
+            }
+            "resource" => {
+                let uri = store_capture(
+                    screenshot.data,
+                    format.mime_type(),
+                    screenshot.bytes,
+                    screenshot.output_width,
+                    screenshot.output_height,
+                    area,
+                );
+                result["encoding"] = json!("resource");
+                result["uri"] = json!(uri);
+                return Ok(ToolOutput::json(&result));
+            }
+            _ => {}
+        }
+
+        Ok(ToolOutput::json(&result)
+        .with(ContentItem::Image {
+            data: screenshot.data,
+            mime_type: format.mime_type().to_string(),
+        }))
+    }
+}
+
+pub(crate) struct CaptureAroundCursor;
+
+impl Tool for CaptureAroundCursor {
+    fn name(&self) -> &str {
+        "capture_around_cursor"
+    }
+
+    fn description(&self) -> &str {
+        "Capture a region centered on the mouse pointer at native resolution, without downscaling, for a close look at what's under it. The region is kept within the pointer's screen, so near an edge the pointer isn't centered; cursor_pixel gives its position in the image. Optionally draws a crosshair on the exact cursor pixel."
+    }
+
+    fn schema(&self) -> Value {
+        // Always lossy here, so webp needs libwebp
+        let formats: &[&str] = if cfg!(feature = "webp-lossy") {
+            &["png", "jpeg", "webp"]
+        } else {
+            &["png", "jpeg"]
+        };
+        json!({
+            "type": "object",
+            "properties": {
+                "width": {
+                    "type": "integer",
+                    "description": "Width of the region in logical pixels (default: 400). Shrunk to the screen's width if larger.",
+                    "default": 400,
+                    "minimum": 1
+                },
+                "height": {
+                    "type": "integer",
+                    "description": "Height of the region in logical pixels (default: 300). Shrunk to the screen's height if larger.",
+                    "default": 300,
+                    "minimum": 1
+                },
+                "crosshair": {
+                    "type": "boolean",
+                    "description": "Draw a crosshair on the cursor pixel (default: false)",
+                    "default": false
+                },
+                "crosshair_color": {
+                    "type": "string",
+                    "description": "Color of the crosshair as \"#rrggbb\" (default: #ff0000)",
+                    "default": "#ff0000"
+                },
+                "format": {
+                    "type": "string",
+                    "enum": formats,
+                    "description": "Output format (default: png, which keeps small text crisp)",
+                    "default": "png"
+                },
+                "quality": {
+                    "type": "integer",
+                    "description": "JPEG/WebP quality 1-100 (default: 80). Ignored for png.",
+                    "default": 80,
+                    "minimum": 1,
+                    "maximum": 100
+                }
+            }
+        })
+    }
+
+    fn requirements(&self) -> ToolRequirements {
+        ToolRequirements {
+            macos_only: true,
+            permissions: &[Permission::ScreenRecording],
+            ..Default::default()
+        }
+    }
+
+    fn read_only(&self) -> bool {
+        true
+    }
+
+    fn call(&self, args: &Value) -> Result<ToolOutput, ToolError> {
+        let width = args.get("width").and_then(|v| v.as_u64()).unwrap_or(400).max(1);
+        let height = args.get("height").and_then(|v| v.as_u64()).unwrap_or(300).max(1);
+        let quality = args.get("quality").and_then(|v| v.as_u64()).unwrap_or(80) as u8;
+        let format = args
+            .get("format")
+            .and_then(|v| v.as_str())
+            .map(|f| CaptureFormat::parse(f, false))
+            .transpose()
+            .map_err(|e| ToolError::invalid_field("format", e))?
+            .unwrap_or(CaptureFormat::Png);
+        let crosshair = if args.get("crosshair").and_then(|v| v.as_bool()).unwrap_or(false) {
+            let color = args
+                .get("crosshair_color")
+                .map(parse_color)
+                .transpose()
+                .map_err(|e| ToolError::invalid_field("crosshair_color", e))?
+                .unwrap_or([255, 0, 0]);
+            Some(CursorMarker {
+                style: CursorStyle::Crosshair,
+                color,
+            })
+        } else {
+            None
+        };
+
+        let (cursor_x, cursor_y) = cursor_position()
+            .ok_or_else(|| ToolError::Unavailable("The cursor position can't be read on this platform".to_string()))?;
+        let (index, screen) = screen_at_point(cursor_x, cursor_y)?
+            .ok_or_else(|| ToolError::NotFound(format!("No screen contains the cursor at ({}, {})", cursor_x, cursor_y)))?;
+
+        // Center on the cursor, then slide the region back onto the screen
+        let width = (width as u32).min(screen.width);
+        let height = (height as u32).min(screen.height);
+        let left = (cursor_x - screen.x as f64 - width as f64 / 2.0)
+            .round()
+            .clamp(0.0, (screen.width - width) as f64) as i32;
+        let top = (cursor_y - screen.y as f64 - height as f64 / 2.0)
+            .round()
+            .clamp(0.0, (screen.height - height) as f64) as i32;
+
+        let area = CaptureArea::Screen {
+            index: Some(index),
+            region: Some((left, top, width, height)),
+            strict_bounds: false,
+        };
+        // Native resolution: nothing is downscaled
+        let options = EncodeOptions {
+            max_width: u32::MAX,
+            max_height: u32::MAX,
+            quality,
+            format,
+            ..EncodeOptions::png()
+        };
+        let screenshot = encode_frame(capture_frame(&area)?, &options, crosshair.as_ref(), None)?;
+        let logical_per_pixel = screenshot.logical_per_pixel();
+        let cursor_pixel_x = ((cursor_x - screenshot.origin_x) / logical_per_pixel).floor() as i64;
+        let cursor_pixel_y = ((cursor_y - screenshot.origin_y) / logical_per_pixel).floor() as i64;
+
+        let result = json!({
+            "type": "image",
+            "format": format.name(),
+            "mimeType": format.mime_type(),
+            "bytes": screenshot.bytes,
+            "quality": format.is_lossy().then_some(screenshot.quality),
+            "output_width": screenshot.output_width,
+            "output_height": screenshot.output_height,
+            "screen_index": index,
+            "region": { "x": left, "y": top, "width": width, "height": height },
+            "origin": { "x": screenshot.origin_x, "y": screenshot.origin_y },
+            "logical_per_pixel": logical_per_pixel,
+            "cursor": { "x": cursor_x, "y": cursor_y },
+            "cursor_pixel": { "x": cursor_pixel_x, "y": cursor_pixel_y },
+            "crosshair": screenshot.cursor.is_some()
+        });
+        Ok(ToolOutput::json(&result).with(ContentItem::Image {
+            data: screenshot.data,
+            mime_type: format.mime_type().to_string(),
+        }))
+    }
+}
+
+pub(crate) struct EstimateCapture;
+
+impl Tool for EstimateCapture {
+    fn name(&self) -> &str {
+        "estimate_capture"
+    }
+
+    fn description(&self) -> &str {
+        "Predict what screen_capture would return for the same arguments, without encoding or transferring the image: the output dimensions, an estimated encoded byte range (low/estimate/high) and, with max_bytes, whether it would exceed it (yes/no/maybe). Use before a large or full-resolution capture."
+    }
+
+    fn schema(&self) -> Value {
+        // Takes screen_capture's area and encoding arguments
+        let mut schema = ScreenCapture.schema();
+        if let Some(properties) = schema["properties"].as_object_mut() {
+            properties.retain(|name, _| {
+                !matches!(
+                    name.as_str(),
+                    "only_if_changed" | "change_threshold" | "reset_baseline" | "include_cursor" | "cursor_style" | "cursor_color"
+                        | "overlay_grid" | "grid_spacing" | "grid_coordinates" | "markers" | "regions" | "save_to" | "path"
+                )
+            });
+        }
+        schema
+    }
+
+    fn requirements(&self) -> ToolRequirements {
+        ToolRequirements {
+            permissions: &[Permission::ScreenRecording],
+            ..Default::default()
+        }
+    }
+
+    fn read_only(&self) -> bool {
+        true
+    }
+
+    fn call(&self, args: &Value) -> Result<ToolOutput, ToolError> {
+        let area = parse_capture_area(args).map_err(ToolError::invalid)?;
+        let options = parse_encode_options(args).map_err(ToolError::invalid)?;
+        Ok(ToolOutput::json(&estimate_capture(&area, &options)?))
+    }
+}
+
+pub(crate) struct ScreenRecord;
+
+impl Tool for ScreenRecord {
+    fn name(&self) -> &str {
+        "screen_record"
+    }
+
+    fn description(&self) -> &str {
+        "Record a screen or region for a few seconds, for when one frame can't show what happened (a toast that came and went, an animation). Returns an animated GIF, or with format: \"frames\" each frame as a JPEG with its timestamp. Capped at 5 seconds, 10 fps, 1280 pixels a side and 5 MB; 3 seconds at 4 fps, 640 wide (the defaults) is usually enough. Start it just before the action that triggers the change."
+    }
+
+    fn schema(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "screen_index": {
+                    "type": "integer",
+                    "description": "Index of the screen to record (default: 0)"
+                },
+                "region": {
+                    "type": "object",
+                    "description": "Region to record in logical pixels (same coordinate system as screen_capture). If not provided, the entire screen.",
+                    "properties": {
+                        "x": { "type": "integer", "description": "X coordinate of top-left corner" },
+                        "y": { "type": "integer", "description": "Y coordinate of top-left corner" },
+                        "width": { "type": "integer", "description": "Width of region" },
+                        "height": { "type": "integer", "description": "Height of region" }
+                    },
+                    "required": ["x", "y", "width", "height"]
+                },
+                "duration_ms": {
+                    "type": "integer",
+                    "description": "How long to record in milliseconds (default: 3000, max: 5000)",
+                    "default": 3000,
+                    "minimum": 1,
+                    "maximum": 5000
+                },
+                "fps": {
+                    "type": "integer",
+                    "description": "Frames per second (default: 4, max: 10)",
+                    "default": 4,
+                    "minimum": 1,
+                    "maximum": 10
+                },
+                "max_width": {
+                    "type": "integer",
+                    "description": "Maximum frame width; larger frames are downscaled (default: 640, max: 1280)",
+                    "default": 640,
+                    "minimum": 1,
+                    "maximum": 1280
+                },
+                "max_height": {
+                    "type": "integer",
+                    "description": "Maximum frame height (default: 640, max: 1280)",
+                    "default": 640,
+                    "minimum": 1,
+                    "maximum": 1280
+                },
+                "format": {
+                    "type": "string",
+                    "enum": RecordFormat::NAMES,
+                    "description": "\"gif\": one animated GIF; \"frames\": a JPEG per frame, listed with their timestamps (default: gif)",
+                    "default": "gif"
+                }
+            }
+        })
+    }
+
+    fn requirements(&self) -> ToolRequirements {
+        ToolRequirements {
+            permissions: &[Permission::ScreenRecording],
+            ..Default::default()
+        }
+    }
+
+    fn read_only(&self) -> bool {
+        true
+    }
+
+    fn call(&self, args: &Value) -> Result<ToolOutput, ToolError> {
+        let area = CaptureArea::Screen {
+            index: args
+                .get("screen_index")
+                .and_then(|v| v.as_u64())
+                .map(|v| v as usize),
+            region: parse_region(args).map_err(|e| ToolError::invalid_field("region", e))?,
+            strict_bounds: false,
+        };
+        let format = args.get("format").and_then(|v| v.as_str()).unwrap_or("gif");
+        let format = RecordFormat::parse(format).ok_or_else(|| {
+            ToolError::invalid_field("format", format!("Unknown format: {} (expected \"gif\" or \"frames\")", format))
+        })?;
+        let options = RecordOptions {
+            duration: Duration::from_millis(
+                args.get("duration_ms")
+                    .and_then(|v| v.as_u64())
+                    .unwrap_or(3000)
+                    .clamp(1, MAX_RECORD_DURATION_MS),
+            ),
+            fps: args
+                .get("fps")
+                .and_then(|v| v.as_u64())
+                .unwrap_or(4)
+                .clamp(1, MAX_RECORD_FPS),
+            max_width: args
+                .get("max_width")
+                .and_then(|v| v.as_u64())
+                .unwrap_or(640)
+                .clamp(1, MAX_RECORD_SIDE as u64) as u32,
+            max_height: args
+                .get("max_height")
+                .and_then(|v| v.as_u64())
+                .unwrap_or(640)
+                .clamp(1, MAX_RECORD_SIDE as u64) as u32,
+            format,
+        };
+
+        record_screen(&area, &options)
+    }
+}
+
+pub(crate) struct FindImage;
+
+impl Tool for FindImage {
+    fn name(&self) -> &str {
+        "find_image"
+    }
+
+    fn description(&self) -> &str {
+        "Find a template image on screen using normalized cross-correlation. Returns the center of the best match in global coordinates (ready for mouse_click), its bounding box and score. Templates cropped from a downscaled screen_capture are matched at the capture's downscale ratio automatically; pass scale to override."
+    }
+
+    fn schema(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "template": {
+                    "type": "string",
+                    "description": "Base64-encoded PNG or JPEG of the image to find"
+                },
+                "screen_index": {
+                    "type": "integer",
+                    "description": "Index of the screen to search (default: 0)",
+                    "default": 0
+                },
+                "region": {
+                    "type": "object",
+                    "description": "Optional region to search in logical pixels (same coordinate system as screen_capture)",
+                    "properties": {
+                        "x": { "type": "integer", "description": "X coordinate of top-left corner" },
+                        "y": { "type": "integer", "description": "Y coordinate of top-left corner" },
+                        "width": { "type": "integer", "description": "Width of region" },
+                        "height": { "type": "integer", "description": "Height of region" }
+                    },
+                    "required": ["x", "y", "width", "height"]
+                },
+                "threshold": {
+                    "type": "number",
+                    "description": "Minimum match score between 0 and 1 (default: 0.8)",
+                    "default": 0.8,
+                    "minimum": 0,
+                    "maximum": 1
+                },
+                "scale": {
+                    "type": "number",
+                    "description": "Factor to enlarge the template by to match native screen pixels, e.g. 2.0 if it was cropped from a capture downscaled by half. If omitted, a small set of likely scales is searched."
+                },
+                "all": {
+                    "type": "boolean",
+                    "description": "Return every match above the threshold, not just the best (default: false)",
+                    "default": false
+                }
+            },
+            "required": ["template"]
+        })
+    }
+
+    fn requirements(&self) -> ToolRequirements {
+        ToolRequirements {
+            permissions: &[Permission::ScreenRecording],
+            ..Default::default()
+        }
+    }
+
+    fn read_only(&self) -> bool {
+        true
+    }
+
+    fn call(&self, args: &Value) -> Result<ToolOutput, ToolError> {
+        find_or_click(args, false)
+    }
+}
+
+pub(crate) struct WaitForImage;
+
+impl Tool for WaitForImage {
+    fn name(&self) -> &str {
+        "wait_for_image"
+    }
+
+    fn description(&self) -> &str {
+        "Wait until a template image appears on screen (as find_image), polling on the server, and return the match in global coordinates. Timing out is not an error: the result is {found: false, timed_out: true, waited_ms}. It's read-only, so other calls, input tools included, run while this waits."
+    }
+
+    fn schema(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "template": {
+                    "type": "string",
+                    "description": "Base64-encoded PNG or JPEG of the image to wait for"
+                },
+                "screen_index": {
+                    "type": "integer",
+                    "description": "Index of the screen to search (default: 0)",
+                    "default": 0
+                },
+                "region": {
+                    "type": "object",
+                    "description": "Optional region to search in logical pixels. Polling a small region is much cheaper than the whole screen.",
+                    "properties": {
+                        "x": { "type": "integer", "description": "X coordinate of top-left corner" },
+                        "y": { "type": "integer", "description": "Y coordinate of top-left corner" },
+                        "width": { "type": "integer", "description": "Width of region" },
+                        "height": { "type": "integer", "description": "Height of region" }
+                    },
+                    "required": ["x", "y", "width", "height"]
+                },
+                "threshold": {
+                    "type": "number",
+                    "description": "Minimum match score between 0 and 1 (default: 0.8)",
+                    "default": 0.8,
+                    "minimum": 0,
+                    "maximum": 1
+                },
+                "scale": {
+                    "type": "number",
+                    "description": "Factor to enlarge the template by to match native screen pixels"
+                },
+                "poll_interval_ms": {
+                    "type": "integer",
+                    "description": "How often to look in milliseconds (default: 500, min: 50)",
+                    "default": 500,
+                    "minimum": 50
+                },
+                "timeout_ms": {
+                    "type": "integer",
+                    "description": "How long to wait in milliseconds (default: 10000, max: 120000)",
+                    "default": 10000,
+                    "maximum": 120000
+                }
+            },
+            "required": ["template"]
+        })
+    }
+
+    fn requirements(&self) -> ToolRequirements {
+        ToolRequirements {
+            permissions: &[Permission::ScreenRecording],
+            ..Default::default()
+        }
+    }
+
+    fn read_only(&self) -> bool {
+        true
+    }
+
+    fn timeout(&self, args: &Value) -> Option<Duration> {
+        own_timeout(args, 10_000, MAX_WAIT_FOR_IMAGE_TIMEOUT_MS)
+    }
+
+    fn call(&self, args: &Value) -> Result<ToolOutput, ToolError> {
+        let template = args
+            .get("template")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| ToolError::missing("template"))?;
+        let area = CaptureArea::Screen {
+            index: args
+                .get("screen_index")
+                .and_then(|v| v.as_u64())
+                .map(|v| v as usize),
+            region: parse_region(args).map_err(|e| ToolError::invalid_field("region", e))?,
+            strict_bounds: false,
+        };
+        let threshold = args
+            .get("threshold")
+            .and_then(|v| v.as_f64())
+            .unwrap_or(0.8) as f32;
+        let scale = args.get("scale").and_then(|v| v.as_f64()).map(|v| v as f32);
+        let poll_interval_ms = args
+            .get("poll_interval_ms")
+            .and_then(|v| v.as_u64())
+            .unwrap_or(500)
+            .max(MIN_CHANGE_POLL_INTERVAL_MS);
+        let timeout_ms = args
+            .get("timeout_ms")
+            .and_then(|v| v.as_u64())
+            .unwrap_or(10_000)
+            .min(MAX_WAIT_FOR_IMAGE_TIMEOUT_MS);
+
+        let result = wait_for_image(
+            template,
+            &area,
+            threshold,
+            scale,
+            Duration::from_millis(poll_interval_ms),
+            Duration::from_millis(timeout_ms),
+        )?;
+        Ok(ToolOutput::json(&result))
+    }
+}
+
+pub(crate) struct ClickImage;
+
+impl Tool for ClickImage {
+    fn name(&self) -> &str {
+        "click_image"
+    }
+
+    fn description(&self) -> &str {
+        "Find a template image on screen (as find_image) and click the center of the best match"
+    }
+
+    fn schema(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "template": {
+                    "type": "string",
+                    "description": "Base64-encoded PNG or JPEG of the image to click"
+                },
+                "screen_index": {
+                    "type": "integer",
+                    "description": "Index of the screen to search (default: 0)",
+                    "default": 0
+                },
+                "region": {
+                    "type": "object",
+                    "description": "Optional region to search in logical pixels",
+                    "properties": {
+                        "x": { "type": "integer", "description": "X coordinate of top-left corner" },
+                        "y": { "type": "integer", "description": "Y coordinate of top-left corner" },
+                        "width": { "type": "integer", "description": "Width of region" },
+                        "height": { "type": "integer", "description": "Height of region" }
+                    },
+                    "required": ["x", "y", "width", "height"]
+                },
+                "threshold": {
+                    "type": "number",
+                    "description": "Minimum match score between 0 and 1 (default: 0.8)",
+                    "default": 0.8,
+                    "minimum": 0,
+                    "maximum": 1
+                },
+                "scale": {
+                    "type": "number",
+                    "description": "Factor to enlarge the template by to match native screen pixels"
+                },
+                "button": {
+                    "type": "string",
+                    "enum": MOUSE_BUTTONS,
+                    "description": "Mouse button to click: left (default), right, middle, or the side buttons back and forward (also x1 and x2), e.g. for browser history",
+                    "default": "left"
+                },
+                "click_count": {
+                    "type": "integer",
+                    "description": "Number of clicks: 2 to double-click, 3 to triple-click (select a line or paragraph) (default: 1, max: 10)",
+                    "default": 1,
+                    "minimum": 1,
+                    "maximum": 10
+                },
+                "interval_ms": {
+                    "type": "integer",
+                    "description": "Gap between clicks (default: 50, max: 2000). Must be below the system double-click time for the clicks to register as one multi-click.",
+                    "default": 50,
+                    "maximum": 2000
+                },
+                "press_duration_ms": {
+                    "type": "integer",
+                    "description": "How long each press is held (default: 10, max: 2000)",
+                    "default": 10,
+                    "maximum": 2000
+                },
+                "double_click": {
+                    "type": "boolean",
+                    "description": "Deprecated: use click_count 2. Ignored when click_count is given.",
+                    "default": false
+                },
+                "modifiers": {
+                    "type": "array",
+                    "items": {
+                        "type": "string",
+                        "enum": ["shift", "control", "alt", "meta"]
+                    },
+                    "description": "Modifier keys to hold during the click, e.g. [\"shift\"] to extend a selection or [\"meta\"] to open a link in a new tab"
+                }
+            },
+            "required": ["template"]
+        })
+    }
+
+    fn requirements(&self) -> ToolRequirements {
+        ToolRequirements {
+            permissions: &[Permission::ScreenRecording, Permission::Accessibility],
+            ..Default::default()
+        }
+    }
+
+    fn sends_input(&self) -> bool {
+        true
+    }
+
+    /// The usual allowance for the search, plus the clicks themselves.
+    fn timeout(&self, args: &Value) -> Option<Duration> {
+        Some(Duration::from_millis(DEFAULT_CALL_TIMEOUT_MS) + ClickOptions::parse(args).duration())
+    }
+
+    fn call(&self, args: &Value) -> Result<ToolOutput, ToolError> {
+        find_or_click(args, true)
+    }
+}
+
+pub(crate) struct WaitForRegionChange;
+
+impl Tool for WaitForRegionChange {
+    fn name(&self) -> &str {
+        "wait_for_region_change"
+    }
+
+    fn description(&self) -> &str {
+        "Block until a screen region changes, then return the new capture; or return {timed_out: true}. Polling happens on the server, so waiting costs nothing. It's read-only, so other calls, input tools included, run while this waits."
+    }
+
+    fn schema(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "screen_index": {
+                    "type": "integer",
+                    "description": "Index of the screen to watch (default: 0, the primary screen)"
+                },
+                "region": {
+                    "type": "object",
+                    "description": "Region to watch, relative to the screen (default: whole screen)",
+                    "properties": {
+                        "x": { "type": "integer" },
+                        "y": { "type": "integer" },
+                        "width": { "type": "integer" },
+                        "height": { "type": "integer" }
+                    },
+                    "required": ["x", "y", "width", "height"]
+                },
+                "poll_interval_ms": {
+                    "type": "integer",
+                    "description": "How often to check in milliseconds (default: 500, min: 50)",
+                    "default": 500,
+                    "minimum": 50
+                },
+                "timeout_ms": {
+                    "type": "integer",
+                    "description": "How long to wait in milliseconds (default: 30000, max: 120000)",
+                    "default": 30000,
+                    "maximum": 120000
+                },
+                "threshold": {
+                    "type": "number",
+                    "description": "Fraction (0-1) of a 64x64 thumbnail's pixels that must differ to count as a change (default: 0, any visible change)",
+                    "default": 0,
+                    "minimum": 0,
+                    "maximum": 1
+                }
+            }
+        })
+    }
+
+    fn requirements(&self) -> ToolRequirements {
+        ToolRequirements {
+            permissions: &[Permission::ScreenRecording],
+            ..Default::default()
+        }
+    }
+
+    fn read_only(&self) -> bool {
+        true
+    }
+
+    fn timeout(&self, args: &Value) -> Option<Duration> {
+        own_timeout(args, 30_000, MAX_WAIT_FOR_CHANGE_TIMEOUT_MS)
+    }
+
+    fn call(&self, args: &Value) -> Result<ToolOutput, ToolError> {
+        let area = CaptureArea::Screen {
+            index: args
+                .get("screen_index")
+                .and_then(|v| v.as_u64())
+                .map(|v| v as usize),
+            region: parse_region(args).map_err(|e| ToolError::invalid_field("region", e))?,
+            strict_bounds: false,
+        };
+        let poll_interval_ms = args
+            .get("poll_interval_ms")
+            .and_then(|v| v.as_u64())
+            .unwrap_or(500)
+            .max(MIN_CHANGE_POLL_INTERVAL_MS);
+        let timeout_ms = args
+            .get("timeout_ms")
+            .and_then(|v| v.as_u64())
+            .unwrap_or(30_000)
+            .min(MAX_WAIT_FOR_CHANGE_TIMEOUT_MS);
+        let threshold = args
+            .get("threshold")
+            .and_then(|v| v.as_f64())
+            .unwrap_or(0.0);
+
+        Ok(wait_for_region_change(
+            &area,
+            Duration::from_millis(poll_interval_ms),
+            Duration::from_millis(timeout_ms),
+            threshold,
+        )?)
+    }
+}
+
+pub(crate) struct CompareScreens;
+
+impl Tool for CompareScreens {
+    fn name(&self) -> &str {
+        "compare_screens"
+    }
+
+    fn description(&self) -> &str {
+        "Capture the screen now and diff it against an earlier capture, to check whether an action changed anything without sending both images. The base is either capture_id, a capture kept with screen_capture save_to: \"resource\" (the same area is captured again), or base_image, a base64 image of the area given by screen_index and region. Returns changed, changed_percent and the changed regions in global coordinates, largest first, each with a center ready for mouse_click; diff_image adds a small picture of the changes."
+    }
+
+    fn schema(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "capture_id": {
+                    "type": "string",
+                    "description": "URI (screenshot://N) of a capture kept with save_to: \"resource\""
+                },
+                "base_image": {
+                    "type": "string",
+                    "description": "Base64-encoded PNG or JPEG to compare against, instead of capture_id"
+                },
+                "screen_index": {
+                    "type": "integer",
+                    "description": "With base_image, the screen to capture (default: 0)"
+                },
+                "region": {
+                    "type": "object",
+                    "description": "With base_image, the region to capture in logical pixels (same coordinate system as screen_capture)",
+                    "properties": {
+                        "x": { "type": "integer", "description": "X coordinate of top-left corner" },
+                        "y": { "type": "integer", "description": "Y coordinate of top-left corner" },
+                        "width": { "type": "integer", "description": "Width of region" },
+                        "height": { "type": "integer", "description": "Height of region" }
+                    },
+                    "required": ["x", "y", "width", "height"]
+                },
+                "channel_tolerance": {
+                    "type": "integer",
+                    "description": "Largest difference in any color channel (0-255) still counted as unchanged (default: 16, enough to absorb JPEG artifacts)",
+                    "default": 16,
+                    "minimum": 0,
+                    "maximum": 255
+                },
+                "min_region_pixels": {
+                    "type": "integer",
+                    "description": "Changed regions with fewer changed pixels than this are ignored as noise (default: 4)",
+                    "default": 4,
+                    "minimum": 1
+                },
+                "ignore_regions": {
+                    "type": "array",
+                    "description": "Areas to leave out, such as a clock, in global logical coordinates (as in the regions returned)",
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "x": { "type": "integer" },
+                            "y": { "type": "integer" },
+                            "width": { "type": "integer" },
+                            "height": { "type": "integer" }
+                        },
+                        "required": ["x", "y", "width", "height"]
+                    }
+                },
+                "size_mismatch": {
+                    "type": "string",
+                    "enum": ["scale", "error"],
+                    "description": "When the base image and the capture differ in size: \"scale\" the capture to the base image's size if they have the same shape, or fail with \"error\" (default: scale)",
+                    "default": "scale"
+                },
+                "diff_image": {
+                    "type": "boolean",
+                    "description": "Also return a PNG at most 512 pixels wide showing changed pixels in red and regions outlined in green (default: false)",
+                    "default": false
+                }
+            }
+        })
+    }
+
+    fn requirements(&self) -> ToolRequirements {
+        ToolRequirements {
+            permissions: &[Permission::ScreenRecording],
+            ..Default::default()
+        }
+    }
+
+    fn read_only(&self) -> bool {
+        true
+    }
+
+    fn call(&self, args: &Value) -> Result<ToolOutput, ToolError> {
+        let capture_id = args.get("capture_id").and_then(|v| v.as_str());
+        let base_image = args.get("base_image").and_then(|v| v.as_str());
+        let (base, area, base_field) = match (capture_id, base_image) {
+            (Some(id), None) => {
+                if args.get("screen_index").is_some() || args.get("region").is_some() {
+                    return Err(ToolError::invalid(
+                        "screen_index and region go with base_image; a capture_id is compared against the area it was taken of",
+                    ));
+                }
+                let (data, area) = stored_capture(id).map_err(|e| ToolError::invalid_field("capture_id", e))?;
+                let base = decode_base64_image(&data).map_err(ToolError::Platform)?;
+                (base, area, "capture_id")
+            }
+            (None, Some(data)) => {
+                let base = decode_base64_image(data).map_err(|e| ToolError::invalid_field("base_image", e))?;
+                let area = CaptureArea::Screen {
+                    index: args
+                        .get("screen_index")
+                        .and_then(|v| v.as_u64())
+                        .map(|v| v as usize),
+                    region: parse_region(args).map_err(|e| ToolError::invalid_field("region", e))?,
+                    strict_bounds: false,
+                };
+                (base, area, "base_image")
+            }
+            (Some(_), Some(_)) => return Err(ToolError::invalid("Pass capture_id or base_image, not both")),
+            (None, None) => return Err(ToolError::invalid("Pass capture_id or base_image to compare against")),
+        };
+
+        let options = parse_diff_options(args)?;
+        Ok(diff_capture(&area, &base, base_field, &options)?.into_output())
+    }
+}
+
+/// Parse the channel_tolerance/min_region_pixels/ignore_regions/
+/// size_mismatch/diff_image arguments shared by compare_screens and
+/// baseline_compare.
+pub(crate) fn parse_diff_options(args: &Value) -> Result<DiffOptions, ToolError> {
+    let mut ignore = Vec::new();
+    if let Some(regions) = args.get("ignore_regions").and_then(|v| v.as_array()) {
+        for (i, region) in regions.iter().enumerate() {
+            let (x, y, width, height) = parse_region(&json!({ "region": region }))
+                .map_err(|e| ToolError::invalid_field("ignore_regions", format!("ignore_regions[{}]: {}", i, e)))?
+                .ok_or_else(|| ToolError::invalid_field("ignore_regions", format!("ignore_regions[{}] is null", i)))?;
+            ignore.push((x as f64, y as f64, width as f64, height as f64));
+        }
+    }
+    Ok(DiffOptions {
+        channel_tolerance: args
+            .get("channel_tolerance")
+            .and_then(|v| v.as_u64())
+            .unwrap_or(16)
+            .min(255) as u8,
+        min_region_pixels: args
+            .get("min_region_pixels")
+            .and_then(|v| v.as_u64())
+            .unwrap_or(4)
+            .max(1),
+        ignore,
+        scale_mismatch: args.get("size_mismatch").and_then(|v| v.as_str()).unwrap_or("scale") == "scale",
+        diff_image: args.get("diff_image").and_then(|v| v.as_bool()).unwrap_or(false),
+    })
+}
+
+pub(crate) struct WaitForElement;
+
+impl Tool for WaitForElement {
+    fn name(&self) -> &str {
+        "wait_for_element"
+    }
+
+    fn description(&self) -> &str {
+        "Wait for a UI element to appear in the accessibility tree, matched by role and/or label. Unlike pixel-based waiting this survives theme and layout changes. Returns the element's bounds (with a center ready for mouse_click) and attributes, or on timeout {found: false, timed_out: true} with the closest labels seen, to help spot typos."
+    }
+
+    fn schema(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "role": {
+                    "type": "string",
+                    "description": "Accessibility role, e.g. \"button\", \"AXTextField\" or \"checkbox\" (case-insensitive, AX prefix optional)"
+                },
+                "label": {
+                    "type": "string",
+                    "description": "Text to find in the element's title, description or identifier (case-insensitive substring)"
+                },
+                "window": {
+                    "type": "string",
+                    "description": "Only search windows whose title or app name contains this (default: the frontmost app)"
+                },
+                "poll_interval_ms": {
+                    "type": "integer",
+                    "description": "How often to check in milliseconds (default: 500, min: 100). Each check stops after this long, so very large trees may be searched only partly; the timeout result reports how much was covered.",
+                    "default": 500,
+                    "minimum": 100
+                },
+                "timeout_ms": {
+                    "type": "integer",
+                    "description": "How long to wait in milliseconds (default: 10000, max: 120000)",
+                    "default": 10000,
+                    "maximum": 120000
+                }
+            }
+        })
+    }
+
+    fn requirements(&self) -> ToolRequirements {
+        ToolRequirements {
+            macos_only: true,
+            permissions: &[Permission::Accessibility],
+            ..Default::default()
+        }
+    }
+
+    fn read_only(&self) -> bool {
+        true
+    }
+
+    fn timeout(&self, args: &Value) -> Option<Duration> {
+        own_timeout(args, 10_000, MAX_WAIT_FOR_ELEMENT_TIMEOUT_MS)
+    }
+
+    fn call(&self, args: &Value) -> Result<ToolOutput, ToolError> {
+        let query = ElementQuery::parse(args).map_err(ToolError::invalid)?;
+        let poll_interval_ms = args
+            .get("poll_interval_ms")
+            .and_then(|v| v.as_u64())
+            .unwrap_or(500)
+            .max(MIN_ELEMENT_POLL_INTERVAL_MS);
+        let timeout_ms = args
+            .get("timeout_ms")
+            .and_then(|v| v.as_u64())
+            .unwrap_or(10_000)
+            .min(MAX_WAIT_FOR_ELEMENT_TIMEOUT_MS);
+
+        Ok(wait_for_element(
+            &query,
+            Duration::from_millis(poll_interval_ms),
+            Duration::from_millis(timeout_ms),
+        )?)
+    }
+}
+
+pub(crate) struct ReadText;
+
+impl Tool for ReadText {
+    fn name(&self) -> &str {
+        "read_text"
+    }
+
+    fn description(&self) -> &str {
+        "Read the text on a screen or region with OCR. Returns the text plus each recognized line with its confidence (0-100) and bounds in global logical coordinates, ready for mouse_click. Much cheaper than a screenshot when only the text matters."
+    }
+
+    fn schema(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "screen_index": {
+                    "type": "integer",
+                    "description": "Index of the screen to read (default: 0, the primary screen)"
+                },
+                "region": {
+                    "type": "object",
+                    "description": "Region to read, relative to the screen (default: whole screen). Smaller regions are faster and more accurate.",
+                    "properties": {
+                        "x": { "type": "integer" },
+                        "y": { "type": "integer" },
+                        "width": { "type": "integer" },
+                        "height": { "type": "integer" }
+                    },
+                    "required": ["x", "y", "width", "height"]
+                },
+                "lang": {
+                    "type": "string",
+                    "description": "Tesseract language code(s), e.g. \"eng\" or \"eng+deu\" (default: Tesseract's default)"
+                }
+            }
+        })
+    }
+
+    fn requirements(&self) -> ToolRequirements {
+        ToolRequirements {
+            permissions: &[Permission::ScreenRecording],
+            missing_feature: (!cfg!(feature = "ocr")).then_some("ocr"),
+            ..Default::default()
+        }
+    }
+
+    fn read_only(&self) -> bool {
+        true
+    }
+
+    fn call(&self, args: &Value) -> Result<ToolOutput, ToolError> {
+        let area = CaptureArea::Screen {
+            index: args
+                .get("screen_index")
+                .and_then(|v| v.as_u64())
+                .map(|v| v as usize),
+            region: parse_region(args).map_err(|e| ToolError::invalid_field("region", e))?,
+            strict_bounds: false,
+        };
+        Ok(read_text(&area, args.get("lang").and_then(|v| v.as_str()))?)
+    }
+}
+
+pub(crate) struct AssertScreen;
+
+impl Tool for AssertScreen {
+    fn name(&self) -> &str {
+        "assert_screen"
+    }
+
+    fn description(&self) -> &str {
+        "Check that the screen is in an expected state. Evaluates every condition and reports pass/fail for each, with the measured values for failures; the result is an error if any condition fails. Pixel and region coordinates are relative to screen_index, like screen_capture."
+    }
+
+    fn schema(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "conditions": {
+                    "type": "array",
+                    "description": "Conditions to check. Each has a type: window_title {contains}, pixel {x, y, color: \"#rrggbb\", tolerance}, phash {region, hash, max_distance} or text {region, contains} (needs a build with the ocr feature). Any may include screen_index. A failed phash reports the measured hash, so a first run against a known-good screen yields the hash to assert on.",
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "type": {
+                                "type": "string",
+                                "enum": ["window_title", "pixel", "phash", "text"]
+                            }
+                        },
+                        "required": ["type"]
+                    }
+                },
+                "capture_failures": {
+                    "type": "boolean",
+                    "description": "Attach a PNG capture of the area around each failed pixel/phash condition (default: false)",
+                    "default": false
+                }
+            },
+            "required": ["conditions"]
+        })
+    }
+
+    fn requirements(&self) -> ToolRequirements {
+        ToolRequirements {
+            permissions: &[Permission::ScreenRecording],
+            ..Default::default()
+        }
+    }
+
+    fn read_only(&self) -> bool {
+        true
+    }
+
+    fn call(&self, args: &Value) -> Result<ToolOutput, ToolError> {
+        let conditions = args
+            .get("conditions")
+            .and_then(|v| v.as_array())
+            .ok_or_else(|| ToolError::missing("conditions"))?;
+        let capture_failures = args
+            .get("capture_failures")
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false);
+
+        Ok(assert_screen(conditions, capture_failures)?)
+    }
+}
+
+pub(crate) struct GetScreenInfo;
+
+impl Tool for GetScreenInfo {
+    fn name(&self) -> &str {
+        "get_screen_info"
+    }
+
+    fn description(&self) -> &str {
+        "Get information about available screens, with each screen's bounds in both logical coordinates (what the mouse tools take) and physical pixels (logical times scale_factor, what a full-resolution screenshot has)"
+    }
+
+    fn schema(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "refresh": {
+                    "type": "boolean",
+                    "description": "Enumerate the screens afresh rather than use the list cached in the last couple of seconds (default: false)",
+                    "default": false
+                }
+            }
+        })
+    }
+
+    fn output_schema(&self) -> Option<Value> {
+        let rect = json!({
+            "type": "object",
+            "properties": {
+                "x": { "type": "number" },
+                "y": { "type": "number" },
+                "width": { "type": "number" },
+                "height": { "type": "number" }
+            },
+            "required": ["x", "y", "width", "height"],
+            "additionalProperties": false
+        });
+        Some(json!({
+            "type": "object",
+            "properties": {
+                "screens": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "index": { "type": "integer", "minimum": 0 },
+                            "id": { "type": "integer" },
+                            "x": { "type": "integer" },
+                            "y": { "type": "integer" },
+                            "width": { "type": "integer", "minimum": 0 },
+                            "height": { "type": "integer", "minimum": 0 },
+                            "scale_factor": { "type": "number" },
+                            "is_primary": { "type": "boolean" },
+                            "logical": rect,
+                            "physical": rect
+                        },
+                        "required": [
+                            "index", "id", "x", "y", "width", "height", "scale_factor", "is_primary", "logical",
+                            "physical"
+                        ],
+                        "additionalProperties": false
+                    }
+                },
+                "count": { "type": "integer", "minimum": 0 }
+            },
+            "required": ["screens", "count"],
+            "additionalProperties": false
+        }))
+    }
+
+    fn read_only(&self) -> bool {
+        true
+    }
+
+    fn call(&self, args: &Value) -> Result<ToolOutput, ToolError> {
+        let refresh = args.get("refresh").and_then(|v| v.as_bool()).unwrap_or(false);
+        Ok(ToolOutput::json(&get_screen_info(refresh)?))
+    }
+}
+
+pub(crate) struct ImageToScreenCoords;
+
+impl Tool for ImageToScreenCoords {
+    fn name(&self) -> &str {
+        "image_to_screen_coords"
+    }
+
+    fn description(&self) -> &str {
+        "Convert a pixel position in an image returned by screen_capture, after any downscaling, into global coordinates that mouse_click and the other mouse tools take directly. Pass the capture's origin and logical_per_pixel through unchanged."
+    }
+
+    fn schema(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "x": { "type": "number", "description": "X position in the returned image" },
+                "y": { "type": "number", "description": "Y position in the returned image" },
+                "origin": {
+                    "type": "object",
+                    "properties": {
+                        "x": { "type": "number" },
+                        "y": { "type": "number" }
+                    },
+                    "required": ["x", "y"],
+                    "description": "The capture result's origin"
+                },
+                "logical_per_pixel": {
+                    "type": "number",
+                    "description": "The capture result's logical_per_pixel"
+                }
+            },
+            "required": ["x", "y", "origin", "logical_per_pixel"]
+        })
+    }
+
+    fn read_only(&self) -> bool {
+        true
+    }
+
+    fn call(&self, args: &Value) -> Result<ToolOutput, ToolError> {
+        image_to_screen_coords(args)
+            .map(|coords| ToolOutput::json(&coords))
+            .map_err(ToolError::invalid)
+    }
+}
+
+/// find_image, and click_image which clicks the center of what it finds.
+fn find_or_click(args: &Value, click: bool) -> Result<ToolOutput, ToolError> {
+    let template = args
+        .get("template")
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| ToolError::missing("template"))?;
+    let screen_index = args
+        .get("screen_index")
+        .and_then(|v| v.as_u64())
+        .map(|v| v as usize);
+    let region = parse_region(args).map_err(|e| ToolError::invalid_field("region", e))?;
+    let threshold = args
+        .get("threshold")
+        .and_then(|v| v.as_f64())
+        .unwrap_or(0.8) as f32;
+    let scale = args.get("scale").and_then(|v| v.as_f64()).map(|v| v as f32);
+    let all = args.get("all").and_then(|v| v.as_bool()).unwrap_or(false);
+
+    let mut result = find_image(template, screen_index, region, threshold, scale, all)?;
+
+    if click {
+        let (x, y) = match (result["x"].as_f64(), result["y"].as_f64()) {
+            (Some(x), Some(y)) => (x, y),
+            _ => {
+                return Err(ToolError::NotFound(format!(
+                    "Image not found (best score {:.3} below threshold {})",
+                    result["best_score"].as_f64().unwrap_or(0.0),
+                    threshold
+                )))
+            }
+        };
+        let button = parse_button(args)?;
+        let clicks = ClickOptions::parse(args);
+        let modifiers = parse_modifiers(args);
+
+        do_mouse_click(Some(x), Some(y), button, clicks, &modifiers)?;
+        result["success"] = json!(true);
+        result["button"] = json!(button_name(button));
+        result["click"] = clicks.to_json();
+        result["modifiers"] = json!(modifiers);
+    }
+
+    Ok(ToolOutput::json(&result))
+}
+
+/// Parse an optional `region` argument of the form {x, y, width, height}.
+/// Bounds against the screen(s) are checked later, in capture_frame.
+pub(crate) fn parse_region(args: &Value) -> Result<Option<(i32, i32, u32, u32)>, String> {
+    let r = match args.get("region") {
+        None | Some(Value::Null) => return Ok(None),
+        Some(r) => r,
+    };
+    let field = |name: &str| -> Result<i32, String> {
+        let value = r
+            .get(name)
+            .and_then(|v| v.as_i64())
+            .ok_or_else(|| format!("region.{} must be an integer", name))?;
+        i32::try_from(value).map_err(|_| format!("region.{} is out of range: {}", name, value))
+    };
+    let (x, y, width, height) = (field("x")?, field("y")?, field("width")?, field("height")?);
+    if width <= 0 || height <= 0 {
+        return Err(format!(
+            "region width and height must be positive (got {}x{})",
+            width, height
+        ));
+    }
+    Ok(Some((x, y, width as u32, height as u32)))
+}
+
+/// Parse the screen_index/region/coordinates/strict_bounds arguments shared
+/// by screen_capture and estimate_capture.
+fn parse_capture_area(args: &Value) -> Result<CaptureArea, String> {
+    let screen_index = args
+        .get("screen_index")
+        .and_then(|v| v.as_u64())
+        .map(|v| v as usize);
+    let region = parse_region(args)?;
+    let strict_bounds = args
+        .get("strict_bounds")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false);
+
+    match args.get("coordinates").and_then(|v| v.as_str()).unwrap_or("screen") {
+        "screen" => Ok(CaptureArea::Screen {
+            index: screen_index,
+            region,
+            strict_bounds,
+        }),
+        "global" => Ok(CaptureArea::Global {
+            region,
+            strict_bounds,
+        }),
+        other => Err(format!(
+            "Unknown coordinates: {} (expected \"screen\" or \"global\")",
+            other
+        )),
+    }
+}
+
+/// Most regions screen_capture takes at once.
+const MAX_CAPTURE_REGIONS: usize = 16;
+
+/// screen_capture with `regions`: capture the screen (or desktop) once and
+/// encode a crop of it per region, returned in order.
+fn capture_regions(
+    args: &Value,
+    area: &CaptureArea,
+    options: &EncodeOptions,
+    cursor: Option<&CursorMarker>,
+    overlay: Option<&Overlay>,
+    delay: Duration,
+) -> Result<ToolOutput, ToolError> {
+    if args.get("region").is_some_and(|v| !v.is_null()) {
+        return Err(ToolError::invalid_field("regions", "Pass region or regions, not both"));
+    }
+    if args.get("only_if_changed").and_then(|v| v.as_bool()).unwrap_or(false) {
+        return Err(ToolError::invalid_field("regions", "only_if_changed doesn't work with regions"));
+    }
+    if args.get("save_to").and_then(|v| v.as_str()).is_some_and(|s| s != "inline") {
+        return Err(ToolError::invalid_field("regions", "regions are only returned inline"));
+    }
+    let items = args
+        .get("regions")
+        .and_then(|v| v.as_array())
+        .ok_or_else(|| ToolError::invalid_field("regions", "regions must be an array"))?;
+    if items.is_empty() || items.len() > MAX_CAPTURE_REGIONS {
+        return Err(ToolError::invalid_field(
+            "regions",
+            format!("Pass 1 to {} regions", MAX_CAPTURE_REGIONS),
+        ));
+    }
+    let mut regions = Vec::new();
+    for (i, item) in items.iter().enumerate() {
+        let region = parse_region(&json!({ "region": item }))
+            .map_err(|e| ToolError::invalid_field("regions", format!("regions[{}]: {}", i, e)))?
+            .ok_or_else(|| ToolError::invalid_field("regions", format!("regions[{}] must be an object", i)))?;
+        let label = item.get("label").and_then(|v| v.as_str()).map(|s| s.to_string());
+        regions.push((region, label));
+    }
+
+    let waited = delay_capture(delay)?;
+    let frame = capture_frame(area)?;
+    let format = options.format;
+    let timing = frame.timing();
+    let mut total = 0;
+    let mut encode_time = Duration::ZERO;
+    let mut screens = Vec::new();
+    let mut described = Vec::new();
+    let mut images = Vec::new();
+    for (i, (region, label)) in regions.into_iter().enumerate() {
+        let crop = crop_frame(&frame, area, region)
+            .map_err(|e| ToolError::invalid_field("regions", format!("regions[{}]: {}", i, e)))?;
+        let screenshot = encode_frame(crop, options, cursor, overlay)?;
+        total += screenshot.bytes;
+        encode_time += screenshot.encode_time;
+        for screen in &screenshot.screens {
+            if !screens.contains(screen) {
+                screens.push(*screen);
+            }
+        }
+        let (x, y, width, height) = region;
+        let mut entry = json!({
+            "label": label,
+            "region": { "x": x, "y": y, "width": width, "height": height },
+            "bytes": screenshot.bytes,
+            "quality": format.is_lossy().then_some(screenshot.quality),
+            "original_width": screenshot.original_width,
+            "original_height": screenshot.original_height,
+            "output_width": screenshot.output_width,
+            "output_height": screenshot.output_height,
+            "clamped": screenshot.clamped,
+            "origin": { "x": screenshot.origin_x, "y": screenshot.origin_y },
+            "logical_per_pixel": screenshot.logical_per_pixel(),
+            "encode_ms": screenshot.encode_time.as_millis() as u64
+        });
+        if cursor.is_some() {
+            entry["cursor"] = match screenshot.cursor {
+                Some((x, y)) => json!({ "x": x, "y": y }),
+                None => Value::Null,
+            };
+        }
+        if let Some(overlay) = screenshot.overlay {
+            entry["overlay"] = overlay;
+        }
+        described.push(entry);
+        images.push(screenshot.data);
+    }
+
+    let mut result = json!({
+        "type": "images",
+        "format": format.name(),
+        "mimeType": format.mime_type(),
+        "count": described.len(),
+        "bytes": total,
+        "screens": screens,
+        "regions": described
+    });
+    add_capture_timing(&mut result, timing, waited);
+    result["encode_ms"] = json!(encode_time.as_millis() as u64);
+    let mut output = ToolOutput::json(&result);
+    for data in images {
+        output = output.with(ContentItem::Image {
+            data,
+            mime_type: format.mime_type().to_string(),
+        });
+    }
+    Ok(output)
+}
+
+/// Upper bound on screen_capture's delay_ms.
+const MAX_CAPTURE_DELAY_MS: u64 = 10_000;
+
+/// Wait out screen_capture's delay_ms, stopping if the call is cancelled.
+/// Returns how long it actually waited.
+fn delay_capture(delay: Duration) -> Result<Option<Duration>, ToolError> {
+    if delay.is_zero() {
+        return Ok(None);
+    }
+    let start = Instant::now();
+    sleep_cancellable(delay)?;
+    Ok(Some(start.elapsed()))
+}
+
+/// Add when a capture was grabbed, its sequence number, how long grabbing
+/// it took and any delay_ms waited beforehand to a screen_capture result.
+fn add_capture_timing(result: &mut Value, timing: CaptureTiming, waited: Option<Duration>) {
+    result["captured_at"] = json!(timing.captured_at.rfc3339());
+    result["capture_seq"] = json!(timing.sequence);
+    result["capture_ms"] = json!(timing.capture_time.as_millis() as u64);
+    if let Some(waited) = waited {
+        result["waited_ms"] = json!(waited.as_millis() as u64);
+    }
+}
+
+/// Parse screen_capture's overlay_grid/grid_spacing/grid_coordinates/markers
+/// arguments. None when there's nothing to draw.
+fn parse_overlay(args: &Value, area: &CaptureArea) -> Result<Option<Overlay>, ToolError> {
+    let grid = args.get("overlay_grid").and_then(|v| v.as_bool()).unwrap_or(false);
+    let grid_spacing = args.get("grid_spacing").and_then(|v| v.as_f64());
+    if grid_spacing.is_some_and(|spacing| spacing < MIN_GRID_SPACING) {
+        return Err(ToolError::invalid_field(
+            "grid_spacing",
+            format!("grid_spacing must be at least {}", MIN_GRID_SPACING),
+        ));
+    }
+    let screen_relative = match args.get("grid_coordinates").and_then(|v| v.as_str()).unwrap_or("global") {
+        "global" => false,
+        "screen" if matches!(area, CaptureArea::Global { .. }) => {
+            return Err(ToolError::invalid_field(
+                "grid_coordinates",
+                "grid_coordinates: \"screen\" needs a screen capture, not coordinates: \"global\"",
+            ))
+        }
+        "screen" => true,
+        other => {
+            return Err(ToolError::invalid_field(
+                "grid_coordinates",
+                format!("Unknown grid_coordinates: {} (expected \"global\" or \"screen\")", other),
+            ))
+        }
+    };
+
+    let mut markers = Vec::new();
+    if let Some(points) = args.get("markers").and_then(|v| v.as_array()) {
+        if points.len() > MAX_OVERLAY_MARKERS {
+            return Err(ToolError::invalid_field(
+                "markers",
+                format!("At most {} markers", MAX_OVERLAY_MARKERS),
+            ));
+        }
+        for (i, point) in points.iter().enumerate() {
+            let coordinate = |name: &str| {
+                point
+                    .get(name)
+                    .and_then(|v| v.as_f64())
+                    .ok_or_else(|| ToolError::invalid_field("markers", format!("markers[{}].{} must be a number", i, name)))
+            };
+            let label = point.get("label").and_then(|v| v.as_str()).map(|s| s.to_string());
+            if label.as_ref().is_some_and(|label| label.chars().count() > MAX_MARKER_LABEL) {
+                return Err(ToolError::invalid_field(
+                    "markers",
+                    format!("markers[{}].label is over {} characters", i, MAX_MARKER_LABEL),
+                ));
+            }
+            markers.push(OverlayPoint {
+                x: coordinate("x")?,
+                y: coordinate("y")?,
+                label,
+            });
+        }
+    }
+
+    if !grid && markers.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(Overlay {
+        grid,
+        grid_spacing,
+        markers,
+        screen_relative,
+    }))
+}
+
+/// Largest zoom factor.
+const MAX_ZOOM: f64 = 8.0;
+
+/// Parse the max_width/max_height/quality/format/lossless/max_bytes/zoom/
+/// filter/sharpen arguments shared by screen_capture and estimate_capture.
+fn parse_encode_options(args: &Value) -> Result<EncodeOptions, String> {
+    let max_width = args
+        .get("max_width")
+        .and_then(|v| v.as_u64())
+        .unwrap_or(DEFAULT_MAX_WIDTH as u64) as u32;
+    let max_height = args
+        .get("max_height")
+        .and_then(|v| v.as_u64())
+        .unwrap_or(DEFAULT_MAX_HEIGHT as u64) as u32;
+    let quality = args.get("quality").and_then(|v| v.as_u64()).unwrap_or(80) as u8;
+    let lossless = args
+        .get("lossless")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false);
+    let format = args
+        .get("format")
+        .and_then(|v| v.as_str())
+        .map(|f| CaptureFormat::parse(f, lossless))
+        .transpose()?
+        .unwrap_or(CaptureFormat::Jpeg);
+    let max_bytes = args
+        .get("max_bytes")
+        .and_then(|v| v.as_u64())
+        .map(|v| v as usize);
+    let zoom = args.get("zoom").and_then(|v| v.as_f64()).unwrap_or(1.0);
+    if !(1.0..=MAX_ZOOM).contains(&zoom) {
+        return Err(format!("zoom must be between 1 and {} (got {})", MAX_ZOOM, zoom));
+    }
+    let filter = match args.get("filter").and_then(|v| v.as_str()) {
+        None => DownscaleFilter::Lanczos3,
+        Some(name) => DownscaleFilter::parse(name).ok_or_else(|| {
+            format!(
+                "Unknown filter: {} (expected one of {})",
+                name,
+                DownscaleFilter::NAMES.join(", ")
+            )
+        })?,
+    };
+    let sharpen = args.get("sharpen").and_then(|v| v.as_bool()).unwrap_or(false);
+    let zoom_filter = match args.get("zoom_filter").and_then(|v| v.as_str()) {
+        None => ZoomFilter::Nearest,
+        Some(name) => ZoomFilter::parse(name).ok_or_else(|| {
+            format!("Unknown zoom_filter: {} (expected \"nearest\" or \"lanczos\")", name)
+        })?,
+    };
+
+    Ok(EncodeOptions {
+        max_width,
+        max_height,
+        quality,
+        format,
+        max_bytes,
+        zoom,
+        zoom_filter,
+        filter,
+        sharpen,
+    })
+}
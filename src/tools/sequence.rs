@@ -0,0 +1,224 @@
+//! Running several input actions as one call.
+
+use serde_json::{json, Value};
+use std::time::{Duration, Instant};
+
+use crate::cancel::{is_cancelled, sleep_cancellable};
+use crate::clock::Timestamp;
+use crate::input::physical::release_held_input;
+use crate::tools::output::{ContentItem, ToolError, ToolOutput};
+use crate::tools::timeout::own_timeout;
+use crate::tools::{run_tool, Tool};
+
+pub(crate) struct RunSequence;
+
+impl Tool for RunSequence {
+    fn name(&self) -> &str {
+        "run_sequence"
+    }
+
+    fn description(&self) -> &str {
+        "Run several tool calls in order in one request, e.g. move, click, wait, type, capture. Each step behaves exactly as if called directly. Returns a summary with each step's result; images from steps (such as screen_capture) follow it in order, referenced from the step by number. Stops at the first failed step unless continue_on_error, and releases any keys or buttons left held if it stops early."
+    }
+
+    fn schema(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "steps": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "tool": { "type": "string", "description": "Tool name (anything but run_sequence)" },
+                            "arguments": { "type": "object", "description": "The tool's arguments" },
+                            "delay_after_ms": {
+                                "type": "integer",
+                                "description": "Pause after this step (default: 0, max: 10000)",
+                                "default": 0,
+                                "minimum": 0,
+                                "maximum": MAX_SEQUENCE_DELAY_MS
+                            }
+                        },
+                        "required": ["tool"]
+                    },
+                    "description": "Tool calls to run in order (max 50)",
+                    "minItems": 1,
+                    "maxItems": MAX_SEQUENCE_STEPS
+                },
+                "continue_on_error": {
+                    "type": "boolean",
+                    "description": "Keep going after a failed step (default: false)",
+                    "default": false
+                },
+                "timeout_ms": {
+                    "type": "integer",
+                    "description": "Overall time limit, checked between steps (default: 60000, max: 600000)",
+                    "default": 60000
+                }
+            },
+            "required": ["steps"]
+        })
+    }
+
+    /// Its steps can send input.
+    fn destructive(&self) -> bool {
+        true
+    }
+
+    fn timeout(&self, args: &Value) -> Option<Duration> {
+        own_timeout(args, DEFAULT_SEQUENCE_TIMEOUT_MS, MAX_SEQUENCE_TIMEOUT_MS)
+    }
+
+    fn call(&self, args: &Value) -> Result<ToolOutput, ToolError> {
+        run_sequence(args)
+    }
+}
+
+/// Upper bound on the number of steps in a run_sequence.
+const MAX_SEQUENCE_STEPS: usize = 50;
+
+/// Default and upper bound for run_sequence's overall timeout_ms.
+const DEFAULT_SEQUENCE_TIMEOUT_MS: u64 = 60_000;
+const MAX_SEQUENCE_TIMEOUT_MS: u64 = 600_000;
+
+/// Upper bound on a step's delay_after_ms.
+const MAX_SEQUENCE_DELAY_MS: u64 = 10_000;
+
+/// Run a list of tool calls in order, each through run_tool exactly as if
+/// it had been called directly, so availability, the self-target guard,
+/// approval and cursor restoration all apply per step. Stops at the first
+/// failed step unless `continue_on_error`, and at the overall timeout,
+/// which is checked between steps. If the sequence stops early, any keys
+/// or buttons still held are released.
+///
+/// Each step's JSON and text content is collected into its entry in the
+/// summary; images are appended to the result in order and referenced from
+/// the entry by their position among the sequence's images.
+fn run_sequence(args: &Value) -> Result<ToolOutput, ToolError> {
+    let steps = args
+        .get("steps")
+        .and_then(|v| v.as_array())
+        .ok_or_else(|| ToolError::missing("steps"))?;
+    if steps.is_empty() {
+        return Err(ToolError::invalid_field("steps", "steps is empty"));
+    }
+    if steps.len() > MAX_SEQUENCE_STEPS {
+        return Err(ToolError::invalid_field(
+            "steps",
+            format!("steps has {} steps (max {})", steps.len(), MAX_SEQUENCE_STEPS),
+        ));
+    }
+    let steps = steps
+        .iter()
+        .enumerate()
+        .map(|(i, step)| {
+            let tool = step
+                .get("tool")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| ToolError::invalid_field("steps", format!("steps[{}]: missing tool", i)))?;
+            if tool == "run_sequence" {
+                return Err(ToolError::invalid_field(
+                    "steps",
+                    format!("steps[{}]: run_sequence can't be nested", i),
+                ));
+            }
+            let arguments = step.get("arguments").cloned().unwrap_or(json!({}));
+            let delay_after_ms = step
+                .get("delay_after_ms")
+                .and_then(|v| v.as_u64())
+                .unwrap_or(0)
+                .min(MAX_SEQUENCE_DELAY_MS);
+            Ok((tool, arguments, delay_after_ms))
+        })
+        .collect::<Result<Vec<(&str, Value, u64)>, ToolError>>()?;
+    let continue_on_error = args
+        .get("continue_on_error")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false);
+    let timeout = Duration::from_millis(
+        args.get("timeout_ms")
+            .and_then(|v| v.as_u64())
+            .unwrap_or(DEFAULT_SEQUENCE_TIMEOUT_MS)
+            .min(MAX_SEQUENCE_TIMEOUT_MS),
+    );
+
+    let start = Instant::now();
+    let mut output = ToolOutput::default();
+    let mut results = Vec::new();
+    let mut images = 0;
+    let mut failed = 0;
+    let mut stopped = false;
+    let mut timed_out = false;
+    for (i, (tool, arguments, delay_after_ms)) in steps.iter().enumerate() {
+        if is_cancelled() {
+            stopped = true;
+            break;
+        }
+        if start.elapsed() >= timeout {
+            timed_out = true;
+            stopped = true;
+            break;
+        }
+
+        let step_start = Instant::now();
+        // A step's unknown tool or bad arguments fail the step rather than
+        // the whole request
+        let step = run_tool(tool, arguments).unwrap_or_else(|e| ToolOutput::failure(&e));
+        let mut content = Vec::new();
+        for item in step.content {
+            match item {
+                ContentItem::Json(value) => content.push(value),
+                ContentItem::Text(text) => content.push(json!(text)),
+                ContentItem::Image { .. } => {
+                    content.push(json!({ "image": images }));
+                    images += 1;
+                    output.content.push(item);
+                }
+            }
+        }
+        let mut result = json!({
+            "index": i,
+            "tool": tool,
+            "is_error": step.is_error,
+            "duration_ms": step_start.elapsed().as_millis() as u64,
+            "taken_at": step.timestamp.map(Timestamp::to_json),
+            "content": content
+        });
+        if step.retries > 0 {
+            result["retries"] = json!(step.retries);
+        }
+        results.push(result);
+
+        if step.is_error {
+            failed += 1;
+            if !continue_on_error {
+                stopped = true;
+                break;
+            }
+        }
+        if *delay_after_ms > 0 && i + 1 < steps.len() {
+            let remaining = timeout.saturating_sub(start.elapsed());
+            // A cancellation is picked up at the top of the next step
+            let _ = sleep_cancellable(Duration::from_millis(*delay_after_ms).min(remaining));
+        }
+    }
+
+    let mut summary = json!({
+        "success": failed == 0 && !timed_out && !is_cancelled(),
+        "steps": steps.len(),
+        "completed": results.len(),
+        "failed": failed,
+        "timed_out": timed_out,
+        "cancelled": is_cancelled(),
+        "duration_ms": start.elapsed().as_millis() as u64,
+        "images": images,
+        "results": results
+    });
+    if stopped {
+        summary["released"] = json!(release_held_input());
+    }
+    output.content.insert(0, ContentItem::Json(summary));
+    output.is_error = stopped;
+    Ok(output)
+}
@@ -0,0 +1,570 @@
+//! Tools that report on the server itself and the system it runs on.
+
+use rdev::EventType;
+use serde_json::{json, Value};
+use std::time::{Duration, Instant};
+
+use crate::audit::audit_tail;
+use crate::backend::{displays, refresh_displays};
+use crate::cancel::sleep_cancellable;
+use crate::capture::{capture_screen_area, desktop_bounds};
+use crate::clock::{format_rfc3339, server_start, Timestamp};
+use crate::config::effective_config;
+use crate::input::failsafe::{failsafe_triggered, reset_failsafe};
+use crate::input::physical::send_event;
+use crate::protocol::protocol_stats;
+use crate::system_info::{hostname, is_session_locked, os_version, session_type, system_uptime, user_name};
+use crate::tools::availability::{permission_granted, request_permission, Permission};
+use crate::tools::cursor::{cursor_position, lock_input};
+use crate::tools::output::{ToolError, ToolOutput};
+use crate::tools::timeout::own_timeout;
+use crate::tools::{Tool, BUILTIN_TOOLS};
+use crate::wayland::{display_session, input_unavailable_reason};
+
+/// Upper bound on how long wait_for_unlock may block.
+const MAX_UNLOCK_TIMEOUT_MS: u64 = 120_000;
+const MIN_UNLOCK_POLL_INTERVAL_MS: u64 = 100;
+
+/// Upper bound on a sleep.
+const MAX_SLEEP_MS: u64 = 10_000;
+
+pub(crate) struct ServerStatus;
+
+impl Tool for ServerStatus {
+    fn name(&self) -> &str {
+        "server_status"
+    }
+
+    fn description(&self) -> &str {
+        "Get the server version, start time, uptime and clock sources. Every tool result carries _meta.timestamp (RFC 3339, UTC) and _meta.monotonic_ms (since server start), taken when the call's first input event was emitted."
+    }
+
+    fn schema(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {}
+        })
+    }
+
+    fn read_only(&self) -> bool {
+        true
+    }
+
+    fn call(&self, _args: &Value) -> Result<ToolOutput, ToolError> {
+        Ok(ToolOutput::json(&server_status()))
+    }
+}
+
+pub(crate) struct ProtocolStats;
+
+impl Tool for ProtocolStats {
+    fn name(&self) -> &str {
+        "protocol_stats"
+    }
+
+    fn description(&self) -> &str {
+        "Count the requests handled since the server started, by method and, for tools/call, by tool: how many, how many were answered with a JSON-RPC error, how many tool calls failed (isError), and the error rate counting both. rejected counts messages that weren't valid JSON-RPC. The call itself is counted once it's answered."
+    }
+
+    fn schema(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {}
+        })
+    }
+
+    fn output_schema(&self) -> Option<Value> {
+        let counts = json!({
+            "type": "object",
+            "properties": {
+                "count": { "type": "integer", "minimum": 0 },
+                "errors": { "type": "integer", "minimum": 0 },
+                "failed": { "type": "integer", "minimum": 0 },
+                "error_rate": { "type": "number", "minimum": 0, "maximum": 1 }
+            },
+            "required": ["count", "errors", "failed", "error_rate"],
+            "additionalProperties": false
+        });
+        Some(json!({
+            "type": "object",
+            "properties": {
+                "started_at": { "type": "string" },
+                "total": counts,
+                "rejected": { "type": "integer", "minimum": 0 },
+                "methods": { "type": "object", "additionalProperties": counts },
+                "tools": { "type": "object", "additionalProperties": counts }
+            },
+            "required": ["started_at", "total", "rejected", "methods", "tools"],
+            "additionalProperties": false
+        }))
+    }
+
+    fn read_only(&self) -> bool {
+        true
+    }
+
+    fn call(&self, _args: &Value) -> Result<ToolOutput, ToolError> {
+        Ok(ToolOutput::json(&protocol_stats()))
+    }
+}
+
+pub(crate) struct GetSystemInfo;
+
+impl Tool for GetSystemInfo {
+    fn name(&self) -> &str {
+        "get_system_info"
+    }
+
+    fn description(&self) -> &str {
+        "Get the environment: OS, version and architecture, hostname, user, session type (aqua, x11, wayland, console or rdp), screen count and the combined desktop bounds, system uptime, and whether the screen is locked or the screensaver is running. Input tools are refused while it is; wait_for_unlock waits it out. Fields the platform can't report are null."
+    }
+
+    fn schema(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {}
+        })
+    }
+
+    fn output_schema(&self) -> Option<Value> {
+        Some(json!({
+            "type": "object",
+            "properties": {
+                "os": { "type": "string" },
+                "os_version": { "type": ["string", "null"] },
+                "arch": { "type": "string" },
+                "hostname": { "type": ["string", "null"] },
+                "user": { "type": ["string", "null"] },
+                "session_type": { "type": ["string", "null"] },
+                "screen_count": { "type": ["integer", "null"], "minimum": 0 },
+                "desktop": {
+                    "type": ["object", "null"],
+                    "properties": {
+                        "x": { "type": "integer" },
+                        "y": { "type": "integer" },
+                        "width": { "type": "integer", "minimum": 0 },
+                        "height": { "type": "integer", "minimum": 0 }
+                    },
+                    "required": ["x", "y", "width", "height"],
+                    "additionalProperties": false
+                },
+                "uptime_secs": { "type": ["integer", "null"], "minimum": 0 },
+                "screen_locked": { "type": ["boolean", "null"] }
+            },
+            "required": [
+                "os", "os_version", "arch", "hostname", "user", "session_type", "screen_count", "desktop",
+                "uptime_secs", "screen_locked"
+            ],
+            "additionalProperties": false
+        }))
+    }
+
+    fn read_only(&self) -> bool {
+        true
+    }
+
+    fn call(&self, _args: &Value) -> Result<ToolOutput, ToolError> {
+        let screens = displays().ok();
+        let desktop = screens.as_ref().filter(|s| !s.is_empty()).map(|screens| {
+            let (left, top, right, bottom) = desktop_bounds(screens);
+            json!({ "x": left, "y": top, "width": right - left, "height": bottom - top })
+        });
+        Ok(ToolOutput::json(&json!({
+            "os": std::env::consts::OS,
+            "os_version": os_version(),
+            "arch": std::env::consts::ARCH,
+            "hostname": hostname(),
+            "user": user_name(),
+            "session_type": session_type(),
+            "screen_count": screens.as_ref().map(Vec::len),
+            "desktop": desktop,
+            "uptime_secs": system_uptime().map(|uptime| uptime.as_secs()),
+            "screen_locked": is_session_locked()
+        })))
+    }
+}
+
+pub(crate) struct WaitForUnlock;
+
+impl Tool for WaitForUnlock {
+    fn name(&self) -> &str {
+        "wait_for_unlock"
+    }
+
+    fn description(&self) -> &str {
+        "Wait for the user to unlock the session and dismiss the screensaver, for when an input tool failed with session_locked. Returns at once if it's already unlocked; otherwise blocks until it is, the call is cancelled or the timeout passes. Reports unlocked, waited_ms and timed_out."
+    }
+
+    fn schema(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "timeout_ms": {
+                    "type": "integer",
+                    "description": "How long to wait in milliseconds (default: 60000, max: 120000)",
+                    "default": 60000,
+                    "maximum": 120000
+                },
+                "poll_interval_ms": {
+                    "type": "integer",
+                    "description": "How often to check in milliseconds (default: 1000, min: 100)",
+                    "default": 1000,
+                    "minimum": 100
+                }
+            }
+        })
+    }
+
+    fn read_only(&self) -> bool {
+        true
+    }
+
+    fn timeout(&self, args: &Value) -> Option<Duration> {
+        own_timeout(args, 60_000, MAX_UNLOCK_TIMEOUT_MS)
+    }
+
+    fn call(&self, args: &Value) -> Result<ToolOutput, ToolError> {
+        let timeout_ms = args
+            .get("timeout_ms")
+            .and_then(|v| v.as_u64())
+            .unwrap_or(60_000)
+            .min(MAX_UNLOCK_TIMEOUT_MS);
+        let poll_interval_ms = args
+            .get("poll_interval_ms")
+            .and_then(|v| v.as_u64())
+            .unwrap_or(1000)
+            .max(MIN_UNLOCK_POLL_INTERVAL_MS);
+        let timeout = Duration::from_millis(timeout_ms);
+
+        let start = Instant::now();
+        let mut was_locked = false;
+        loop {
+            let locked = is_session_locked().ok_or_else(|| {
+                ToolError::Unavailable("Can't tell whether the session is locked on this system".to_string())
+            })?;
+            let waited = start.elapsed();
+            if !locked || waited >= timeout {
+                if was_locked && !locked {
+                    log::info!("Session unlocked after {} ms", waited.as_millis());
+                }
+                return Ok(ToolOutput::json(&json!({
+                    "unlocked": !locked,
+                    "waited_ms": waited.as_millis() as u64,
+                    "timed_out": locked
+                })));
+            }
+            was_locked = true;
+            sleep_cancellable(Duration::from_millis(poll_interval_ms).min(timeout - waited))?;
+        }
+    }
+}
+
+pub(crate) struct Sleep;
+
+impl Tool for Sleep {
+    fn name(&self) -> &str {
+        "sleep"
+    }
+
+    fn description(&self) -> &str {
+        "Wait for a fixed time, e.g. for an animation or page load to finish before the next step of a run_sequence. Stops early if the call is cancelled. Reports the requested_ms and the waited_ms actually waited."
+    }
+
+    fn schema(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "duration_ms": {
+                    "type": "integer",
+                    "description": "How long to wait in milliseconds (max: 10000)",
+                    "minimum": 0,
+                    "maximum": MAX_SLEEP_MS
+                }
+            },
+            "required": ["duration_ms"]
+        })
+    }
+
+    fn read_only(&self) -> bool {
+        true
+    }
+
+    fn call(&self, args: &Value) -> Result<ToolOutput, ToolError> {
+        let duration_ms = args
+            .get("duration_ms")
+            .and_then(|v| v.as_u64())
+            .ok_or_else(|| ToolError::missing("duration_ms"))?
+            .min(MAX_SLEEP_MS);
+
+        let start = Instant::now();
+        sleep_cancellable(Duration::from_millis(duration_ms))?;
+        Ok(ToolOutput::json(&json!({
+            "requested_ms": duration_ms,
+            "waited_ms": start.elapsed().as_millis() as u64
+        })))
+    }
+}
+
+pub(crate) struct CheckPermissions;
+
+impl Tool for CheckPermissions {
+    fn name(&self) -> &str {
+        "check_permissions"
+    }
+
+    fn description(&self) -> &str {
+        "Report whether the macOS Accessibility (needed for input) and Screen Recording (needed for capture) permissions are granted, the System Settings pane to grant each in, and which tools need them. Pass prompt: true to show the system prompt for missing ones. On other platforms no permissions are needed."
+    }
+
+    fn schema(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "prompt": {
+                    "type": "boolean",
+                    "description": "Show the system prompt for each missing permission (default: false)",
+                    "default": false
+                }
+            }
+        })
+    }
+
+    fn read_only(&self) -> bool {
+        true
+    }
+
+    fn call(&self, args: &Value) -> Result<ToolOutput, ToolError> {
+        Ok(ToolOutput::json(&check_permissions(
+            args.get("prompt").and_then(|v| v.as_bool()).unwrap_or(false),
+        )))
+    }
+}
+
+pub(crate) struct SelfTest;
+
+impl Tool for SelfTest {
+    fn name(&self) -> &str {
+        "self_test"
+    }
+
+    fn description(&self) -> &str {
+        "Check that the server can do its job: enumerate screens, capture a 1x1 pixel and simulate input (on macOS, whether Accessibility and Screen Recording are granted). Returns {ok, screens_ok, capture_ok, input_ok, details}, with the error behind each failure. Run it first to find missing permissions before automating."
+    }
+
+    fn schema(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {}
+        })
+    }
+
+    fn call(&self, _args: &Value) -> Result<ToolOutput, ToolError> {
+        Ok(ToolOutput::json(&self_test()))
+    }
+}
+
+pub(crate) struct GetConfig;
+
+impl Tool for GetConfig {
+    fn name(&self) -> &str {
+        "get_config"
+    }
+
+    fn description(&self) -> &str {
+        "Get the defaults used when a call leaves an argument out: capture quality, max_width, max_height and format (screen_capture, estimate_capture), and input type_delay_ms (type_text), click_interval_ms (mouse_click), drag_duration_ms (mouse_drag) and keyboard_layout (type_text). from_config lists those set by the server's config file; safety shows the screens and tools it restricts to."
+    }
+
+    fn schema(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {}
+        })
+    }
+
+    fn read_only(&self) -> bool {
+        true
+    }
+
+    fn call(&self, _args: &Value) -> Result<ToolOutput, ToolError> {
+        Ok(ToolOutput::json(&effective_config()))
+    }
+}
+
+pub(crate) struct GetAuditTail;
+
+impl Tool for GetAuditTail {
+    fn name(&self) -> &str {
+        "get_audit_tail"
+    }
+
+    fn description(&self) -> &str {
+        "Get the last entries of the server's audit log, oldest first: one per tool call run, with its timestamp, tool, arguments, outcome and duration_ms. Useful for recovering what was done before a restart. Fails if the server was started without an audit log."
+    }
+
+    fn schema(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "count": {
+                    "type": "integer",
+                    "description": "Number of entries (default: 20, max: 1000)",
+                    "default": 20,
+                    "minimum": 1,
+                    "maximum": 1000
+                }
+            }
+        })
+    }
+
+    fn read_only(&self) -> bool {
+        true
+    }
+
+    fn call(&self, args: &Value) -> Result<ToolOutput, ToolError> {
+        let count = args.get("count").and_then(|v| v.as_u64()).unwrap_or(20) as usize;
+        let (path, entries) = audit_tail(count).map_err(ToolError::Unavailable)?;
+        Ok(ToolOutput::json(&json!({
+            "path": path.display().to_string(),
+            "entries": entries
+        })))
+    }
+}
+
+pub(crate) struct FailsafeReset;
+
+impl Tool for FailsafeReset {
+    fn name(&self) -> &str {
+        "failsafe_reset"
+    }
+
+    fn description(&self) -> &str {
+        "Allow input tools again after the user triggered the failsafe by holding the cursor in a screen corner (calls fail with failsafe_triggered until then). Only reset once the user wants automation to carry on. Returns {was_triggered}."
+    }
+
+    fn schema(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {}
+        })
+    }
+
+    fn idempotent(&self) -> bool {
+        true
+    }
+
+    fn call(&self, _args: &Value) -> Result<ToolOutput, ToolError> {
+        Ok(ToolOutput::json(&json!({ "was_triggered": reset_failsafe() })))
+    }
+}
+
+fn server_status() -> Value {
+    let (started_wall, started) = server_start();
+    json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "started_at": format_rfc3339(started_wall),
+        "uptime_ms": started.elapsed().as_millis() as u64,
+        "now": Timestamp::now().to_json(),
+        "failsafe_triggered": failsafe_triggered(),
+        "clock": {
+            "timestamp": "system wall clock, UTC",
+            "monotonic_ms": "monotonic clock, milliseconds since started_at",
+            "taken_at": "first OS input event of the call, or the start of execution if it emitted none"
+        }
+    })
+}
+
+/// Report each permission's status, where to grant it and which tools need
+/// it, optionally triggering the system prompts for missing ones first.
+fn check_permissions(prompt: bool) -> Value {
+    let permissions: Vec<Value> = Permission::ALL
+        .into_iter()
+        .map(|permission| {
+            let mut granted = permission_granted(permission);
+            let prompted = prompt && !granted;
+            if prompted {
+                granted = request_permission(permission);
+            }
+            let needed_by: Vec<&str> = BUILTIN_TOOLS
+                .iter()
+                .filter(|tool| tool.requirements().permissions.contains(&permission))
+                .map(|tool| tool.name())
+                .collect();
+            json!({
+                "name": permission.name(),
+                "granted": granted,
+                "prompted": prompted,
+                "settings_pane": permission.settings_pane(),
+                "needs_restart": permission.needs_restart(),
+                "needed_by": needed_by
+            })
+        })
+        .collect();
+
+    json!({
+        "platform": std::env::consts::OS,
+        "required": cfg!(target_os = "macos"),
+        "all_granted": permissions.iter().all(|p| p["granted"] == json!(true)),
+        "permissions": permissions
+    })
+}
+
+/// Check that the server can do its job: that screens can be enumerated,
+/// that a 1x1 area can be captured and that input can be simulated, by
+/// checking permissions and posting a zero-distance move to where the
+/// cursor already is.
+fn self_test() -> Value {
+    let mut details = json!({
+        "platform": std::env::consts::OS,
+        "session": display_session(),
+        "version": env!("CARGO_PKG_VERSION"),
+        "permissions": {
+            "accessibility": permission_granted(Permission::Accessibility),
+            "screen_recording": permission_granted(Permission::ScreenRecording)
+        }
+    });
+
+    let screens = refresh_displays();
+    let screens_ok = matches!(&screens, Ok(screens) if !screens.is_empty());
+    details["screens"] = match &screens {
+        Ok(screens) => json!({ "count": screens.len() }),
+        Err(e) => json!({ "error": e }),
+    };
+
+    let capture = screens.and_then(|screens| {
+        let screen = screens.first().ok_or("No screens found")?;
+        capture_screen_area(screen, (0, 0, 1, 1))
+    });
+    let capture_ok = capture.is_ok() && permission_granted(Permission::ScreenRecording);
+    details["capture"] = match &capture {
+        Ok(img) => json!({ "width": img.width(), "height": img.height() }),
+        Err(e) => json!({ "error": e }),
+    };
+    if capture.is_ok() && !capture_ok {
+        details["capture"]["warning"] =
+            json!("Capture works but without Screen Recording only the desktop background is visible");
+    }
+
+    let input = (|| {
+        if !permission_granted(Permission::Accessibility) {
+            return Err("Accessibility permission not granted".to_string());
+        }
+        if let Some(reason) = input_unavailable_reason() {
+            return Err(reason);
+        }
+        let (x, y) = cursor_position().ok_or("Cursor position unavailable")?;
+        let _input = lock_input();
+        send_event(&EventType::MouseMove { x, y }).map_err(|e| format!("Input simulation failed: {:?}", e))
+    })();
+    let input_ok = input.is_ok();
+    if let Err(e) = input {
+        details["input"] = json!({ "error": e });
+    }
+
+    json!({
+        "ok": screens_ok && capture_ok && input_ok,
+        "screens_ok": screens_ok,
+        "capture_ok": capture_ok,
+        "input_ok": input_ok,
+        "details": details
+    })
+}
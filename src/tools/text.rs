@@ -0,0 +1,469 @@
+//! Text editing tools, built from the mouse and keyboard primitives.
+
+use rdev::{Button, Key};
+use serde_json::{json, Value};
+use std::thread;
+use std::time::Duration;
+
+use crate::cancel::is_cancelled;
+use crate::capture::coords::{to_global, CoordinateSpace};
+use crate::input::layout::{active_layout, LAYOUTS};
+use crate::input::physical::THROTTLED;
+use crate::input::{
+    do_mouse_click, do_mouse_drag_path, do_paste_text, do_type_text, first_untypable, resolve_key, shortcut_modifier,
+    tap_key, tap_key_repeated, ClickOptions, TypingRhythm,
+};
+use crate::tools::availability::{Permission, ToolRequirements};
+use crate::tools::guard::check_focused_window;
+use crate::tools::limits::dry_run;
+use crate::tools::output::{ContentItem, ToolError, ToolOutput};
+use crate::tools::timeout::DEFAULT_CALL_TIMEOUT_MS;
+use crate::tools::Tool;
+
+/// Upper bound on select_text's count.
+const MAX_SELECT_COUNT: u64 = 1_000;
+
+/// Upper bound on select_text's duration_ms.
+const MAX_SELECT_DRAG_MS: u64 = 5_000;
+
+/// Pause between select_text's shift+arrow taps.
+const SELECT_TAP_DELAY: Duration = Duration::from_millis(10);
+
+/// Pause after placing the caret, so the click is handled before the keys.
+const CARET_SETTLE: Duration = Duration::from_millis(50);
+
+/// Events a single click sends: the move there, the press and the release.
+const CLICK_EVENTS: u64 = 3;
+
+/// Upper bound on set_text_field's delay_ms.
+const MAX_FIELD_TYPE_DELAY_MS: u64 = 1_000;
+
+/// How long set_text_field's paste waits for the app to read the clipboard
+/// before restoring it.
+const FIELD_PASTE_SETTLE: Duration = Duration::from_millis(200);
+
+/// A point given as `x_field` and `y_field`, made global, or None if
+/// neither is given.
+fn optional_point(args: &Value, x_field: &str, y_field: &str) -> Result<Option<(f64, f64)>, ToolError> {
+    match (
+        args.get(x_field).and_then(|v| v.as_f64()),
+        args.get(y_field).and_then(|v| v.as_f64()),
+    ) {
+        (Some(x), Some(y)) => {
+            let space = CoordinateSpace::from_args(args).map_err(ToolError::invalid)?;
+            Ok(Some(to_global(&space, (x, y))?))
+        }
+        (None, None) => Ok(None),
+        (Some(_), None) => Err(ToolError::missing(y_field)),
+        (None, Some(_)) => Err(ToolError::missing(x_field)),
+    }
+}
+
+/// Click once with the left button at `point`.
+fn click_at((x, y): (f64, f64), modifiers: &[String]) -> Result<(), String> {
+    do_mouse_click(Some(x), Some(y), Button::Left, ClickOptions::default(), modifiers)
+}
+
+/// The key and modifiers that extend a selection one step in `direction`,
+/// a character or, with `by_word`, a word at a time. Lines are selected to
+/// their start and end with Cmd+Shift+arrow on macOS, where Home and End
+/// scroll instead, and Shift+Home/End elsewhere.
+fn selection_keys(direction: &str, by_word: bool) -> Result<(Key, Vec<String>), ToolError> {
+    let macos = cfg!(target_os = "macos");
+    let mut modifiers = vec!["shift".to_string()];
+    let key = match direction {
+        "left" | "right" if by_word => {
+            modifiers.push(if macos { "alt" } else { "control" }.to_string());
+            if direction == "left" {
+                Key::LeftArrow
+            } else {
+                Key::RightArrow
+            }
+        }
+        "left" => Key::LeftArrow,
+        "right" => Key::RightArrow,
+        "up" => Key::UpArrow,
+        "down" => Key::DownArrow,
+        "line_start" if macos => {
+            modifiers.push("meta".to_string());
+            Key::LeftArrow
+        }
+        "line_end" if macos => {
+            modifiers.push("meta".to_string());
+            Key::RightArrow
+        }
+        "line_start" => Key::Home,
+        "line_end" => Key::End,
+        _ => {
+            return Err(ToolError::invalid_field(
+                "direction",
+                format!(
+                    "Unknown direction {:?}: expected left, right, up, down, line_start or line_end",
+                    direction
+                ),
+            ))
+        }
+    };
+    Ok((key, modifiers))
+}
+
+pub(crate) struct SelectText;
+
+impl Tool for SelectText {
+    fn name(&self) -> &str {
+        "select_text"
+    }
+
+    fn description(&self) -> &str {
+        "Select text, to copy it (key_tap c with meta on macOS, control elsewhere) or type over it. mode coordinates presses at x/y and drags to to_x/to_y, or with method shift_click clicks at x/y and shift-clicks at to_x/to_y. mode keyboard clicks at x/y if given to place the caret, then extends the selection count steps in direction with Shift held (by_word to move a word at a time). The result reports the mode and the number of input events sent; whether the app selected what was intended can only be checked with a screenshot or by copying."
+    }
+
+    fn schema(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "mode": {
+                    "type": "string",
+                    "enum": ["coordinates", "keyboard"],
+                    "description": "coordinates: select from x/y to to_x/to_y with the mouse. keyboard: extend the selection from the caret with shift+arrow keys."
+                },
+                "x": { "type": "number", "description": "Where the selection starts; required for coordinates, and in keyboard mode clicked first to place the caret" },
+                "y": { "type": "number", "description": "Where the selection starts; required for coordinates, and in keyboard mode clicked first to place the caret" },
+                "to_x": { "type": "number", "description": "With coordinates, where the selection ends" },
+                "to_y": { "type": "number", "description": "With coordinates, where the selection ends" },
+                "method": {
+                    "type": "string",
+                    "enum": ["drag", "shift_click"],
+                    "description": "With coordinates, drag from start to end (default), or click the start and shift-click the end, which some editors handle more reliably",
+                    "default": "drag"
+                },
+                "duration_ms": {
+                    "type": "integer",
+                    "description": format!("With the drag method, how long the drag takes (default: 300, max: {})", MAX_SELECT_DRAG_MS),
+                    "default": 300,
+                    "minimum": 0,
+                    "maximum": MAX_SELECT_DRAG_MS
+                },
+                "direction": {
+                    "type": "string",
+                    "enum": ["left", "right", "up", "down", "line_start", "line_end"],
+                    "description": "With keyboard, which way to extend the selection; line_start and line_end select to the start or end of the line"
+                },
+                "count": {
+                    "type": "integer",
+                    "description": format!("With keyboard, how many steps to extend the selection by (default: 1, max: {})", MAX_SELECT_COUNT),
+                    "default": 1,
+                    "minimum": 1,
+                    "maximum": MAX_SELECT_COUNT
+                },
+                "by_word": {
+                    "type": "boolean",
+                    "description": "With keyboard and left or right, step a word at a time instead of a character (default: false)",
+                    "default": false
+                }
+            },
+            "required": ["mode"]
+        })
+    }
+
+    fn requirements(&self) -> ToolRequirements {
+        ToolRequirements {
+            permissions: &[Permission::Accessibility],
+            ..Default::default()
+        }
+    }
+
+    fn sends_input(&self) -> bool {
+        true
+    }
+
+    fn screen_relative(&self) -> bool {
+        true
+    }
+
+    fn call(&self, args: &Value) -> Result<ToolOutput, ToolError> {
+        let mode = args
+            .get("mode")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| ToolError::missing("mode"))?;
+        let start = optional_point(args, "x", "y")?;
+
+        match mode {
+            "coordinates" => {
+                let start = start.ok_or_else(|| ToolError::missing("x"))?;
+                let end = optional_point(args, "to_x", "to_y")?.ok_or_else(|| ToolError::missing("to_x"))?;
+                let method = args.get("method").and_then(|v| v.as_str()).unwrap_or("drag");
+                let events = match method {
+                    "drag" => {
+                        let duration_ms = args
+                            .get("duration_ms")
+                            .and_then(|v| v.as_u64())
+                            .unwrap_or(300)
+                            .min(MAX_SELECT_DRAG_MS);
+                        let moves = do_mouse_drag_path(&[start, end], &[duration_ms], Button::Left, &[])?;
+                        // The move to the start, the press, the moves and the release
+                        moves as u64 + 3
+                    }
+                    "shift_click" => {
+                        click_at(start, &[])?;
+                        click_at(end, &["shift".to_string()])?;
+                        // The shift-click adds the shift press and release
+                        CLICK_EVENTS * 2 + 2
+                    }
+                    _ => {
+                        return Err(ToolError::invalid_field(
+                            "method",
+                            format!("Unknown method {:?}: expected drag or shift_click", method),
+                        ))
+                    }
+                };
+                Ok(ToolOutput::json(&json!({
+                    "success": true,
+                    "mode": mode,
+                    "method": method,
+                    "from": { "x": start.0, "y": start.1 },
+                    "to": { "x": end.0, "y": end.1 },
+                    "events": events
+                })))
+            }
+            "keyboard" => {
+                let direction = args
+                    .get("direction")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| ToolError::missing("direction"))?;
+                let count = args
+                    .get("count")
+                    .and_then(|v| v.as_u64())
+                    .unwrap_or(1)
+                    .clamp(1, MAX_SELECT_COUNT);
+                let by_word = args.get("by_word").and_then(|v| v.as_bool()).unwrap_or(false);
+                let (key, modifiers) = selection_keys(direction, by_word)?;
+
+                let mut events = 0;
+                if let Some(start) = start {
+                    click_at(start, &[])?;
+                    events += CLICK_EVENTS;
+                    thread::sleep(CARET_SETTLE);
+                }
+                let taps = tap_key_repeated(key, &modifiers, count, SELECT_TAP_DELAY)?;
+                // A press and release per tap, and per modifier held around them
+                events += taps * 2 + modifiers.len() as u64 * 2;
+                let mut result = json!({
+                    "success": true,
+                    "mode": mode,
+                    "direction": direction,
+                    "by_word": by_word,
+                    "count": taps,
+                    "modifiers": modifiers,
+                    "events": events
+                });
+                if let Some((x, y)) = start {
+                    result["clicked"] = json!({ "x": x, "y": y });
+                }
+                Ok(ToolOutput::json(&result))
+            }
+            _ => Err(ToolError::invalid_field(
+                "mode",
+                format!("Unknown mode {:?}: expected coordinates or keyboard", mode),
+            )),
+        }
+    }
+}
+
+pub(crate) struct SetTextField;
+
+impl Tool for SetTextField {
+    fn name(&self) -> &str {
+        "set_text_field"
+    }
+
+    fn description(&self) -> &str {
+        "Replace the contents of a text field in one call: click it at x/y if given, select all (Cmd+A on macOS, Ctrl+A elsewhere), delete the selection, then type or paste text, optionally pressing tab or enter afterwards. An empty text just clears the field. Typing fails before touching the field if a character has no key on the layout. The result lists the steps taken; if one fails, the error lists the steps completed before it and the one that failed, so the field's state is known."
+    }
+
+    fn schema(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "text": {
+                    "type": "string",
+                    "description": "The field's new contents"
+                },
+                "x": { "type": "number", "description": "X coordinate to click first, to focus the field" },
+                "y": { "type": "number", "description": "Y coordinate to click first, to focus the field" },
+                "method": {
+                    "type": "string",
+                    "enum": ["type", "paste"],
+                    "description": "type the text key by key (default), or paste it through the clipboard as paste_text does, which is exact for any text but needs macOS",
+                    "default": "type"
+                },
+                "delay_ms": {
+                    "type": "integer",
+                    "description": format!("With type, delay between keystrokes in milliseconds (default: 20, max: {})", MAX_FIELD_TYPE_DELAY_MS),
+                    "default": 20,
+                    "minimum": 0,
+                    "maximum": MAX_FIELD_TYPE_DELAY_MS
+                },
+                "layout": {
+                    "type": "string",
+                    "enum": LAYOUTS.iter().map(|l| l.name).collect::<Vec<_>>(),
+                    "description": "With type, keyboard layout to type for, overriding detection"
+                },
+                "then": {
+                    "type": "string",
+                    "enum": ["tab", "enter"],
+                    "description": "Key to press afterwards: tab to move to the next field, or enter to submit"
+                },
+                "require_focused_window": {
+                    "type": "string",
+                    "description": "Only send input if the focused window's title or app name contains this (case-insensitive); otherwise fail with permission_denied and send nothing"
+                }
+            },
+            "required": ["text"]
+        })
+    }
+
+    fn requirements(&self) -> ToolRequirements {
+        ToolRequirements {
+            permissions: &[Permission::Accessibility],
+            ..Default::default()
+        }
+    }
+
+    fn sends_input(&self) -> bool {
+        true
+    }
+
+    fn screen_relative(&self) -> bool {
+        true
+    }
+
+    /// The usual allowance plus, when typing, the time spent waiting between
+    /// characters.
+    fn timeout(&self, args: &Value) -> Option<Duration> {
+        if args.get("method").and_then(|v| v.as_str()) == Some("paste") {
+            return None;
+        }
+        let chars = args.get("text").and_then(|v| v.as_str())?.chars().count() as u64;
+        let delay_ms = args.get("delay_ms").and_then(|v| v.as_u64()).unwrap_or(20);
+        Some(Duration::from_millis(DEFAULT_CALL_TIMEOUT_MS.saturating_add(chars.saturating_mul(delay_ms))))
+    }
+
+    fn call(&self, args: &Value) -> Result<ToolOutput, ToolError> {
+        let text = args
+            .get("text")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| ToolError::missing("text"))?;
+        let click = optional_point(args, "x", "y")?;
+        let method = args.get("method").and_then(|v| v.as_str()).unwrap_or("type");
+        let delay_ms = args
+            .get("delay_ms")
+            .and_then(|v| v.as_u64())
+            .unwrap_or(20)
+            .min(MAX_FIELD_TYPE_DELAY_MS);
+        let then = match args.get("then").and_then(|v| v.as_str()) {
+            None => None,
+            Some("tab") => Some(("tab", Key::Tab)),
+            Some("enter") => Some(("enter", Key::Return)),
+            Some(other) => {
+                return Err(ToolError::invalid_field(
+                    "then",
+                    format!("Unknown key {:?}: expected tab or enter", other),
+                ))
+            }
+        };
+        // Everything is checked before the field is touched, so a bad
+        // argument can't leave it cleared
+        let layout = match method {
+            "type" => Some(
+                active_layout(args.get("layout").and_then(|v| v.as_str()))
+                    .map_err(|e| ToolError::invalid_field("layout", e))?,
+            ),
+            "paste" if cfg!(target_os = "macos") => None,
+            "paste" => {
+                return Err(ToolError::Unavailable(
+                    "Pasting needs clipboard access, which is only supported on macOS; use method type".to_string(),
+                ))
+            }
+            _ => {
+                return Err(ToolError::invalid_field(
+                    "method",
+                    format!("Unknown method {:?}: expected type or paste", method),
+                ))
+            }
+        };
+        // Typing would skip characters without a key and leave the field
+        // holding something else, so refuse before clearing it
+        let untypable = layout.as_ref().and_then(|active| first_untypable(text, active.layout));
+        if let Some((index, c)) = untypable {
+            return Err(ToolError::invalid_field(
+                "text",
+                format!(
+                    "No key types {:?} (U+{:04X}) at index {}; the field wasn't touched. Use method paste instead.",
+                    c, c as u32, index
+                ),
+            ));
+        }
+        let select_all = resolve_key("a")?;
+        check_focused_window(args)?;
+
+        let mut steps: Vec<&str> = Vec::new();
+        let mut step = "";
+        let mut result = json!({ "method": method });
+        let outcome = (|| -> Result<(), String> {
+            if let Some(point) = click {
+                step = "click";
+                click_at(point, &[])?;
+                steps.push(step);
+                thread::sleep(CARET_SETTLE);
+            }
+            step = "select_all";
+            tap_key(select_all, &[shortcut_modifier().to_string()])?;
+            steps.push(step);
+            step = "delete";
+            tap_key(Key::Backspace, &[])?;
+            steps.push(step);
+            if !text.is_empty() {
+                step = method;
+                match &layout {
+                    Some(active) => {
+                        let typed = do_type_text(text, &TypingRhythm::fixed(delay_ms), active.layout)?.to_json(text);
+                        result["chars"] = typed["chars"].clone();
+                        result["layout"] = json!(active.layout.name);
+                    }
+                    None => {
+                        let restore_error = do_paste_text(text, FIELD_PASTE_SETTLE, true)?;
+                        result["chars"] = json!(text.chars().count());
+                        result["clipboard_restored"] = json!(restore_error.is_none() && !dry_run());
+                        if let Some(e) = restore_error {
+                            log::warn!("set_text_field couldn't restore the clipboard: {}", e);
+                            result["restore_error"] = json!(e);
+                        }
+                    }
+                }
+                steps.push(step);
+            }
+            if let Some((name, key)) = then {
+                step = name;
+                tap_key(key, &[])?;
+                steps.push(step);
+            }
+            Ok(())
+        })();
+
+        if let Err(e) = outcome {
+            // Let the pipeline answer cancellation, the failsafe and throttling
+            if is_cancelled() || THROTTLED.get() {
+                return Err(e.into());
+            }
+            let error = ToolError::Platform(format!("set_text_field failed at {}: {}", step, e));
+            return Ok(ToolOutput::failure(&error).with(ContentItem::Json(json!({
+                "completed_steps": steps,
+                "failed_step": step
+            }))));
+        }
+        result["success"] = json!(true);
+        result["steps"] = json!(steps);
+        Ok(ToolOutput::json(&result))
+    }
+}
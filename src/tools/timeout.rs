@@ -0,0 +1,131 @@
+//! Giving up on tool calls that run too long, such as a capture stuck in
+//! the OS while the display sleeps, so one call can't wedge the server.
+
+use serde_json::{json, Value};
+use std::cell::Cell;
+use std::sync::atomic::Ordering;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+
+use crate::cancel::{cancel_flag, enter_cancel_flag};
+use crate::config::loaded_config;
+use crate::http::{call_stream, enter_call_stream};
+use crate::input::physical::release_held_input;
+use crate::session::{current_session, enter_session};
+use crate::tools::cursor::execute_restoring_cursor;
+use crate::tools::output::{ContentItem, ToolError, ToolOutput};
+use crate::tools::{server_arguments, Tool};
+
+/// How long a call may run, for tools that don't declare their own and
+/// when the config file doesn't say.
+pub(crate) const DEFAULT_CALL_TIMEOUT_MS: u64 = 30_000;
+
+/// Upper bound on a call's `timeout_ms`.
+pub(crate) const MAX_CALL_TIMEOUT_MS: u64 = 600_000;
+
+/// Added to the time a tool says it will wait, for the work around the
+/// wait.
+pub(crate) const TIMEOUT_GRACE: Duration = Duration::from_secs(10);
+
+thread_local! {
+    /// Whether this thread is running a call under its timeout. Calls made
+    /// from it, i.e. run_sequence's steps, run inline under the same one.
+    static ON_CALL_THREAD: Cell<bool> = const { Cell::new(false) };
+}
+
+/// The timeout of a tool that waits up to its own `timeout_ms` argument
+/// (default `default_ms`, at most `max_ms`): that wait plus a grace period.
+pub(crate) fn own_timeout(args: &Value, default_ms: u64, max_ms: u64) -> Option<Duration> {
+    let ms = args
+        .get("timeout_ms")
+        .and_then(|v| v.as_u64())
+        .unwrap_or(default_ms)
+        .min(max_ms);
+    Some(Duration::from_millis(ms) + TIMEOUT_GRACE)
+}
+
+/// The timeout of a tool that takes `duration_ms` (at most `max_ms`) to run:
+/// that plus a grace period.
+pub(crate) fn duration_timeout(args: &Value, max_ms: u64) -> Option<Duration> {
+    let ms = args.get("duration_ms").and_then(|v| v.as_u64())?.min(max_ms);
+    Some(Duration::from_millis(ms) + TIMEOUT_GRACE)
+}
+
+/// How long this call may run: the call's `timeout_ms` if the tool doesn't
+/// have one of its own, else the config file's for the tool, else the
+/// tool's own, else the config file's default, else 30 seconds.
+fn call_timeout(tool: &dyn Tool, args: &Value) -> Duration {
+    if let Some(ms) = server_arguments(tool, args).get("timeout_ms").and_then(|v| v.as_u64()) {
+        return Duration::from_millis(ms.min(MAX_CALL_TIMEOUT_MS));
+    }
+    let config = &loaded_config().config.timeouts;
+    if let Some(&ms) = config.tools.get(tool.name()) {
+        return Duration::from_millis(ms);
+    }
+    tool.timeout(args)
+        .unwrap_or_else(|| Duration::from_millis(config.default_ms.unwrap_or(DEFAULT_CALL_TIMEOUT_MS)))
+}
+
+/// Run a tool on a thread of its own and wait for it up to its timeout. A
+/// call that takes longer is answered with `timed_out` and flagged as
+/// cancelled, so it stops at its next cancellation check; whatever it
+/// returns later is discarded. Keys and buttons it left held are released
+/// both when it times out and when it finally returns, in case it pressed
+/// more in between.
+pub(crate) fn execute_with_timeout(tool: &'static dyn Tool, args: &Value) -> Result<ToolOutput, ToolError> {
+    if ON_CALL_THREAD.get() {
+        return execute_restoring_cursor(tool, args);
+    }
+    let timeout = call_timeout(tool, args);
+    let session = current_session();
+    // A call that isn't cancellable otherwise still needs a flag to stop it
+    let cancel = cancel_flag().unwrap_or_default();
+    let stream = call_stream();
+    let (sender, receiver) = mpsc::channel();
+    let worker = {
+        let args = args.clone();
+        let cancel = cancel.clone();
+        thread::spawn(move || {
+            ON_CALL_THREAD.set(true);
+            enter_session(session);
+            enter_cancel_flag(Some(cancel));
+            enter_call_stream(stream);
+            let output = execute_restoring_cursor(tool, &args);
+            if sender.send(output).is_err() {
+                let released = release_held_input();
+                log::warn!(
+                    "{} finished after timing out; discarded its result{}",
+                    tool.name(),
+                    if released.is_empty() {
+                        String::new()
+                    } else {
+                        format!(" and released {}", released.join(", "))
+                    }
+                );
+            }
+        })
+    };
+
+    match receiver.recv_timeout(timeout) {
+        Ok(output) => output,
+        Err(RecvTimeoutError::Timeout) => {
+            cancel.store(true, Ordering::SeqCst);
+            log::warn!("{} timed out after {} ms", tool.name(), timeout.as_millis());
+            let error = ToolError::TimedOut(format!(
+                "{} timed out after {} ms. It was told to stop, and anything it returns later is discarded",
+                tool.name(),
+                timeout.as_millis()
+            ));
+            Ok(ToolOutput::failure(&error).with(ContentItem::Json(json!({
+                "timeout_ms": timeout.as_millis() as u64,
+                "released": release_held_input()
+            }))))
+        }
+        // The worker panicked; let it reach the caller as if it had been here
+        Err(RecvTimeoutError::Disconnected) => match worker.join() {
+            Err(panic) => std::panic::resume_unwind(panic),
+            Ok(()) => Err(ToolError::Platform(format!("{} stopped without a result", tool.name()))),
+        },
+    }
+}
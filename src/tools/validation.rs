@@ -0,0 +1,380 @@
+//! Checking tool arguments against the advertised input schemas, and, in
+//! debug builds, results against the declared output schemas.
+
+use serde_json::{json, Value};
+
+use crate::accessibility::edit_distance;
+use crate::tools::output::ToolError;
+use crate::tools::Tool;
+
+/// Check a call's arguments against the tool's advertised schema, so that
+/// what's advertised and what's accepted can't drift apart. Every
+/// violation is reported, prefixed with its JSON pointer, e.g.
+/// `/region/width: expected integer >= 1, got 0`. Valid arguments are
+/// returned with integral numbers such as `100.0` given where an integer
+/// is expected turned into integers, as the tools read them.
+pub(crate) fn validate_arguments(tool: &dyn Tool, args: &Value) -> Result<Value, ToolError> {
+    let definition = tool.definition();
+    let schema = &definition["inputSchema"];
+    let mut violations = Vec::new();
+    check_schema(schema, args, "", &mut violations);
+    let Some((first, _)) = violations.first() else {
+        let mut args = args.clone();
+        integral_floats_to_integers(schema, &mut args);
+        return Ok(args);
+    };
+
+    let field = first.split('/').nth(1).map(|f| f.replace("~1", "/").replace("~0", "~"));
+    Err(ToolError::InvalidParams {
+        field,
+        message: format!("Invalid arguments for {}: {}", tool.name(), describe_violations(&violations)),
+    })
+}
+
+/// Check a result's structured content against the tool's declared output
+/// schema. Only done in debug builds, where a mismatch panics, so that the
+/// two can't drift apart unnoticed.
+pub(crate) fn debug_check_output(tool: &dyn Tool, structured: &Value) {
+    if !cfg!(debug_assertions) {
+        return;
+    }
+    let Some(schema) = tool.output_schema() else {
+        return;
+    };
+    let mut violations = Vec::new();
+    check_schema(&schema, structured, "", &mut violations);
+    assert!(
+        violations.is_empty(),
+        "{} result doesn't match its outputSchema: {}",
+        tool.name(),
+        describe_violations(&violations)
+    );
+}
+
+/// Violations joined into one message, each prefixed with its pointer.
+fn describe_violations(violations: &[(String, String)]) -> String {
+    violations
+        .iter()
+        .map(|(pointer, problem)| format!("{}: {}", if pointer.is_empty() { "/" } else { pointer }, problem))
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+/// Validate `value` against the subset of JSON Schema the tool schemas use:
+/// type, enum, minimum, maximum, minItems, maxItems, items, properties,
+/// required, additionalProperties and oneOf. Other keywords are ignored.
+fn check_schema(schema: &Value, value: &Value, pointer: &str, violations: &mut Vec<(String, String)>) {
+    if let Some(alternatives) = schema.get("oneOf").and_then(|v| v.as_array()) {
+        let results: Vec<Vec<(String, String)>> = alternatives
+            .iter()
+            .map(|alternative| {
+                let mut found = Vec::new();
+                check_schema(alternative, value, pointer, &mut found);
+                found
+            })
+            .collect();
+        let matching = results.iter().filter(|found| found.is_empty()).count();
+        if matching == 0 {
+            // If only one alternative has the right type, its complaints are
+            // the useful ones
+            let same_type: Vec<usize> = (0..alternatives.len())
+                .filter(|&i| schema_types(&alternatives[i]).iter().any(|t| has_type(value, t)))
+                .collect();
+            match same_type[..] {
+                [i] => violations.extend(results[i].iter().cloned()),
+                _ => {
+                    let types: Vec<&str> = alternatives.iter().flat_map(schema_types).collect();
+                    violations.push((
+                        pointer.to_string(),
+                        format!("expected {}, got {}", types.join(" or "), describe_value(value)),
+                    ));
+                }
+            }
+        } else if matching > 1 {
+            violations.push((pointer.to_string(), "matches more than one allowed form".to_string()));
+        }
+    }
+
+    let types = schema_types(schema);
+    if !types.is_empty() && !types.iter().any(|t| has_type(value, t)) {
+        violations.push((
+            pointer.to_string(),
+            format!("expected {}, got {}", describe_schema(schema), describe_value(value)),
+        ));
+        return;
+    }
+
+    if let Some(allowed) = schema.get("enum").and_then(|v| v.as_array()) {
+        if !allowed.contains(value) {
+            let allowed: Vec<String> = allowed.iter().map(|v| v.to_string()).collect();
+            violations.push((
+                pointer.to_string(),
+                format!("expected one of {}, got {}", allowed.join(", "), value),
+            ));
+        }
+    }
+
+    if let Some(n) = value.as_f64() {
+        let below = schema.get("minimum").and_then(|v| v.as_f64()).is_some_and(|min| n < min);
+        let above = schema.get("maximum").and_then(|v| v.as_f64()).is_some_and(|max| n > max);
+        if below || above {
+            violations.push((
+                pointer.to_string(),
+                format!("expected {}, got {}", describe_schema(schema), value),
+            ));
+        }
+    }
+
+    if let Some(items) = value.as_array() {
+        let min = schema.get("minItems").and_then(|v| v.as_u64());
+        let max = schema.get("maxItems").and_then(|v| v.as_u64());
+        if min.is_some_and(|min| (items.len() as u64) < min) {
+            violations.push((
+                pointer.to_string(),
+                format!("expected at least {} items, got {}", min.unwrap_or_default(), items.len()),
+            ));
+        }
+        if max.is_some_and(|max| items.len() as u64 > max) {
+            violations.push((
+                pointer.to_string(),
+                format!("expected at most {} items, got {}", max.unwrap_or_default(), items.len()),
+            ));
+        }
+        if let Some(item_schema) = schema.get("items") {
+            for (i, item) in items.iter().enumerate() {
+                check_schema(item_schema, item, &format!("{}/{}", pointer, i), violations);
+            }
+        }
+    }
+
+    if let Some(object) = value.as_object() {
+        let properties = schema.get("properties").and_then(|v| v.as_object());
+        let required = schema.get("required").and_then(|v| v.as_array());
+        for name in required.into_iter().flatten().filter_map(|v| v.as_str()) {
+            if !object.contains_key(name) {
+                violations.push((pointer_to(pointer, name), "required".to_string()));
+            }
+        }
+        let closed = schema.get("additionalProperties") == Some(&json!(false));
+        for (key, item) in object {
+            match properties.and_then(|p| p.get(key)) {
+                Some(property) => check_schema(property, item, &pointer_to(pointer, key), violations),
+                None if closed => {
+                    let suggestion = properties
+                        .into_iter()
+                        .flat_map(|p| p.keys())
+                        .map(|name| (edit_distance(key, name), name))
+                        .filter(|&(distance, _)| distance <= 2)
+                        .min();
+                    let problem = match suggestion {
+                        Some((_, name)) => format!("unknown argument; did you mean {}?", name),
+                        None => "unknown argument".to_string(),
+                    };
+                    violations.push((pointer_to(pointer, key), problem));
+                }
+                None => {}
+            }
+        }
+    }
+}
+
+/// Turn floats with no fractional part into integers wherever `schema`
+/// expects an integer and not any number.
+fn integral_floats_to_integers(schema: &Value, value: &mut Value) {
+    let types = schema_types(schema);
+    if types.contains(&"integer") && !types.contains(&"number") && value.is_f64() {
+        if let Some(n) = value.as_f64().filter(|n| n.fract() == 0.0) {
+            *value = if n < 0.0 { json!(n as i64) } else { json!(n as u64) };
+        }
+        return;
+    }
+    match value {
+        Value::Array(items) => {
+            if let Some(item_schema) = schema.get("items") {
+                for item in items {
+                    integral_floats_to_integers(item_schema, item);
+                }
+            }
+        }
+        Value::Object(object) => {
+            let properties = schema.get("properties").and_then(|v| v.as_object());
+            for (key, item) in object {
+                if let Some(property) = properties.and_then(|p| p.get(key)) {
+                    integral_floats_to_integers(property, item);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// The JSON pointer of `key` within the value at `pointer`.
+fn pointer_to(pointer: &str, key: &str) -> String {
+    format!("{}/{}", pointer, key.replace('~', "~0").replace('/', "~1"))
+}
+
+/// The types a schema allows; empty if it doesn't say.
+fn schema_types(schema: &Value) -> Vec<&str> {
+    match schema.get("type") {
+        Some(Value::String(t)) => vec![t.as_str()],
+        Some(Value::Array(types)) => types.iter().filter_map(|t| t.as_str()).collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn has_type(value: &Value, schema_type: &str) -> bool {
+    match schema_type {
+        // JSON Schema counts 100.0 as an integer, and clients send them
+        "integer" => value.is_i64() || value.is_u64() || value.as_f64().is_some_and(|n| n.fract() == 0.0),
+        "number" => value.is_number(),
+        "string" => value.is_string(),
+        "boolean" => value.is_boolean(),
+        "array" => value.is_array(),
+        "object" => value.is_object(),
+        "null" => value.is_null(),
+        _ => true,
+    }
+}
+
+/// What a schema expects, e.g. `integer >= 1` or `number between 0 and 1`.
+fn describe_schema(schema: &Value) -> String {
+    let types = schema_types(schema).join(" or ");
+    let min = schema.get("minimum").filter(|v| v.is_number());
+    let max = schema.get("maximum").filter(|v| v.is_number());
+    match (min, max) {
+        (Some(min), Some(max)) => format!("{} between {} and {}", types, min, max),
+        (Some(min), None) => format!("{} >= {}", types, min),
+        (None, Some(max)) => format!("{} <= {}", types, max),
+        (None, None) => types,
+    }
+}
+
+/// A value's JSON type, with the value itself for scalars.
+fn describe_value(value: &Value) -> String {
+    match value {
+        Value::Null => "null".to_string(),
+        Value::Bool(b) => format!("boolean {}", b),
+        Value::Number(n) if n.is_f64() => format!("number {}", n),
+        Value::Number(n) => format!("integer {}", n),
+        Value::String(s) => format!("string {}", Value::String(s.clone())),
+        Value::Array(_) => "array".to_string(),
+        Value::Object(_) => "object".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tools::BUILTIN_TOOLS;
+
+    /// A value of a type `schema` doesn't allow, or None if it allows any.
+    fn wrong_type(schema: &Value) -> Option<Value> {
+        let types = schema_types(schema);
+        if types.is_empty() {
+            return None;
+        }
+        [
+            json!("text"),
+            json!(1.5),
+            json!(true),
+            json!([]),
+            json!({ "nested": 1 }),
+        ]
+        .into_iter()
+        .find(|value| !types.iter().any(|t| has_type(value, t)))
+    }
+
+    /// Payloads that break one rule of a property's schema, with what the
+    /// violation message starts with.
+    fn violations_of(name: &str, schema: &Value) -> Vec<(Value, &'static str)> {
+        let integer = schema_types(schema).contains(&"integer");
+        let number = |n: f64| if integer { json!(n as i64) } else { json!(n) };
+        let mut payloads = Vec::new();
+        if let Some(value) = wrong_type(schema) {
+            payloads.push((value, "expected"));
+        }
+        if let Some(min) = schema.get("minimum").and_then(|v| v.as_f64()) {
+            payloads.push((number(min.floor() - 1.0), "expected"));
+        }
+        if let Some(max) = schema.get("maximum").and_then(|v| v.as_f64()) {
+            payloads.push((number(max.ceil() + 1.0), "expected"));
+        }
+        // An enum of another type rejects a string as the wrong type
+        if schema.get("enum").is_some() && schema_types(schema).contains(&"string") {
+            payloads.push((json!("not-one-of-them"), "expected one of"));
+        }
+        if let Some(max) = schema.get("maxItems").and_then(|v| v.as_u64()) {
+            payloads.push((json!(vec![Value::Null; max as usize + 1]), "expected at most"));
+        }
+        if let Some(min) = schema.get("minItems").and_then(|v| v.as_u64()).filter(|&min| min > 0) {
+            payloads.push((json!(vec![Value::Null; min as usize - 1]), "expected at least"));
+        }
+        payloads
+            .into_iter()
+            .map(|(value, problem)| (json!({ name: value }), problem))
+            .collect()
+    }
+
+    fn message(tool: &dyn Tool, args: &Value) -> String {
+        match validate_arguments(tool, args) {
+            Err(ToolError::InvalidParams { message, .. }) => message,
+            other => panic!("{} accepted {}: {:?}", tool.name(), args, other),
+        }
+    }
+
+    #[test]
+    fn every_tool_rejects_payloads_that_break_its_schema() {
+        for &tool in BUILTIN_TOOLS {
+            let definition = tool.definition();
+    let schema = &definition["inputSchema"];
+            let message = |args: &Value| message(tool, args);
+
+            assert!(message(&json!("x")).contains("/: expected object"), "{}", tool.name());
+            assert!(
+                message(&json!({ "not_an_argument": 1 })).contains("/not_an_argument: unknown argument"),
+                "{}",
+                tool.name()
+            );
+            let required: Vec<&str> = schema["required"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|v| v.as_str())
+                .collect();
+            if !required.is_empty() {
+                let missing = message(&json!({}));
+                for name in required {
+                    assert!(
+                        missing.contains(&format!("/{}: required", name)),
+                        "{}: {}",
+                        tool.name(),
+                        missing
+                    );
+                }
+            }
+
+            for (name, property) in schema["properties"].as_object().unwrap() {
+                for (args, problem) in violations_of(name, property) {
+                    let message = message(&args);
+                    assert!(
+                        message.contains(&format!("/{}: {}", name, problem)),
+                        "{} with {}: {}",
+                        tool.name(),
+                        args,
+                        message
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn integral_numbers_are_accepted_as_integers() {
+        let tool = crate::tools::find_tool("screen_capture").unwrap();
+        let region = json!({ "x": 0.0, "y": 0, "width": 64.0, "height": 32 });
+        let args = validate_arguments(tool, &json!({ "max_width": 480.0, "region": region })).unwrap();
+        assert_eq!(args["max_width"].as_u64(), Some(480));
+        assert_eq!(args["region"]["width"].as_u64(), Some(64));
+        assert!(message(tool, &json!({ "max_width": 480.5 })).contains("/max_width: expected integer"));
+    }
+}
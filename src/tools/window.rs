@@ -0,0 +1,326 @@
+//! Window tools.
+
+use serde_json::{json, Value};
+use std::thread;
+use std::time::Duration;
+
+use crate::accessibility::{set_window_frame, set_window_state};
+use crate::backend::displays;
+use crate::capture::desktop_bounds;
+use crate::input::physical::screen_at_point;
+use crate::tools::availability::{Permission, ToolRequirements};
+use crate::tools::limits::dry_run;
+use crate::tools::output::{ToolError, ToolOutput};
+use crate::tools::timeout::own_timeout;
+use crate::tools::Tool;
+use crate::window::{
+    find_window, focus_window, frontmost_window, WindowState, MAX_FOCUS_TIMEOUT_MS, WINDOW_ANIMATION_MS,
+};
+
+pub(crate) struct FocusWindow;
+
+impl Tool for FocusWindow {
+    fn name(&self) -> &str {
+        "focus_window"
+    }
+
+    fn description(&self) -> &str {
+        "Bring a window to the front and give it focus before clicking or typing into it, so input can't land in whatever the user switched to. Finds the window by id or by a title or app name substring, raises it, activates its app and waits until it's actually frontmost. Returns the window with its bounds."
+    }
+
+    fn schema(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "title": {
+                    "type": "string",
+                    "description": "Text to find in the window's title or app name (case-insensitive substring); the frontmost match wins"
+                },
+                "id": {
+                    "type": "integer",
+                    "description": "The window's id, as reported by get_active_window. Takes precedence over title."
+                },
+                "timeout_ms": {
+                    "type": "integer",
+                    "description": format!("How long to wait for the window to come to the front in milliseconds (default: 2000, max: {})", MAX_FOCUS_TIMEOUT_MS),
+                    "default": 2000,
+                    "maximum": MAX_FOCUS_TIMEOUT_MS
+                }
+            }
+        })
+    }
+
+    fn requirements(&self) -> ToolRequirements {
+        ToolRequirements {
+            macos_only: true,
+            permissions: &[Permission::Accessibility],
+            ..Default::default()
+        }
+    }
+
+    fn idempotent(&self) -> bool {
+        true
+    }
+
+    fn timeout(&self, args: &Value) -> Option<Duration> {
+        own_timeout(args, 2000, MAX_FOCUS_TIMEOUT_MS)
+    }
+
+    fn call(&self, args: &Value) -> Result<ToolOutput, ToolError> {
+        let id = args.get("id").and_then(|v| v.as_u64());
+        let title = args.get("title").and_then(|v| v.as_str());
+        if id.is_none() && title.is_none() {
+            return Err(ToolError::invalid("Pass a window title or id"));
+        }
+        let timeout_ms = args
+            .get("timeout_ms")
+            .and_then(|v| v.as_u64())
+            .unwrap_or(2000)
+            .min(MAX_FOCUS_TIMEOUT_MS);
+
+        let window = find_window(id, title, false)?.ok_or_else(|| {
+            ToolError::NotFound(match id {
+                Some(id) => format!("No window with id {}", id),
+                None => format!("No window whose title or app name contains \"{}\"", title.unwrap_or_default()),
+            })
+        })?;
+
+        // Focus isn't input, but it still changes what the user sees
+        if dry_run() {
+            return Ok(ToolOutput::json(&json!({
+                "success": true,
+                "window": window.to_json(),
+                "simulated": true
+            })));
+        }
+
+        let (window, waited) = focus_window(&window, Duration::from_millis(timeout_ms))?;
+        Ok(ToolOutput::json(&json!({
+            "success": true,
+            "window": window.to_json(),
+            "waited_ms": waited.as_millis() as u64
+        })))
+    }
+}
+
+pub(crate) struct GetActiveWindow;
+
+impl Tool for GetActiveWindow {
+    fn name(&self) -> &str {
+        "get_active_window"
+    }
+
+    fn description(&self) -> &str {
+        "Get the frontmost window: its title, app name, pid, id, bounds in global logical coordinates (ready for screen_capture with coordinates: \"global\") and the screen_index its center is on. Every field is null when no window is in front, e.g. on an empty desktop. Titles are empty without Screen Recording permission."
+    }
+
+    fn schema(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {}
+        })
+    }
+
+    fn output_schema(&self) -> Option<Value> {
+        Some(json!({
+            "type": "object",
+            "properties": {
+                "title": { "type": ["string", "null"] },
+                "app_name": { "type": ["string", "null"] },
+                "pid": { "type": ["integer", "null"] },
+                "id": { "type": ["integer", "null"] },
+                "x": { "type": ["number", "null"] },
+                "y": { "type": ["number", "null"] },
+                "width": { "type": ["number", "null"] },
+                "height": { "type": ["number", "null"] },
+                "screen_index": { "type": ["integer", "null"], "minimum": 0 }
+            },
+            "required": ["title", "app_name", "pid", "id", "x", "y", "width", "height", "screen_index"],
+            "additionalProperties": false
+        }))
+    }
+
+    fn requirements(&self) -> ToolRequirements {
+        ToolRequirements {
+            macos_only: true,
+            ..Default::default()
+        }
+    }
+
+    fn read_only(&self) -> bool {
+        true
+    }
+
+    fn call(&self, _args: &Value) -> Result<ToolOutput, ToolError> {
+        let Some(window) = frontmost_window()? else {
+            return Ok(ToolOutput::json(&json!({
+                "title": null,
+                "app_name": null,
+                "pid": null,
+                "id": null,
+                "x": null,
+                "y": null,
+                "width": null,
+                "height": null,
+                "screen_index": null
+            })));
+        };
+        let screen_index = screen_at_point(window.x + window.width / 2.0, window.y + window.height / 2.0)?
+            .map(|(index, _)| index);
+        let mut result = window.to_json();
+        result["screen_index"] = json!(screen_index);
+        Ok(ToolOutput::json(&result))
+    }
+}
+
+/// How long after moving or resizing a window its bounds are read back.
+const WINDOW_SETTLE_MS: u64 = 100;
+
+pub(crate) struct WindowManage;
+
+impl Tool for WindowManage {
+    fn name(&self) -> &str {
+        "window_manage"
+    }
+
+    fn description(&self) -> &str {
+        "Move, resize, minimize, maximize, restore or full-screen a window, found by id or by a title or app name substring (minimized windows included). Give x/y and/or width/height in global logical coordinates (see get_screen_info), or a state. The result reports the window's actual bounds afterwards, since apps and the window manager may adjust the request."
+    }
+
+    fn schema(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "title": {
+                    "type": "string",
+                    "description": "Text to find in the window's title or app name (case-insensitive substring); the frontmost match wins"
+                },
+                "id": {
+                    "type": "integer",
+                    "description": "The window's id, as reported by get_active_window. Takes precedence over title."
+                },
+                "x": { "type": "number", "description": "New left edge in global logical coordinates (default: unchanged)" },
+                "y": { "type": "number", "description": "New top edge in global logical coordinates (default: unchanged)" },
+                "width": { "type": "number", "description": "New width (default: unchanged)", "minimum": 1 },
+                "height": { "type": "number", "description": "New height (default: unchanged)", "minimum": 1 },
+                "state": {
+                    "type": "string",
+                    "enum": WindowState::NAMES,
+                    "description": "minimized, maximized (zoomed, as with the title bar's zoom button), restored (neither minimized nor full screen) or fullscreen. Only restored can be combined with new bounds, which are applied after it."
+                }
+            }
+        })
+    }
+
+    fn requirements(&self) -> ToolRequirements {
+        ToolRequirements {
+            macos_only: true,
+            permissions: &[Permission::Accessibility],
+            ..Default::default()
+        }
+    }
+
+    fn idempotent(&self) -> bool {
+        true
+    }
+
+    fn call(&self, args: &Value) -> Result<ToolOutput, ToolError> {
+        let id = args.get("id").and_then(|v| v.as_u64());
+        let title = args.get("title").and_then(|v| v.as_str());
+        if id.is_none() && title.is_none() {
+            return Err(ToolError::invalid("Pass a window title or id"));
+        }
+        let number = |name: &str| args.get(name).and_then(|v| v.as_f64());
+        let (x, y, width, height) = (number("x"), number("y"), number("width"), number("height"));
+        let resizing = x.is_some() || y.is_some() || width.is_some() || height.is_some();
+        let state = match args.get("state").and_then(|v| v.as_str()) {
+            Some(name) => Some(
+                WindowState::parse(name)
+                    .ok_or_else(|| ToolError::invalid_field("state", format!("Unknown window state: {}", name)))?,
+            ),
+            None => None,
+        };
+        if state.is_none() && !resizing {
+            return Err(ToolError::invalid("Pass new bounds (x, y, width, height) or a state"));
+        }
+        if resizing && state.is_some_and(|s| s != WindowState::Restored) {
+            return Err(ToolError::invalid_field(
+                "state",
+                "Only the restored state can be combined with new bounds",
+            ));
+        }
+
+        let window = find_window(id, title, true)?.ok_or_else(|| {
+            ToolError::NotFound(match id {
+                Some(id) => format!("No window with id {}", id),
+                None => format!("No window whose title or app name contains \"{}\"", title.unwrap_or_default()),
+            })
+        })?;
+
+        // The window's top-left corner has to be on a screen, so that its
+        // title bar can be reached, and the rest within the desktop
+        let frame = resizing.then(|| {
+            (
+                x.unwrap_or(window.x),
+                y.unwrap_or(window.y),
+                width.unwrap_or(window.width),
+                height.unwrap_or(window.height),
+            )
+        });
+        if let Some((x, y, width, height)) = frame {
+            let screens = displays()?;
+            let (left, top, right, bottom) = desktop_bounds(&screens);
+            let inside = x >= left as f64 && y >= top as f64 && x + width <= right as f64 && y + height <= bottom as f64;
+            if !inside || screen_at_point(x, y)?.is_none() {
+                return Err(ToolError::OutOfBounds(format!(
+                    "Window bounds ({}, {}) {}x{} aren't on the desktop ({}, {}) to ({}, {}), with the top-left corner on a screen",
+                    x, y, width, height, left, top, right, bottom
+                )));
+            }
+        }
+
+        let requested = frame.map(|(x, y, width, height)| json!({ "x": x, "y": y, "width": width, "height": height }));
+        if dry_run() {
+            return Ok(ToolOutput::json(&json!({
+                "success": true,
+                "window": window.to_json(),
+                "state": state.map(WindowState::as_str),
+                "requested": requested,
+                "simulated": true
+            })));
+        }
+
+        let mut window = window;
+        if let Some(state) = state {
+            set_window_state(&window, state)?;
+            thread::sleep(Duration::from_millis(WINDOW_ANIMATION_MS));
+            // Leaving full screen or a minimize moves the window; it's
+            // matched by its current bounds below
+            if let Some(current) = find_window(Some(window.id), None, true)? {
+                window = current;
+            }
+        }
+        if let Some((x, y, width, height)) = frame {
+            set_window_frame(&window, (x, y), (width, height))?;
+            thread::sleep(Duration::from_millis(WINDOW_SETTLE_MS));
+        }
+
+        let after = find_window(Some(window.id), None, true)?;
+        let adjusted = match (frame, &after) {
+            (Some((x, y, width, height)), Some(after)) => {
+                (after.x - x).abs() >= 1.0
+                    || (after.y - y).abs() >= 1.0
+                    || (after.width - width).abs() >= 1.0
+                    || (after.height - height).abs() >= 1.0
+            }
+            _ => false,
+        };
+        Ok(ToolOutput::json(&json!({
+            "success": true,
+            "window": after.map(|w| w.to_json()),
+            "state": state.map(WindowState::as_str),
+            "requested": requested,
+            "adjusted": adjusted
+        })))
+    }
+}
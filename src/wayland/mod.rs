@@ -0,0 +1,88 @@
+//! Wayland sessions. Capture works there through the screenshots crate,
+//! which asks GNOME Shell, the xdg-desktop-portal Screenshot API or wlroots'
+//! screencopy in turn. rdev's input only reaches XWayland windows, so input
+//! tools are withheld unless the server is built with the `wayland`
+//! feature, which sends input through the RemoteDesktop portal, or
+//! `DESKTOP_MCP_XWAYLAND_INPUT` is set.
+
+#[cfg(all(target_os = "linux", feature = "wayland"))]
+mod portal;
+
+use crate::tools::limits::dry_run;
+
+/// Where the user is pointed for more on Wayland support.
+const SEE_README: &str = "see Wayland in the README";
+
+/// Whether the server is running in a Wayland session.
+#[cfg(target_os = "linux")]
+pub(crate) fn wayland_session() -> bool {
+    let session_type = std::env::var("XDG_SESSION_TYPE").unwrap_or_default();
+    session_type.eq_ignore_ascii_case("wayland") || std::env::var_os("WAYLAND_DISPLAY").is_some_and(|v| !v.is_empty())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn wayland_session() -> bool {
+    false
+}
+
+/// Whether the user has accepted that input only reaches XWayland windows.
+fn xwayland_input() -> bool {
+    std::env::var("DESKTOP_MCP_XWAYLAND_INPUT").is_ok_and(|v| v == "1" || v.eq_ignore_ascii_case("true"))
+}
+
+/// The display session, for self_test: "wayland", "x11" or None elsewhere.
+pub(crate) fn display_session() -> Option<&'static str> {
+    if wayland_session() {
+        Some("wayland")
+    } else if cfg!(target_os = "linux") {
+        Some("x11")
+    } else {
+        None
+    }
+}
+
+/// Start sending input through the RemoteDesktop portal, if this is a
+/// Wayland session and the build supports it. The desktop asks the user to
+/// allow remote control; input tools are offered once they have.
+pub fn start_wayland_input() {
+    if !wayland_session() {
+        return;
+    }
+    log::info!("Wayland session: capture goes through the compositor or the desktop portal");
+    if dry_run() || xwayland_input() {
+        return;
+    }
+    #[cfg(all(target_os = "linux", feature = "wayland"))]
+    portal::start();
+    #[cfg(not(all(target_os = "linux", feature = "wayland")))]
+    log::warn!(
+        "Wayland session: input tools are disabled; rebuild with --features wayland or set DESKTOP_MCP_XWAYLAND_INPUT=1"
+    );
+}
+
+/// Why input tools can't be offered in this session, or None if they can.
+pub(crate) fn input_unavailable_reason() -> Option<String> {
+    if !wayland_session() || dry_run() || xwayland_input() {
+        return None;
+    }
+    #[cfg(all(target_os = "linux", feature = "wayland"))]
+    return portal::unavailable_reason().map(|reason| format!("Wayland session: {} ({})", reason, SEE_README));
+    #[cfg(not(all(target_os = "linux", feature = "wayland")))]
+    Some(format!(
+        "Wayland session: input isn't supported by this build; rebuild with --features wayland, or set DESKTOP_MCP_XWAYLAND_INPUT=1 to reach XWayland windows only ({})",
+        SEE_README
+    ))
+}
+
+/// `error` with a note that this is a Wayland session, for capture
+/// failures, which are otherwise hard to tell from a missing display.
+pub(crate) fn with_wayland_note(error: String) -> String {
+    if wayland_session() {
+        format!(
+            "{} (Wayland session: capture needs GNOME Shell, the desktop portal's Screenshot API or wlroots screencopy; {})",
+            error, SEE_README
+        )
+    } else {
+        error
+    }
+}
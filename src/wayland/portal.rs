@@ -0,0 +1,383 @@
+//! Input through the xdg-desktop-portal RemoteDesktop API. A session is
+//! started in the background when the server starts, and the desktop asks
+//! the user to allow remote control; until they do, input tools aren't
+//! offered. The session also shares the monitors through the ScreenCast
+//! API, since absolute pointer positions are given relative to a shared
+//! monitor's stream.
+
+use dbus::arg::{AppendAll, Iter, PropMap, ReadAll, RefArg, TypeMismatchError, Variant};
+use dbus::blocking::Connection;
+use dbus::message::{MatchRule, SignalArgs};
+use rdev::{Button, EventType, Key, SimulateError};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::backend::{set_input_backend, InputBackend};
+
+const PORTAL_DESTINATION: &str = "org.freedesktop.portal.Desktop";
+const PORTAL_PATH: &str = "/org/freedesktop/portal/desktop";
+const REMOTE_DESKTOP: &str = "org.freedesktop.portal.RemoteDesktop";
+const SCREEN_CAST: &str = "org.freedesktop.portal.ScreenCast";
+
+/// How long to wait for a portal request, including the user answering the
+/// desktop's prompt.
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Timeout for a method call itself.
+const CALL_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// RemoteDesktop device types: keyboard and pointer.
+const DEVICE_TYPES: u32 = 1 | 2;
+
+/// ScreenCast source types: monitors.
+const SOURCE_MONITOR: u32 = 1;
+
+/// Linux input event codes for mouse buttons.
+const BTN_LEFT: i32 = 0x110;
+const BTN_RIGHT: i32 = 0x111;
+const BTN_MIDDLE: i32 = 0x112;
+const BTN_SIDE: i32 = 0x113;
+const BTN_EXTRA: i32 = 0x114;
+
+/// Linux input event codes (X11 keycodes minus 8) for rdev's keys.
+#[rustfmt::skip]
+const KEY_CODES: &[(Key, i32)] = &[
+    (Key::Alt, 56), (Key::AltGr, 100), (Key::Backspace, 14), (Key::CapsLock, 58),
+    (Key::ControlLeft, 29), (Key::ControlRight, 97), (Key::Delete, 111), (Key::DownArrow, 108),
+    (Key::End, 107), (Key::Escape, 1), (Key::F1, 59), (Key::F2, 60), (Key::F3, 61), (Key::F4, 62),
+    (Key::F5, 63), (Key::F6, 64), (Key::F7, 65), (Key::F8, 66), (Key::F9, 67), (Key::F10, 68),
+    (Key::F11, 87), (Key::F12, 88), (Key::Home, 102), (Key::LeftArrow, 105), (Key::MetaLeft, 125),
+    (Key::MetaRight, 126), (Key::PageDown, 109), (Key::PageUp, 104), (Key::Return, 28),
+    (Key::RightArrow, 106), (Key::ShiftLeft, 42), (Key::ShiftRight, 54), (Key::Space, 57),
+    (Key::Tab, 15), (Key::UpArrow, 103), (Key::PrintScreen, 99), (Key::ScrollLock, 70),
+    (Key::Pause, 119), (Key::NumLock, 69), (Key::BackQuote, 41), (Key::Num1, 2), (Key::Num2, 3),
+    (Key::Num3, 4), (Key::Num4, 5), (Key::Num5, 6), (Key::Num6, 7), (Key::Num7, 8), (Key::Num8, 9),
+    (Key::Num9, 10), (Key::Num0, 11), (Key::Minus, 12), (Key::Equal, 13), (Key::KeyQ, 16),
+    (Key::KeyW, 17), (Key::KeyE, 18), (Key::KeyR, 19), (Key::KeyT, 20), (Key::KeyY, 21),
+    (Key::KeyU, 22), (Key::KeyI, 23), (Key::KeyO, 24), (Key::KeyP, 25), (Key::LeftBracket, 26),
+    (Key::RightBracket, 27), (Key::KeyA, 30), (Key::KeyS, 31), (Key::KeyD, 32), (Key::KeyF, 33),
+    (Key::KeyG, 34), (Key::KeyH, 35), (Key::KeyJ, 36), (Key::KeyK, 37), (Key::KeyL, 38),
+    (Key::SemiColon, 39), (Key::Quote, 40), (Key::BackSlash, 43), (Key::IntlBackslash, 86),
+    (Key::KeyZ, 44), (Key::KeyX, 45), (Key::KeyC, 46), (Key::KeyV, 47), (Key::KeyB, 48),
+    (Key::KeyN, 49), (Key::KeyM, 50), (Key::Comma, 51), (Key::Dot, 52), (Key::Slash, 53),
+    (Key::Insert, 110), (Key::KpReturn, 96), (Key::KpMinus, 74), (Key::KpPlus, 78),
+    (Key::KpMultiply, 55), (Key::KpDivide, 98), (Key::Kp0, 82), (Key::Kp1, 79), (Key::Kp2, 80),
+    (Key::Kp3, 81), (Key::Kp4, 75), (Key::Kp5, 76), (Key::Kp6, 77), (Key::Kp7, 71), (Key::Kp8, 72),
+    (Key::Kp9, 73), (Key::KpDelete, 83),
+];
+
+fn key_code(key: Key) -> Option<i32> {
+    match key {
+        // Raw codes are X11 keycodes, as on X11
+        Key::Unknown(code) => code.checked_sub(8).map(|code| code as i32),
+        key => KEY_CODES.iter().find(|(k, _)| *k == key).map(|&(_, code)| code),
+    }
+}
+
+fn button_code(button: Button) -> Option<i32> {
+    match button {
+        Button::Left => Some(BTN_LEFT),
+        Button::Right => Some(BTN_RIGHT),
+        Button::Middle => Some(BTN_MIDDLE),
+        // X11's back and forward buttons
+        Button::Unknown(8) => Some(BTN_SIDE),
+        Button::Unknown(9) => Some(BTN_EXTRA),
+        Button::Unknown(_) => None,
+    }
+}
+
+/// The Response signal of a portal request.
+struct Response {
+    status: u32,
+    results: PropMap,
+}
+
+impl ReadAll for Response {
+    fn read(i: &mut Iter) -> Result<Self, TypeMismatchError> {
+        Ok(Response {
+            status: i.read()?,
+            results: i.read()?,
+        })
+    }
+}
+
+impl SignalArgs for Response {
+    const NAME: &'static str = "Response";
+    const INTERFACE: &'static str = "org.freedesktop.portal.Request";
+}
+
+/// A shared monitor, in global logical coordinates.
+#[derive(Debug)]
+struct Stream {
+    node_id: u32,
+    x: f64,
+    y: f64,
+    width: f64,
+    height: f64,
+}
+
+impl Stream {
+    fn contains(&self, x: f64, y: f64) -> bool {
+        x >= self.x && y >= self.y && x < self.x + self.width && y < self.y + self.height
+    }
+}
+
+/// A started RemoteDesktop session.
+struct Session {
+    connection: Connection,
+    handle: dbus::Path<'static>,
+    streams: Vec<Stream>,
+}
+
+enum State {
+    Starting,
+    Ready(Session),
+    Failed(String),
+}
+
+static STATE: Mutex<State> = Mutex::new(State::Starting);
+
+/// Send input through the portal, and start a session in the background.
+pub(super) fn start() {
+    set_input_backend(PortalInput);
+    thread::spawn(|| {
+        log::info!("Wayland session: asking the desktop to allow remote control for input");
+        let state = match Session::start() {
+            Ok(session) => {
+                log::info!("Wayland session: remote control allowed; input tools are available");
+                State::Ready(session)
+            }
+            Err(e) => {
+                log::warn!("Wayland session: input tools are disabled: {}", e);
+                State::Failed(e)
+            }
+        };
+        *STATE.lock().unwrap() = state;
+    });
+}
+
+/// Why input can't be sent yet, or None once the session has started.
+pub(super) fn unavailable_reason() -> Option<String> {
+    match &*STATE.lock().unwrap() {
+        State::Starting => Some("waiting for the user to allow remote control in the desktop's prompt".to_string()),
+        State::Ready(_) => None,
+        State::Failed(e) => Some(format!(
+            "the RemoteDesktop portal failed ({}); restart the server to ask again",
+            e
+        )),
+    }
+}
+
+fn variant(value: impl RefArg + 'static) -> Variant<Box<dyn RefArg>> {
+    Variant(Box::new(value))
+}
+
+/// A token for a request's handle_token, unique within this connection.
+fn next_token() -> String {
+    static NEXT: AtomicU32 = AtomicU32::new(0);
+    format!("desktop_mcp_{}", NEXT.fetch_add(1, Ordering::SeqCst))
+}
+
+/// `value` with any variant wrappers removed.
+fn unwrap_variant(value: &dyn RefArg) -> &dyn RefArg {
+    if value.signature().starts_with('v') {
+        if let Some(inner) = value.as_iter().and_then(|mut items| items.next()) {
+            return unwrap_variant(inner);
+        }
+    }
+    value
+}
+
+/// The integers in a struct such as (ii).
+fn integers(value: &dyn RefArg) -> Vec<f64> {
+    let value = unwrap_variant(value);
+    match value.as_iter() {
+        Some(items) => items.filter_map(|item| unwrap_variant(item).as_i64()).map(|v| v as f64).collect(),
+        None => Vec::new(),
+    }
+}
+
+/// The streams in Start's results, an array of (node_id, properties).
+fn parse_streams(results: &PropMap) -> Vec<Stream> {
+    let Some(streams) = results.get("streams").and_then(|v| unwrap_variant(&v.0).as_iter()) else {
+        return Vec::new();
+    };
+    streams
+        .filter_map(|stream| {
+            let mut fields = stream.as_iter()?;
+            let node_id = fields.next()?.as_u64()? as u32;
+            let mut properties = fields.next()?.as_iter()?;
+            let (mut position, mut size) = (vec![0.0, 0.0], Vec::new());
+            while let (Some(key), Some(value)) = (properties.next(), properties.next()) {
+                match key.as_str() {
+                    Some("position") => position = integers(value),
+                    Some("size") => size = integers(value),
+                    _ => {}
+                }
+            }
+            Some(Stream {
+                node_id,
+                x: *position.first()?,
+                y: *position.get(1)?,
+                width: *size.first()?,
+                height: *size.get(1)?,
+            })
+        })
+        .collect()
+}
+
+impl Session {
+    fn start() -> Result<Session, String> {
+        let connection = Connection::new_session().map_err(|e| format!("No D-Bus session bus: {}", e))?;
+
+        let mut options = PropMap::new();
+        options.insert("session_handle_token".to_string(), variant(next_token()));
+        let results = request(&connection, REMOTE_DESKTOP, "CreateSession", options, |options| (options,))?;
+        let handle = results
+            .get("session_handle")
+            .and_then(|v| unwrap_variant(&v.0).as_str())
+            .and_then(|s| dbus::Path::new(s.to_string()).ok())
+            .ok_or("CreateSession returned no session handle")?;
+
+        let mut options = PropMap::new();
+        options.insert("types".to_string(), variant(DEVICE_TYPES));
+        request(&connection, REMOTE_DESKTOP, "SelectDevices", options, |options| {
+            (handle.clone(), options)
+        })?;
+
+        let mut options = PropMap::new();
+        options.insert("types".to_string(), variant(SOURCE_MONITOR));
+        options.insert("multiple".to_string(), variant(true));
+        request(&connection, SCREEN_CAST, "SelectSources", options, |options| {
+            (handle.clone(), options)
+        })?;
+
+        let results = request(&connection, REMOTE_DESKTOP, "Start", PropMap::new(), |options| {
+            (handle.clone(), "", options)
+        })?;
+        let streams = parse_streams(&results);
+        if streams.is_empty() {
+            return Err("no monitor was shared, so pointer positions can't be given".to_string());
+        }
+        Ok(Session {
+            connection,
+            handle,
+            streams,
+        })
+    }
+
+    fn notify<A: AppendAll>(&self, method: &str, args: A) -> Result<(), String> {
+        self.connection
+            .with_proxy(PORTAL_DESTINATION, PORTAL_PATH, CALL_TIMEOUT)
+            .method_call::<(), _, _, _>(REMOTE_DESKTOP, method, args)
+            .map_err(|e| format!("{} failed: {}", method, e))
+    }
+
+    fn send(&self, event: &EventType) -> Result<(), String> {
+        let handle = || self.handle.clone();
+        match *event {
+            EventType::MouseMove { x, y } => {
+                let stream = self
+                    .streams
+                    .iter()
+                    .find(|s| s.contains(x, y))
+                    .ok_or_else(|| format!("({}, {}) isn't on a shared monitor", x, y))?;
+                self.notify(
+                    "NotifyPointerMotionAbsolute",
+                    (handle(), PropMap::new(), stream.node_id, x - stream.x, y - stream.y),
+                )
+            }
+            EventType::ButtonPress(button) | EventType::ButtonRelease(button) => {
+                let code = button_code(button).ok_or_else(|| format!("No button code for {:?}", button))?;
+                let state = matches!(event, EventType::ButtonPress(_)) as u32;
+                self.notify("NotifyPointerButton", (handle(), PropMap::new(), code, state))
+            }
+            EventType::KeyPress(key) | EventType::KeyRelease(key) => {
+                let code = key_code(key).ok_or_else(|| format!("No key code for {:?}", key))?;
+                let state = matches!(event, EventType::KeyPress(_)) as u32;
+                self.notify("NotifyKeyboardKeycode", (handle(), PropMap::new(), code, state))
+            }
+            EventType::Wheel { delta_x, delta_y } => {
+                // rdev's positive delta_y scrolls up; the portal's scrolls down
+                if delta_y != 0 {
+                    self.notify("NotifyPointerAxisDiscrete", (handle(), PropMap::new(), 0u32, -delta_y as i32))?;
+                }
+                if delta_x != 0 {
+                    self.notify("NotifyPointerAxisDiscrete", (handle(), PropMap::new(), 1u32, delta_x as i32))?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Make a portal request and wait for its response. `args` builds the
+/// method's arguments around the options, to which a handle_token is added.
+fn request<A: AppendAll>(
+    connection: &Connection,
+    interface: &str,
+    method: &str,
+    mut options: PropMap,
+    args: impl FnOnce(PropMap) -> A,
+) -> Result<PropMap, String> {
+    let token = next_token();
+    options.insert("handle_token".to_string(), variant(token.clone()));
+
+    // Listen on the request's path before calling, so the response can't
+    // arrive first
+    let sender = connection.unique_name().trim_start_matches(':').replace('.', "_");
+    let path = format!("{}/request/{}/{}", PORTAL_PATH, sender, token);
+    let rule = MatchRule::new_signal(Response::INTERFACE, Response::NAME)
+        .with_path(dbus::Path::new(path).map_err(|e| format!("Bad request path: {}", e))?);
+    let response: Arc<Mutex<Option<Response>>> = Arc::new(Mutex::new(None));
+    let slot = response.clone();
+    let listener = connection
+        .add_match(rule, move |r: Response, _, _| {
+            *slot.lock().unwrap() = Some(r);
+            false
+        })
+        .map_err(|e| format!("Failed to listen for the {} response: {}", method, e))?;
+
+    let result = (|| {
+        connection
+            .with_proxy(PORTAL_DESTINATION, PORTAL_PATH, CALL_TIMEOUT)
+            .method_call::<(dbus::Path<'static>,), _, _, _>(interface, method, args(options))
+            .map_err(|e| format!("{} failed: {}", method, e))?;
+        let deadline = Instant::now() + RESPONSE_TIMEOUT;
+        loop {
+            if let Some(response) = response.lock().unwrap().take() {
+                return match response.status {
+                    0 => Ok(response.results),
+                    1 => Err(format!("{} was cancelled by the user", method)),
+                    _ => Err(format!("{} failed", method)),
+                };
+            }
+            if Instant::now() >= deadline {
+                return Err(format!("Timed out waiting for {}", method));
+            }
+            connection
+                .process(Duration::from_millis(100))
+                .map_err(|e| format!("D-Bus error: {}", e))?;
+        }
+    })();
+    let _ = connection.remove_match(listener);
+    result
+}
+
+/// Input through the RemoteDesktop session, once started.
+pub(super) struct PortalInput;
+
+impl InputBackend for PortalInput {
+    fn simulate(&self, event: &EventType) -> Result<(), SimulateError> {
+        let state = STATE.lock().unwrap();
+        let State::Ready(session) = &*state else {
+            return Err(SimulateError);
+        };
+        session.send(event).map_err(|e| {
+            log::warn!("Portal input failed: {}", e);
+            SimulateError
+        })
+    }
+}
@@ -0,0 +1,222 @@
+//! The on-screen windows and which one is frontmost.
+
+use serde_json::{json, Value};
+use std::time::{Duration, Instant};
+
+use crate::accessibility::raise_window;
+use crate::backend::screen_backend;
+use crate::cancel::sleep_cancellable;
+
+/// A top-level window. Bounds are in global logical coordinates.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WindowInfo {
+    pub id: u64,
+    pub title: String,
+    pub app_name: String,
+    pub pid: i64,
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+impl WindowInfo {
+    pub(crate) fn to_json(&self) -> Value {
+        json!({
+            "id": self.id,
+            "title": self.title,
+            "app_name": self.app_name,
+            "pid": self.pid,
+            "x": self.x,
+            "y": self.y,
+            "width": self.width,
+            "height": self.height
+        })
+    }
+}
+
+/// On-screen application windows, front to back. Titles need Screen
+/// Recording permission; without it they come back empty.
+pub(crate) fn list_windows() -> Result<Vec<WindowInfo>, String> {
+    screen_backend().windows(true)
+}
+
+/// Application windows including off-screen ones, such as minimized
+/// windows and those on other Spaces.
+fn list_all_windows() -> Result<Vec<WindowInfo>, String> {
+    screen_backend().windows(false)
+}
+
+/// The real application windows, front to back, optionally only the
+/// on-screen ones.
+#[cfg(target_os = "macos")]
+pub(crate) fn system_windows(on_screen_only: bool) -> Result<Vec<WindowInfo>, String> {
+    use core_foundation::base::{CFType, TCFType};
+    use core_foundation::dictionary::{CFDictionary, CFDictionaryRef};
+    use core_foundation::number::CFNumber;
+    use core_foundation::string::{CFString, CFStringRef};
+    use core_graphics::geometry::CGRect;
+    use core_graphics::window::{
+        copy_window_info, kCGNullWindowID, kCGWindowBounds, kCGWindowLayer,
+        kCGWindowListExcludeDesktopElements, kCGWindowListOptionAll, kCGWindowListOptionOnScreenOnly,
+        kCGWindowName, kCGWindowNumber, kCGWindowOwnerName, kCGWindowOwnerPID,
+    };
+
+    let which = if on_screen_only {
+        kCGWindowListOptionOnScreenOnly
+    } else {
+        kCGWindowListOptionAll
+    };
+    let windows = copy_window_info(which | kCGWindowListExcludeDesktopElements, kCGNullWindowID)
+        .ok_or("Failed to list windows")?;
+
+    let mut result = Vec::new();
+    for item in windows.iter() {
+        let dict: CFDictionary<CFString, CFType> =
+            unsafe { CFDictionary::wrap_under_get_rule(*item as CFDictionaryRef) };
+        let get = |key: CFStringRef| dict.find(unsafe { CFString::wrap_under_get_rule(key) });
+        let number = |key: CFStringRef| {
+            get(key)
+                .and_then(|v| v.downcast::<CFNumber>())
+                .and_then(|n| n.to_i64())
+        };
+        let string = |key: CFStringRef| {
+            get(key)
+                .and_then(|v| v.downcast::<CFString>())
+                .map(|s| s.to_string())
+                .unwrap_or_default()
+        };
+
+        // Layer 0 holds ordinary application windows; the menu bar, dock
+        // and overlays live on other layers
+        if number(unsafe { kCGWindowLayer }) != Some(0) {
+            continue;
+        }
+        let bounds = get(unsafe { kCGWindowBounds })
+            .and_then(|v| v.downcast::<CFDictionary>())
+            .and_then(|d| CGRect::from_dict_representation(&d));
+        let Some(bounds) = bounds else {
+            continue;
+        };
+
+        result.push(WindowInfo {
+            id: number(unsafe { kCGWindowNumber }).unwrap_or(0) as u64,
+            title: string(unsafe { kCGWindowName }),
+            app_name: string(unsafe { kCGWindowOwnerName }),
+            pid: number(unsafe { kCGWindowOwnerPID }).unwrap_or(0),
+            x: bounds.origin.x,
+            y: bounds.origin.y,
+            width: bounds.size.width,
+            height: bounds.size.height,
+        });
+    }
+
+    Ok(result)
+}
+
+#[cfg(not(target_os = "macos"))]
+pub(crate) fn system_windows(_on_screen_only: bool) -> Result<Vec<WindowInfo>, String> {
+    Err("Window information is only supported on macOS".to_string())
+}
+
+/// The frontmost application window, if any.
+pub(crate) fn frontmost_window() -> Result<Option<WindowInfo>, String> {
+    Ok(list_windows()?.into_iter().next())
+}
+
+/// The frontmost on-screen window containing the global point (x, y).
+pub(crate) fn window_at_point(x: f64, y: f64) -> Result<Option<WindowInfo>, String> {
+    Ok(list_windows()?
+        .into_iter()
+        .find(|w| x >= w.x && y >= w.y && x < w.x + w.width && y < w.y + w.height))
+}
+
+/// The window with `id`, or else the frontmost one whose title or app name
+/// contains `name` (case-insensitive). On-screen windows are preferred;
+/// with `include_off_screen`, minimized and hidden windows are searched
+/// after them.
+pub(crate) fn find_window(
+    id: Option<u64>,
+    name: Option<&str>,
+    include_off_screen: bool,
+) -> Result<Option<WindowInfo>, String> {
+    let name = name.map(str::to_lowercase);
+    let matches = |w: &WindowInfo| match (id, &name) {
+        (Some(id), _) => w.id == id,
+        (None, Some(name)) => w.title.to_lowercase().contains(name) || w.app_name.to_lowercase().contains(name),
+        (None, None) => false,
+    };
+    if let Some(window) = list_windows()?.into_iter().find(|w| matches(w)) {
+        return Ok(Some(window));
+    }
+    if !include_off_screen {
+        return Ok(None);
+    }
+    Ok(list_all_windows()?.into_iter().find(|w| matches(w)))
+}
+
+/// A window state window_manage can set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum WindowState {
+    Minimized,
+    /// Zoomed, as the title bar's zoom button does.
+    Maximized,
+    /// Neither minimized nor full screen.
+    Restored,
+    Fullscreen,
+}
+
+impl WindowState {
+    pub(crate) const NAMES: [&'static str; 4] = ["minimized", "maximized", "restored", "fullscreen"];
+
+    pub(crate) fn parse(name: &str) -> Option<WindowState> {
+        match name {
+            "minimized" => Some(WindowState::Minimized),
+            "maximized" => Some(WindowState::Maximized),
+            "restored" => Some(WindowState::Restored),
+            "fullscreen" => Some(WindowState::Fullscreen),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            WindowState::Minimized => "minimized",
+            WindowState::Maximized => "maximized",
+            WindowState::Restored => "restored",
+            WindowState::Fullscreen => "fullscreen",
+        }
+    }
+}
+
+/// How long window state changes take to animate, before the window's new
+/// bounds can be read back.
+pub(crate) const WINDOW_ANIMATION_MS: u64 = 500;
+
+/// How often focus_window checks whether the window has come to the front.
+const FOCUS_POLL_MS: u64 = 50;
+
+/// Upper bound on how long focus_window waits for the window.
+pub(crate) const MAX_FOCUS_TIMEOUT_MS: u64 = 10_000;
+
+/// Raise `window` and activate its app, then wait until it's the frontmost
+/// window. Returns it as it is once in front, since raising can move it,
+/// and how long that took.
+pub(crate) fn focus_window(window: &WindowInfo, timeout: Duration) -> Result<(WindowInfo, Duration), String> {
+    raise_window(window)?;
+    let start = Instant::now();
+    loop {
+        if let Some(front) = frontmost_window()?.filter(|w| w.id == window.id) {
+            return Ok((front, start.elapsed()));
+        }
+        if start.elapsed() >= timeout {
+            return Err(format!(
+                "\"{}\" ({}) didn't come to the front within {}ms",
+                window.title,
+                window.app_name,
+                timeout.as_millis()
+            ));
+        }
+        sleep_cancellable(Duration::from_millis(FOCUS_POLL_MS))?;
+    }
+}
@@ -0,0 +1,76 @@
+//! Calls that need the user to approve them on the physical keyboard,
+//! approved or denied through keys injected as if pressed on it.
+
+mod common;
+
+use rdev::{EventType, Key};
+use serde_json::{json, Value};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use common::*;
+
+/// Require approval for mouse_move, with the default chord, ctrl+shift+y.
+fn setup_approval() -> Harness {
+    std::env::set_var("DESKTOP_MCP_REQUIRE_APPROVAL", "mouse_move");
+    std::env::set_var("DESKTOP_MCP_APPROVAL_TIMEOUT_MS", "5000");
+    setup()
+}
+
+/// Call mouse_move, pressing `keys` on the "physical" keyboard once the
+/// server is waiting for approval, and return the response.
+fn move_pressing(harness: &Harness, keys: &[Key]) -> Value {
+    let call = thread::spawn(|| call("mouse_move", json!({ "x": 10, "y": 20 })));
+    let start = Instant::now();
+    while !harness.input.physical(EventType::KeyPress(keys[0])) {
+        assert!(start.elapsed() < Duration::from_secs(5), "Nothing waited for approval");
+        thread::sleep(Duration::from_millis(10));
+    }
+    for key in &keys[1..] {
+        harness.input.physical(EventType::KeyPress(*key));
+    }
+    for key in keys.iter().rev() {
+        harness.input.physical(EventType::KeyRelease(*key));
+    }
+    call.join().unwrap()
+}
+
+/// The JSON content item carrying the approval outcome.
+fn approval(response: &Value) -> Value {
+    response["result"]["content"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter_map(|item| serde_json::from_str::<Value>(item["text"].as_str()?).ok())
+        .find(|value| value.get("approval").is_some())
+        .unwrap_or_else(|| panic!("No approval in {}", response))
+}
+
+#[test]
+fn the_chord_approves_the_call() {
+    let harness = setup_approval();
+    let response = move_pressing(&harness, &[Key::ControlLeft, Key::ShiftRight, Key::KeyY]);
+    assert!(response["result"].get("isError").is_none(), "{}", response);
+    assert_eq!(approval(&response)["approval"], json!("approved"));
+    assert_eq!(harness.input.events(), vec![EventType::MouseMove { x: 10.0, y: 20.0 }]);
+}
+
+#[test]
+fn the_key_without_its_modifiers_doesnt_approve() {
+    let harness = setup_approval();
+    // y alone is ignored; Escape then denies
+    let response = move_pressing(&harness, &[Key::KeyY, Key::Escape]);
+    assert_eq!(approval(&response)["approval"], json!("denied"));
+    assert_eq!(harness.input.events(), vec![]);
+}
+
+#[test]
+fn escape_denies_the_call() {
+    let harness = setup_approval();
+    let response = move_pressing(&harness, &[Key::Escape]);
+    assert_eq!(response["result"]["isError"], json!(true), "{}", response);
+    let result = approval(&response);
+    assert_eq!(result["approval"], json!("denied"));
+    assert_eq!(result["error_code"], json!("permission_denied"));
+    assert_eq!(harness.input.events(), vec![]);
+}
@@ -0,0 +1,50 @@
+mod common;
+
+use serde_json::{json, Value};
+
+use common::*;
+
+fn setup_audited() -> Harness {
+    let path = std::env::temp_dir().join(format!("desktop-mcp-audit-{}.jsonl", std::process::id()));
+    desktop_mcp::open_audit_log(Some(path)).unwrap();
+    setup()
+}
+
+fn last_entries(count: usize) -> Vec<Value> {
+    let tail = call_ok("get_audit_tail", json!({ "count": count }));
+    tail["entries"].as_array().unwrap_or_else(|| panic!("No entries in {}", tail)).clone()
+}
+
+fn last_entry() -> Value {
+    last_entries(1).pop().unwrap()
+}
+
+#[test]
+fn typed_text_is_logged_only_by_length() {
+    let _harness = setup_audited();
+    call_ok("type_text", json!({ "text": "hunter2", "delay_ms": 0 }));
+    let entry = last_entry();
+    assert_eq!(entry["tool"], json!("type_text"));
+    assert_eq!(entry["arguments"]["text"], json!({ "redacted": true, "chars": 7 }));
+    assert!(!entry.to_string().contains("hunter2"), "{}", entry);
+
+    call_ok("set_text_field", json!({ "text": "pässword", "delay_ms": 0, "layout": "de" }));
+    let entry = last_entry();
+    assert_eq!(entry["arguments"]["text"], json!({ "redacted": true, "chars": 8 }));
+}
+
+#[test]
+fn text_inside_sequences_is_redacted_too() {
+    let _harness = setup_audited();
+    call_ok(
+        "run_sequence",
+        json!({ "steps": [{ "tool": "type_text", "arguments": { "text": "s3cret", "delay_ms": 0 } }] }),
+    );
+    // The step is logged as well as the sequence
+    let entries = last_entries(2);
+    assert_eq!(entries[0]["tool"], json!("type_text"));
+    assert_eq!(entries[1]["tool"], json!("run_sequence"));
+    for entry in entries {
+        assert!(!entry.to_string().contains("s3cret"), "{}", entry);
+    }
+}
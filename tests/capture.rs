@@ -0,0 +1,207 @@
+mod common;
+
+use image::imageops;
+use serde_json::json;
+
+use desktop_mcp::backend::{FakeInput, FakeScreen};
+
+use common::*;
+
+#[test]
+fn region_is_cropped_from_the_screen() {
+    let _harness = setup();
+    let response = call("screen_capture", json!({ "region": { "x": 100, "y": 50, "width": 64, "height": 32 }, "format": "png" }));
+    let images = images(&response);
+    assert_eq!(images.len(), 1);
+    let expected = imageops::crop_imm(&gradient(1920, 1080), 100, 50, 64, 32).to_image();
+    assert_eq!(images[0], expected);
+
+    let result = json_content(&response["result"]);
+    assert_eq!(result["origin"], json!({ "x": 100.0, "y": 50.0 }));
+    assert_eq!(result["output_width"], json!(64));
+    assert_eq!(result["output_height"], json!(32));
+    assert_eq!(result["region_applied"], json!(true));
+}
+
+#[test]
+fn region_on_a_scaled_screen_is_in_logical_points() {
+    let screen = gradient(1920, 1080);
+    let _harness = setup_screen(&screen, 2.0, FakeInput::default());
+    let response = call("screen_capture", json!({ "region": { "x": 100, "y": 50, "width": 64, "height": 32 }, "format": "png" }));
+    // Twice the pixels, starting twice as far in
+    let expected = imageops::crop_imm(&screen, 200, 100, 128, 64).to_image();
+    assert_eq!(images(&response), vec![expected]);
+
+    let result = json_content(&response["result"]);
+    assert_eq!(result["origin"], json!({ "x": 100.0, "y": 50.0 }));
+    assert_eq!(result["logical_per_pixel"], json!(0.5));
+    assert_eq!((result["original_width"].clone(), result["original_height"].clone()), (json!(128), json!(64)));
+}
+
+#[test]
+fn downscaled_captures_report_how_to_map_back() {
+    let _harness = setup();
+    let response = call(
+        "screen_capture",
+        json!({ "region": { "x": 0, "y": 0, "width": 400, "height": 200 }, "max_width": 100, "format": "png" }),
+    );
+    let images = images(&response);
+    assert_eq!(images[0].dimensions(), (100, 50));
+    let result = json_content(&response["result"]);
+    assert_eq!(result["output_width"], json!(100));
+    assert_eq!(result["original_width"], json!(400));
+    assert_eq!(result["scale_ratio"], json!(0.25));
+    assert_eq!(result["logical_per_pixel"], json!(4.0));
+}
+
+#[test]
+fn capture_result_shape() {
+    let _harness = setup();
+    let response = call("screen_capture", json!({ "max_width": 480, "format": "jpeg" }));
+    let content = response["result"]["content"].as_array().unwrap();
+    assert_eq!(content.len(), 2, "{}", response);
+    assert_eq!(content[0]["type"], json!("text"));
+    assert_eq!(content[1]["type"], json!("image"));
+    assert_eq!(content[1]["mimeType"], json!("image/jpeg"));
+    assert!(response["result"]["_meta"]["timestamp"].is_string());
+
+    let result = json_content(&response["result"]);
+    for field in [
+        "type", "format", "mimeType", "encoding", "bytes", "origin", "original_width", "original_height",
+        "output_width", "output_height", "scale_ratio", "logical_per_pixel", "screen_index", "captured_at",
+        "capture_seq",
+    ] {
+        assert!(result.get(field).is_some(), "{} missing from {}", field, result);
+    }
+    assert_eq!(result["original_width"], json!(1920));
+    assert_eq!(result["output_width"], json!(480));
+    assert_eq!(result["output_height"], json!(270));
+    assert_eq!(images(&response)[0].dimensions(), (480, 270));
+}
+
+#[test]
+fn screen_info_describes_the_fake_screen() {
+    let _harness = setup_screen(&gradient(640, 400), 2.0, FakeInput::default());
+    let response = call_ok("get_screen_info", json!({ "refresh": true }));
+    let screens = response["screens"].as_array().unwrap_or_else(|| panic!("No screens in {}", response));
+    assert_eq!(screens.len(), 1);
+    assert_eq!(screens[0]["width"], json!(320));
+    assert_eq!(screens[0]["height"], json!(200));
+    assert_eq!(screens[0]["scale_factor"], json!(2.0));
+}
+
+/// An 800x600 screen whose top-left corner is at (-800, -600), as a monitor
+/// left of and above the primary one would be.
+fn setup_negative_origin() -> Harness {
+    let screen = FakeScreen::from_png(&png(&gradient(800, 600)), 1.0).unwrap().with_origin(-800, -600);
+    setup_fakes(screen, FakeInput::default())
+}
+
+#[test]
+fn screen_regions_with_negative_origins_are_rejected_with_the_screen_size() {
+    let _harness = setup_negative_origin();
+    for (x, y) in [(-1, 0), (0, -1), (-800, -600)] {
+        let error = call_err(
+            "screen_capture",
+            json!({ "region": { "x": x, "y": y, "width": 10, "height": 10 }, "format": "png" }),
+        );
+        let message = error["message"].as_str().unwrap();
+        assert!(message.contains("negative") && message.contains("800x600"), "({}, {}): {}", x, y, message);
+    }
+}
+
+#[test]
+fn region_fields_past_i32_are_rejected_rather_than_wrapped() {
+    let _harness = setup();
+    for field in ["x", "width"] {
+        let mut region = json!({ "x": 0, "y": 0, "width": 10, "height": 10 });
+        region[field] = json!(3_000_000_000u64);
+        let error = call_err("screen_capture", json!({ "region": region, "format": "png" }));
+        assert!(error["message"].as_str().unwrap().contains("out of range"), "{}: {}", field, error);
+    }
+}
+
+#[test]
+fn screen_regions_on_a_negative_origin_screen_report_global_origins() {
+    let _harness = setup_negative_origin();
+    let response = call("screen_capture", json!({ "region": { "x": 100, "y": 50, "width": 64, "height": 32 }, "format": "png" }));
+    let expected = imageops::crop_imm(&gradient(800, 600), 100, 50, 64, 32).to_image();
+    assert_eq!(images(&response), vec![expected]);
+    assert_eq!(json_content(&response["result"])["origin"], json!({ "x": -700.0, "y": -550.0 }));
+}
+
+#[test]
+fn global_regions_at_negative_coordinates_are_captured() {
+    let _harness = setup_negative_origin();
+    let response = call(
+        "screen_capture",
+        json!({ "region": { "x": -700, "y": -550, "width": 64, "height": 32 }, "coordinates": "global", "format": "png" }),
+    );
+    let expected = imageops::crop_imm(&gradient(800, 600), 100, 50, 64, 32).to_image();
+    assert_eq!(images(&response), vec![expected]);
+    let result = json_content(&response["result"]);
+    assert_eq!(result["origin"], json!({ "x": -700.0, "y": -550.0 }));
+    assert_eq!(result["clamped"], json!(false));
+    assert_eq!(result["screens"], json!([0]));
+}
+
+#[test]
+fn global_regions_past_a_negative_edge_are_clamped_or_refused() {
+    let _harness = setup_negative_origin();
+    let region = json!({ "x": -900, "y": -650, "width": 200, "height": 100 });
+    let response = call("screen_capture", json!({ "region": region, "coordinates": "global", "format": "png" }));
+    let expected = imageops::crop_imm(&gradient(800, 600), 0, 0, 100, 50).to_image();
+    assert_eq!(images(&response), vec![expected]);
+    let result = json_content(&response["result"]);
+    assert_eq!(result["origin"], json!({ "x": -800.0, "y": -600.0 }));
+    assert_eq!(result["clamped"], json!(true));
+
+    let error = call_err(
+        "screen_capture",
+        json!({ "region": region, "coordinates": "global", "strict_bounds": true, "format": "png" }),
+    );
+    assert!(error["message"].as_str().unwrap().contains("past the desktop edge"), "{}", error);
+}
+
+#[test]
+fn screen_relative_mouse_coordinates_on_a_negative_origin_screen_are_global() {
+    let harness = setup_negative_origin();
+    call_ok("mouse_move", json!({ "x": 10, "y": 20, "screen_index": 0 }));
+    assert_eq!(harness.input.events(), vec![rdev::EventType::MouseMove { x: -790.0, y: -580.0 }]);
+}
+
+/// Pixels from a hash of their position: the worst case for every format.
+fn noise(width: u32, height: u32) -> image::RgbaImage {
+    image::RgbaImage::from_fn(width, height, |x, y| {
+        let h = (x.wrapping_mul(0x9E37_79B1) ^ y.wrapping_mul(0x85EB_CA77)).wrapping_mul(0xC2B2_AE3D);
+        image::Rgba([h as u8, (h >> 8) as u8, (h >> 16) as u8, 255])
+    })
+}
+
+#[test]
+fn estimates_bracket_the_encoded_size() {
+    let cases = [
+        ("gradient", gradient(1920, 1080), json!({ "format": "jpeg" })),
+        ("gradient", gradient(1920, 1080), json!({ "format": "jpeg", "max_width": 960, "quality": 50 })),
+        ("noise", noise(1920, 1080), json!({ "format": "jpeg", "max_width": 1280 })),
+        ("noise", noise(1920, 1080), json!({ "format": "png", "max_width": 640 })),
+    ];
+    for (name, screen, args) in cases {
+        let _harness = setup_screen(&screen, 1.0, FakeInput::default());
+        let estimate = call_ok("estimate_capture", args.clone());
+        let captured = call_ok("screen_capture", args.clone());
+        assert_eq!(estimate["output_width"], captured["output_width"], "{} {}", name, args);
+
+        let actual = captured["bytes"].as_u64().unwrap();
+        let bytes = &estimate["bytes"];
+        let (low, high) = (bytes["low"].as_u64().unwrap(), bytes["high"].as_u64().unwrap());
+        assert!(
+            (low..=high).contains(&actual),
+            "{} {}: {} bytes, estimated {}",
+            name,
+            args,
+            actual,
+            bytes
+        );
+    }
+}
@@ -0,0 +1,127 @@
+//! Helpers shared by the integration tests: calling tools the way a client
+//! would, against a fake screen and fake input.
+
+#![allow(dead_code)]
+
+use base64::Engine;
+use image::{DynamicImage, ImageFormat, Rgba, RgbaImage};
+use serde_json::{json, Value};
+use std::io::Cursor;
+use std::sync::{Mutex, MutexGuard};
+
+use desktop_mcp::backend::{set_input_backend, set_screen_backend, FakeInput, FakeScreen};
+use desktop_mcp::{handle_request_catching_panics, parse_request};
+
+/// The backends are global, so tests in one binary take turns.
+static LOCK: Mutex<()> = Mutex::new(());
+
+/// A test's hold on the backends, with the fake input it installed.
+pub struct Harness {
+    pub input: FakeInput,
+    _lock: MutexGuard<'static, ()>,
+}
+
+/// Install fresh fakes: a 1920x1080 screen at scale 1 and input that
+/// records what it's sent.
+pub fn setup() -> Harness {
+    setup_with(FakeInput::default())
+}
+
+/// As setup, but with the given fake input.
+pub fn setup_with(input: FakeInput) -> Harness {
+    setup_screen(&gradient(1920, 1080), 1.0, input)
+}
+
+/// Install a screen showing `image` at `scale_factor`, and `input`.
+pub fn setup_screen(image: &RgbaImage, scale_factor: f32, input: FakeInput) -> Harness {
+    setup_fakes(FakeScreen::from_png(&png(image), scale_factor).unwrap(), input)
+}
+
+/// Install `screen` and `input`.
+pub fn setup_fakes(screen: FakeScreen, input: FakeInput) -> Harness {
+    let lock = LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    set_screen_backend(screen);
+    set_input_backend(input.clone());
+    Harness { input, _lock: lock }
+}
+
+/// A screen image whose every pixel is distinct enough to locate a crop by.
+pub fn gradient(width: u32, height: u32) -> RgbaImage {
+    RgbaImage::from_fn(width, height, |x, y| Rgba([(x % 256) as u8, (y % 256) as u8, ((x / 256) * 16 + y / 256) as u8, 255]))
+}
+
+pub fn png(image: &RgbaImage) -> Vec<u8> {
+    let mut png = Vec::new();
+    DynamicImage::ImageRgba8(image.clone())
+        .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+        .unwrap();
+    png
+}
+
+/// The images in a tool result, decoded.
+pub fn images(response: &Value) -> Vec<RgbaImage> {
+    response["result"]["content"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter(|item| item["type"] == json!("image"))
+        .map(|item| {
+            let data = base64::engine::general_purpose::STANDARD
+                .decode(item["data"].as_str().unwrap())
+                .unwrap();
+            image::load_from_memory(&data).unwrap().to_rgba8()
+        })
+        .collect()
+}
+
+/// Send one JSON-RPC request and return the response.
+pub fn request(method: &str, params: Value) -> Value {
+    let line = json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params }).to_string();
+    let request = parse_request(&line).unwrap_or_else(|e| panic!("Bad request: {:?}", e));
+    serde_json::to_value(handle_request_catching_panics(&request)).unwrap()
+}
+
+/// Call a tool and return the JSON-RPC response.
+pub fn call(tool: &str, arguments: Value) -> Value {
+    request("tools/call", json!({ "name": tool, "arguments": arguments }))
+}
+
+/// The JSON a tool result carries: without a session the oldest protocol
+/// revision is spoken, so it's the first text item that parses as JSON
+/// rather than structuredContent.
+pub fn json_content(result: &Value) -> Value {
+    result["content"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|item| item["text"].as_str())
+        .find_map(|text| serde_json::from_str(text).ok())
+        .unwrap_or(Value::Null)
+}
+
+/// Call a tool that should succeed and return its JSON result.
+pub fn call_ok(tool: &str, arguments: Value) -> Value {
+    let response = call(tool, arguments);
+    let result = &response["result"];
+    assert!(
+        response.get("error").is_none() && result["isError"] != json!(true),
+        "{} failed: {}",
+        tool,
+        response
+    );
+    json_content(result)
+}
+
+/// Call a tool that should fail and return its error: the JSON-RPC error,
+/// or for a failed call, the result's error details with its message.
+pub fn call_err(tool: &str, arguments: Value) -> Value {
+    let response = call(tool, arguments);
+    if let Some(error) = response.get("error") {
+        return error.clone();
+    }
+    let result = &response["result"];
+    assert_eq!(result["isError"], json!(true), "{} succeeded: {}", tool, response);
+    let mut error = json_content(result);
+    error["message"] = result["content"][0]["text"].clone();
+    error
+}
@@ -0,0 +1,41 @@
+mod common;
+
+use serde_json::{json, Value};
+
+use common::*;
+
+/// The restoration report appended to a result, if any.
+fn cursor_report(response: &Value) -> Option<Value> {
+    response["result"]["content"]
+        .as_array()?
+        .iter()
+        .filter_map(|item| item["text"].as_str())
+        .filter_map(|text| serde_json::from_str::<Value>(text).ok())
+        .find(|value| value.get("cursor_restored").is_some())
+}
+
+#[test]
+#[cfg(not(target_os = "macos"))]
+fn restoring_after_input_reports_when_the_cursor_cant_be_read() {
+    let _harness = setup();
+    let response = call("mouse_click", json!({ "x": 10, "y": 20, "restore_cursor": true }));
+    let report = cursor_report(&response).unwrap_or_else(|| panic!("No report in {}", response));
+    assert_eq!(report["cursor_restored"], json!(false));
+    assert!(report["reason"].as_str().unwrap().contains("can't be read"), "{}", report);
+}
+
+#[test]
+fn calls_that_send_no_input_leave_the_cursor_alone() {
+    let harness = setup();
+    let response = call("get_screen_info", json!({ "restore_cursor": true }));
+    assert_eq!(cursor_report(&response), None, "{}", response);
+    assert_eq!(harness.input.events(), vec![]);
+}
+
+#[test]
+fn input_calls_dont_restore_unless_asked() {
+    let harness = setup();
+    let response = call("mouse_move", json!({ "x": 10, "y": 20 }));
+    assert_eq!(cursor_report(&response), None, "{}", response);
+    assert_eq!(harness.input.events().len(), 1);
+}
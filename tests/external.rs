@@ -0,0 +1,129 @@
+//! External tools, run from the scripts in tests/fixtures/external.
+
+#![cfg(unix)]
+
+mod common;
+
+use serde_json::json;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use common::*;
+
+fn setup_external() -> Harness {
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/external/tools.json");
+    std::env::set_var("DESKTOP_MCP_EXTERNAL_TOOLS", path);
+    setup()
+}
+
+#[test]
+fn external_tools_are_listed_unless_their_name_is_taken() {
+    let _harness = setup_external();
+    let tools = request("tools/list", json!({}))["result"]["tools"].clone();
+    let tools = tools.as_array().unwrap();
+    let echo = tools.iter().find(|t| t["name"] == json!("echo_args")).unwrap();
+    assert_eq!(echo["inputSchema"]["required"], json!(["message"]));
+    assert_eq!(tools.iter().filter(|t| t["name"] == json!("mouse_move")).count(), 1);
+}
+
+#[test]
+fn arguments_go_in_on_stdin_and_json_comes_back() {
+    let _harness = setup_external();
+    let result = call_ok("echo_args", json!({ "message": "hello" }));
+    assert_eq!(result, json!({ "message": "hello" }));
+
+    let error = call_err("echo_args", json!({}));
+    assert_eq!(error["code"], json!(-32602), "{}", error);
+}
+
+#[test]
+fn bare_program_names_are_found_on_path() {
+    let _harness = setup_external();
+    let result = call_ok("path_cat", json!({ "n": 1 }));
+    assert_eq!(result, json!({ "n": 1 }));
+}
+
+#[test]
+fn failures_name_what_went_wrong() {
+    let _harness = setup_external();
+    let error = call_err("fail", json!({}));
+    assert_eq!(error["error"], json!("exit_status"), "{}", error);
+    assert_eq!(error["exit_code"], json!(3));
+    assert_eq!(error["stderr"], json!("something went wrong"));
+
+    let start = Instant::now();
+    let error = call_err("slow", json!({}));
+    assert_eq!(error["error"], json!("timeout"), "{}", error);
+    assert!(start.elapsed() < Duration::from_secs(4));
+
+    let error = call_err("chatty", json!({}));
+    assert_eq!(error["error"], json!("output_too_large"), "{}", error);
+    assert_eq!(error["max_output_bytes"], json!(100));
+}
+
+#[test]
+fn read_only_tools_dont_hold_up_input() {
+    let harness = setup_external();
+    let pause = thread::spawn(|| call_ok("pause", json!({})));
+    thread::sleep(Duration::from_millis(200));
+    let start = Instant::now();
+    call_ok("mouse_move", json!({ "x": 10, "y": 20 }));
+    assert!(start.elapsed() < Duration::from_millis(700), "mouse_move waited {:?}", start.elapsed());
+    pause.join().unwrap();
+    assert_eq!(harness.input.events().len(), 1);
+}
+
+#[test]
+fn tools_hold_up_input_unless_they_say_otherwise() {
+    let _harness = setup_external();
+    let elapsed = |tool: &'static str| {
+        let running = thread::spawn(move || call_ok(tool, json!({})));
+        thread::sleep(Duration::from_millis(200));
+        let start = Instant::now();
+        call_ok("mouse_move", json!({ "x": 10, "y": 20 }));
+        let elapsed = start.elapsed();
+        running.join().unwrap();
+        elapsed
+    };
+    assert!(elapsed("hold") >= Duration::from_millis(500), "mouse_move ran alongside hold");
+    let alongside = elapsed("pause_alongside");
+    assert!(alongside < Duration::from_millis(700), "mouse_move waited {:?}", alongside);
+}
+
+#[test]
+fn server_arguments_are_kept_from_tools_unless_their_schema_claims_them() {
+    let _harness = setup_external();
+    let result = call_ok("echo_args", json!({ "message": "hello", "limit": 1, "restore_cursor": false }));
+    assert_eq!(result, json!({ "message": "hello" }));
+
+    // paged declares a limit of its own, so it gets it and the result isn't
+    // cut to it
+    let result = call_ok("paged", json!({ "items": [1, 2, 3], "limit": 1 }));
+    assert_eq!(result, json!({ "items": [1, 2, 3], "limit": 1 }));
+}
+
+/// Dry run can't be turned off again, so the test runs in a process of its
+/// own: this test binary, started again for just this test.
+#[test]
+fn a_dry_run_runs_nothing() {
+    if std::env::var_os("DESKTOP_MCP_TEST_DRY_RUN").is_none() {
+        let status = std::process::Command::new(std::env::current_exe().unwrap())
+            .args(["--exact", "a_dry_run_runs_nothing"])
+            .env("DESKTOP_MCP_TEST_DRY_RUN", "1")
+            .status()
+            .unwrap();
+        assert!(status.success());
+        return;
+    }
+    desktop_mcp::set_dry_run();
+    let _harness = setup_external();
+
+    // fail would exit with an error if it ran
+    let result = call_ok("fail", json!({}));
+    assert_eq!(result["simulated"], json!(true), "{}", result);
+    assert!(result["command"][0].as_str().unwrap().ends_with("fail.sh"), "{}", result);
+    assert_eq!(result["input"], json!({}));
+
+    let result = call_ok("notify", json!({ "title": "Done" }));
+    assert_eq!(result["simulated"], json!(true), "{}", result);
+}
@@ -0,0 +1,107 @@
+//! Input refused while the MCP client's own window is frontmost, with the
+//! windows on a fake screen.
+
+mod common;
+
+use rdev::EventType;
+use serde_json::json;
+
+use desktop_mcp::backend::{FakeInput, FakeScreen, WindowInfo};
+
+use common::*;
+
+/// App name substring that marks the client's windows in these tests.
+const CLIENT_PATTERN: &str = "test client";
+
+fn window(id: u64, title: &str, app_name: &str, pid: i64) -> WindowInfo {
+    WindowInfo {
+        id,
+        title: title.to_string(),
+        app_name: app_name.to_string(),
+        pid,
+        x: 0.0,
+        y: 0.0,
+        width: 800.0,
+        height: 600.0,
+    }
+}
+
+/// A window of the client's, recognized by its app name.
+fn client_window() -> WindowInfo {
+    window(1, "Chat", "My Test Client", 999_999)
+}
+
+fn editor_window() -> WindowInfo {
+    window(2, "notes.txt", "Editor", 999_998)
+}
+
+/// Install a screen showing `windows`, frontmost first.
+fn setup_windows(windows: Vec<WindowInfo>) -> Harness {
+    // Read once per process, so every test sets the same value
+    std::env::set_var("DESKTOP_MCP_CLIENT_WINDOW_PATTERNS", CLIENT_PATTERN);
+    let screen = FakeScreen::from_png(&png(&gradient(1920, 1080)), 1.0).unwrap();
+    setup_fakes(screen.with_windows(windows), FakeInput::default())
+}
+
+#[test]
+fn input_to_the_clients_window_is_refused() {
+    let harness = setup_windows(vec![client_window(), editor_window()]);
+    let error = call_err("mouse_move", json!({ "x": 10, "y": 20 }));
+    assert_eq!(error["error"], json!("SelfTargetBlocked"), "{}", error);
+    assert_eq!(error["error_code"], json!("permission_denied"));
+    assert_eq!(error["window"]["title"], json!("Chat"));
+    assert!(error["message"].as_str().unwrap().contains("My Test Client"), "{}", error);
+    assert_eq!(harness.input.events(), vec![]);
+}
+
+#[test]
+fn every_input_tool_is_guarded() {
+    let harness = setup_windows(vec![client_window()]);
+    for (tool, args) in [
+        ("mouse_click", json!({ "x": 10, "y": 20 })),
+        ("key_tap", json!({ "key": "a" })),
+        ("type_text", json!({ "text": "hi", "delay_ms": 0 })),
+    ] {
+        let error = call_err(tool, args);
+        assert_eq!(error["error"], json!("SelfTargetBlocked"), "{}: {}", tool, error);
+    }
+    assert_eq!(harness.input.events(), vec![]);
+}
+
+#[cfg(unix)]
+#[test]
+fn windows_of_an_ancestor_process_are_the_clients() {
+    // The test runner's parent stands in for the client that spawned us
+    let parent = std::os::unix::process::parent_id() as i64;
+    let harness = setup_windows(vec![window(3, "Terminal", "Shell", parent)]);
+    let error = call_err("mouse_move", json!({ "x": 10, "y": 20 }));
+    assert_eq!(error["error"], json!("SelfTargetBlocked"), "{}", error);
+    assert_eq!(harness.input.events(), vec![]);
+}
+
+#[test]
+fn input_is_allowed_when_another_window_is_frontmost() {
+    let harness = setup_windows(vec![editor_window(), client_window()]);
+    call_ok("mouse_move", json!({ "x": 10, "y": 20 }));
+    assert_eq!(harness.input.events(), vec![EventType::MouseMove { x: 10.0, y: 20.0 }]);
+}
+
+#[test]
+fn input_is_allowed_without_a_frontmost_window() {
+    let harness = setup_windows(vec![]);
+    call_ok("mouse_move", json!({ "x": 10, "y": 20 }));
+    assert_eq!(harness.input.events(), vec![EventType::MouseMove { x: 10.0, y: 20.0 }]);
+}
+
+#[test]
+fn the_guard_can_be_overridden_for_a_call() {
+    let harness = setup_windows(vec![client_window()]);
+    call_ok("mouse_move", json!({ "x": 10, "y": 20, "allow_self_target": true }));
+    assert_eq!(harness.input.events(), vec![EventType::MouseMove { x: 10.0, y: 20.0 }]);
+}
+
+#[test]
+fn tools_that_send_no_input_arent_guarded() {
+    let _harness = setup_windows(vec![client_window()]);
+    call_ok("screen_capture", json!({ "max_width": 480 }));
+}
@@ -0,0 +1,39 @@
+mod common;
+
+use rdev::{EventType, Key};
+use serde_json::json;
+
+use common::*;
+
+fn tap(key: Key) -> Vec<EventType> {
+    vec![EventType::KeyPress(key), EventType::KeyRelease(key)]
+}
+
+fn shifted(key: Key) -> Vec<EventType> {
+    let mut events = vec![EventType::KeyPress(Key::ShiftLeft)];
+    events.extend(tap(key));
+    events.push(EventType::KeyRelease(Key::ShiftLeft));
+    events
+}
+
+#[test]
+fn type_text_holds_shift_only_around_shifted_characters() {
+    let harness = setup();
+    let result = call_ok("type_text", json!({ "text": "aB!c", "delay_ms": 0, "layout": "us" }));
+    let expected: Vec<EventType> = [tap(Key::KeyA), shifted(Key::KeyB), shifted(Key::Num1), tap(Key::KeyC)].concat();
+    assert_eq!(harness.input.events(), expected);
+
+    assert_eq!(result["chars"], json!(4));
+    assert_eq!(result["requested_chars"], json!(4));
+    assert_eq!(result["complete"], json!(true));
+    assert_eq!(result["skipped"], json!([]));
+    assert_eq!(result["layout"], json!("us"));
+}
+
+#[test]
+fn type_text_follows_the_layout() {
+    let harness = setup();
+    call_ok("type_text", json!({ "text": "zy", "delay_ms": 0, "layout": "de" }));
+    // German keyboards swap Y and Z
+    assert_eq!(harness.input.events(), [tap(Key::KeyY), tap(Key::KeyZ)].concat());
+}
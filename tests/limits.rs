@@ -0,0 +1,28 @@
+mod common;
+
+use serde_json::json;
+
+use common::*;
+
+/// The config file's limits, loaded once for every test here.
+fn setup_limited() -> Harness {
+    let path = std::env::temp_dir().join(format!("desktop-mcp-limits-{}.toml", std::process::id()));
+    std::fs::write(&path, "[limits]\nmax_type_text_length = 5\n").unwrap();
+    desktop_mcp::load_config(Some(path.clone())).unwrap();
+    let _ = std::fs::remove_file(path);
+    setup()
+}
+
+#[test]
+fn text_length_limit_covers_every_typing_tool() {
+    let harness = setup_limited();
+    for tool in ["type_text", "set_text_field"] {
+        let error = call_err(tool, json!({ "text": "too long", "delay_ms": 0 }));
+        assert_eq!(error["error_code"], json!("throttled"), "{}: {}", tool, error);
+        assert!(error["message"].as_str().unwrap().contains("max 5"), "{}: {}", tool, error);
+    }
+    assert_eq!(harness.input.events(), vec![]);
+
+    call_ok("set_text_field", json!({ "text": "short", "delay_ms": 0 }));
+    assert!(!harness.input.events().is_empty());
+}
@@ -0,0 +1,135 @@
+//! Shared-server connections from --listen.
+
+use serde_json::{json, Value};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use desktop_mcp::{serve_http, serve_listen};
+
+const PING: &str = r#"{"jsonrpc":"2.0","id":7,"method":"ping"}"#;
+
+/// Keep trying to connect until the server is listening.
+fn connect<T>(connect: impl Fn() -> std::io::Result<T>) -> T {
+    let start = Instant::now();
+    loop {
+        match connect() {
+            Ok(stream) => return stream,
+            Err(e) if start.elapsed() > Duration::from_secs(5) => panic!("Couldn't connect: {}", e),
+            Err(_) => thread::sleep(Duration::from_millis(20)),
+        }
+    }
+}
+
+fn free_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port()
+}
+
+fn ping(mut stream: impl Read + Write) -> Value {
+    writeln!(stream, "{}", PING).unwrap();
+    let mut line = String::new();
+    BufReader::new(stream).read_line(&mut line).unwrap();
+    serde_json::from_str(&line).unwrap()
+}
+
+#[test]
+fn tcp_needs_a_token() {
+    let error = serve_listen("tcp:127.0.0.1:1", None).unwrap_err();
+    assert!(error.contains("token"), "{}", error);
+    assert!(serve_listen("tcp:127.0.0.1:1", Some(String::new())).is_err());
+    assert!(serve_listen("tcp:0.0.0.0:1", Some("secret".to_string())).is_err());
+}
+
+#[test]
+fn tcp_clients_must_send_the_token_first() {
+    let port = free_port();
+    thread::spawn(move || serve_listen(&format!("tcp:127.0.0.1:{}", port), Some("secret".to_string())));
+
+    let mut refused = connect(|| TcpStream::connect(("127.0.0.1", port)));
+    writeln!(refused, "{}", PING).unwrap();
+    let mut rest = Vec::new();
+    refused.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    assert_eq!(refused.read_to_end(&mut rest).unwrap_or(0), 0, "{}", String::from_utf8_lossy(&rest));
+
+    let mut wrong = connect(|| TcpStream::connect(("127.0.0.1", port)));
+    writeln!(wrong, "guess").unwrap();
+    wrong.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    assert_eq!(wrong.read_to_end(&mut rest).unwrap_or(0), 0);
+
+    let mut client = connect(|| TcpStream::connect(("127.0.0.1", port)));
+    writeln!(client, "secret").unwrap();
+    assert_eq!(ping(client), json!({ "jsonrpc": "2.0", "id": 7, "result": {} }));
+}
+
+#[cfg(unix)]
+#[test]
+fn unix_socket_is_private_from_the_start() {
+    use std::os::unix::fs::PermissionsExt;
+    use std::os::unix::net::UnixStream;
+
+    let dir = std::env::temp_dir().join(format!("desktop-mcp-listen-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("server.sock");
+    let spec = format!("unix:{}", path.display());
+    thread::spawn(move || serve_listen(&spec, None));
+
+    let client = connect(|| UnixStream::connect(&path));
+    let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o600);
+    assert_eq!(ping(client), json!({ "jsonrpc": "2.0", "id": 7, "result": {} }));
+    // Nothing is left behind from binding it
+    let entries: Vec<_> = std::fs::read_dir(&dir).unwrap().map(|e| e.unwrap().file_name()).collect();
+    assert_eq!(entries, vec![std::ffi::OsString::from("server.sock")]);
+}
+
+/// POST an initialize request over HTTP and return the session id it's
+/// given.
+fn http_session_id(port: u16) -> String {
+    let body = r#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{"protocolVersion":"2025-06-18","capabilities":{},"clientInfo":{"name":"test","version":"0"}}}"#;
+    let mut stream = connect(|| TcpStream::connect(("127.0.0.1", port)));
+    write!(
+        stream,
+        "POST /mcp HTTP/1.1\r\nHost: 127.0.0.1\r\nAuthorization: Bearer secret\r\nContent-Type: application/json\r\nAccept: application/json, text/event-stream\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        body.len(),
+        body
+    )
+    .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    response
+        .lines()
+        .find_map(|line| line.strip_prefix("Mcp-Session-Id: "))
+        .unwrap_or_else(|| panic!("No session id in {}", response))
+        .trim()
+        .to_string()
+}
+
+#[test]
+fn http_session_ids_are_random() {
+    let port = free_port();
+    thread::spawn(move || serve_http(&format!("127.0.0.1:{}", port), Some("secret".to_string())));
+
+    let first = http_session_id(port);
+    let second = http_session_id(port);
+    for id in [&first, &second] {
+        assert_eq!(id.len(), 32, "{}", id);
+        assert!(id.chars().all(|c| c.is_ascii_hexdigit()), "{}", id);
+    }
+    assert_ne!(first, second);
+}
+
+#[test]
+fn http_requests_without_the_token_are_refused_before_their_body() {
+    let port = free_port();
+    thread::spawn(move || serve_http(&format!("127.0.0.1:{}", port), Some("secret".to_string())));
+
+    // The body never comes, so only a refusal from the headers can answer
+    let mut stream = connect(|| TcpStream::connect(("127.0.0.1", port)));
+    stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    write!(stream, "POST /mcp HTTP/1.1\r\nHost: 127.0.0.1\r\nAuthorization: Bearer wrong\r\nContent-Length: 1000000\r\n\r\n")
+        .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    assert!(response.starts_with("HTTP/1.1 401 "), "{}", response);
+}
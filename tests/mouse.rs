@@ -0,0 +1,278 @@
+mod common;
+
+use rdev::{Button, EventType};
+use serde_json::{json, Value};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use common::*;
+
+/// Each mouse tool that takes a button, with arguments it accepts.
+fn button_calls() -> Vec<(&'static str, Value)> {
+    vec![
+        ("mouse_click", json!({ "x": 10, "y": 20 })),
+        ("mouse_drag", json!({ "from_x": 10, "from_y": 20, "to_x": 30, "to_y": 40, "duration_ms": 0 })),
+        ("mouse_drag_path", json!({ "points": [{ "x": 10, "y": 20 }, { "x": 30, "y": 40 }], "segment_duration_ms": 0 })),
+        ("mouse_long_press", json!({ "x": 10, "y": 20, "hold_ms": 0 })),
+    ]
+}
+
+fn with_button(args: &Value, button: &str) -> Value {
+    let mut args = args.clone();
+    args["button"] = json!(button);
+    args
+}
+
+#[test]
+fn misspelled_buttons_are_rejected_before_anything_is_sent() {
+    let harness = setup();
+    for (tool, args) in button_calls() {
+        for button in ["rigth", "Middle ", "LEFT", "", "x3"] {
+            let error = call_err(tool, with_button(&args, button));
+            assert_eq!(error["code"], json!(-32602), "{} with {:?}: {}", tool, button, error);
+            assert_eq!(error["data"]["field"], json!("button"), "{} with {:?}", tool, button);
+            let message = error["message"].as_str().unwrap();
+            for name in ["left", "right", "middle", "back", "forward", "x1", "x2"] {
+                assert!(message.contains(name), "{} doesn't list {}: {}", tool, name, message);
+            }
+        }
+    }
+    assert_eq!(harness.input.events(), vec![]);
+}
+
+#[test]
+fn button_schemas_list_the_accepted_names() {
+    let _harness = setup();
+    let tools = request("tools/list", json!({}))["result"]["tools"].clone();
+    for (tool, _) in button_calls() {
+        let definition = tools
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|t| t["name"] == json!(tool))
+            .unwrap_or_else(|| panic!("{} isn't listed", tool));
+        assert_eq!(
+            definition["inputSchema"]["properties"]["button"]["enum"],
+            json!(["left", "right", "middle", "back", "forward", "x1", "x2"]),
+            "{}",
+            tool
+        );
+    }
+}
+
+#[test]
+fn side_button_aliases_press_the_same_button() {
+    let harness = setup();
+    call_ok("mouse_click", json!({ "x": 10, "y": 20, "button": "back" }));
+    call_ok("mouse_click", json!({ "x": 10, "y": 20, "button": "x1" }));
+    let presses: Vec<Button> = harness
+        .input
+        .events()
+        .into_iter()
+        .filter_map(|e| match e {
+            EventType::ButtonPress(button) => Some(button),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(presses.len(), 2);
+    assert_eq!(presses[0], presses[1]);
+    assert!(matches!(presses[0], Button::Unknown(_)));
+}
+
+#[test]
+fn each_button_is_pressed_and_released() {
+    let harness = setup();
+    for (name, button) in [("left", Button::Left), ("right", Button::Right), ("middle", Button::Middle)] {
+        let result = call_ok("mouse_click", json!({ "x": 10, "y": 20, "button": name }));
+        assert_eq!(result["button"], json!(name));
+        let events = harness.input.events();
+        assert_eq!(
+            events[events.len() - 2..],
+            [EventType::ButtonPress(button), EventType::ButtonRelease(button)]
+        );
+    }
+}
+
+/// The positions of every move in `events`.
+fn moves(events: &[EventType]) -> Vec<(f64, f64)> {
+    events
+        .iter()
+        .filter_map(|e| match *e {
+            EventType::MouseMove { x, y } => Some((x, y)),
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn drag_presses_moves_in_even_steps_and_releases() {
+    let harness = setup();
+    call_ok("mouse_drag", json!({ "from_x": 100, "from_y": 200, "to_x": 500, "to_y": 400, "duration_ms": 0 }));
+    let events = harness.input.events();
+
+    // Move to the start, press, drag, release at the end
+    assert_eq!(events[0], EventType::MouseMove { x: 100.0, y: 200.0 });
+    assert_eq!(events[1], EventType::ButtonPress(Button::Left));
+    assert_eq!(*events.last().unwrap(), EventType::ButtonRelease(Button::Left));
+    let path = moves(&events[2..events.len() - 1]);
+    assert_eq!(path.len(), events.len() - 3, "only moves between press and release");
+    assert_eq!(*path.last().unwrap(), (500.0, 400.0));
+
+    // About 8 px apart and evenly spaced along the line
+    let steps = path.len() as f64;
+    let length = (400.0f64).hypot(200.0);
+    assert!((length / steps - 8.0).abs() < 1.0, "{} steps", steps);
+    let mut previous = (100.0, 200.0);
+    for &(x, y) in &path {
+        assert!(((x - previous.0).hypot(y - previous.1) - length / steps).abs() < 1e-6);
+        assert!(((y - 200.0) - (x - 100.0) / 2.0).abs() < 1e-6, "({}, {}) is off the line", x, y);
+        previous = (x, y);
+    }
+}
+
+#[test]
+fn short_drags_still_take_several_steps() {
+    let harness = setup();
+    call_ok("mouse_drag", json!({ "from_x": 10, "from_y": 10, "to_x": 12, "to_y": 10, "duration_ms": 0 }));
+    let events = harness.input.events();
+    assert_eq!(moves(&events[2..events.len() - 1]).len(), 20);
+}
+
+#[test]
+fn drag_holds_modifiers_around_the_whole_drag() {
+    let harness = setup();
+    call_ok(
+        "mouse_drag",
+        json!({ "from_x": 10, "from_y": 10, "to_x": 50, "to_y": 10, "duration_ms": 0, "modifiers": ["alt"] }),
+    );
+    let events = harness.input.events();
+    let press = events.iter().position(|e| *e == EventType::ButtonPress(Button::Left)).unwrap();
+    let release = events.iter().position(|e| *e == EventType::ButtonRelease(Button::Left)).unwrap();
+    let alt_down = events.iter().position(|e| *e == EventType::KeyPress(rdev::Key::Alt)).unwrap();
+    let alt_up = events.iter().position(|e| *e == EventType::KeyRelease(rdev::Key::Alt)).unwrap();
+    assert!(alt_down < press && release < alt_up, "{:?}", events);
+}
+
+#[test]
+fn drag_result_shape() {
+    let _harness = setup();
+    let result = call_ok("mouse_drag", json!({ "from_x": 10, "from_y": 20, "to_x": 30, "to_y": 40, "duration_ms": 0 }));
+    assert_eq!(result["success"], json!(true));
+    assert_eq!(result["from"], json!({ "x": 10.0, "y": 20.0 }));
+    assert_eq!(result["to"], json!({ "x": 30.0, "y": 40.0 }));
+    assert_eq!(result["modifiers"], json!([]));
+}
+
+#[test]
+fn click_timings_over_the_maximum_are_rejected() {
+    let harness = setup();
+    for field in ["interval_ms", "press_duration_ms"] {
+        let mut args = json!({ "x": 10, "y": 20, "click_count": 2 });
+        args[field] = json!(2001);
+        let error = call_err("mouse_click", args);
+        assert_eq!(error["code"], json!(-32602), "{}: {}", field, error);
+        assert_eq!(error["data"]["field"], json!(field));
+    }
+    assert_eq!(harness.input.events(), vec![]);
+}
+
+/// A drag path of `n` points along a line.
+fn path(n: usize) -> Value {
+    json!((0..n).map(|i| json!({ "x": 10 + i, "y": 20 })).collect::<Vec<_>>())
+}
+
+#[test]
+fn long_paths_dont_fail_on_their_default_duration() {
+    let _harness = setup();
+    // 500ms a segment would be over a minute, past the cap. Started rather
+    // than rejected, the drag runs until the call's own timeout stops it.
+    let error = call_err("mouse_drag_path", json!({ "points": path(200), "timeout_ms": 100 }));
+    assert_eq!(error["error_code"], json!("timed_out"), "{}", error);
+    // Let the abandoned drag stop before another test installs its fakes
+    std::thread::sleep(std::time::Duration::from_millis(200));
+}
+
+#[test]
+fn paths_over_the_point_limit_are_rejected() {
+    let harness = setup();
+    let error = call_err("mouse_drag_path", json!({ "points": path(1001), "segment_duration_ms": 0 }));
+    assert_eq!(error["code"], json!(-32602), "{}", error);
+    assert_eq!(error["data"]["field"], json!("points"));
+    assert_eq!(harness.input.events(), vec![]);
+}
+
+/// Milliseconds since the epoch of an RFC 3339 UTC time as the server
+/// formats it, "YYYY-MM-DDTHH:MM:SS.mmmZ".
+fn epoch_ms(timestamp: &str) -> i64 {
+    let field = |range: std::ops::Range<usize>| timestamp[range].parse::<i64>().unwrap();
+    let (year, month, day) = (field(0..4), field(5..7), field(8..10));
+    // Days from civil date (Howard Hinnant's algorithm)
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let doy = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let days = era * 146_097 + yoe * 365 + yoe / 4 - yoe / 100 + doy - 719_468;
+    let secs = days * 86400 + field(11..13) * 3600 + field(14..16) * 60 + field(17..19);
+    secs * 1000 + field(20..23)
+}
+
+fn now_ms() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as i64
+}
+
+#[test]
+fn results_are_timestamped_during_the_call() {
+    let _harness = setup();
+    let monotonic = || call_ok("server_status", json!({}))["now"]["monotonic_ms"].as_i64().unwrap();
+    let (wall_before, monotonic_before) = (now_ms(), monotonic());
+    let response = call("mouse_move", json!({ "x": 10, "y": 20 }));
+    let (wall_after, monotonic_after) = (now_ms(), monotonic());
+
+    let meta = &response["result"]["_meta"];
+    // The timestamp is truncated to the millisecond, as is wall_before
+    let wall = epoch_ms(meta["timestamp"].as_str().unwrap());
+    assert!((wall_before..=wall_after).contains(&wall), "{} not in {}..={}", wall, wall_before, wall_after);
+    let monotonic = meta["monotonic_ms"].as_i64().unwrap();
+    assert!(
+        (monotonic_before..=monotonic_after).contains(&monotonic),
+        "{} not in {}..={}",
+        monotonic,
+        monotonic_before,
+        monotonic_after
+    );
+}
+
+#[test]
+fn long_press_moves_presses_holds_in_place_and_releases() {
+    let harness = setup();
+    let result = call_ok("mouse_long_press", json!({ "x": 10, "y": 20, "hold_ms": 350, "button": "right" }));
+    assert_eq!(result["aborted"], json!(false));
+    assert!(result["held_ms"].as_u64().unwrap() >= 350, "{}", result);
+
+    let events = harness.input.events();
+    let here = EventType::MouseMove { x: 10.0, y: 20.0 };
+    assert_eq!(events[..2], [here, EventType::ButtonPress(Button::Right)], "{:?}", events);
+    assert_eq!(events.last(), Some(&EventType::ButtonRelease(Button::Right)), "{:?}", events);
+    // The position is re-posted every 100ms while held, and nothing else
+    let held = &events[2..events.len() - 1];
+    assert!(held.len() >= 3, "{:?}", events);
+    assert!(held.iter().all(|e| *e == here), "{:?}", events);
+}
+
+#[test]
+fn long_press_aborted_by_escape_still_releases() {
+    let harness = setup();
+    let call = std::thread::spawn(|| call_ok("mouse_long_press", json!({ "x": 10, "y": 20, "hold_ms": 10000 })));
+    let start = std::time::Instant::now();
+    while !harness.input.events().contains(&EventType::ButtonPress(Button::Left)) {
+        assert!(start.elapsed().as_secs() < 5, "The button was never pressed");
+        std::thread::sleep(std::time::Duration::from_millis(10));
+    }
+    assert!(harness.input.physical(EventType::KeyPress(rdev::Key::Escape)), "Nothing listened for Escape");
+    let result = call.join().unwrap();
+
+    assert_eq!(result["aborted"], json!(true), "{}", result);
+    assert_eq!(result["success"], json!(false));
+    assert!(result["held_ms"].as_u64().unwrap() < 5000, "{}", result);
+    let events = harness.input.events();
+    assert_eq!(events.last(), Some(&EventType::ButtonRelease(Button::Left)), "{:?}", events);
+}
@@ -0,0 +1,121 @@
+//! Keys and buttons are let go however a call ends, even when the OS
+//! refuses an event part-way through.
+
+mod common;
+
+use rdev::{Button, EventType, Key};
+use serde_json::json;
+
+use desktop_mcp::backend::FakeInput;
+
+use common::*;
+
+/// Whether every press of `pressed` in `events` is followed by a release.
+/// There may be more releases than presses, since a release another test
+/// still owed can be sent during this one.
+fn released(events: &[EventType], pressed: EventType) -> bool {
+    let release = match pressed {
+        EventType::KeyPress(key) => EventType::KeyRelease(key),
+        EventType::ButtonPress(button) => EventType::ButtonRelease(button),
+        other => panic!("{:?} isn't a press", other),
+    };
+    let presses = events.iter().filter(|e| **e == pressed).count();
+    let releases = events.iter().filter(|e| **e == release).count();
+    let last = |event: EventType| events.iter().rposition(|e| *e == event);
+    presses > 0 && releases >= presses && last(release) > last(pressed)
+}
+
+#[test]
+fn shift_is_released_when_a_shifted_key_fails() {
+    let input = FakeInput::default();
+    input.fail_on(EventType::KeyPress(Key::KeyB));
+    let harness = setup_with(input);
+    let error = call_err("type_text", json!({ "text": "aBc", "delay_ms": 0, "layout": "us" }));
+    assert_eq!(error["error_code"], json!("platform_error"), "{}", error);
+
+    let events = harness.input.events();
+    assert!(released(&events, EventType::KeyPress(Key::ShiftLeft)), "{:?}", events);
+    // Typing stopped at the failure
+    assert!(!events.contains(&EventType::KeyPress(Key::KeyC)), "{:?}", events);
+}
+
+#[test]
+fn shift_is_released_when_a_key_release_fails() {
+    let input = FakeInput::default();
+    input.fail_on(EventType::KeyRelease(Key::KeyB));
+    let harness = setup_with(input);
+    call_err("type_text", json!({ "text": "B", "delay_ms": 0, "layout": "us" }));
+    let events = harness.input.events();
+    assert!(released(&events, EventType::KeyPress(Key::ShiftLeft)), "{:?}", events);
+}
+
+#[test]
+fn modifiers_are_released_when_the_key_fails() {
+    let input = FakeInput::default();
+    input.fail_on(EventType::KeyPress(Key::KeyS));
+    let harness = setup_with(input);
+    call_err("key_tap", json!({ "key": "s", "modifiers": ["control", "shift"] }));
+    let events = harness.input.events();
+    assert!(released(&events, EventType::KeyPress(Key::ControlLeft)), "{:?}", events);
+    assert!(released(&events, EventType::KeyPress(Key::ShiftLeft)), "{:?}", events);
+}
+
+#[test]
+fn later_modifiers_are_released_when_the_first_release_fails() {
+    let input = FakeInput::default();
+    // Modifiers are released in reverse, so shift goes first
+    input.fail_on(EventType::KeyRelease(Key::ShiftLeft));
+    let harness = setup_with(input);
+    let error = call_err("key_tap", json!({ "key": "s", "modifiers": ["control", "shift"] }));
+    assert!(error["message"].as_str().unwrap().contains("Modifier release failed"), "{}", error);
+    let events = harness.input.events();
+    assert!(released(&events, EventType::KeyPress(Key::KeyS)), "{:?}", events);
+    assert!(released(&events, EventType::KeyPress(Key::ControlLeft)), "{:?}", events);
+}
+
+#[test]
+fn a_key_whose_release_fails_is_released_once_the_os_accepts_it() {
+    let input = FakeInput::default();
+    input.fail_on(EventType::KeyRelease(Key::KeyS));
+    let harness = setup_with(input);
+    call_err("key_tap", json!({ "key": "s", "modifiers": ["control"] }));
+    let events = harness.input.events();
+    assert!(released(&events, EventType::KeyPress(Key::ControlLeft)), "{:?}", events);
+    assert!(!events.contains(&EventType::KeyRelease(Key::KeyS)), "{:?}", events);
+    drop(harness);
+
+    // The release is still owed, and is sent once the next call fails
+    let input = FakeInput::default();
+    input.fail_on(EventType::KeyPress(Key::KeyA));
+    let harness = setup_with(input);
+    call_err("key_tap", json!({ "key": "a" }));
+    assert!(harness.input.events().contains(&EventType::KeyRelease(Key::KeyS)));
+}
+
+#[test]
+fn drag_button_is_released_when_a_move_fails() {
+    let input = FakeInput::default();
+    // The last step of the drag
+    input.fail_on(EventType::MouseMove { x: 300.0, y: 100.0 });
+    let harness = setup_with(input);
+    call_err(
+        "mouse_drag",
+        json!({ "from_x": 100, "from_y": 100, "to_x": 300, "to_y": 100, "duration_ms": 0, "modifiers": ["alt"] }),
+    );
+    let events = harness.input.events();
+    assert!(released(&events, EventType::ButtonPress(Button::Left)), "{:?}", events);
+    assert!(released(&events, EventType::KeyPress(Key::Alt)), "{:?}", events);
+}
+
+#[test]
+fn drag_path_button_is_released_when_a_move_fails() {
+    let input = FakeInput::default();
+    input.fail_on(EventType::MouseMove { x: 50.0, y: 50.0 });
+    let harness = setup_with(input);
+    call_err(
+        "mouse_drag_path",
+        json!({ "points": [{ "x": 10, "y": 10 }, { "x": 50, "y": 50 }, { "x": 90, "y": 10 }], "segment_duration_ms": 0, "button": "right" }),
+    );
+    let events = harness.input.events();
+    assert!(released(&events, EventType::ButtonPress(Button::Right)), "{:?}", events);
+}
@@ -0,0 +1,34 @@
+//! The config file's `[safety] screen_indices` allowlist.
+
+mod common;
+
+use serde_json::json;
+
+use common::*;
+
+/// The config file's allowlist, loaded once for every test here.
+fn setup_restricted() -> Harness {
+    let path = std::env::temp_dir().join(format!("desktop-mcp-safety-{}.toml", std::process::id()));
+    std::fs::write(&path, "[safety]\nscreen_indices = [0]\n").unwrap();
+    desktop_mcp::load_config(Some(path.clone())).unwrap();
+    let _ = std::fs::remove_file(path);
+    setup()
+}
+
+#[test]
+fn screens_outside_the_list_are_refused() {
+    let _harness = setup_restricted();
+    let error = call_err("screen_capture", json!({ "screen_index": 1 }));
+    assert_eq!(error["error_code"], json!("permission_denied"), "{}", error);
+    call_ok("screen_capture", json!({ "screen_index": 0, "max_width": 480 }));
+}
+
+#[test]
+fn stored_captures_can_be_compared_against() {
+    let _harness = setup_restricted();
+    let region = json!({ "x": 100, "y": 50, "width": 64, "height": 32 });
+    let capture = call_ok("screen_capture", json!({ "region": region, "format": "png", "save_to": "resource" }));
+    let uri = capture["uri"].as_str().unwrap();
+    let result = call_ok("compare_screens", json!({ "capture_id": uri }));
+    assert_eq!(result["changed"], json!(false), "{}", result);
+}
@@ -0,0 +1,119 @@
+mod common;
+
+use rdev::{EventType, Key};
+use serde_json::{json, Value};
+
+use common::*;
+
+fn tap(key: Key) -> [EventType; 2] {
+    [EventType::KeyPress(key), EventType::KeyRelease(key)]
+}
+
+#[test]
+fn set_text_field_selects_all_deletes_types_and_tabs() {
+    let harness = setup();
+    let result = call_ok("set_text_field", json!({ "text": "Ab", "then": "tab", "delay_ms": 0 }));
+    assert_eq!(result["steps"], json!(["select_all", "delete", "type", "tab"]));
+    assert_eq!(result["chars"], json!(2));
+    assert_eq!(result["success"], json!(true));
+
+    let modifier = if cfg!(target_os = "macos") { Key::MetaLeft } else { Key::ControlLeft };
+    let mut expected = vec![EventType::KeyPress(modifier)];
+    expected.extend(tap(Key::KeyA));
+    expected.push(EventType::KeyRelease(modifier));
+    expected.extend(tap(Key::Backspace));
+    expected.push(EventType::KeyPress(Key::ShiftLeft));
+    expected.extend(tap(Key::KeyA));
+    expected.push(EventType::KeyRelease(Key::ShiftLeft));
+    expected.extend(tap(Key::KeyB));
+    expected.extend(tap(Key::Tab));
+    assert_eq!(harness.input.events(), expected);
+}
+
+#[test]
+fn set_text_field_refuses_untypable_text_before_touching_the_field() {
+    let harness = setup();
+    let error = call_err("set_text_field", json!({ "text": "snow ☃ man", "layout": "us", "then": "enter" }));
+    assert_eq!(error["code"], json!(-32602), "{}", error);
+    assert_eq!(error["data"]["field"], json!("text"));
+    let message = error["message"].as_str().unwrap();
+    assert!(message.contains("U+2603") && message.contains("index 5"), "{}", message);
+    assert_eq!(harness.input.events(), vec![]);
+}
+
+#[test]
+fn set_text_field_with_empty_text_just_clears() {
+    let harness = setup();
+    let result = call_ok("set_text_field", json!({ "text": "" }));
+    assert_eq!(result["steps"], json!(["select_all", "delete"]));
+    assert!(harness.input.events().ends_with(&tap(Key::Backspace)));
+}
+
+/// Every JSON item in a tool result, merged: a failed call's error details
+/// and the JSON that follows them.
+fn all_json(response: &Value) -> Value {
+    let mut merged = json!({});
+    for item in response["result"]["content"].as_array().unwrap() {
+        let parsed = item["text"].as_str().and_then(|text| serde_json::from_str(text).ok());
+        if let Some(Value::Object(fields)) = parsed {
+            merged.as_object_mut().unwrap().extend(fields);
+        }
+    }
+    merged
+}
+
+#[test]
+fn type_text_skips_untypable_characters_and_reports_them() {
+    let harness = setup();
+    let result = call_ok("type_text", json!({ "text": "a☃b日c", "delay_ms": 0, "layout": "us" }));
+    assert_eq!(harness.input.events(), [tap(Key::KeyA), tap(Key::KeyB), tap(Key::KeyC)].concat());
+    assert_eq!(result["chars"], json!(3));
+    assert_eq!(result["requested_chars"], json!(5));
+    assert_eq!(result["complete"], json!(false));
+    assert_eq!(
+        result["skipped"],
+        json!([
+            { "index": 1, "char": "☃", "codepoint": "U+2603" },
+            { "index": 3, "char": "日", "codepoint": "U+65E5" }
+        ])
+    );
+}
+
+#[test]
+fn strict_type_text_refuses_untypable_text_before_typing_anything() {
+    let harness = setup();
+    let response = call("type_text", json!({ "text": "a☃b日c", "delay_ms": 0, "layout": "us", "strict": true }));
+    assert_eq!(response["result"]["isError"], json!(true), "{}", response);
+    let result = all_json(&response);
+    assert_eq!(result["error_code"], json!("invalid_params"));
+    assert_eq!(result["field"], json!("text"));
+    assert_eq!(result["index"], json!(1));
+    assert_eq!(result["char"], json!("☃"));
+    assert_eq!(result["codepoint"], json!("U+2603"));
+    assert_eq!(result["chars"], json!(0));
+    assert_eq!(harness.input.events(), vec![]);
+}
+
+#[test]
+fn strict_type_text_indexes_characters_not_bytes() {
+    let harness = setup();
+    // ö and ß have keys on German keyboards, and take two bytes each
+    let response = call("type_text", json!({ "text": "öß☃", "delay_ms": 0, "layout": "de", "strict": true }));
+    let result = all_json(&response);
+    assert_eq!(result["index"], json!(2), "{}", response);
+    assert_eq!(result["layout"], json!("de"));
+    assert_eq!(harness.input.events(), vec![]);
+}
+
+#[test]
+fn strict_type_text_types_text_that_has_every_key() {
+    let harness = setup();
+    let result = call_ok("type_text", json!({ "text": "öß a", "delay_ms": 0, "layout": "de", "strict": true }));
+    assert_eq!(result["complete"], json!(true));
+    assert_eq!(result["chars"], json!(4));
+    assert_eq!(result["bytes"], json!(6));
+    assert_eq!(
+        harness.input.events(),
+        [tap(Key::SemiColon), tap(Key::Minus), tap(Key::Space), tap(Key::KeyA)].concat()
+    );
+}
@@ -0,0 +1,70 @@
+//! wait_for_user_click, with clicks injected as if made on the real mouse.
+
+mod common;
+
+use rdev::{Button, EventType};
+use serde_json::{json, Value};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use desktop_mcp::backend::{FakeInput, FakeScreen, WindowInfo};
+
+use common::*;
+
+fn window(id: u64, title: &str, (x, y, width, height): (f64, f64, f64, f64)) -> WindowInfo {
+    WindowInfo {
+        id,
+        title: title.to_string(),
+        app_name: "App".to_string(),
+        pid: 999_999,
+        x,
+        y,
+        width,
+        height,
+    }
+}
+
+/// A small dialog in front of a window filling the screen.
+fn setup_windows() -> Harness {
+    std::env::set_var("DESKTOP_MCP_ENABLE_USER_CLICK", "1");
+    let screen = FakeScreen::from_png(&png(&gradient(1920, 1080)), 1.0).unwrap().with_windows(vec![
+        window(1, "Dialog", (100.0, 100.0, 400.0, 300.0)),
+        window(2, "Document", (0.0, 0.0, 1920.0, 1040.0)),
+    ]);
+    setup_fakes(screen, FakeInput::default())
+}
+
+/// Wait for a click, clicking the physical left button at (x, y) once the
+/// server is listening, and return the result.
+fn click_at(harness: &Harness, x: f64, y: f64) -> Value {
+    let call = thread::spawn(|| call_ok("wait_for_user_click", json!({ "timeout_ms": 5000 })));
+    let start = Instant::now();
+    while !harness.input.physical(EventType::MouseMove { x, y }) {
+        assert!(start.elapsed() < Duration::from_secs(5), "Nothing waited for the click");
+        thread::sleep(Duration::from_millis(10));
+    }
+    harness.input.physical(EventType::ButtonPress(Button::Left));
+    harness.input.physical(EventType::ButtonRelease(Button::Left));
+    call.join().unwrap()
+}
+
+#[test]
+fn the_click_reports_the_frontmost_window_under_it() {
+    let harness = setup_windows();
+    let result = click_at(&harness, 200.0, 150.0);
+    assert_eq!(result["clicked"], json!(true), "{}", result);
+    assert_eq!((result["x"].clone(), result["y"].clone()), (json!(200.0), json!(150.0)));
+    assert_eq!(result["button"], json!("left"));
+    assert_eq!(result["window"]["title"], json!("Dialog"));
+
+    let result = click_at(&harness, 1000.0, 500.0);
+    assert_eq!(result["window"]["title"], json!("Document"), "{}", result);
+}
+
+#[test]
+fn a_click_outside_every_window_has_none() {
+    let harness = setup_windows();
+    let result = click_at(&harness, 1000.0, 1060.0);
+    assert_eq!(result["clicked"], json!(true), "{}", result);
+    assert_eq!(result["window"], Value::Null);
+}